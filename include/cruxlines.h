@@ -0,0 +1,59 @@
+#ifndef CRUXLINES_H
+#define CRUXLINES_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// One ranked definition. Strings are NUL-terminated and owned by the
+// enclosing [`CruxlinesRows`].
+typedef struct CruxlinesRow {
+  char *path;
+  char *name;
+  // Symbol kind, e.g. `function` or `class`.
+  char *kind;
+  size_t line;
+  size_t column;
+  double rank;
+  double local_score;
+  double file_rank;
+  size_t reference_count;
+  // Text of the definition's line, as analyzed.
+  char *definition_line;
+} CruxlinesRow;
+
+// Rows in rank order, released with [`cruxlines_rows_free`].
+typedef struct CruxlinesRows {
+  struct CruxlinesRow *rows;
+  size_t len;
+} CruxlinesRows;
+
+// Analyzes the `n` files or directories in `paths` and stores the ranked
+// rows in `out_rows`. The repo root is the closest ancestor of the first
+// path that holds `.git`; relative paths are resolved against the current
+// directory. Returns 0 on success and -1 on failure, in which case
+// [`cruxlines_last_error`] describes the problem and `out_rows` is left
+// untouched.
+//
+// # Safety
+//
+// `paths` must point to `n` valid NUL-terminated strings and `out_rows`
+// to writable memory for one [`CruxlinesRows`].
+int32_t cruxlines_analyze(const char *const *paths, size_t n, struct CruxlinesRows *out_rows);
+
+// Releases rows returned by [`cruxlines_analyze`] and resets `rows` to
+// empty. Passing null or empty rows is a no-op.
+//
+// # Safety
+//
+// `rows` must be null or filled in by [`cruxlines_analyze`] and not freed
+// before.
+void cruxlines_rows_free(struct CruxlinesRows *rows);
+
+// Message of the last failed call on this thread, or null. The string stays
+// valid until another call fails on this thread.
+const char *cruxlines_last_error(void);
+
+#endif  /* CRUXLINES_H */
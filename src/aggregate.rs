@@ -0,0 +1,398 @@
+use std::collections::{HashMap, HashSet};
+
+use lasso::Spur;
+
+use crate::analysis::OutputRow;
+use crate::find_references::{Location, link_name};
+use crate::intern::resolve;
+use crate::languages::Ecosystem;
+
+/// Combined score of every ranked definition in one file.
+#[derive(Debug, Clone)]
+pub struct FileSummary {
+    pub path: Spur,
+    /// Sum of the ranks of the file's definitions.
+    pub total_rank: f64,
+    /// Rank of the file's highest-ranked definition.
+    pub max_rank: f64,
+    pub definition_count: usize,
+    /// The file's highest-ranked definition.
+    pub top_symbol: Location,
+}
+
+impl FileSummary {
+    pub fn path_str(&self) -> &'static str {
+        resolve(self.path)
+    }
+}
+
+/// Collapses rows into one summary per definition path, highest total rank
+/// first.
+pub fn summarize_files(rows: &[OutputRow]) -> Vec<FileSummary> {
+    let mut by_path: HashMap<Spur, FileSummary> = HashMap::new();
+    for row in rows {
+        let summary = by_path
+            .entry(row.definition.path)
+            .or_insert_with(|| FileSummary {
+                path: row.definition.path,
+                total_rank: 0.0,
+                max_rank: f64::NEG_INFINITY,
+                definition_count: 0,
+                top_symbol: row.definition,
+            });
+        summary.total_rank += row.rank;
+        summary.definition_count += 1;
+        if row.rank > summary.max_rank {
+            summary.max_rank = row.rank;
+            summary.top_symbol = row.definition;
+        }
+    }
+
+    let mut summaries: Vec<FileSummary> = by_path.into_values().collect();
+    summaries.sort_by(|a, b| {
+        b.total_rank
+            .partial_cmp(&a.total_rank)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.path_str().cmp(b.path_str()))
+    });
+    summaries
+}
+
+/// Combined score of every ranked definition one CODEOWNERS owner owns.
+#[derive(Debug, Clone)]
+pub struct OwnerSummary {
+    /// `None` for definitions in files no CODEOWNERS line matches.
+    pub owner: Option<String>,
+    pub total_rank: f64,
+    pub max_rank: f64,
+    pub definition_count: usize,
+    pub top_symbol: Location,
+}
+
+/// Collapses rows into one summary per owner, highest total rank first. A
+/// definition with several owners counts fully towards each of them.
+pub fn summarize_owners(rows: &[OutputRow]) -> Vec<OwnerSummary> {
+    let mut by_owner: HashMap<Option<&str>, OwnerSummary> = HashMap::new();
+    for row in rows {
+        let owners: Vec<Option<&str>> = if row.owners.is_empty() {
+            vec![None]
+        } else {
+            row.owners
+                .iter()
+                .map(|owner| Some(owner.as_str()))
+                .collect()
+        };
+        for owner in owners {
+            let summary = by_owner.entry(owner).or_insert_with(|| OwnerSummary {
+                owner: owner.map(str::to_string),
+                total_rank: 0.0,
+                max_rank: f64::NEG_INFINITY,
+                definition_count: 0,
+                top_symbol: row.definition,
+            });
+            summary.total_rank += row.rank;
+            summary.definition_count += 1;
+            if row.rank > summary.max_rank {
+                summary.max_rank = row.rank;
+                summary.top_symbol = row.definition;
+            }
+        }
+    }
+
+    let mut summaries: Vec<OwnerSummary> = by_owner.into_values().collect();
+    summaries.sort_by(|a, b| {
+        b.total_rank
+            .partial_cmp(&a.total_rank)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.owner.cmp(&b.owner))
+    });
+    summaries
+}
+
+/// A name defined in more than one file of an ecosystem. References match
+/// such names by name alone unless an import resolves them, and each
+/// definition's score is divided by the number of definitions. Qualified
+/// names such as Java's `User.save` count under their member name.
+#[derive(Debug, Clone)]
+pub struct AmbiguousName {
+    pub name: Spur,
+    pub ecosystem: Ecosystem,
+    /// References to any of the definitions.
+    pub reference_count: usize,
+    /// The colliding definitions, highest rank first.
+    pub definitions: Vec<Location>,
+}
+
+impl AmbiguousName {
+    pub fn name_str(&self) -> &'static str {
+        resolve(self.name)
+    }
+}
+
+/// Groups rows by name within each ranking ecosystem and keeps the names defined in
+/// several files, most referenced first, since those lose the most to
+/// name-based matching.
+pub fn ambiguous_names(rows: &[OutputRow]) -> Vec<AmbiguousName> {
+    let mut by_name: HashMap<(Ecosystem, Spur), Vec<&OutputRow>> = HashMap::new();
+    for row in rows {
+        by_name
+            .entry((row.ecosystem, link_name(row.definition.name)))
+            .or_default()
+            .push(row);
+    }
+
+    let mut names: Vec<AmbiguousName> = by_name
+        .into_iter()
+        .filter(|(_, rows)| {
+            rows.iter()
+                .map(|row| row.definition.path)
+                .collect::<HashSet<_>>()
+                .len()
+                > 1
+        })
+        .map(|((ecosystem, name), mut rows)| {
+            rows.sort_by(|a, b| {
+                b.rank
+                    .partial_cmp(&a.rank)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| a.definition.path_str().cmp(b.definition.path_str()))
+                    .then_with(|| a.definition.line.cmp(&b.definition.line))
+            });
+            AmbiguousName {
+                name,
+                ecosystem,
+                reference_count: rows.iter().map(|row| row.reference_count).sum(),
+                definitions: rows.iter().map(|row| row.definition).collect(),
+            }
+        })
+        .collect();
+    names.sort_by(|a, b| {
+        b.reference_count
+            .cmp(&a.reference_count)
+            .then_with(|| a.name_str().cmp(b.name_str()))
+            .then_with(|| a.definitions[0].path_str().cmp(b.definitions[0].path_str()))
+    });
+    names
+}
+
+/// The rows of one ecosystem, ranked on their own.
+#[derive(Debug, Clone)]
+pub struct EcosystemSection {
+    pub ecosystem: Ecosystem,
+    /// Number of definitions in the ecosystem, even once `rows` is cut.
+    pub definition_count: usize,
+    /// Sum of the definitions' ranks.
+    pub total_rank: f64,
+    /// Rank of the ecosystem's highest-ranked definition.
+    pub max_rank: f64,
+    /// The rows, highest rank first, with each rank divided by `max_rank`
+    /// so the top row of every section has rank 1.
+    pub rows: Vec<OutputRow>,
+}
+
+/// Splits rows into one section per ranking ecosystem, highest total rank
+/// first. Ranks from different ecosystems come from separate graphs, so each
+/// section is normalized against its own top row.
+pub fn split_by_ecosystem(rows: Vec<OutputRow>) -> Vec<EcosystemSection> {
+    let mut by_ecosystem: HashMap<Ecosystem, Vec<OutputRow>> = HashMap::new();
+    for row in rows {
+        by_ecosystem.entry(row.ecosystem).or_default().push(row);
+    }
+
+    let mut sections: Vec<EcosystemSection> = by_ecosystem
+        .into_iter()
+        .map(|(ecosystem, mut rows)| {
+            let total_rank = rows.iter().map(|row| row.rank).sum();
+            let max_rank = rows.iter().map(|row| row.rank).fold(0.0, f64::max);
+            if max_rank > 0.0 {
+                for row in &mut rows {
+                    row.rank /= max_rank;
+                }
+            }
+            EcosystemSection {
+                ecosystem,
+                definition_count: rows.len(),
+                total_rank,
+                max_rank,
+                rows,
+            }
+        })
+        .collect();
+    sections.sort_by(|a, b| {
+        b.total_rank
+            .partial_cmp(&a.total_rank)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| {
+                a.rows[0]
+                    .definition
+                    .path_str()
+                    .cmp(b.rows[0].definition.path_str())
+            })
+    });
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ambiguous_names, split_by_ecosystem, summarize_files, summarize_owners};
+    use crate::analysis::{OutputRow, ScoreBreakdown};
+    use std::path::Path;
+
+    use crate::find_references::Location;
+    use crate::intern::intern;
+    use crate::languages::{Ecosystem, SymbolKind, ecosystem_for_language, language_for_path};
+
+    fn row(path: &str, line: usize, name: &str, rank: f64) -> OutputRow {
+        OutputRow {
+            rank,
+            local_score: rank,
+            file_rank: 1.0,
+            symbol_rank: None,
+            definition: Location {
+                path: intern(path),
+                line,
+                column: 1,
+                name: intern(name),
+            },
+            kind: SymbolKind::Function,
+            ecosystem: ecosystem_for_language(
+                language_for_path(Path::new(path)).expect("known extension"),
+            ),
+            definition_line: String::new(),
+            references: Vec::new(),
+            reference_count: 0,
+            breakdown: ScoreBreakdown::default(),
+            owners: Vec::new(),
+            is_entry_point: false,
+            execution_count: None,
+        }
+    }
+
+    #[test]
+    fn sums_ranks_and_keeps_top_symbol_per_file() {
+        let rows = vec![
+            row("a.py", 1, "big", 0.5),
+            row("b.py", 1, "lone", 0.6),
+            row("a.py", 4, "small", 0.2),
+        ];
+
+        let summaries = summarize_files(&rows);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].path_str(), "a.py");
+        assert!((summaries[0].total_rank - 0.7).abs() < 1e-9);
+        assert_eq!(summaries[0].max_rank, 0.5);
+        assert_eq!(summaries[0].definition_count, 2);
+        assert_eq!(summaries[0].top_symbol.name_str(), "big");
+        assert_eq!(summaries[1].path_str(), "b.py");
+    }
+
+    #[test]
+    fn credits_every_owner_and_groups_unowned_rows() {
+        let mut shared = row("a.py", 1, "shared", 0.5);
+        shared.owners = vec!["@core".to_string(), "@web".to_string()];
+        let mut web = row("b.py", 1, "web", 0.3);
+        web.owners = vec!["@web".to_string()];
+        let rows = vec![shared, web, row("c.py", 1, "orphan", 0.1)];
+
+        let summaries = summarize_owners(&rows);
+        let owners: Vec<_> = summaries
+            .iter()
+            .map(|summary| (summary.owner.as_deref(), summary.definition_count))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![(Some("@web"), 2), (Some("@core"), 1), (None, 1)]
+        );
+        assert!((summaries[0].total_rank - 0.8).abs() < 1e-9);
+        assert_eq!(summaries[0].top_symbol.name_str(), "shared");
+    }
+
+    #[test]
+    fn lists_names_defined_in_several_files_of_one_ecosystem() {
+        let mut shared = row("b.py", 1, "load", 0.2);
+        shared.reference_count = 3;
+        let mut other = row("a.py", 7, "load", 0.4);
+        other.reference_count = 2;
+        let mut helper = row("c.py", 1, "helper", 0.1);
+        helper.reference_count = 1;
+        // A namespace is ranked in a graph of its own, so it collides with nothing.
+        let mut namespaced = row("scripts/h.py", 1, "unique", 0.1);
+        namespaced.ecosystem = Ecosystem::namespaced(0, Ecosystem::Python);
+        let rows = vec![
+            shared,
+            other,
+            helper,
+            row("d.py", 1, "helper", 0.1),
+            // Same file twice, or another ecosystem, is not a collision.
+            row("e.py", 1, "local", 0.1),
+            row("e.py", 9, "local", 0.1),
+            row("f.rs", 1, "unique", 0.1),
+            row("g.py", 1, "unique", 0.1),
+            namespaced,
+        ];
+
+        let names = ambiguous_names(&rows);
+        let summary: Vec<_> = names
+            .iter()
+            .map(|name| {
+                (
+                    name.name_str(),
+                    name.reference_count,
+                    name.definitions
+                        .iter()
+                        .map(|definition| definition.path_str())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("load", 5, vec!["a.py", "b.py"]),
+                ("helper", 1, vec!["c.py", "d.py"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn normalizes_each_ecosystem_against_its_own_top_row() {
+        let mut scripts = row("scripts/d.py", 1, "tool", 0.01);
+        scripts.ecosystem = Ecosystem::namespaced(0, Ecosystem::Python);
+        let rows = vec![
+            row("a.py", 1, "big", 0.8),
+            row("b.rs", 1, "lone", 0.05),
+            row("c.py", 1, "small", 0.2),
+            scripts,
+        ];
+
+        let sections = split_by_ecosystem(rows);
+        let summary: Vec<_> = sections
+            .iter()
+            .map(|section| {
+                (
+                    section.ecosystem,
+                    section.definition_count,
+                    section
+                        .rows
+                        .iter()
+                        .map(|row| (row.definition.name_str(), row.rank))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Ecosystem::Python, 2, vec![("big", 1.0), ("small", 0.25)]),
+                (Ecosystem::Rust, 1, vec![("lone", 1.0)]),
+                (
+                    Ecosystem::namespaced(0, Ecosystem::Python),
+                    1,
+                    vec![("tool", 1.0)]
+                ),
+            ]
+        );
+        assert!((sections[0].total_rank - 1.0).abs() < 1e-9);
+        assert_eq!(sections[0].max_rank, 0.8);
+    }
+}
@@ -1,37 +1,134 @@
+use petgraph::graph::Graph;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use lasso::Spur;
 use rayon::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
+use crate::builder::Cruxlines;
 use crate::cache::FileCache;
+use crate::codeowners::CodeOwners;
+use crate::error::{CruxlinesError, Diagnostics};
+use crate::filter::DefinitionFilter;
 use crate::find_references::{
-    Location, ReferenceEdge, ReferenceScan, find_references, find_references_cached,
+    Location, ReferenceCounts, ReferenceEdge, ReferenceScan, ScanStats, count_references,
+    find_references, link_name,
 };
-use crate::graph::build_file_graph;
-use crate::intern::intern;
-use crate::io::{CruxlinesError, gather_paths};
-use crate::languages::Ecosystem;
+use crate::graph::{
+    betweenness, build_file_graph, build_reference_graph, file_graph_hash, in_degrees, normalized,
+    page_rank, reachable_definitions,
+};
+use crate::languages::{Ecosystem, SymbolKind};
+use crate::namespaces::Namespaces;
+use crate::options::{Centrality, Encoding, Options, ReferenceAggregation, Sample, Weights};
+use crate::progress::Phase;
+use crate::source::{FileSystemSource, SourceProvider};
 
 #[derive(Debug, Clone)]
 pub struct OutputRow {
     pub rank: f64,
     pub local_score: f64,
     pub file_rank: f64,
+    /// Normalized symbol-graph PageRank, present when `Options::symbol_rank` is set.
+    pub symbol_rank: Option<f64>,
     pub definition: Location,
+    pub kind: SymbolKind,
+    /// The graph the definition was ranked in: its language ecosystem, or
+    /// that ecosystem within one of `Options::namespaces`.
+    pub ecosystem: Ecosystem,
     /// Definition line text from the input snapshot.
     pub definition_line: String,
-    /// Heuristic reference locations; may include false positives.
+    /// Heuristic reference locations; may include false positives. Empty
+    /// with `Options::low_memory`.
     pub references: Vec<Location>,
+    /// Number of references, also known with `Options::low_memory`.
+    pub reference_count: usize,
+    /// Inputs of `local_score`, for re-weighting or display.
+    pub breakdown: ScoreBreakdown,
+    /// Owners of the definition's file according to the repo's
+    /// `CODEOWNERS`; empty when no line matches.
+    pub owners: Vec<String>,
+    /// The definition is where a program starts, such as `fn main` or a
+    /// package's `main` file.
+    pub is_entry_point: bool,
+    /// How often the definition ran according to `Options::coverage`;
+    /// `None` without coverage data for its file.
+    pub execution_count: Option<u64>,
+}
+
+/// Named components behind a row's `local_score`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScoreBreakdown {
+    pub reference_count: usize,
+    /// Files the references come from.
+    pub distinct_referencing_files: usize,
+    /// Sum of each reference's frecency multiplier (frecency raised to
+    /// `Weights::frecency`), before file ranks and test multipliers.
+    pub frecency_weight_sum: f64,
+    /// Divisor for names defined more than once in the ecosystem:
+    /// `name_count ^ Weights::name_ambiguity`.
+    pub ambiguity_penalty: f64,
+    /// References from package manifests (`package.json`,
+    /// `pyproject.toml`), which make the definition public API.
+    pub manifest_references: usize,
+}
+
+/// Ranked rows plus the files that were skipped because they could not be
+/// read, decoded or parsed.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub rows: Vec<OutputRow>,
+    pub diagnostics: Diagnostics,
+    pub stats: ScanStats,
 }
 
 pub fn cruxlines(
     repo_root: &PathBuf,
     ecosystems: &std::collections::HashSet<Ecosystem>,
-) -> Result<Vec<OutputRow>, CruxlinesError> {
-    let paths = gather_paths(repo_root, ecosystems);
-    cruxlines_from_paths(paths, Some(repo_root.clone()))
+) -> Result<Analysis, CruxlinesError> {
+    Cruxlines::builder()
+        .ecosystems(ecosystems.iter().copied())
+        .run_repo(repo_root)
+}
+
+pub fn cruxlines_with_options(
+    repo_root: &PathBuf,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) -> Result<Analysis, CruxlinesError> {
+    Cruxlines::builder()
+        .options(options.clone())
+        .ecosystems(ecosystems.iter().copied())
+        .run_repo(repo_root)
+}
+
+/// Analyzes only the given files or directories. `repo_root` is still used
+/// for frecency and the file cache.
+pub fn cruxlines_in_paths(
+    repo_root: &Path,
+    paths: &[PathBuf],
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) -> Result<Analysis, CruxlinesError> {
+    Cruxlines::builder()
+        .options(options.clone())
+        .ecosystems(ecosystems.iter().copied())
+        .run_paths(repo_root, paths)
+}
+
+/// Analyzes the files tracked at a git `revision` instead of the working
+/// tree. Frecency still comes from the current history.
+pub fn cruxlines_at_revision(
+    repo_root: &Path,
+    revision: &str,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) -> Result<Analysis, CruxlinesError> {
+    Cruxlines::builder()
+        .options(options.clone())
+        .ecosystems(ecosystems.iter().copied())
+        .run_revision(repo_root, revision)
 }
 
 #[doc(hidden)]
@@ -39,131 +136,844 @@ pub fn cruxlines_from_inputs(
     inputs: Vec<(PathBuf, String)>,
     repo_root: Option<PathBuf>,
 ) -> Vec<OutputRow> {
-    let inputs = inputs.into_iter().map(Ok);
-    let (scan, frecency) = compute_edges_and_frecency(inputs, repo_root).unwrap_or_else(|_| {
-        (
-            ReferenceScan {
-                edges: Vec::new(),
-                definition_lines: HashMap::new(),
-            },
-            HashMap::new(),
-        )
-    });
+    let mut builder = Cruxlines::builder();
+    if let Some(repo_root) = repo_root {
+        builder = builder.repo_root(repo_root);
+    }
+    builder
+        .run_inputs(inputs)
+        .map(|analysis| analysis.rows)
+        .unwrap_or_default()
+}
 
-    let grouped_by_ecosystem = group_edges_by_ecosystem(scan.edges);
-    let capacity: usize = grouped_by_ecosystem
-        .values()
-        .map(|grouped| grouped.len())
-        .sum();
-    let mut output_rows = Vec::with_capacity(capacity);
-    for grouped in grouped_by_ecosystem.into_values() {
-        let file_ranks = rank_files(&grouped);
-
-        let mut name_counts: FxHashMap<Spur, usize> = FxHashMap::default();
-        for definition in grouped.keys() {
-            *name_counts.entry(definition.name).or_default() += 1;
-        }
+pub fn cruxlines_from_paths(
+    paths: Vec<PathBuf>,
+    repo_root: Option<PathBuf>,
+    options: &Options,
+) -> Result<Analysis, CruxlinesError> {
+    let source = FileSystemSource::new(paths);
+    cruxlines_from_source(&source, repo_root.as_deref(), options)
+}
 
-        output_rows.extend(build_rows(
-            grouped,
-            &file_ranks,
+/// Analyzes the files supplied by `source`. `repo_root` enables frecency and,
+/// for sources that read from disk, the file cache.
+pub fn cruxlines_from_source(
+    source: &impl SourceProvider,
+    repo_root: Option<&Path>,
+    options: &Options,
+) -> Result<Analysis, CruxlinesError> {
+    Ok(cruxlines_iter(source, repo_root, options)?.into_analysis())
+}
+
+/// Like [`cruxlines_from_source`], but returns the rows as an iterator in
+/// rank order. Scores are computed up front; each row's references are only
+/// sorted and moved into the row when it is pulled, so consumers can start
+/// rendering right away.
+pub fn cruxlines_iter(
+    source: &impl SourceProvider,
+    repo_root: Option<&Path>,
+    options: &Options,
+) -> Result<RankedRows, CruxlinesError> {
+    let filter = DefinitionFilter::new(options)?;
+    let namespaces = Namespaces::new(repo_root, options)?;
+    if options.low_memory {
+        let (counts, frecency, authors, cache) = scan_with_history(repo_root, options, |cache| {
+            count_references(source, repo_root, cache, options, &namespaces)
+        });
+        report_rank(options, 0);
+        let ranked = rank_counts(
+            counts,
             &frecency,
-            &name_counts,
-            &scan.definition_lines,
-        ));
+            &authors,
+            repo_root,
+            options,
+            &filter,
+            cache.as_ref(),
+        );
+        report_rank(options, 1);
+        return Ok(ranked);
     }
+    let scanned = ScannedSource::scan(source, repo_root, options, &namespaces);
+    report_rank(options, 0);
+    let ranked = scanned.into_ranked(&filter);
+    report_rank(options, 1);
+    Ok(ranked)
+}
 
-    output_rows.sort_by(|a, b| {
-        b.rank
-            .partial_cmp(&a.rank)
-            .unwrap_or(std::cmp::Ordering::Equal)
-            .then_with(|| {
-                let key_a = (
-                    a.definition.path,
-                    a.definition.line,
-                    a.definition.column,
-                    a.definition.name,
-                );
-                let key_b = (
-                    b.definition.path,
-                    b.definition.line,
-                    b.definition.column,
-                    b.definition.name,
-                );
-                key_a.cmp(&key_b)
+/// Rows in rank order, built lazily. See [`cruxlines_iter`].
+#[derive(Debug)]
+pub struct RankedRows {
+    scored: std::vec::IntoIter<ScoredDefinition>,
+    references: HashMap<Location, Vec<Location>>,
+    definition_lines: HashMap<Location, String>,
+    owners: CodeOwners,
+    diagnostics: Diagnostics,
+    stats: ScanStats,
+}
+
+impl RankedRows {
+    /// Files that were skipped because they could not be read, decoded or
+    /// parsed.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Counts of files, definitions and references seen by the scan.
+    pub fn stats(&self) -> &ScanStats {
+        &self.stats
+    }
+
+    /// Builds all remaining rows at once, in parallel.
+    pub fn into_analysis(mut self) -> Analysis {
+        let pending: Vec<_> = self
+            .scored
+            .by_ref()
+            .map(|scored| {
+                let references = self
+                    .references
+                    .remove(&scored.definition)
+                    .unwrap_or_default();
+                let line = self
+                    .definition_lines
+                    .remove(&scored.definition)
+                    .unwrap_or_default();
+                (scored, references, line)
             })
-    });
-    output_rows
+            .collect();
+        let rows = pending
+            .into_par_iter()
+            .map(|(scored, references, line)| scored.into_row(references, line, &self.owners))
+            .collect();
+        Analysis {
+            rows,
+            diagnostics: self.diagnostics,
+            stats: self.stats,
+        }
+    }
 }
 
-pub fn cruxlines_from_paths(
-    paths: Vec<PathBuf>,
+impl Iterator for RankedRows {
+    type Item = OutputRow;
+
+    fn next(&mut self) -> Option<OutputRow> {
+        let scored = self.scored.next()?;
+        let references = self
+            .references
+            .remove(&scored.definition)
+            .unwrap_or_default();
+        let line = self
+            .definition_lines
+            .remove(&scored.definition)
+            .unwrap_or_default();
+        Some(scored.into_row(references, line, &self.owners))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scored.size_hint()
+    }
+}
+
+impl ExactSizeIterator for RankedRows {}
+
+/// A definition's scores, computed before its row is materialized.
+#[derive(Debug)]
+struct ScoredDefinition {
+    rank: f64,
+    local_score: f64,
+    file_rank: f64,
+    symbol_rank: Option<f64>,
+    definition: Location,
+    kind: SymbolKind,
+    ecosystem: Ecosystem,
+    breakdown: ScoreBreakdown,
+    is_entry_point: bool,
+    execution_count: Option<u64>,
+}
+
+impl ScoredDefinition {
+    fn quantize(&mut self, digits: u32) {
+        self.rank = quantize(self.rank, digits);
+        self.local_score = quantize(self.local_score, digits);
+        self.file_rank = quantize(self.file_rank, digits);
+        self.symbol_rank = self.symbol_rank.map(|rank| quantize(rank, digits));
+        self.breakdown.frecency_weight_sum = quantize(self.breakdown.frecency_weight_sum, digits);
+        self.breakdown.ambiguity_penalty = quantize(self.breakdown.ambiguity_penalty, digits);
+    }
+
+    fn into_row(
+        self,
+        mut references: Vec<Location>,
+        definition_line: String,
+        owners: &CodeOwners,
+    ) -> OutputRow {
+        references.sort_by(location_order);
+        OutputRow {
+            rank: self.rank,
+            local_score: self.local_score,
+            file_rank: self.file_rank,
+            symbol_rank: self.symbol_rank,
+            definition: self.definition,
+            kind: self.kind,
+            ecosystem: self.ecosystem,
+            definition_line,
+            references,
+            reference_count: self.breakdown.reference_count,
+            breakdown: self.breakdown,
+            owners: owners.owners(Path::new(self.definition.path_str())),
+            is_entry_point: self.is_entry_point,
+            execution_count: self.execution_count,
+        }
+    }
+}
+
+/// A scan of a source with its references grouped into a graph per
+/// ecosystem, and the history they are weighted by. Keeping it lets
+/// long-running callers such as the daemon explain definitions without
+/// scanning again.
+pub struct ScannedSource {
+    grouped_by_ecosystem: HashMap<Ecosystem, HashMap<Location, Vec<Location>>>,
+    /// The rest of the scan; its edges are in `grouped_by_ecosystem`.
+    scan: ReferenceScan,
+    frecency: HashMap<Spur, f64>,
+    authors: HashMap<Spur, usize>,
+    cache: Option<FileCache>,
     repo_root: Option<PathBuf>,
-) -> Result<Vec<OutputRow>, CruxlinesError> {
-    let (scan, frecency) = if let Some(ref root) = repo_root {
-        compute_edges_and_frecency_cached(paths, root)?
-    } else {
-        let inputs = paths.into_iter().filter_map(read_input);
-        compute_edges_and_frecency(inputs, repo_root)?
-    };
+    options: Options,
+}
 
-    let grouped_by_ecosystem = group_edges_by_ecosystem(scan.edges);
-    let capacity: usize = grouped_by_ecosystem
-        .values()
-        .map(|grouped| grouped.len())
-        .sum();
+impl ScannedSource {
+    /// Ranks `source` like [`cruxlines_from_source`], also returning the
+    /// scan the rows were ranked from. `Options::low_memory` is ignored,
+    /// since explanations need every reference.
+    pub fn analyze(
+        source: &impl SourceProvider,
+        repo_root: Option<&Path>,
+        options: &Options,
+    ) -> Result<(Analysis, Self), CruxlinesError> {
+        let filter = DefinitionFilter::new(options)?;
+        let namespaces = Namespaces::new(repo_root, options)?;
+        let mut scanned = Self::scan(source, repo_root, options, &namespaces);
+        let (scored, references) = scanned.score(scanned.grouped_by_ecosystem.clone(), &filter);
+        let analysis = finish_ranking(
+            scored,
+            references,
+            scanned.scan.definition_lines.clone(),
+            std::mem::take(&mut scanned.scan.diagnostics),
+            scanned.scan.stats.clone(),
+            repo_root,
+            options,
+        )
+        .into_analysis();
+        Ok((analysis, scanned))
+    }
 
-    let mut output_rows = Vec::with_capacity(capacity);
-    for (_ecosystem, grouped) in grouped_by_ecosystem {
-        let file_ranks = rank_files(&grouped);
+    fn scan(
+        source: &impl SourceProvider,
+        repo_root: Option<&Path>,
+        options: &Options,
+        namespaces: &Namespaces,
+    ) -> Self {
+        let (mut scan, frecency, authors, cache) = scan_with_history(repo_root, options, |cache| {
+            find_references(source, repo_root, cache, options, namespaces)
+        });
+        Self {
+            grouped_by_ecosystem: group_edges_by_ecosystem(std::mem::take(&mut scan.edges)),
+            scan,
+            frecency,
+            authors,
+            cache,
+            repo_root: repo_root.map(Path::to_path_buf),
+            options: options.clone(),
+        }
+    }
+
+    fn into_ranked(mut self, filter: &DefinitionFilter) -> RankedRows {
+        let grouped_by_ecosystem = std::mem::take(&mut self.grouped_by_ecosystem);
+        let (scored, references) = self.score(grouped_by_ecosystem, filter);
+        finish_ranking(
+            scored,
+            references,
+            self.scan.definition_lines,
+            self.scan.diagnostics,
+            self.scan.stats,
+            self.repo_root.as_deref(),
+            &self.options,
+        )
+    }
+
+    /// Scores the definitions `filter` keeps, returning them with their
+    /// references. Ranks are computed from the whole `grouped_by_ecosystem`.
+    fn score(
+        &self,
+        grouped_by_ecosystem: HashMap<Ecosystem, HashMap<Location, Vec<Location>>>,
+        filter: &DefinitionFilter,
+    ) -> (Vec<ScoredDefinition>, HashMap<Location, Vec<Location>>) {
+        let (scan, options) = (&self.scan, &self.options);
+        let capacity: usize = grouped_by_ecosystem
+            .values()
+            .map(|grouped| grouped.len())
+            .sum();
 
-        let mut name_counts: FxHashMap<Spur, usize> = FxHashMap::default();
-        for definition in grouped.keys() {
-            *name_counts.entry(definition.name).or_default() += 1;
+        let reachable = reachable_from_entry_points(
+            &grouped_by_ecosystem,
+            &scan.definition_kinds,
+            &scan.entry_points,
+            options,
+        );
+        let execution_counts = execution_counts(options, scan.definition_kinds.keys());
+
+        let in_reach = (!options.reachable_from.is_empty()).then(|| {
+            reachable_definitions(
+                grouped_by_ecosystem.values().flatten(),
+                scan.definition_kinds.keys(),
+                scan.definition_kinds.keys().copied().filter(|definition| {
+                    options
+                        .reachable_from
+                        .iter()
+                        .any(|root| root.matches(definition))
+                }),
+            )
+        });
+
+        let mut scored = Vec::with_capacity(capacity);
+        let mut references = HashMap::with_capacity(capacity);
+        for (ecosystem, mut grouped) in grouped_by_ecosystem {
+            let ranks = self.ranks(&grouped, ecosystem);
+            // Filtered-out definitions still shape the ranks above, but are
+            // dropped before scoring so their references are never collected.
+            if !filter.is_empty() || in_reach.is_some() {
+                grouped.retain(|definition, _| {
+                    filter.keeps(definition, &scan.definition_kinds)
+                        && in_reach
+                            .as_ref()
+                            .is_none_or(|in_reach| in_reach.contains(definition))
+                });
+            }
+            let reference_weight = |definition: &Location, reference: &Location| {
+                ranks.reference_factors(
+                    definition,
+                    reference,
+                    &self.frecency,
+                    &scan.test_ranges,
+                    options.weights_for(definition.path_str()),
+                )
+            };
+
+            let context = ranks.context(
+                &scan.definition_kinds,
+                &scan.entry_points,
+                reachable.as_ref(),
+                &self.authors,
+                execution_counts.as_ref(),
+                options,
+            );
+            scored.extend(score_definitions(&grouped, &context, &reference_weight));
+            references.extend(grouped);
         }
+        (scored, references)
+    }
 
-        let rows = build_rows(
+    fn ranks(
+        &self,
+        grouped: &HashMap<Location, Vec<Location>>,
+        ecosystem: Ecosystem,
+    ) -> EcosystemRanks {
+        let file_edges = self
+            .scan
+            .file_edges
+            .get(&ecosystem)
+            .map_or(&[][..], Vec::as_slice);
+        EcosystemRanks::new(
             grouped,
-            &file_ranks,
-            &frecency,
-            &name_counts,
-            &scan.definition_lines,
+            file_edges,
+            ecosystem,
+            self.repo_root.as_deref(),
+            &self.options,
+            self.cache.as_ref(),
+        )
+    }
+}
+
+/// Usages of a definition from one file: (usage file, inside test-only
+/// code, count).
+type UsageCount = (Spur, bool, u32);
+
+/// Ranks usage counts from [`count_references`] with the same formula as
+/// [`ScannedSource`]. Rows carry reference counts but no reference locations,
+/// and the symbol rank and entry point boost are skipped since they need
+/// every usage's position.
+fn rank_counts(
+    counts: ReferenceCounts,
+    frecency: &HashMap<Spur, f64>,
+    authors: &HashMap<Spur, usize>,
+    repo_root: Option<&Path>,
+    options: &Options,
+    filter: &DefinitionFilter,
+    cache: Option<&FileCache>,
+) -> RankedRows {
+    let mut usages_by_ecosystem: HashMap<Ecosystem, FxHashMap<Location, Vec<UsageCount>>> =
+        HashMap::new();
+    for ((id, usage_path, in_test_code), count) in counts.counts {
+        let (definition, ecosystem) = counts.definitions[id as usize];
+        usages_by_ecosystem
+            .entry(ecosystem)
+            .or_default()
+            .entry(definition)
+            .or_default()
+            .push((usage_path, in_test_code, count));
+    }
+
+    let execution_counts = execution_counts(options, counts.definition_kinds.keys());
+    let mut scored = Vec::new();
+    for (ecosystem, mut usages) in usages_by_ecosystem {
+        let file_ranks = rank_files_cached(
+            usages
+                .iter()
+                .flat_map(|(definition, usages)| {
+                    usages.iter().map(|(path, _, _)| (definition.path, *path))
+                })
+                .chain(
+                    counts
+                        .file_edges
+                        .get(&ecosystem)
+                        .into_iter()
+                        .flatten()
+                        .copied(),
+                )
+                .collect(),
+            ecosystem,
+            options,
+            cache,
+        );
+        let name_counts = name_counts(usages.keys());
+        let test_files = test_files(
+            usages.values().flatten().map(|(path, _, _)| *path),
+            repo_root,
         );
-        output_rows.extend(rows);
+        let external_files = external_files(usages.values().flatten().map(|(path, _, _)| *path));
+        if !filter.is_empty() {
+            usages.retain(|definition, _| filter.keeps(definition, &counts.definition_kinds));
+        }
+        let context = ScoringContext {
+            ecosystem,
+            file_ranks: &file_ranks,
+            symbol_ranks: None,
+            name_counts: &name_counts,
+            external_files: &external_files,
+            definition_kinds: &counts.definition_kinds,
+            entry_points: &counts.entry_points,
+            reachable: None,
+            authors,
+            execution_counts: execution_counts.as_ref(),
+            options,
+            aggregation: options.reference_aggregation,
+        };
+        let ecosystem_scored: Vec<ScoredDefinition> = usages
+            .par_iter()
+            .map(|(definition, usages)| {
+                let weights = options.weights_for(definition.path_str());
+                let file_counts = file_counts(
+                    usages
+                        .iter()
+                        .map(|(path, _, count)| (*path, *count as usize)),
+                    context.aggregation,
+                );
+                let weighted_refs: f64 = usages
+                    .iter()
+                    .map(|(path, in_test_code, count)| {
+                        let file_rank = file_ranks.get(path).copied().unwrap_or(0.0);
+                        let mut weight = frecency_weight(frecency, *path, weights);
+                        if *in_test_code || test_files.contains(path) {
+                            weight *= weights.test_references;
+                        }
+                        if *path == definition.path {
+                            weight *= weights.same_file_references;
+                        }
+                        weight *= external_multiplier(&external_files, *path, weights);
+                        let share = file_counts
+                            .get(path)
+                            .map_or(1.0, |count| context.aggregation.share(*count));
+                        file_rank * weight * f64::from(*count) * share
+                    })
+                    .sum();
+                let breakdown = ScoreBreakdown {
+                    reference_count: usages.iter().map(|(_, _, count)| *count as usize).sum(),
+                    distinct_referencing_files: usages
+                        .iter()
+                        .map(|(path, _, _)| *path)
+                        .collect::<FxHashSet<_>>()
+                        .len(),
+                    frecency_weight_sum: usages
+                        .iter()
+                        .map(|(path, _, count)| {
+                            frecency_weight(frecency, *path, weights) * f64::from(*count)
+                        })
+                        .sum(),
+                    ambiguity_penalty: 1.0,
+                    manifest_references: usages
+                        .iter()
+                        .filter(|(path, _, _)| {
+                            external_files.get(path) == Some(&ExternalFile::Manifest)
+                        })
+                        .map(|(_, _, count)| *count as usize)
+                        .sum(),
+                };
+                context.score(*definition, weighted_refs, breakdown)
+            })
+            .collect();
+        scored.extend(ecosystem_scored);
+    }
+
+    finish_ranking(
+        scored,
+        HashMap::new(),
+        counts.definition_lines,
+        counts.diagnostics,
+        counts.stats,
+        repo_root,
+        options,
+    )
+}
+
+/// Score breakdown of one definition, see [`explain_definition`].
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    /// The definition's row, as ranked by [`cruxlines_from_source`] before
+    /// quantizing.
+    pub row: OutputRow,
+    /// What each reference adds to `weighted_references`, in location order.
+    pub references: Vec<ReferenceWeight>,
+    /// Sum of the reference weights.
+    pub weighted_references: f64,
+    /// Referenced definitions sharing the definition's name in its
+    /// ecosystem.
+    pub name_count: usize,
+    /// `Weights::entry_reachable` when the definition is reachable from an
+    /// entry point, else `1.0`.
+    pub entry_boost: f64,
+    /// Distinct author count of the definition's file raised to
+    /// `Weights::authors`; `1.0` without author counts.
+    pub author_boost: f64,
+    /// One plus the definition's execution count raised to
+    /// `Weights::coverage`; `1.0` without coverage data.
+    pub coverage_boost: f64,
+    pub weights: Weights,
+}
+
+/// A reference's contribution to a definition's score: the product of its
+/// fields.
+#[derive(Debug, Clone, Copy)]
+pub struct ReferenceWeight {
+    pub location: Location,
+    /// PageRank of the referencing file.
+    pub file_rank: f64,
+    /// Frecency of the referencing file, raised to `Weights::frecency`.
+    pub frecency: f64,
+    /// `Weights::test_references` for references from test code, else `1.0`.
+    pub test_multiplier: f64,
+    /// `Weights::same_file_references` for references from the definition's
+    /// own file, else `1.0`.
+    pub same_file_multiplier: f64,
+    /// `Weights::pipeline_references` for references from pipeline YAML,
+    /// `Weights::manifest_references` for references from package
+    /// manifests, else `1.0`.
+    pub external_multiplier: f64,
+    /// Share kept under `Options::reference_aggregation`, given how many
+    /// references to the definition the same file makes; `1.0` when every
+    /// occurrence counts.
+    pub file_share: f64,
+}
+
+impl ReferenceWeight {
+    pub fn weight(&self) -> f64 {
+        self.file_rank
+            * (self.frecency
+                * (self.test_multiplier * self.same_file_multiplier * self.external_multiplier))
+            * self.file_share
+    }
+}
+
+/// Explains the rank of every definition on `line` of `path`, recomputing
+/// the factors [`cruxlines_from_source`] multiplies together. `path` is
+/// compared with the paths `source` reports. Definitions without references
+/// are not ranked and get no explanation.
+pub fn explain_definition(
+    source: &impl SourceProvider,
+    repo_root: Option<&Path>,
+    options: &Options,
+    path: &Path,
+    line: usize,
+) -> Result<Vec<Explanation>, CruxlinesError> {
+    let namespaces = Namespaces::new(repo_root, options)?;
+    Ok(ScannedSource::scan(source, repo_root, options, &namespaces).explain(path, line))
+}
+
+impl ScannedSource {
+    /// Like [`explain_definition`], from this scan.
+    pub fn explain(&self, path: &Path, line: usize) -> Vec<Explanation> {
+        let (scan, options) = (&self.scan, &self.options);
+        let owners = CodeOwners::discover(self.repo_root.as_deref());
+        let reachable = reachable_from_entry_points(
+            &self.grouped_by_ecosystem,
+            &scan.definition_kinds,
+            &scan.entry_points,
+            options,
+        );
+        let execution_counts = execution_counts(options, scan.definition_kinds.keys());
+        let mut explanations = Vec::new();
+        for (ecosystem, grouped) in &self.grouped_by_ecosystem {
+            let targets: Vec<Location> = grouped
+                .keys()
+                .filter(|definition| {
+                    definition.line == line && Path::new(definition.path_str()) == path
+                })
+                .copied()
+                .collect();
+            if targets.is_empty() {
+                continue;
+            }
+            let ranks = self.ranks(grouped, *ecosystem);
+            let context = ranks.context(
+                &scan.definition_kinds,
+                &scan.entry_points,
+                reachable.as_ref(),
+                &self.authors,
+                execution_counts.as_ref(),
+                options,
+            );
+            let reference_weight = |definition: &Location, reference: &Location| {
+                ranks.reference_factors(
+                    definition,
+                    reference,
+                    &self.frecency,
+                    &scan.test_ranges,
+                    options.weights_for(definition.path_str()),
+                )
+            };
+            for definition in targets {
+                let references = &grouped[&definition];
+                let file_counts = file_counts(
+                    references.iter().map(|reference| (reference.path, 1)),
+                    context.aggregation,
+                );
+                let mut contributions: Vec<ReferenceWeight> = references
+                    .iter()
+                    .map(|reference| {
+                        let (frecency, test_multiplier, same_file_multiplier, external_multiplier) =
+                            reference_weight(&definition, reference);
+                        ReferenceWeight {
+                            location: *reference,
+                            file_rank: ranks
+                                .file_ranks
+                                .get(&reference.path)
+                                .copied()
+                                .unwrap_or(0.0),
+                            frecency,
+                            test_multiplier,
+                            same_file_multiplier,
+                            external_multiplier,
+                            file_share: file_counts
+                                .get(&reference.path)
+                                .map_or(1.0, |count| context.aggregation.share(*count)),
+                        }
+                    })
+                    .collect();
+                let weighted_references = contributions.iter().map(ReferenceWeight::weight).sum();
+                contributions.sort_by(|a, b| location_order(&a.location, &b.location));
+                let line_text = scan
+                    .definition_lines
+                    .get(&definition)
+                    .cloned()
+                    .unwrap_or_default();
+                let single = HashMap::from([(definition, references.clone())]);
+                let row = score_definitions(&single, &context, &reference_weight)
+                    .remove(0)
+                    .into_row(references.clone(), line_text, &owners);
+                explanations.push(Explanation {
+                    row,
+                    references: contributions,
+                    weighted_references,
+                    name_count: ranks
+                        .name_counts
+                        .get(&link_name(definition.name))
+                        .copied()
+                        .unwrap_or(1),
+                    entry_boost: context.entry_boost(&definition),
+                    author_boost: context.author_boost(&definition),
+                    coverage_boost: context
+                        .coverage_boost(&definition, context.execution_count(&definition)),
+                    weights: *options.weights_for(definition.path_str()),
+                });
+            }
+        }
+        explanations.sort_by(|a, b| location_order(&a.row.definition, &b.row.definition));
+        explanations
     }
+}
 
-    output_rows.sort_by(|a, b| {
+/// Sorts scored definitions into rank order and applies the row filters.
+fn finish_ranking(
+    mut scored: Vec<ScoredDefinition>,
+    references: HashMap<Location, Vec<Location>>,
+    definition_lines: HashMap<Location, String>,
+    diagnostics: Diagnostics,
+    stats: ScanStats,
+    repo_root: Option<&Path>,
+    options: &Options,
+) -> RankedRows {
+    if let Some(digits) = options.quantize {
+        for entry in &mut scored {
+            entry.quantize(digits);
+        }
+    }
+    scored.sort_by(|a, b| {
         b.rank
             .partial_cmp(&a.rank)
             .unwrap_or(std::cmp::Ordering::Equal)
-            .then_with(|| {
-                let key_a = (
-                    a.definition.path,
-                    a.definition.line,
-                    a.definition.column,
-                    a.definition.name,
-                );
-                let key_b = (
-                    b.definition.path,
-                    b.definition.line,
-                    b.definition.column,
-                    b.definition.name,
-                );
-                key_a.cmp(&key_b)
-            })
+            .then_with(|| location_order(&a.definition, &b.definition))
     });
+    apply_row_filters(&mut scored, options);
+    RankedRows {
+        scored: scored.into_iter(),
+        references,
+        definition_lines,
+        owners: CodeOwners::discover(repo_root),
+        diagnostics,
+        stats,
+    }
+}
 
-    Ok(output_rows)
+/// Rounds `value` to `digits` decimal places, so scores that differ only in
+/// the last bits (across platforms or thread schedules) compare equal.
+fn quantize(value: f64, digits: u32) -> f64 {
+    let scale = 10_f64.powi(digits.min(15) as i32);
+    (value * scale).round() / scale
+}
+
+/// Orders locations by path text, position and name. Interned handles are
+/// not compared because their order depends on which thread interned first.
+fn location_order(a: &Location, b: &Location) -> std::cmp::Ordering {
+    (a.path_str(), a.line, a.column, a.name_str()).cmp(&(
+        b.path_str(),
+        b.line,
+        b.column,
+        b.name_str(),
+    ))
+}
+
+/// Applies the rank-based output filters; entries must already be sorted by
+/// rank.
+fn apply_row_filters(scored: &mut Vec<ScoredDefinition>, options: &Options) {
+    if let Some(min_references) = options.min_references {
+        scored.retain(|entry| entry.breakdown.reference_count >= min_references);
+    }
+    if let Some(min_rank) = options.min_rank {
+        scored.retain(|entry| entry.rank >= min_rank);
+    }
+    if let Some(top) = options.top {
+        scored.truncate(top);
+    }
+    if let Some(sample) = options.sample {
+        sample_rows(scored, sample);
+    }
+}
+
+/// Keeps `sample.size` entries drawn without replacement, each with
+/// probability proportional to its rank (Efraimidis–Spirakis: every entry
+/// gets the key `ln(u) / rank` and the largest keys win). Kept entries stay
+/// in rank order.
+fn sample_rows(scored: &mut Vec<ScoredDefinition>, sample: Sample) {
+    if scored.len() <= sample.size {
+        return;
+    }
+    let mut state = sample.seed;
+    let mut keys: Vec<(f64, usize)> = (0..scored.len())
+        .map(|index| {
+            // 53 random bits as a float in (0, 1].
+            let u = ((splitmix64(&mut state) >> 11) + 1) as f64 / (1u64 << 53) as f64;
+            let rank = scored[index].rank;
+            let key = if rank > 0.0 {
+                u.ln() / rank
+            } else {
+                f64::NEG_INFINITY
+            };
+            (key, index)
+        })
+        .collect();
+    keys.sort_by(|a, b| {
+        b.0.partial_cmp(&a.0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.1.cmp(&b.1))
+    });
+    let mut keep = vec![false; scored.len()];
+    for (_, index) in keys.into_iter().take(sample.size) {
+        keep[index] = true;
+    }
+    let mut kept = keep.into_iter();
+    scored.retain(|_| kept.next().unwrap_or(false));
+}
+
+/// SplitMix64, a small seedable generator; the output only has to be
+/// stable across platforms, not cryptographically strong.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// [`rank_files`], reusing the ranks stored in `cache` while the
+/// ecosystem's file graph is the same as when they were computed.
+/// Personalized ranks change with the anchors and are not cached.
+fn rank_files_cached(
+    pairs: Vec<(Spur, Spur)>,
+    ecosystem: Ecosystem,
+    options: &Options,
+    cache: Option<&FileCache>,
+) -> FxHashMap<Spur, f64> {
+    let Some(cache) = cache.filter(|_| options.personalize.is_empty()) else {
+        return rank_files(pairs, options);
+    };
+    let key = match options.centrality {
+        Centrality::PageRank => format!("{ecosystem:?}"),
+        centrality => format!("{ecosystem:?}/{centrality:?}"),
+    };
+    let graph_hash = file_graph_hash(&pairs);
+    if let Some(ranks) = cache.get_ranks(&key, graph_hash) {
+        return ranks
+            .into_iter()
+            .map(|(path, rank)| (crate::intern::intern(&path), rank))
+            .collect();
+    }
+    let ranks = rank_files(pairs, options);
+    let stored: Vec<(String, f64)> = ranks
+        .iter()
+        .map(|(path, rank)| (crate::intern::resolve(*path).to_string(), *rank))
+        .collect();
+    let _ = cache.set_ranks(&key, graph_hash, &stored);
+    ranks
 }
 
-fn rank_files(grouped: &HashMap<Location, Vec<Location>>) -> FxHashMap<Spur, f64> {
-    let (graph, indices) = build_file_graph(grouped);
+/// File-level centrality over (definition file, usage file) pairs.
+fn rank_files(
+    pairs: impl IntoIterator<Item = (Spur, Spur)>,
+    options: &Options,
+) -> FxHashMap<Spur, f64> {
+    let (graph, indices) = build_file_graph(pairs);
 
     if graph.node_count() == 0 {
         return FxHashMap::default();
     }
 
-    let ranks = petgraph::algo::page_rank::parallel_page_rank(&graph, 0.85_f64, 5, None);
+    let ranks = match options.centrality {
+        Centrality::PageRank => {
+            let personalization = personalization(&graph, &options.personalize);
+            page_rank(&graph, 0.85, 5, personalization.as_deref())
+        }
+        Centrality::Betweenness => normalized(betweenness(&graph)),
+        Centrality::InDegree => normalized(in_degrees(&graph)),
+    };
 
     let mut out = FxHashMap::default();
     for (path, idx) in indices {
@@ -172,77 +982,559 @@ fn rank_files(grouped: &HashMap<Location, Vec<Location>>) -> FxHashMap<Spur, f64
     out
 }
 
-fn compute_edges_and_frecency(
-    inputs: impl IntoIterator<Item = Result<(PathBuf, String), CruxlinesError>>,
-    repo_root: Option<PathBuf>,
-) -> Result<(ReferenceScan, HashMap<Spur, f64>), CruxlinesError> {
-    let frecency_handle = std::thread::spawn(move || frecency_scores(repo_root.as_deref()));
+/// Jump weights spreading evenly over the files of `graph` under any of
+/// `anchors`, or `None` when no file is, as in an ecosystem the anchors are
+/// not part of.
+fn personalization(graph: &Graph<Spur, ()>, anchors: &[PathBuf]) -> Option<Vec<f64>> {
+    if anchors.is_empty() {
+        return None;
+    }
+    let anchored: Vec<bool> = graph
+        .node_weights()
+        .map(|path| {
+            let path = Path::new(crate::intern::resolve(*path));
+            anchors.iter().any(|anchor| path.starts_with(anchor))
+        })
+        .collect();
+    let count = anchored.iter().filter(|anchored| **anchored).count();
+    (count > 0).then(|| {
+        anchored
+            .iter()
+            .map(|anchored| if *anchored { 1.0 / count as f64 } else { 0.0 })
+            .collect()
+    })
+}
 
-    let scan = find_references(inputs)?;
-    let frecency = frecency_handle.join().unwrap_or_default();
+/// Symbol-level PageRank, normalized so the average definition scores 1.0.
+fn rank_symbols(grouped: &HashMap<Location, Vec<Location>>) -> FxHashMap<Location, f64> {
+    let (graph, indices) = build_reference_graph(grouped);
+
+    if graph.node_count() == 0 {
+        return FxHashMap::default();
+    }
+
+    let ranks = page_rank(&graph, 0.85, 5, None);
+    let node_count = graph.node_count() as f64;
 
-    Ok((scan, frecency))
+    let mut out = FxHashMap::default();
+    for (definition, idx) in indices {
+        out.insert(definition, ranks[idx.index()] * node_count);
+    }
+    out
 }
 
-fn compute_edges_and_frecency_cached(
-    paths: Vec<PathBuf>,
-    repo_root: &Path,
-) -> Result<(ReferenceScan, HashMap<Spur, f64>), CruxlinesError> {
-    let cache = FileCache::new(repo_root);
+/// Stable description of the configured reference-kind overrides, or `None`
+/// when every language uses its built-in list.
+fn reference_kinds_key(options: &Options) -> Option<String> {
+    if options.reference_kinds.is_empty() {
+        return None;
+    }
+    let mut languages: Vec<_> = options.reference_kinds.iter().collect();
+    languages.sort_by_key(|(language, _)| **language);
+    Some(
+        languages
+            .into_iter()
+            .map(|(language, kinds)| {
+                format!(
+                    "{}+{}-{}",
+                    language.name(),
+                    kinds.add.join(","),
+                    kinds.remove.join(",")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(";"),
+    )
+}
 
-    let repo_root_clone = repo_root.to_path_buf();
-    let frecency_handle =
-        std::thread::spawn(move || frecency_scores(Some(repo_root_clone.as_path())));
+/// Ranking is reported as a single step.
+fn report_rank(options: &Options, done: usize) {
+    if let Some(progress) = &options.progress {
+        progress.report(Phase::Rank, done, Some(1));
+    }
+}
 
-    let scan = find_references_cached(paths, &cache)?;
+/// Cache subdirectory for results that depend on more than the file
+/// contents: reference-kind overrides and custom languages.
+fn cache_namespace(options: &Options) -> Option<String> {
+    let parts: Vec<String> = [
+        reference_kinds_key(options),
+        crate::languages::custom::fingerprint(),
+        (options.scan.encoding != Encoding::Strict)
+            .then(|| format!("encoding={:?}", options.scan.encoding)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    (!parts.is_empty()).then(|| parts.join("|"))
+}
+
+/// Runs `scan` with the file cache while frecency and author counts are
+/// computed on other threads.
+fn scan_with_history<T>(
+    repo_root: Option<&Path>,
+    options: &Options,
+    scan: impl FnOnce(Option<&FileCache>) -> T,
+) -> (
+    T,
+    HashMap<Spur, f64>,
+    HashMap<Spur, usize>,
+    Option<FileCache>,
+) {
+    let cache = repo_root
+        .filter(|_| options.use_cache)
+        .map(FileCache::new)
+        .map(|cache| match cache_namespace(options) {
+            Some(key) => cache.namespaced(&key),
+            None => cache,
+        });
+
+    // WebAssembly has no threads, so history is read before the scan there.
+    if cfg!(target_family = "wasm") {
+        let frecency = options.frecency.scores(repo_root);
+        let authors = options.authors.counts(repo_root);
+        return (scan(cache.as_ref()), frecency, authors, cache);
+    }
+
+    let repo_root_clone = repo_root.map(Path::to_path_buf);
+    let frecency = options.frecency.clone();
+    let frecency_handle = std::thread::spawn(move || frecency.scores(repo_root_clone.as_deref()));
+    let repo_root_clone = repo_root.map(Path::to_path_buf);
+    let authors = options.authors.clone();
+    let authors_handle = std::thread::spawn(move || authors.counts(repo_root_clone.as_deref()));
+
+    let scanned = scan(cache.as_ref());
     let frecency = frecency_handle.join().unwrap_or_default();
+    let authors = authors_handle.join().unwrap_or_default();
 
-    Ok((scan, frecency))
+    (scanned, frecency, authors, cache)
 }
 
-fn build_rows(
-    grouped: HashMap<Location, Vec<Location>>,
-    file_ranks: &FxHashMap<Spur, f64>,
-    frecency: &HashMap<Spur, f64>,
-    name_counts: &FxHashMap<Spur, usize>,
-    definition_lines: &HashMap<Location, String>,
-) -> Vec<OutputRow> {
-    grouped
-        .into_par_iter()
-        .map(|(definition, mut references)| {
-            references.sort_by(|a, b| {
-                let key_a = (a.path, a.line, a.column, a.name);
-                let key_b = (b.path, b.line, b.column, b.name);
-                key_a.cmp(&key_b)
-            });
-            let name_count = name_counts.get(&definition.name).copied().unwrap_or(1) as f64;
-            let weighted_refs: f64 = references
-                .iter()
-                .map(|reference| {
-                    let file_rank = file_ranks.get(&reference.path).copied().unwrap_or(0.0);
-                    let frecency = frecency.get(&reference.path).copied().unwrap_or(1.0);
-                    file_rank * frecency
-                })
-                .sum();
-            let local_score = weighted_refs / name_count;
-            let file_rank = file_ranks.get(&definition.path).copied().unwrap_or(0.0);
-            let rank = local_score * file_rank;
-            let definition_line = definition_lines
+/// Graph-wide factors of one ecosystem's scores.
+struct EcosystemRanks {
+    ecosystem: Ecosystem,
+    file_ranks: FxHashMap<Spur, f64>,
+    symbol_ranks: Option<FxHashMap<Location, f64>>,
+    name_counts: FxHashMap<Spur, usize>,
+    test_files: FxHashSet<Spur>,
+    external_files: FxHashMap<Spur, ExternalFile>,
+}
+
+impl EcosystemRanks {
+    fn new(
+        grouped: &HashMap<Location, Vec<Location>>,
+        file_edges: &[(Spur, Spur)],
+        ecosystem: Ecosystem,
+        repo_root: Option<&Path>,
+        options: &Options,
+        cache: Option<&FileCache>,
+    ) -> Self {
+        Self {
+            ecosystem,
+            file_ranks: rank_files_cached(
+                grouped
+                    .iter()
+                    .flat_map(|(definition, usages)| {
+                        usages.iter().map(|usage| (definition.path, usage.path))
+                    })
+                    .chain(file_edges.iter().copied())
+                    .collect(),
+                ecosystem,
+                options,
+                cache,
+            ),
+            symbol_ranks: options.symbol_rank.then(|| rank_symbols(grouped)),
+            name_counts: name_counts(grouped.keys()),
+            test_files: test_files(
+                grouped.values().flatten().map(|usage| usage.path),
+                repo_root,
+            ),
+            external_files: external_files(grouped.values().flatten().map(|usage| usage.path)),
+        }
+    }
+
+    /// A reference's frecency multiplier, test-code multiplier, same-file
+    /// multiplier and external multiplier.
+    fn reference_factors(
+        &self,
+        definition: &Location,
+        reference: &Location,
+        frecency: &HashMap<Spur, f64>,
+        test_ranges: &FxHashMap<Spur, Vec<(usize, usize)>>,
+        weights: &Weights,
+    ) -> ReferenceFactors {
+        let test_multiplier = if is_test_reference(reference, &self.test_files, test_ranges) {
+            weights.test_references
+        } else {
+            1.0
+        };
+        let same_file_multiplier = if reference.path == definition.path {
+            weights.same_file_references
+        } else {
+            1.0
+        };
+        (
+            frecency_weight(frecency, reference.path, weights),
+            test_multiplier,
+            same_file_multiplier,
+            external_multiplier(&self.external_files, reference.path, weights),
+        )
+    }
+
+    fn context<'a>(
+        &'a self,
+        definition_kinds: &'a HashMap<Location, SymbolKind>,
+        entry_points: &'a FxHashSet<Location>,
+        reachable: Option<&'a FxHashSet<Location>>,
+        authors: &'a HashMap<Spur, usize>,
+        execution_counts: Option<&'a FxHashMap<Location, u64>>,
+        options: &'a Options,
+    ) -> ScoringContext<'a> {
+        ScoringContext {
+            ecosystem: self.ecosystem,
+            file_ranks: &self.file_ranks,
+            symbol_ranks: self.symbol_ranks.as_ref(),
+            name_counts: &self.name_counts,
+            external_files: &self.external_files,
+            definition_kinds,
+            entry_points,
+            reachable,
+            authors,
+            execution_counts,
+            options,
+            aggregation: options.reference_aggregation,
+        }
+    }
+}
+
+/// A reference's frecency, test-code, same-file and external multipliers.
+type ReferenceFactors = (f64, f64, f64, f64);
+
+/// A referencing file outside the source code, whose references are
+/// weighted separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExternalFile {
+    Pipeline,
+    Manifest,
+}
+
+/// Pipeline and manifest files among `paths`, classified once per file so
+/// scoring doesn't match every reference's path.
+fn external_files(paths: impl Iterator<Item = Spur>) -> FxHashMap<Spur, ExternalFile> {
+    let mut seen = FxHashSet::default();
+    let mut external_files = FxHashMap::default();
+    for spur in paths {
+        if !seen.insert(spur) {
+            continue;
+        }
+        let path = Path::new(crate::intern::resolve(spur));
+        if crate::pipelines::is_pipeline_file(path) {
+            external_files.insert(spur, ExternalFile::Pipeline);
+        } else if crate::pipelines::is_manifest_file(path) {
+            external_files.insert(spur, ExternalFile::Manifest);
+        }
+    }
+    external_files
+}
+
+/// `Weights::pipeline_references` for references from pipeline YAML,
+/// `Weights::manifest_references` for references from package manifests,
+/// else `1.0`.
+fn external_multiplier(
+    external_files: &FxHashMap<Spur, ExternalFile>,
+    path: Spur,
+    weights: &Weights,
+) -> f64 {
+    match external_files.get(&path) {
+        Some(ExternalFile::Pipeline) => weights.pipeline_references,
+        Some(ExternalFile::Manifest) => weights.manifest_references,
+        None => 1.0,
+    }
+}
+
+/// Frecency of `path` raised to the frecency weight; files without a score
+/// count as `1.0`.
+fn frecency_weight(frecency: &HashMap<Spur, f64>, path: Spur, weights: &Weights) -> f64 {
+    frecency
+        .get(&path)
+        .copied()
+        .unwrap_or(1.0)
+        .powf(weights.frecency)
+}
+
+/// Per-ecosystem inputs shared by every definition's score.
+struct ScoringContext<'a> {
+    ecosystem: Ecosystem,
+    file_ranks: &'a FxHashMap<Spur, f64>,
+    symbol_ranks: Option<&'a FxHashMap<Location, f64>>,
+    name_counts: &'a FxHashMap<Spur, usize>,
+    /// Pipeline and manifest files among the referencing files.
+    external_files: &'a FxHashMap<Spur, ExternalFile>,
+    definition_kinds: &'a HashMap<Location, SymbolKind>,
+    entry_points: &'a FxHashSet<Location>,
+    /// Definitions reachable from an entry point, when
+    /// `Weights::entry_reachable` boosts them.
+    reachable: Option<&'a FxHashSet<Location>>,
+    /// Distinct authors of each file, for `Weights::authors`.
+    authors: &'a HashMap<Spur, usize>,
+    /// Definitions' execution counts, with `Options::coverage`.
+    execution_counts: Option<&'a FxHashMap<Location, u64>>,
+    /// Source of each definition's weights.
+    options: &'a Options,
+    aggregation: ReferenceAggregation,
+}
+
+impl ScoringContext<'_> {
+    /// Combines a definition's summed reference weights with its file rank,
+    /// name ambiguity, symbol rank, entry point reachability, authors and
+    /// execution count. `breakdown` carries the reference
+    /// totals; its ambiguity penalty is filled in here.
+    fn score(
+        &self,
+        definition: Location,
+        weighted_refs: f64,
+        mut breakdown: ScoreBreakdown,
+    ) -> ScoredDefinition {
+        let weights = self.weights(&definition);
+        let name_count = self
+            .name_counts
+            .get(&link_name(definition.name))
+            .copied()
+            .unwrap_or(1) as f64;
+        breakdown.ambiguity_penalty = name_count.powf(weights.name_ambiguity);
+        let local_score = weighted_refs.powf(weights.references) / breakdown.ambiguity_penalty;
+        let file_rank = self
+            .file_ranks
+            .get(&definition.path)
+            .copied()
+            .unwrap_or(0.0);
+        let symbol_rank = self
+            .symbol_ranks
+            .map(|ranks| ranks.get(&definition).copied().unwrap_or(0.0));
+        let execution_count = self.execution_count(&definition);
+        let rank = local_score
+            * file_rank.powf(weights.file_rank)
+            * symbol_rank.unwrap_or(1.0)
+            * self.entry_boost(&definition)
+            * self.author_boost(&definition)
+            * self.coverage_boost(&definition, execution_count);
+        ScoredDefinition {
+            rank,
+            local_score,
+            file_rank,
+            symbol_rank,
+            definition,
+            kind: self
+                .definition_kinds
                 .get(&definition)
-                .cloned()
-                .unwrap_or_default();
-            OutputRow {
-                rank,
-                local_score,
-                file_rank,
-                definition,
-                definition_line,
-                references,
+                .copied()
+                .unwrap_or_default(),
+            ecosystem: self.ecosystem,
+            breakdown,
+            is_entry_point: self.entry_points.contains(&definition),
+            execution_count,
+        }
+    }
+
+    fn weights(&self, definition: &Location) -> &Weights {
+        self.options.weights_for(definition.path_str())
+    }
+
+    /// Distinct author count of the definition's file raised to
+    /// `Weights::authors`; files without a count have one author.
+    fn author_boost(&self, definition: &Location) -> f64 {
+        let authors = self.authors.get(&definition.path).copied().unwrap_or(1);
+        (authors.max(1) as f64).powf(self.weights(definition).authors)
+    }
+
+    fn execution_count(&self, definition: &Location) -> Option<u64> {
+        self.execution_counts?.get(definition).copied()
+    }
+
+    /// One plus the execution count raised to `Weights::coverage`; `1.0`
+    /// without a count.
+    fn coverage_boost(&self, definition: &Location, execution_count: Option<u64>) -> f64 {
+        execution_count.map_or(1.0, |count| {
+            (1.0 + count as f64).powf(self.weights(definition).coverage)
+        })
+    }
+
+    /// `Weights::entry_reachable` for definitions reachable from an entry
+    /// point, else `1.0`.
+    fn entry_boost(&self, definition: &Location) -> f64 {
+        if self
+            .reachable
+            .is_some_and(|reachable| reachable.contains(definition))
+        {
+            self.weights(definition).entry_reachable
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Each definition's execution count under `Options::coverage`: the
+/// highest count from its line up to the next definition in its file.
+fn execution_counts<'a>(
+    options: &Options,
+    definitions: impl Iterator<Item = &'a Location>,
+) -> Option<FxHashMap<Location, u64>> {
+    let coverage = options.coverage.as_deref()?;
+    let mut lines_by_path: FxHashMap<Spur, Vec<&Location>> = FxHashMap::default();
+    for definition in definitions {
+        lines_by_path
+            .entry(definition.path)
+            .or_default()
+            .push(definition);
+    }
+    let mut counts = FxHashMap::default();
+    for (path, mut definitions) in lines_by_path {
+        definitions.sort_by_key(|definition| definition.line);
+        let path = Path::new(crate::intern::resolve(path));
+        for (index, definition) in definitions.iter().enumerate() {
+            let next = definitions[index + 1..]
+                .iter()
+                .map(|other| other.line)
+                .find(|line| *line > definition.line);
+            if let Some(hits) = coverage.span_hits(path, definition.line, next) {
+                counts.insert(**definition, hits);
+            }
+        }
+    }
+    Some(counts)
+}
+
+/// Definitions reachable from `entry_points` through references, or `None`
+/// when `Weights::entry_reachable` leaves them unboosted.
+fn reachable_from_entry_points(
+    grouped_by_ecosystem: &HashMap<Ecosystem, HashMap<Location, Vec<Location>>>,
+    definition_kinds: &HashMap<Location, SymbolKind>,
+    entry_points: &FxHashSet<Location>,
+    options: &Options,
+) -> Option<FxHashSet<Location>> {
+    let boosted = options
+        .all_weights()
+        .any(|weights| weights.entry_reachable != 1.0);
+    (boosted && !entry_points.is_empty()).then(|| {
+        reachable_definitions(
+            grouped_by_ecosystem.values().flatten(),
+            definition_kinds.keys(),
+            entry_points.iter().copied(),
+        )
+    })
+}
+
+/// Scores each definition from its references. `reference_weight` returns a
+/// reference's frecency, test-code and same-file multipliers.
+fn score_definitions(
+    grouped: &HashMap<Location, Vec<Location>>,
+    context: &ScoringContext,
+    reference_weight: &(dyn Fn(&Location, &Location) -> ReferenceFactors + Sync),
+) -> Vec<ScoredDefinition> {
+    grouped
+        .par_iter()
+        .map(|(definition, references)| {
+            let file_counts = file_counts(
+                references.iter().map(|reference| (reference.path, 1)),
+                context.aggregation,
+            );
+            let mut weighted_refs = 0.0;
+            let mut frecency_weight_sum = 0.0;
+            for reference in references {
+                let file_rank = context
+                    .file_ranks
+                    .get(&reference.path)
+                    .copied()
+                    .unwrap_or(0.0);
+                let (frecency, test_multiplier, same_file_multiplier, external_multiplier) =
+                    reference_weight(definition, reference);
+                let share = file_counts
+                    .get(&reference.path)
+                    .map_or(1.0, |count| context.aggregation.share(*count));
+                weighted_refs += file_rank
+                    * (frecency * (test_multiplier * same_file_multiplier * external_multiplier))
+                    * share;
+                frecency_weight_sum += frecency;
             }
+            let breakdown = ScoreBreakdown {
+                reference_count: references.len(),
+                distinct_referencing_files: references
+                    .iter()
+                    .map(|reference| reference.path)
+                    .collect::<FxHashSet<_>>()
+                    .len(),
+                frecency_weight_sum,
+                ambiguity_penalty: 1.0,
+                manifest_references: references
+                    .iter()
+                    .filter(|reference| {
+                        context.external_files.get(&reference.path) == Some(&ExternalFile::Manifest)
+                    })
+                    .count(),
+            };
+            context.score(*definition, weighted_refs, breakdown)
         })
         .collect()
 }
 
+/// References per referencing file, from `(file, count)` pairs. Empty when
+/// every occurrence counts in full, which needs no counts.
+fn file_counts(
+    counts: impl Iterator<Item = (Spur, usize)>,
+    aggregation: ReferenceAggregation,
+) -> FxHashMap<Spur, usize> {
+    let mut files = FxHashMap::default();
+    if aggregation != ReferenceAggregation::Occurrences {
+        for (path, count) in counts {
+            *files.entry(path).or_default() += count;
+        }
+    }
+    files
+}
+
+/// Definitions per name among the referenced definitions of an ecosystem,
+/// keyed by the name references link through.
+fn name_counts<'a>(definitions: impl Iterator<Item = &'a Location>) -> FxHashMap<Spur, usize> {
+    let mut name_counts: FxHashMap<Spur, usize> = FxHashMap::default();
+    for definition in definitions {
+        *name_counts.entry(link_name(definition.name)).or_default() += 1;
+    }
+    name_counts
+}
+
+/// Paths of referencing files that are test files, judged relative to the
+/// repo root so a checkout under e.g. `~/test/` isn't all test code.
+fn test_files(paths: impl Iterator<Item = Spur>, repo_root: Option<&Path>) -> FxHashSet<Spur> {
+    let mut seen = FxHashSet::default();
+    let mut test_files = FxHashSet::default();
+    for spur in paths {
+        if !seen.insert(spur) {
+            continue;
+        }
+        let path = Path::new(crate::intern::resolve(spur));
+        let relative = repo_root
+            .and_then(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path);
+        if crate::languages::is_test_path(relative) {
+            test_files.insert(spur);
+        }
+    }
+    test_files
+}
+
+fn is_test_reference(
+    reference: &Location,
+    test_files: &FxHashSet<Spur>,
+    test_ranges: &FxHashMap<Spur, Vec<(usize, usize)>>,
+) -> bool {
+    test_files.contains(&reference.path)
+        || test_ranges.get(&reference.path).is_some_and(|ranges| {
+            ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&reference.line))
+        })
+}
+
 fn group_edges_by_ecosystem(
     edges: Vec<ReferenceEdge>,
 ) -> HashMap<Ecosystem, HashMap<Location, Vec<Location>>> {
@@ -260,45 +1552,65 @@ fn group_edges_by_ecosystem(
     grouped_by_ecosystem
 }
 
-fn frecency_scores(repo_root: Option<&std::path::Path>) -> HashMap<Spur, f64> {
-    let Some(repo_root) = repo_root else {
-        return HashMap::new();
-    };
-    if !repo_root.join(".git").is_dir() {
-        return HashMap::new();
-    }
-    let Ok(scores) = frecenfile::analyze_repo(repo_root, None, None) else {
-        return HashMap::new();
-    };
-    let mut out = HashMap::new();
-    for (path, score) in scores {
-        let full_path = repo_root.join(path);
-        out.insert(intern(&full_path.to_string_lossy()), score);
-    }
-    out
-}
-
-fn read_input(path: PathBuf) -> Option<Result<(PathBuf, String), CruxlinesError>> {
-    let bytes = match std::fs::read(&path) {
-        Ok(bytes) => bytes,
-        Err(source) => {
-            return Some(Err(CruxlinesError::ReadFile { path, source }));
-        }
-    };
-    let contents = match String::from_utf8(bytes) {
-        Ok(contents) => contents,
-        Err(_) => return None,
-    };
-    Some(Ok((path, contents)))
-}
-
 #[cfg(test)]
 mod tests {
-    use super::{cruxlines_from_inputs, group_edges_by_ecosystem};
+    use super::{
+        cruxlines_from_inputs, cruxlines_from_source, cruxlines_iter, explain_definition,
+        group_edges_by_ecosystem, quantize, rank_files_cached,
+    };
+    use crate::cache::FileCache;
     use crate::find_references::{Location, ReferenceEdge};
     use crate::intern::intern;
     use crate::languages::Ecosystem;
-    use std::path::PathBuf;
+    use crate::options::{Options, ReferenceAggregation, ReferenceDedup, Sample, Weights};
+    use crate::source::InMemorySource;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn file_ranks_are_reused_while_the_graph_is_unchanged() {
+        let repo = std::env::temp_dir().join(format!(
+            "cruxlines-rank-cache-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("time")
+                .as_nanos()
+        ));
+        let cache = FileCache::new(&repo);
+        let (a, b, c) = (intern("rc/a.py"), intern("rc/b.py"), intern("rc/c.py"));
+        let pairs = vec![(a, b), (a, c)];
+
+        let computed = rank_files_cached(
+            pairs.clone(),
+            Ecosystem::Python,
+            &Options::default(),
+            Some(&cache),
+        );
+        assert_eq!(computed.len(), 3);
+        // Swap in recognizable ranks for the same graph: they come back as-is.
+        let graph_hash = super::file_graph_hash(&pairs);
+        cache
+            .set_ranks("Python", graph_hash, &[("rc/a.py".to_string(), 42.0)])
+            .expect("store ranks");
+        let reversed = vec![(a, c), (a, b), (a, b)];
+        let cached = rank_files_cached(
+            reversed,
+            Ecosystem::Python,
+            &Options::default(),
+            Some(&cache),
+        );
+        assert_eq!(cached.get(&a), Some(&42.0));
+        assert_eq!(cached.len(), 1);
+
+        let changed = rank_files_cached(
+            vec![(a, b)],
+            Ecosystem::Python,
+            &Options::default(),
+            Some(&cache),
+        );
+        assert_eq!(changed.len(), 2);
+        assert_ne!(changed.get(&a), Some(&42.0));
+    }
 
     #[test]
     fn analyze_paths_produces_rows() {
@@ -376,6 +1688,419 @@ mod tests {
         assert!(b_score > 0.0);
     }
 
+    #[test]
+    fn row_filters_apply_min_rank_then_top() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("a.py"),
+                "def foo():\n    pass\n\ndef bar():\n    pass\n\ndef baz():\n    pass\n"
+                    .to_string(),
+            ),
+            (
+                PathBuf::from("b.py"),
+                "from a import foo, bar, baz\n\nfoo()\nfoo()\nbar()\n".to_string(),
+            ),
+        ]);
+        let rows = |options: &Options| {
+            cruxlines_from_source(&source, None, options)
+                .expect("analyze")
+                .rows
+        };
+        let all = rows(&Options::default());
+        assert_eq!(all.len(), 3);
+        let baz_rank = all[2].rank;
+
+        let min_filtered = rows(&Options {
+            min_rank: Some(baz_rank + 1e-9),
+            ..Options::default()
+        });
+        assert_eq!(min_filtered.len(), 2);
+
+        let top_filtered = rows(&Options {
+            top: Some(1),
+            ..Options::default()
+        });
+        assert_eq!(top_filtered.len(), 1);
+        assert_eq!(top_filtered[0].definition.name_str(), "foo");
+    }
+
+    #[test]
+    fn sample_prefers_higher_ranked_rows() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("a.py"),
+                "def foo():\n    pass\n\ndef baz():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("b.py"),
+                "from a import foo, baz\n\nfoo()\nfoo()\nfoo()\nfoo()\nfoo()\nbaz()\n".to_string(),
+            ),
+        ]);
+        let mut foo_picks = 0;
+        for seed in 0..50 {
+            let options = Options {
+                sample: Some(Sample { size: 1, seed }),
+                ..Options::default()
+            };
+            let rows = cruxlines_from_source(&source, None, &options)
+                .expect("analyze")
+                .rows;
+            assert_eq!(rows.len(), 1);
+            if rows[0].definition.name_str() == "foo" {
+                foo_picks += 1;
+            }
+        }
+        assert!(foo_picks > 30, "foo picked {foo_picks} of 50 times");
+    }
+
+    #[test]
+    fn ties_are_ordered_by_path_text() {
+        // Intern the later path first so handle order disagrees with text order.
+        intern("tie_z.py");
+        let rows = cruxlines_from_inputs(
+            vec![
+                (
+                    PathBuf::from("tie_z.py"),
+                    "def foo():\n    pass\n".to_string(),
+                ),
+                (
+                    PathBuf::from("tie_a.py"),
+                    "def bar():\n    pass\n".to_string(),
+                ),
+                (
+                    PathBuf::from("main.py"),
+                    "from tie_z import foo\nfrom tie_a import bar\n\nfoo()\nbar()\n".to_string(),
+                ),
+            ],
+            None,
+        );
+        let paths: Vec<&str> = rows.iter().map(|row| row.definition.path_str()).collect();
+        assert_eq!(paths, vec!["tie_a.py", "tie_z.py"]);
+    }
+
+    #[test]
+    fn quantize_rounds_to_decimal_places() {
+        assert_eq!(quantize(0.123_456_7, 3), 0.123);
+        assert_eq!(quantize(0.1 + 0.2, 6), 0.3);
+    }
+
+    #[test]
+    fn low_memory_mode_matches_full_scan() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("src/a.py"),
+                "def foo():\n    pass\n\ndef bar():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("src/b.py"),
+                "from a import foo, bar\n\nfoo()\nfoo()\nbar()\n".to_string(),
+            ),
+            (
+                PathBuf::from("tests/test_a.py"),
+                "from a import bar\n\nbar()\nbar()\n".to_string(),
+            ),
+            (
+                PathBuf::from("src/lib.rs"),
+                "pub fn parse() {}\n\n#[cfg(test)]\nmod tests {\n    fn check() { parse(); }\n}\n"
+                    .to_string(),
+            ),
+            (
+                PathBuf::from("src/main.rs"),
+                "fn main() { parse(); }\n".to_string(),
+            ),
+        ]);
+        let summary = |options: &Options| {
+            cruxlines_from_source(&source, None, options)
+                .expect("analyze")
+                .rows
+                .into_iter()
+                .map(|row| {
+                    (
+                        row.definition,
+                        quantize(row.rank, 9),
+                        row.reference_count,
+                        row.definition_line,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let full = summary(&Options::default());
+        let low_memory = summary(&Options {
+            low_memory: true,
+            ..Options::default()
+        });
+        assert!(!full.is_empty());
+        assert_eq!(low_memory, full);
+    }
+
+    #[test]
+    fn explanation_reproduces_the_rank() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("src/a.py"),
+                "def foo():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("src/b.py"),
+                "from a import foo\n\nfoo()\n".to_string(),
+            ),
+            (
+                PathBuf::from("tests/test_a.py"),
+                "from a import foo\n\nfoo()\n".to_string(),
+            ),
+        ]);
+        let options = Options {
+            symbol_rank: true,
+            ..Options::default()
+        };
+        let rows = cruxlines_from_source(&source, None, &options)
+            .expect("analyze")
+            .rows;
+        let row = rows
+            .iter()
+            .find(|row| row.definition.name_str() == "foo")
+            .expect("foo is ranked");
+
+        let explanations =
+            explain_definition(&source, None, &options, &PathBuf::from("src/a.py"), 1)
+                .expect("explain");
+        assert_eq!(explanations.len(), 1);
+        let explanation = &explanations[0];
+        assert_eq!(explanation.row.definition, row.definition);
+        assert_eq!(explanation.row.rank, row.rank);
+        assert_eq!(explanation.references.len(), 4);
+        let tests: Vec<_> = explanation
+            .references
+            .iter()
+            .filter(|reference| reference.test_multiplier != 1.0)
+            .map(|reference| reference.location.path_str())
+            .collect();
+        assert_eq!(tests, vec!["tests/test_a.py", "tests/test_a.py"]);
+        let weighted: f64 = explanation
+            .references
+            .iter()
+            .map(|reference| reference.weight())
+            .sum();
+        assert!((weighted - explanation.weighted_references).abs() < 1e-12);
+
+        let none = explain_definition(&source, None, &options, &PathBuf::from("src/a.py"), 2)
+            .expect("explain");
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn rows_carry_a_score_breakdown() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("a.py"),
+                "def helper():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("c.py"),
+                "def helper():\n    pass\n".to_string(),
+            ),
+            (PathBuf::from("b.py"), "helper()\nhelper()\n".to_string()),
+            (PathBuf::from("d.py"), "helper()\n".to_string()),
+        ]);
+        let rows = cruxlines_from_source(&source, None, &Options::default())
+            .expect("analyze")
+            .rows;
+        let row = rows
+            .iter()
+            .find(|row| row.definition.path_str() == "a.py")
+            .expect("helper is ranked");
+        let breakdown = row.breakdown;
+        assert_eq!(breakdown.reference_count, row.reference_count);
+        assert_eq!(breakdown.reference_count, 3);
+        assert_eq!(breakdown.distinct_referencing_files, 2);
+        assert_eq!(breakdown.frecency_weight_sum, 3.0);
+        assert_eq!(breakdown.ambiguity_penalty, 2.0);
+    }
+
+    #[test]
+    fn stats_count_files_definitions_and_references() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("defs.py"),
+                "def add():\n    pass\n\ndef unused():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("main.py"),
+                "add()\nadd()\nmissing()\n".to_string(),
+            ),
+            (PathBuf::from("lib.rs"), "fn run() {}\n".to_string()),
+        ]);
+        let stats = |options: &Options| {
+            cruxlines_from_source(&source, None, options)
+                .expect("analyze")
+                .stats
+        };
+        let full = stats(&Options::default());
+        assert_eq!(
+            full.files,
+            HashMap::from([(Ecosystem::Python, 2), (Ecosystem::Rust, 1)])
+        );
+        assert_eq!(full.definitions, 3);
+        assert_eq!(full.reference_edges, 2);
+        assert_eq!(full.unresolved_references, 1);
+        let low_memory = stats(&Options {
+            low_memory: true,
+            ..Options::default()
+        });
+        assert_eq!(low_memory, full);
+    }
+
+    #[test]
+    fn same_file_references_count_for_less() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("a.py"),
+                "def helper():\n    pass\n\ndef util():\n    pass\n\nhelper()\nhelper()\n"
+                    .to_string(),
+            ),
+            (PathBuf::from("b.py"), "util()\n".to_string()),
+        ]);
+        let ranks = |same_file_references: f64, low_memory: bool| {
+            let options = Options {
+                low_memory,
+                weights: Weights {
+                    same_file_references,
+                    ..Weights::default()
+                },
+                ..Options::default()
+            };
+            cruxlines_from_source(&source, None, &options)
+                .expect("analyze")
+                .rows
+                .into_iter()
+                .map(|row| (row.definition.name_str().to_string(), row.rank))
+                .collect::<HashMap<_, _>>()
+        };
+        for low_memory in [false, true] {
+            let full = ranks(1.0, low_memory);
+            let dampened = ranks(0.5, low_memory);
+            assert!((dampened["helper"] - full["helper"] * 0.5).abs() < 1e-12);
+            assert_eq!(dampened["util"], full["util"]);
+        }
+    }
+
+    #[test]
+    fn chatty_files_count_once_when_aggregating_by_file() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("lib.py"),
+                "def log():\n    pass\n\ndef fetch():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("noisy.py"),
+                "log()\nlog()\nlog()\nlog()\nfetch()\n".to_string(),
+            ),
+        ]);
+        let rows = |reference_aggregation: ReferenceAggregation, low_memory: bool| {
+            let options = Options {
+                low_memory,
+                reference_aggregation,
+                ..Options::default()
+            };
+            cruxlines_from_source(&source, None, &options)
+                .expect("analyze")
+                .rows
+                .into_iter()
+                .map(|row| (row.definition.name_str().to_string(), row))
+                .collect::<HashMap<_, _>>()
+        };
+        for low_memory in [false, true] {
+            let occurrences = rows(ReferenceAggregation::Occurrences, low_memory);
+            assert!((occurrences["log"].rank - 4.0 * occurrences["fetch"].rank).abs() < 1e-12);
+            let files = rows(ReferenceAggregation::Files, low_memory);
+            assert!((files["log"].rank - files["fetch"].rank).abs() < 1e-12);
+            assert_eq!(files["log"].reference_count, 4);
+            let log = rows(ReferenceAggregation::Log, low_memory);
+            let expected = (1.0 + 4f64.ln()) * log["fetch"].rank;
+            assert!((log["log"].rank - expected).abs() < 1e-12);
+        }
+
+        let options = Options {
+            reference_aggregation: ReferenceAggregation::Log,
+            ..Options::default()
+        };
+        let explanation = explain_definition(&source, None, &options, Path::new("lib.py"), 1)
+            .expect("explain")
+            .remove(0);
+        assert_eq!(
+            explanation.row.rank,
+            rows(ReferenceAggregation::Log, false)["log"].rank
+        );
+        assert!(
+            explanation
+                .references
+                .iter()
+                .all(|reference| reference.file_share == (1.0 + 4f64.ln()) / 4.0)
+        );
+    }
+
+    #[test]
+    fn nested_calls_on_one_line_count_once_when_deduplicating_by_line() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("lib.py"),
+                "def wrap(x):\n    return x\n\ndef fetch():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("app.py"),
+                "wrap(wrap(1))\nwrap(2)\nfetch()\n".to_string(),
+            ),
+        ]);
+        let rows = |reference_dedup: ReferenceDedup, low_memory: bool| {
+            let options = Options {
+                low_memory,
+                reference_dedup,
+                ..Options::default()
+            };
+            cruxlines_from_source(&source, None, &options)
+                .expect("analyze")
+                .rows
+                .into_iter()
+                .map(|row| (row.definition.name_str().to_string(), row))
+                .collect::<HashMap<_, _>>()
+        };
+        for low_memory in [false, true] {
+            for (dedup, expected) in [
+                (ReferenceDedup::None, 3),
+                (ReferenceDedup::Location, 3),
+                (ReferenceDedup::Line, 2),
+            ] {
+                let rows = rows(dedup, low_memory);
+                assert_eq!(rows["wrap"].reference_count, expected, "{dedup:?}");
+                let ratio = rows["wrap"].rank / rows["fetch"].rank;
+                assert!((ratio - expected as f64).abs() < 1e-9, "{dedup:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn iterator_yields_rows_in_rank_order() {
+        let source = InMemorySource::new([
+            (
+                PathBuf::from("a.py"),
+                "def foo():\n    pass\n\ndef bar():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("b.py"),
+                "from a import foo, bar\n\nfoo()\nfoo()\nbar()\n".to_string(),
+            ),
+        ]);
+        let collected = cruxlines_from_source(&source, None, &Options::default())
+            .expect("analyze")
+            .rows;
+        let mut rows = cruxlines_iter(&source, None, &Options::default()).expect("analyze");
+        assert_eq!(rows.len(), collected.len());
+        let first = rows.next().expect("first row");
+        assert_eq!(first.definition, collected[0].definition);
+        assert_eq!(first.references, collected[0].references);
+        assert_eq!(rows.len(), collected.len() - 1);
+    }
+
     #[test]
     fn groups_edges_without_extension_by_ecosystem() {
         let edge = ReferenceEdge {
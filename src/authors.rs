@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+#[cfg(feature = "native")]
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use lasso::Spur;
+
+use crate::intern::intern;
+#[cfg(feature = "native")]
+use crate::repo::git_command;
+
+/// Supplies the number of distinct authors of each file. Widely co-edited
+/// files are usually load-bearing, so their definitions get a boost; files
+/// without a count count as one author.
+pub trait AuthorsProvider: Send + Sync {
+    /// Counts keyed by the same paths the analyzed source reports.
+    fn authors(&self, repo_root: Option<&Path>) -> HashMap<PathBuf, usize>;
+}
+
+/// Where author counts come from.
+#[derive(Clone, Default)]
+pub enum Authors {
+    /// No author counts; no file is boosted.
+    #[default]
+    Disabled,
+    /// Distinct commit author emails in the git history of the repo root.
+    /// Without the `native` feature this behaves like
+    /// [`Authors::Disabled`].
+    Git,
+    Custom(Arc<dyn AuthorsProvider>),
+}
+
+impl fmt::Debug for Authors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Authors::Disabled => f.write_str("Disabled"),
+            Authors::Git => f.write_str("Git"),
+            Authors::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Authors {
+    pub(crate) fn counts(&self, repo_root: Option<&Path>) -> HashMap<Spur, usize> {
+        let counts = match self {
+            Authors::Disabled => HashMap::new(),
+            Authors::Git => git_authors(repo_root),
+            Authors::Custom(provider) => provider.authors(repo_root),
+        };
+        counts
+            .into_iter()
+            .map(|(path, count)| (intern(&path.to_string_lossy()), count))
+            .collect()
+    }
+}
+
+#[cfg(feature = "native")]
+fn git_authors(repo_root: Option<&Path>) -> HashMap<PathBuf, usize> {
+    let Some(repo_root) = repo_root else {
+        return HashMap::new();
+    };
+    // Each commit is a NUL-prefixed author line followed by its files.
+    let output = git_command(repo_root)
+        .args([
+            "-c",
+            "core.quotePath=false",
+            "log",
+            "--no-merges",
+            "--format=%x00%aE",
+            "--name-only",
+        ])
+        .output();
+    let Ok(output) = output else {
+        return HashMap::new();
+    };
+    if !output.status.success() {
+        return HashMap::new();
+    }
+    let mut authors: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut author = "";
+    for line in stdout.lines() {
+        if let Some(email) = line.strip_prefix('\0') {
+            author = email;
+        } else if !line.is_empty() {
+            authors.entry(line).or_default().insert(author);
+        }
+    }
+    authors
+        .into_iter()
+        .map(|(path, authors)| (repo_root.join(path), authors.len()))
+        .collect()
+}
+
+#[cfg(not(feature = "native"))]
+fn git_authors(_repo_root: Option<&Path>) -> HashMap<PathBuf, usize> {
+    HashMap::new()
+}
@@ -0,0 +1,58 @@
+//! Coarse labels for ranks, for readers who have no feel for raw scores.
+//! Ranks are first normalized against the highest rank shown, so the
+//! thresholds are fractions of the top row.
+
+/// How a bucket is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketScale {
+    /// `critical`, `high`, `medium` or `low`.
+    Labels,
+    /// One to five stars, e.g. `★★★☆☆`.
+    Stars,
+}
+
+/// Minimum normalized rank of each bucket, read from the `[buckets]` table
+/// of `.cruxlines.toml`. Both lists are in descending order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Buckets {
+    /// Minimums for `critical`, `high` and `medium`; anything below is
+    /// `low`.
+    pub labels: [f64; 3],
+    /// Minimums for five, four, three and two stars; anything below gets
+    /// one star.
+    pub stars: [f64; 4],
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self {
+            labels: [0.5, 0.2, 0.05],
+            stars: [0.5, 0.2, 0.05, 0.01],
+        }
+    }
+}
+
+impl Buckets {
+    /// The bucket of `rank` when the highest rank shown is `max_rank`.
+    pub fn bucket(&self, scale: BucketScale, rank: f64, max_rank: f64) -> String {
+        let normalized = if max_rank > 0.0 { rank / max_rank } else { 0.0 };
+        match scale {
+            BucketScale::Labels => {
+                let level = self
+                    .labels
+                    .iter()
+                    .position(|minimum| normalized >= *minimum)
+                    .unwrap_or(3);
+                ["critical", "high", "medium", "low"][level].to_string()
+            }
+            BucketScale::Stars => {
+                let below = self
+                    .stars
+                    .iter()
+                    .position(|minimum| normalized >= *minimum)
+                    .unwrap_or(4);
+                format!("{}{}", "★".repeat(5 - below), "☆".repeat(below))
+            }
+        }
+    }
+}
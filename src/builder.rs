@@ -0,0 +1,223 @@
+//! [`Cruxlines::builder`], which collects the settings of an analysis in
+//! one place and then runs it on a repository, a git revision, in-memory
+//! files or any [`SourceProvider`]. The `cruxlines*` free functions are
+//! shorthands for it.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{Analysis, cruxlines_from_paths, cruxlines_from_source};
+use crate::config::Config;
+use crate::error::CruxlinesError;
+use crate::frecency::Frecency;
+use crate::io::{IgnoreRules, gather_paths_counted};
+use crate::languages::{Ecosystem, SymbolKind, all_ecosystems};
+use crate::options::{NamePattern, Options, Weights};
+use crate::progress::ProgressHook;
+use crate::source::{GitRevisionSource, InMemorySource, SourceProvider};
+
+/// Entry point of the builder API; see [`Cruxlines::builder`].
+#[derive(Debug, Clone, Copy)]
+pub struct Cruxlines;
+
+impl Cruxlines {
+    /// A builder with [`Options::default`], analyzing every ecosystem.
+    pub fn builder() -> CruxlinesBuilder {
+        CruxlinesBuilder::default()
+    }
+}
+
+/// Settings of an analysis. Each `run_*` method borrows the builder, so one
+/// builder can run several analyses.
+#[derive(Debug, Clone, Default)]
+pub struct CruxlinesBuilder {
+    options: Options,
+    /// `None` selects every built-in and registered ecosystem.
+    ecosystems: Option<HashSet<Ecosystem>>,
+    repo_root: Option<PathBuf>,
+}
+
+impl CruxlinesBuilder {
+    /// Analyzes only files of these ecosystems.
+    pub fn ecosystems(mut self, ecosystems: impl IntoIterator<Item = Ecosystem>) -> Self {
+        self.ecosystems = Some(ecosystems.into_iter().collect());
+        self
+    }
+
+    /// Replaces every option, including those set by earlier calls.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Applies the settings of a `.cruxlines.toml`, such as one found with
+    /// [`Config::discover`]. Its `[files]` globs add to the excluded ones.
+    pub fn config(mut self, config: Config) -> Self {
+        self.options.weights = config.weights;
+        self.options.scoped_weights = config.scoped_weights;
+        self.options.reference_kinds = config.reference_kinds;
+        self.options.common_names = config.common_names;
+        self.options.namespaces = config.namespaces;
+        self.options.reference_dedup = config.reference_dedup;
+        self.options.exclude.extend(config.exclude);
+        self
+    }
+
+    /// Reuses parse results from the on-disk file cache; on by default.
+    pub fn cache(mut self, use_cache: bool) -> Self {
+        self.options.use_cache = use_cache;
+        self
+    }
+
+    pub fn frecency(mut self, frecency: Frecency) -> Self {
+        self.options.frecency = frecency;
+        self
+    }
+
+    pub fn weights(mut self, weights: Weights) -> Self {
+        self.options.weights = weights;
+        self
+    }
+
+    /// Keeps only the `top` highest-ranked rows.
+    pub fn top(mut self, top: usize) -> Self {
+        self.options.top = Some(top);
+        self
+    }
+
+    pub fn min_rank(mut self, min_rank: f64) -> Self {
+        self.options.min_rank = Some(min_rank);
+        self
+    }
+
+    pub fn min_references(mut self, min_references: usize) -> Self {
+        self.options.min_references = Some(min_references);
+        self
+    }
+
+    /// Keeps only definitions of these kinds.
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = SymbolKind>) -> Self {
+        self.options.kinds = kinds.into_iter().collect();
+        self
+    }
+
+    /// Keeps only definitions whose name matches `name`.
+    pub fn name(mut self, name: NamePattern) -> Self {
+        self.options.name = Some(name);
+        self
+    }
+
+    /// Keeps only definitions in these files or directories. Every scanned
+    /// file still contributes to the ranks.
+    pub fn definition_paths(mut self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.options.definition_paths = Some(paths.into_iter().collect());
+        self
+    }
+
+    /// Scans only files matching these globs, relative to the repo root.
+    pub fn include(mut self, globs: impl IntoIterator<Item = String>) -> Self {
+        self.options.include.extend(globs);
+        self
+    }
+
+    /// Skips files and directories matching these globs, relative to the
+    /// repo root.
+    pub fn exclude(mut self, globs: impl IntoIterator<Item = String>) -> Self {
+        self.options.exclude.extend(globs);
+        self
+    }
+
+    /// Counts references without keeping their locations; see
+    /// `Options::low_memory`.
+    pub fn low_memory(mut self, low_memory: bool) -> Self {
+        self.options.low_memory = low_memory;
+        self
+    }
+
+    pub fn progress(mut self, progress: ProgressHook) -> Self {
+        self.options.progress = Some(progress);
+        self
+    }
+
+    /// Repo root of [`Self::run_inputs`] and [`Self::run_source`], which
+    /// enables frecency and the file cache and, for inputs, applies its
+    /// ignore files. The other `run_*` methods take their own.
+    pub fn repo_root(mut self, repo_root: impl Into<PathBuf>) -> Self {
+        self.repo_root = Some(repo_root.into());
+        self
+    }
+
+    /// The options collected so far.
+    pub fn as_options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Analyzes the repository at `repo_root`.
+    pub fn run_repo(&self, repo_root: impl AsRef<Path>) -> Result<Analysis, CruxlinesError> {
+        let repo_root = repo_root.as_ref();
+        self.run_paths(repo_root, &[repo_root.to_path_buf()])
+    }
+
+    /// Analyzes only the given files or directories. `repo_root` is still
+    /// used for frecency and the file cache.
+    pub fn run_paths(
+        &self,
+        repo_root: impl AsRef<Path>,
+        paths: &[PathBuf],
+    ) -> Result<Analysis, CruxlinesError> {
+        let repo_root = repo_root.as_ref();
+        let (paths, skipped) =
+            gather_paths_counted(repo_root, paths, &self.selected_ecosystems(), &self.options)?;
+        let mut analysis =
+            cruxlines_from_paths(paths, Some(repo_root.to_path_buf()), &self.options)?;
+        analysis.stats.skipped_large_files = skipped.large;
+        analysis.stats.skipped_excess_files = skipped.excess;
+        Ok(analysis)
+    }
+
+    /// Analyzes the files tracked at a git `revision` instead of the working
+    /// tree. Frecency still comes from the current history.
+    pub fn run_revision(
+        &self,
+        repo_root: impl AsRef<Path>,
+        revision: &str,
+    ) -> Result<Analysis, CruxlinesError> {
+        let repo_root = repo_root.as_ref();
+        let ecosystems = self.selected_ecosystems();
+        let mut source = GitRevisionSource::new(repo_root, revision)?;
+        source.retain(|path| {
+            crate::ecosystem_for_path(path).is_some_and(|ecosystem| ecosystems.contains(&ecosystem))
+        });
+        cruxlines_from_source(&source, Some(repo_root), &self.options)
+    }
+
+    /// Analyzes file contents held in memory, keyed by path. Inputs of other
+    /// ecosystems are dropped when [`Self::ecosystems`] was called.
+    pub fn run_inputs(
+        &self,
+        inputs: impl IntoIterator<Item = (PathBuf, String)>,
+    ) -> Result<Analysis, CruxlinesError> {
+        let mut inputs: Vec<(PathBuf, String)> = inputs.into_iter().collect();
+        if let Some(ecosystems) = &self.ecosystems {
+            inputs.retain(|(path, _)| {
+                crate::ecosystem_for_path(path)
+                    .is_some_and(|ecosystem| ecosystems.contains(&ecosystem))
+            });
+        }
+        // Inputs under a repo root follow the same ignore rules as a walk.
+        if let Some(repo_root) = &self.repo_root {
+            let mut rules = IgnoreRules::new(repo_root, self.options.scan);
+            inputs.retain(|(path, _)| !rules.is_ignored(path));
+        }
+        self.run_source(&InMemorySource::new(inputs))
+    }
+
+    /// Analyzes the files supplied by `source`, whatever their ecosystem.
+    pub fn run_source(&self, source: &impl SourceProvider) -> Result<Analysis, CruxlinesError> {
+        cruxlines_from_source(source, self.repo_root.as_deref(), &self.options)
+    }
+
+    fn selected_ecosystems(&self) -> HashSet<Ecosystem> {
+        self.ecosystems.clone().unwrap_or_else(all_ecosystems)
+    }
+}
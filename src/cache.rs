@@ -7,11 +7,14 @@ use directories::ProjectDirs;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 
-use crate::find_references::{Location, SerializedLocation};
-use crate::languages::Ecosystem;
+use crate::find_references::{
+    FfiExport, FileResult, ImportBinding, Location, SerializedFfiExport, SerializedImportBinding,
+    SerializedLocation,
+};
+use crate::languages::{Ecosystem, SymbolKind};
 
 // Bump version when cache format changes
-const CACHE_VERSION: u32 = 3;
+const CACHE_VERSION: u32 = 20;
 
 #[derive(Serialize, Deserialize)]
 struct CachedFile {
@@ -23,17 +26,25 @@ struct CachedFile {
     definitions: Vec<SerializedLocation>,
     references: Vec<SerializedLocation>,
     definition_lines: Vec<(SerializedLocation, String)>,
+    definition_kinds: Vec<(SerializedLocation, SymbolKind)>,
+    imports: Vec<SerializedImportBinding>,
+    test_ranges: Vec<(usize, usize)>,
+    ffi_exports: Vec<SerializedFfiExport>,
+    entry_points: Vec<SerializedLocation>,
+    partial: bool,
 }
 
-pub struct FileCache {
-    cache_dir: PathBuf,
+/// File ranks of one ecosystem, valid while its file graph hashes to
+/// `graph_hash`.
+#[derive(Serialize, Deserialize)]
+struct CachedRanks {
+    version: u32,
+    graph_hash: u64,
+    ranks: Vec<(String, f64)>,
 }
 
-pub struct CachedFileResult {
-    pub ecosystem: Ecosystem,
-    pub definitions: Vec<Location>,
-    pub references: Vec<Location>,
-    pub definition_lines: FxHashMap<Location, String>,
+pub struct FileCache {
+    cache_dir: PathBuf,
 }
 
 impl FileCache {
@@ -51,8 +62,17 @@ impl FileCache {
         Self { cache_dir }
     }
 
+    /// Keeps entries in a separate directory per `key`, so scans that extract
+    /// different data from the same files don't read each other's results.
+    pub fn namespaced(self, key: &str) -> Self {
+        let key_hash = hash_path(Path::new(key));
+        Self {
+            cache_dir: self.cache_dir.join(format!("{:016x}", key_hash)),
+        }
+    }
+
     /// Try to load cached data for a file. Returns None if cache miss or invalid.
-    pub fn get(&self, path: &Path) -> Option<CachedFileResult> {
+    pub fn get(&self, path: &Path) -> Option<FileResult> {
         let cache_path = self.cache_path(path);
         let bytes = fs::read(&cache_path).ok()?;
         let (cached, _): (CachedFile, _) =
@@ -85,24 +105,41 @@ impl FileCache {
             .into_iter()
             .map(|(loc, line)| (Location::from(loc), line))
             .collect();
+        let definition_kinds: FxHashMap<Location, SymbolKind> = cached
+            .definition_kinds
+            .into_iter()
+            .map(|(loc, kind)| (Location::from(loc), kind))
+            .collect();
+        let imports: Vec<ImportBinding> = cached
+            .imports
+            .into_iter()
+            .map(ImportBinding::from)
+            .collect();
 
-        Some(CachedFileResult {
+        Some(FileResult {
             ecosystem: cached.ecosystem,
             definitions,
             references,
             definition_lines,
+            definition_kinds,
+            imports,
+            test_ranges: cached.test_ranges,
+            ffi_exports: cached
+                .ffi_exports
+                .into_iter()
+                .map(FfiExport::from)
+                .collect(),
+            entry_points: cached
+                .entry_points
+                .into_iter()
+                .map(Location::from)
+                .collect(),
+            partial: cached.partial,
         })
     }
 
     /// Store cached data for a file.
-    pub fn set(
-        &self,
-        path: &Path,
-        ecosystem: Ecosystem,
-        definitions: &[Location],
-        references: &[Location],
-        definition_lines: &FxHashMap<Location, String>,
-    ) -> io::Result<()> {
+    pub fn set(&self, path: &Path, result: &FileResult) -> io::Result<()> {
         // Get current mtime and size
         let metadata = fs::metadata(path)?;
         let mtime = metadata.modified()?;
@@ -110,24 +147,55 @@ impl FileCache {
         let (mtime_secs, mtime_nanos) = system_time_to_parts(mtime);
 
         // Convert Location to SerializedLocation for storage
-        let definitions_ser: Vec<SerializedLocation> =
-            definitions.iter().map(SerializedLocation::from).collect();
-        let references_ser: Vec<SerializedLocation> =
-            references.iter().map(SerializedLocation::from).collect();
-        let definition_lines_ser: Vec<(SerializedLocation, String)> = definition_lines
+        let definitions_ser: Vec<SerializedLocation> = result
+            .definitions
+            .iter()
+            .map(SerializedLocation::from)
+            .collect();
+        let references_ser: Vec<SerializedLocation> = result
+            .references
+            .iter()
+            .map(SerializedLocation::from)
+            .collect();
+        let definition_lines_ser: Vec<(SerializedLocation, String)> = result
+            .definition_lines
             .iter()
             .map(|(k, v)| (SerializedLocation::from(k), v.clone()))
             .collect();
+        let definition_kinds_ser: Vec<(SerializedLocation, SymbolKind)> = result
+            .definition_kinds
+            .iter()
+            .map(|(k, v)| (SerializedLocation::from(k), *v))
+            .collect();
+        let imports_ser: Vec<SerializedImportBinding> = result
+            .imports
+            .iter()
+            .map(SerializedImportBinding::from)
+            .collect();
 
         let cached = CachedFile {
             version: CACHE_VERSION,
             mtime_secs,
             mtime_nanos,
             size,
-            ecosystem,
+            ecosystem: result.ecosystem,
             definitions: definitions_ser,
             references: references_ser,
             definition_lines: definition_lines_ser,
+            definition_kinds: definition_kinds_ser,
+            imports: imports_ser,
+            test_ranges: result.test_ranges.clone(),
+            ffi_exports: result
+                .ffi_exports
+                .iter()
+                .map(SerializedFfiExport::from)
+                .collect(),
+            entry_points: result
+                .entry_points
+                .iter()
+                .map(SerializedLocation::from)
+                .collect(),
+            partial: result.partial,
         };
 
         let bytes = bincode::serde::encode_to_vec(&cached, bincode::config::standard())
@@ -142,6 +210,39 @@ impl FileCache {
         Ok(())
     }
 
+    /// Load the file ranks stored for `ecosystem`, if they were computed for
+    /// the same graph.
+    pub fn get_ranks(&self, ecosystem: &str, graph_hash: u64) -> Option<Vec<(String, f64)>> {
+        let bytes = fs::read(self.ranks_path(ecosystem)).ok()?;
+        let (cached, _): (CachedRanks, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).ok()?;
+        (cached.version == CACHE_VERSION && cached.graph_hash == graph_hash).then_some(cached.ranks)
+    }
+
+    /// Store the file ranks of `ecosystem`, replacing those of its previous
+    /// graph.
+    pub fn set_ranks(
+        &self,
+        ecosystem: &str,
+        graph_hash: u64,
+        ranks: &[(String, f64)],
+    ) -> io::Result<()> {
+        let cached = CachedRanks {
+            version: CACHE_VERSION,
+            graph_hash,
+            ranks: ranks.to_vec(),
+        };
+        let bytes = bincode::serde::encode_to_vec(&cached, bincode::config::standard())
+            .map_err(io::Error::other)?;
+        fs::create_dir_all(&self.cache_dir)?;
+        fs::write(self.ranks_path(ecosystem), bytes)
+    }
+
+    fn ranks_path(&self, ecosystem: &str) -> PathBuf {
+        let hash = hash_path(Path::new(ecosystem));
+        self.cache_dir.join(format!("ranks-{:016x}.bin", hash))
+    }
+
     fn cache_path(&self, source_path: &Path) -> PathBuf {
         let hash = hash_path(source_path);
         self.cache_dir.join(format!("{:016x}.bin", hash))
@@ -0,0 +1,249 @@
+//! C API, enabled with the `capi` feature. `include/cruxlines.h` declares
+//! these functions and is kept in sync by hand; `cbindgen --config
+//! cbindgen.toml --output include/cruxlines.h` can regenerate it.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::{Path, PathBuf};
+
+use crate::analysis::OutputRow;
+use crate::builder::Cruxlines;
+use crate::repo::find_repo_root;
+
+/// One ranked definition. Strings are NUL-terminated and owned by the
+/// enclosing [`CruxlinesRows`].
+#[repr(C)]
+pub struct CruxlinesRow {
+    pub path: *mut c_char,
+    pub name: *mut c_char,
+    /// Symbol kind, e.g. `function` or `class`.
+    pub kind: *mut c_char,
+    pub line: usize,
+    pub column: usize,
+    pub rank: f64,
+    pub local_score: f64,
+    pub file_rank: f64,
+    pub reference_count: usize,
+    /// Text of the definition's line, as analyzed.
+    pub definition_line: *mut c_char,
+}
+
+/// Rows in rank order, released with [`cruxlines_rows_free`].
+#[repr(C)]
+pub struct CruxlinesRows {
+    pub rows: *mut CruxlinesRow,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Analyzes the `n` files or directories in `paths` and stores the ranked
+/// rows in `out_rows`. The repo root is the closest ancestor of the first
+/// path that holds `.git`; relative paths are resolved against the current
+/// directory. Returns 0 on success and -1 on failure, in which case
+/// [`cruxlines_last_error`] describes the problem and `out_rows` is left
+/// untouched.
+///
+/// # Safety
+///
+/// `paths` must point to `n` valid NUL-terminated strings and `out_rows`
+/// to writable memory for one [`CruxlinesRows`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cruxlines_analyze(
+    paths: *const *const c_char,
+    n: usize,
+    out_rows: *mut CruxlinesRows,
+) -> i32 {
+    if paths.is_null() || out_rows.is_null() || n == 0 {
+        return fail("paths and out_rows must be non-null and n positive".to_string());
+    }
+    // SAFETY: the caller passes `n` valid C strings.
+    let paths: Vec<PathBuf> = unsafe { std::slice::from_raw_parts(paths, n) }
+        .iter()
+        .map(|path| PathBuf::from(unsafe { CStr::from_ptr(*path) }.to_string_lossy().as_ref()))
+        .collect();
+    let analyzed = catch_unwind(AssertUnwindSafe(|| analyze(&paths)))
+        .unwrap_or_else(|_| Err("analysis panicked".to_string()));
+    match analyzed {
+        Ok(rows) => {
+            let rows: Box<[CruxlinesRow]> = rows.iter().map(c_row).collect();
+            let len = rows.len();
+            // SAFETY: the caller passes writable memory for the result.
+            unsafe {
+                out_rows.write(CruxlinesRows {
+                    rows: Box::into_raw(rows).cast(),
+                    len,
+                });
+            }
+            0
+        }
+        Err(message) => fail(message),
+    }
+}
+
+/// Releases rows returned by [`cruxlines_analyze`] and resets `rows` to
+/// empty. Passing null or empty rows is a no-op.
+///
+/// # Safety
+///
+/// `rows` must be null or filled in by [`cruxlines_analyze`] and not freed
+/// before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cruxlines_rows_free(rows: *mut CruxlinesRows) {
+    // SAFETY: `rows` came from `cruxlines_analyze`, which boxed the slice
+    // and every string in it.
+    let Some(rows) = (unsafe { rows.as_mut() }) else {
+        return;
+    };
+    if !rows.rows.is_null() {
+        let slice = std::ptr::slice_from_raw_parts_mut(rows.rows, rows.len);
+        for row in unsafe { Box::from_raw(slice) } {
+            for string in [row.path, row.name, row.kind, row.definition_line] {
+                drop(unsafe { CString::from_raw(string) });
+            }
+        }
+    }
+    rows.rows = std::ptr::null_mut();
+    rows.len = 0;
+}
+
+/// Message of the last failed call on this thread, or null. The string stays
+/// valid until another call fails on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn cruxlines_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+fn analyze(paths: &[PathBuf]) -> Result<Vec<OutputRow>, String> {
+    let cwd = std::env::current_dir().map_err(|err| err.to_string())?;
+    let paths: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| cwd.join(path).components().collect())
+        .collect();
+    let repo_root = repo_root(&paths[0]);
+    Cruxlines::builder()
+        .run_paths(&repo_root, &paths)
+        .map(|analysis| analysis.rows)
+        .map_err(|err| err.to_string())
+}
+
+/// The repo root holding `path`, else the directory of `path`.
+fn repo_root(path: &Path) -> PathBuf {
+    find_repo_root(path).unwrap_or_else(|| {
+        if path.is_dir() {
+            path.to_path_buf()
+        } else {
+            path.parent().unwrap_or(path).to_path_buf()
+        }
+    })
+}
+
+fn c_row(row: &OutputRow) -> CruxlinesRow {
+    CruxlinesRow {
+        path: c_string(row.definition.path_str()),
+        name: c_string(row.definition.name_str()),
+        kind: c_string(row.kind.as_str()),
+        line: row.definition.line,
+        column: row.definition.column,
+        rank: row.rank,
+        local_score: row.local_score,
+        file_rank: row.file_rank,
+        reference_count: row.reference_count,
+        definition_line: c_string(&row.definition_line),
+    }
+}
+
+/// Copies `value` into a C string, dropping any interior NUL bytes.
+fn c_string(value: &str) -> *mut c_char {
+    CString::new(value.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+fn fail(message: String) -> i32 {
+    LAST_ERROR.with(|error| *error.borrow_mut() = CString::new(message).ok());
+    -1
+}
+
+#[cfg(all(test, feature = "capi"))]
+mod tests {
+    use super::{CruxlinesRows, cruxlines_analyze, cruxlines_last_error, cruxlines_rows_free};
+    use std::ffi::{CStr, CString, c_char};
+
+    fn empty_rows() -> CruxlinesRows {
+        CruxlinesRows {
+            rows: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn last_error() -> String {
+        let error = cruxlines_last_error();
+        assert!(!error.is_null(), "expected an error message");
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn analyzes_reads_and_frees_rows() {
+        let dir = std::env::temp_dir().join(format!("cruxlines-capi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        std::fs::write(dir.join("a.py"), "def foo():\n    pass\n").expect("write a");
+        std::fs::write(dir.join("b.py"), "from a import foo\n\nfoo()\n").expect("write b");
+
+        let path = CString::new(dir.to_string_lossy().as_bytes()).expect("c path");
+        let paths = [path.as_ptr()];
+        let mut rows = empty_rows();
+        let status = unsafe { cruxlines_analyze(paths.as_ptr(), paths.len(), &mut rows) };
+        assert_eq!(status, 0);
+        assert_eq!(rows.len, 1);
+        let row = unsafe { &*rows.rows };
+        let text = |string: *mut c_char| unsafe { CStr::from_ptr(string) }.to_string_lossy();
+        assert_eq!(text(row.name), "foo");
+        assert_eq!(text(row.kind), "function");
+        assert!(text(row.path).ends_with("a.py"));
+        assert_eq!(text(row.definition_line), "def foo():");
+        assert_eq!((row.line, row.reference_count), (1, 2));
+
+        unsafe { cruxlines_rows_free(&mut rows) };
+        assert!(rows.rows.is_null());
+        assert_eq!(rows.len, 0);
+        // Freed rows are emptied, so freeing them again does nothing.
+        unsafe { cruxlines_rows_free(&mut rows) };
+        std::fs::remove_dir_all(&dir).expect("cleanup");
+    }
+
+    #[test]
+    fn rejects_null_and_empty_paths() {
+        let mut rows = empty_rows();
+        let status = unsafe { cruxlines_analyze(std::ptr::null(), 1, &mut rows) };
+        assert_eq!(status, -1);
+        assert!(last_error().contains("non-null"));
+        assert!(rows.rows.is_null());
+
+        let path = CString::new(".").expect("c path");
+        let paths = [path.as_ptr()];
+        let status = unsafe { cruxlines_analyze(paths.as_ptr(), 0, &mut rows) };
+        assert_eq!(status, -1);
+        assert!(last_error().contains("n positive"));
+        assert!(rows.rows.is_null());
+    }
+
+    #[test]
+    fn freeing_null_or_empty_rows_is_a_no_op() {
+        let mut rows = empty_rows();
+        unsafe { cruxlines_rows_free(&mut rows) };
+        unsafe { cruxlines_rows_free(std::ptr::null_mut()) };
+        assert!(rows.rows.is_null());
+        assert_eq!(rows.len, 0);
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// Where GitHub looks for the file, in order of precedence.
+const LOCATIONS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Owner rules from the repo's `CODEOWNERS` file.
+#[derive(Debug, Default)]
+pub(crate) struct CodeOwners {
+    /// One matcher per line, in file order; the last matching line wins.
+    rules: Vec<(Gitignore, Vec<String>)>,
+}
+
+impl CodeOwners {
+    /// Reads the first `CODEOWNERS` file found under `repo_root`. A missing
+    /// file or repo root gives no owners.
+    pub(crate) fn discover(repo_root: Option<&Path>) -> Self {
+        let Some(repo_root) = repo_root else {
+            return Self::default();
+        };
+        LOCATIONS
+            .iter()
+            .find_map(|location| std::fs::read_to_string(repo_root.join(location)).ok())
+            .map(|contents| Self::parse(repo_root, &contents))
+            .unwrap_or_default()
+    }
+
+    /// Parses `pattern owner...` lines. Patterns follow gitignore rules
+    /// relative to `repo_root`; a pattern without owners unsets ownership.
+    fn parse(repo_root: &Path, contents: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let Some(pattern) = fields.next() else {
+                continue;
+            };
+            let owners = fields
+                .take_while(|field| !field.starts_with('#'))
+                .map(str::to_string)
+                .collect();
+            let mut builder = GitignoreBuilder::new(repo_root);
+            if builder.add_line(None, pattern).is_err() {
+                continue;
+            }
+            if let Ok(matcher) = builder.build() {
+                rules.push((matcher, owners));
+            }
+        }
+        Self { rules }
+    }
+
+    /// Owners of the file at `path`, empty when no line matches it.
+    pub(crate) fn owners(&self, path: &Path) -> Vec<String> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(matcher, _)| {
+                path.starts_with(matcher.path())
+                    && matches!(
+                        matcher.matched_path_or_any_parents(path, false),
+                        Match::Ignore(_)
+                    )
+            })
+            .map(|(_, owners)| owners.clone())
+            .unwrap_or_default()
+    }
+}
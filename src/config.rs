@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+use crate::buckets::Buckets;
+use crate::error::CruxlinesError;
+use crate::languages::{Ecosystem, Language};
+use crate::options::{
+    CommonNames, Namespace, ReferenceDedup, ReferenceKinds, ScopedWeights, Weights,
+};
+
+/// Name of the config file picked up from the repo root.
+pub const CONFIG_FILE_NAME: &str = ".cruxlines.toml";
+
+/// Settings read from a `.cruxlines.toml` file.
+///
+/// Only a small TOML subset is understood: `[weights]`, `[buckets]`,
+/// `[files]`, `[namespaces]`, `[references]`, `[references.<language>]` and
+/// `[common_names.<language>]` table headers, `key = number`,
+/// `key = "string"`, `key = [number, ...]` and `key = ["string", ...]` pairs
+/// on a single line, and `#` comments.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub weights: Weights,
+    pub reference_kinds: HashMap<Language, ReferenceKinds>,
+    /// `dedup` in `[references]`: `"none"`, `"location"` or `"line"`.
+    pub reference_dedup: ReferenceDedup,
+    /// `[common_names.<language>]` tables, keyed by the language's
+    /// ecosystem: `javascript` also covers TypeScript and TSX.
+    pub common_names: HashMap<Ecosystem, CommonNames>,
+    pub namespaces: Vec<Namespace>,
+    pub buckets: Buckets,
+    /// Gitignore-style globs, relative to the repo root, for files and
+    /// directories to skip.
+    pub exclude: Vec<String>,
+    /// Weights set by `.cruxlines.toml` files in subdirectories, parents
+    /// before children.
+    pub scoped_weights: Vec<ScopedWeights>,
+}
+
+impl Config {
+    /// Loads `.cruxlines.toml` from `repo_root`, or the defaults when there
+    /// is none, and then the ones in its subdirectories with
+    /// [`Config::with_nested`].
+    pub fn discover(repo_root: &Path) -> Result<Self, CruxlinesError> {
+        let path = repo_root.join(CONFIG_FILE_NAME);
+        let config = if path.is_file() {
+            Self::from_file(&path)?
+        } else {
+            Self::default()
+        };
+        config.with_nested(repo_root)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, CruxlinesError> {
+        let contents = read(path)?;
+        Self::parse(&contents).map_err(|message| config_error(path, message))
+    }
+
+    /// Applies the `.cruxlines.toml` files below `repo_root` (skipping
+    /// ignored and hidden directories) to their subtrees, the way nested
+    /// `.gitignore` files work.
+    ///
+    /// A nested file can set `[weights]`, `[files]` and `[namespaces]`.
+    /// Weights it leaves out are inherited from the closest parent config
+    /// and apply to the definitions under its directory. Its globs are
+    /// relative to its directory and only match below it. Its namespaces
+    /// take precedence over its parents', and a namespace named like one of
+    /// theirs adds files to it.
+    pub fn with_nested(mut self, repo_root: &Path) -> Result<Self, CruxlinesError> {
+        let mut dirs: Vec<PathBuf> = WalkBuilder::new(repo_root)
+            .hidden(false)
+            .require_git(false)
+            .filter_entry(|entry| {
+                entry.depth() == 0
+                    || !entry.file_type().is_some_and(|kind| kind.is_dir())
+                    || !entry.file_name().to_string_lossy().starts_with('.')
+            })
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.depth() > 1 && entry.file_name() == CONFIG_FILE_NAME)
+            .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+            .collect();
+        // Parents sort before their subdirectories.
+        dirs.sort();
+        for dir in dirs {
+            let path = dir.join(CONFIG_FILE_NAME);
+            let inherited = self
+                .scoped_weights
+                .iter()
+                .rev()
+                .find(|scoped| dir.starts_with(&scoped.dir))
+                .map_or(self.weights, |scoped| scoped.weights);
+            let mut nested = Self {
+                weights: inherited,
+                ..Self::default()
+            };
+            Self::parse_into(&mut nested, &read(&path)?, true)
+                .map_err(|message| config_error(&path, message))?;
+            let prefix = dir
+                .strip_prefix(repo_root)
+                .unwrap_or(&dir)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            self.exclude
+                .extend(nested.exclude.iter().map(|glob| scope_glob(&prefix, glob)));
+            let mut namespaces = Vec::new();
+            for namespace in nested.namespaces {
+                let paths = namespace.paths.iter().map(|glob| scope_glob(&prefix, glob));
+                match self
+                    .namespaces
+                    .iter_mut()
+                    .find(|existing| existing.name == namespace.name)
+                {
+                    Some(existing) => existing.paths.extend(paths),
+                    None => namespaces.push(Namespace {
+                        name: namespace.name,
+                        paths: paths.collect(),
+                    }),
+                }
+            }
+            self.namespaces.splice(0..0, namespaces);
+            if nested.weights != inherited {
+                self.scoped_weights.push(ScopedWeights {
+                    dir,
+                    weights: nested.weights,
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        Self::parse_into(&mut config, contents, false)?;
+        Ok(config)
+    }
+
+    /// Reads `contents` on top of `config`. `nested` files may only set
+    /// settings that can differ per subtree.
+    fn parse_into(config: &mut Self, contents: &str, nested: bool) -> Result<(), String> {
+        let mut table = String::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                table = name.trim().to_string();
+                let known = match table
+                    .strip_prefix("references.")
+                    .or_else(|| table.strip_prefix("common_names."))
+                {
+                    Some(language) => Language::from_name(language).is_some(),
+                    None => matches!(
+                        table.as_str(),
+                        "weights" | "buckets" | "files" | "namespaces" | "references"
+                    ),
+                };
+                if !known {
+                    return Err(format!("line {line_number}: unknown table [{table}]"));
+                }
+                if nested && !matches!(table.as_str(), "weights" | "files" | "namespaces") {
+                    return Err(format!(
+                        "line {line_number}: [{table}] is only read from the repo root"
+                    ));
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {line_number}: expected `key = value`"));
+            };
+            let key = key.trim();
+            if let Some(language) = table
+                .strip_prefix("references.")
+                .and_then(Language::from_name)
+            {
+                let kinds = parse_string_array(value).ok_or_else(|| {
+                    format!("line {line_number}: `{key}` must be an array of strings")
+                })?;
+                let entry = config.reference_kinds.entry(language).or_default();
+                match key {
+                    "add" => entry.add = kinds,
+                    "remove" => entry.remove = kinds,
+                    _ => return Err(format!("line {line_number}: unknown key `{table}.{key}`")),
+                }
+                continue;
+            }
+            if let Some(language) = table
+                .strip_prefix("common_names.")
+                .and_then(Language::from_name)
+            {
+                let names = parse_string_array(value).ok_or_else(|| {
+                    format!("line {line_number}: `{key}` must be an array of strings")
+                })?;
+                let entry = config
+                    .common_names
+                    .entry(crate::languages::ecosystem_for_language(language))
+                    .or_default();
+                match key {
+                    "add" => entry.add.extend(names),
+                    "remove" => entry.remove.extend(names),
+                    _ => return Err(format!("line {line_number}: unknown key `{table}.{key}`")),
+                }
+                continue;
+            }
+            if table == "namespaces" {
+                let paths = parse_string_array(value).ok_or_else(|| {
+                    format!("line {line_number}: `{key}` must be an array of strings")
+                })?;
+                config.namespaces.push(Namespace {
+                    name: key.to_string(),
+                    paths,
+                });
+                continue;
+            }
+            if table == "files" {
+                let globs = parse_string_array(value).ok_or_else(|| {
+                    format!("line {line_number}: `{key}` must be an array of strings")
+                })?;
+                match key {
+                    "exclude" => config.exclude = globs,
+                    _ => return Err(format!("line {line_number}: unknown key `{table}.{key}`")),
+                }
+                continue;
+            }
+            if table == "references" {
+                let dedup = match (key, parse_string(value)) {
+                    ("dedup", Some("none")) => ReferenceDedup::None,
+                    ("dedup", Some("location")) => ReferenceDedup::Location,
+                    ("dedup", Some("line")) => ReferenceDedup::Line,
+                    ("dedup", _) => {
+                        return Err(format!(
+                            "line {line_number}: `dedup` must be \"none\", \"location\" or \"line\""
+                        ));
+                    }
+                    _ => return Err(format!("line {line_number}: unknown key `{table}.{key}`")),
+                };
+                config.reference_dedup = dedup;
+                continue;
+            }
+            if table == "buckets" {
+                let thresholds = parse_number_array(value);
+                let slot: &mut [f64] = match key {
+                    "labels" => &mut config.buckets.labels,
+                    "stars" => &mut config.buckets.stars,
+                    _ => return Err(format!("line {line_number}: unknown key `{table}.{key}`")),
+                };
+                match thresholds {
+                    Some(thresholds) if thresholds.len() == slot.len() => {
+                        slot.copy_from_slice(&thresholds)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "line {line_number}: `{key}` must be an array of {} numbers",
+                            slot.len()
+                        ));
+                    }
+                }
+                continue;
+            }
+            let value: f64 = value
+                .trim()
+                .replace('_', "")
+                .parse()
+                .map_err(|_| format!("line {line_number}: `{key}` must be a number"))?;
+            let slot = match (table.as_str(), key) {
+                ("weights", "frecency") => &mut config.weights.frecency,
+                ("weights", "file_rank") => &mut config.weights.file_rank,
+                ("weights", "references") => &mut config.weights.references,
+                ("weights", "name_ambiguity") => &mut config.weights.name_ambiguity,
+                ("weights", "test_references") => &mut config.weights.test_references,
+                ("weights", "same_file_references") => &mut config.weights.same_file_references,
+                ("weights", "pipeline_references") => &mut config.weights.pipeline_references,
+                ("weights", "manifest_references") => &mut config.weights.manifest_references,
+                ("weights", "entry_reachable") => &mut config.weights.entry_reachable,
+                ("weights", "authors") => &mut config.weights.authors,
+                ("weights", "coverage") => &mut config.weights.coverage,
+                ("", _) => return Err(format!("line {line_number}: `{key}` is outside a table")),
+                _ => return Err(format!("line {line_number}: unknown key `{table}.{key}`")),
+            };
+            *slot = value;
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<String, CruxlinesError> {
+    std::fs::read_to_string(path).map_err(|source| CruxlinesError::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn config_error(path: &Path, message: String) -> CruxlinesError {
+    CruxlinesError::Config {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+/// Rewrites a gitignore-style glob from the config in `dir` (relative to
+/// the repo root) so it is relative to the repo root and only matches below
+/// `dir`.
+fn scope_glob(dir: &str, glob: &str) -> String {
+    let (negation, glob) = match glob.strip_prefix('!') {
+        Some(glob) => ("!", glob),
+        None => ("", glob),
+    };
+    // Like in .gitignore, a glob with no inner `/` matches at any depth.
+    if glob.trim_end_matches('/').contains('/') {
+        format!("{negation}{dir}/{}", glob.trim_start_matches('/'))
+    } else {
+        format!("{negation}{dir}/**/{glob}")
+    }
+}
+
+/// Parses a single-line array of double-quoted strings such as
+/// `["identifier", "field_identifier"]`.
+fn parse_string_array(value: &str) -> Option<Vec<String>> {
+    let inner = value.trim().strip_prefix('[')?.strip_suffix(']')?.trim();
+    inner
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            item.strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Parses a quoted string such as `"line"`.
+fn parse_string(value: &str) -> Option<&str> {
+    value.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Parses a single-line array of numbers such as `[0.5, 0.2, 0.05]`.
+fn parse_number_array(value: &str) -> Option<Vec<f64>> {
+    let inner = value.trim().strip_prefix('[')?.strip_suffix(']')?.trim();
+    inner
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.replace('_', "").parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::languages::{Ecosystem, Language};
+    use crate::options::ReferenceDedup;
+    use std::path::Path;
+
+    #[test]
+    fn parses_weights_table() {
+        let config = Config::parse(
+            "# scoring\n[weights]\nfrecency = 0 # ignore history\nname_ambiguity = 0.5\nsame_file_references = 1\n",
+        )
+        .expect("valid config");
+        assert_eq!(config.weights.frecency, 0.0);
+        assert_eq!(config.weights.name_ambiguity, 0.5);
+        assert_eq!(config.weights.same_file_references, 1.0);
+        assert_eq!(config.weights.file_rank, 1.0);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = Config::parse("[weights]\nfrecncy = 2\n").expect_err("typo");
+        assert_eq!(err, "line 2: unknown key `weights.frecncy`");
+    }
+
+    #[test]
+    fn parses_reference_kind_tables() {
+        let config = Config::parse(
+            "[references.rust]\nadd = [\"field_identifier\"]\n\n[references.python]\nremove = [\"identifier\", \"attribute\"]\n",
+        )
+        .expect("valid config");
+        let rust = &config.reference_kinds[&Language::Rust];
+        assert_eq!(rust.add, vec!["field_identifier"]);
+        assert!(rust.remove.is_empty());
+        let python = &config.reference_kinds[&Language::Python];
+        assert_eq!(python.remove, vec!["identifier", "attribute"]);
+
+        let err = Config::parse("[references.cobol]\nadd = []\n").expect_err("language");
+        assert_eq!(err, "line 1: unknown table [references.cobol]");
+        let err = Config::parse("[references.go]\nadd = \"x\"\n").expect_err("array");
+        assert_eq!(err, "line 2: `add` must be an array of strings");
+    }
+
+    #[test]
+    fn parses_the_reference_dedup_policy() {
+        let config =
+            Config::parse("[references]\ndedup = \"line\"\n\n[references.rust]\nadd = []\n")
+                .expect("valid config");
+        assert_eq!(config.reference_dedup, ReferenceDedup::Line);
+        assert_eq!(Config::default().reference_dedup, ReferenceDedup::None);
+
+        let err = Config::parse("[references]\ndedup = \"file\"\n").expect_err("policy");
+        assert_eq!(
+            err,
+            "line 2: `dedup` must be \"none\", \"location\" or \"line\""
+        );
+        let err = Config::parse("[references]\nkinds = 1\n").expect_err("key");
+        assert_eq!(err, "line 2: unknown key `references.kinds`");
+    }
+
+    #[test]
+    fn parses_common_name_tables() {
+        let config = Config::parse("[common_names.rust]\nadd = [\"log\"]\nremove = [\"new\"]\n")
+            .expect("valid config");
+        let rust = &config.common_names[&Ecosystem::Rust];
+        assert_eq!(rust.add, vec!["log"]);
+        assert_eq!(rust.remove, vec!["new"]);
+
+        let config = Config::parse(
+            "[common_names.javascript]\nremove = [\"get\"]\n[common_names.tsx]\nadd = [\"h\"]\n",
+        )
+        .expect("valid config");
+        let javascript = &config.common_names[&Ecosystem::JavaScript];
+        assert_eq!(javascript.remove, vec!["get"]);
+        assert_eq!(javascript.add, vec!["h"]);
+
+        let err = Config::parse("[common_names.rust]\nkeep = []\n").expect_err("key");
+        assert_eq!(err, "line 2: unknown key `common_names.rust.keep`");
+    }
+
+    #[test]
+    fn parses_namespaces_in_order() {
+        let config = Config::parse(
+            "[namespaces]\nfirmware = [\"firmware/scripts/*.js\"]\ntools = [\"tools/**\", \"scripts/**\"]\n",
+        )
+        .expect("valid config");
+        let namespaces: Vec<_> = config
+            .namespaces
+            .iter()
+            .map(|namespace| (namespace.name.as_str(), namespace.paths.len()))
+            .collect();
+        assert_eq!(namespaces, vec![("firmware", 1), ("tools", 2)]);
+    }
+
+    #[test]
+    fn nested_configs_inherit_weights_and_scope_their_globs() {
+        let root =
+            std::env::temp_dir().join(format!("cruxlines-config-nested-{}", std::process::id()));
+        let api = root.join("services/api");
+        std::fs::create_dir_all(api.join("v2")).expect("create dirs");
+        std::fs::write(
+            root.join(".cruxlines.toml"),
+            "[weights]\nfrecency = 0\n\n[namespaces]\ntools = [\"tools/**\"]\n",
+        )
+        .expect("write root");
+        std::fs::write(
+            api.join(".cruxlines.toml"),
+            "[weights]\nname_ambiguity = 0.5\n\n[files]\nexclude = [\"gen/\", \"/fixtures/*.py\"]\n\n[namespaces]\napi = [\"*.py\"]\ntools = [\"scripts/\"]\n",
+        )
+        .expect("write api");
+        std::fs::write(api.join("v2/.cruxlines.toml"), "[weights]\nfile_rank = 2\n")
+            .expect("write v2");
+
+        let config = Config::discover(&root).expect("valid configs");
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(config.weights.name_ambiguity, 1.0);
+        let scoped: Vec<_> = config
+            .scoped_weights
+            .iter()
+            .map(|scoped| {
+                (
+                    scoped.dir.strip_prefix(&root).expect("under root"),
+                    scoped.weights.frecency,
+                    scoped.weights.name_ambiguity,
+                    scoped.weights.file_rank,
+                )
+            })
+            .collect();
+        assert_eq!(
+            scoped,
+            vec![
+                (Path::new("services/api"), 0.0, 0.5, 1.0),
+                (Path::new("services/api/v2"), 0.0, 0.5, 2.0),
+            ]
+        );
+        assert_eq!(
+            config.exclude,
+            vec!["services/api/**/gen/", "services/api/fixtures/*.py"]
+        );
+        let namespaces: Vec<_> = config
+            .namespaces
+            .iter()
+            .map(|namespace| (namespace.name.as_str(), namespace.paths.clone()))
+            .collect();
+        assert_eq!(
+            namespaces,
+            vec![
+                ("api", vec!["services/api/**/*.py".to_string()]),
+                (
+                    "tools",
+                    vec![
+                        "tools/**".to_string(),
+                        "services/api/**/scripts/".to_string()
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_bucket_thresholds() {
+        let config = Config::parse("[buckets]\nlabels = [0.8, 0.4, 0.1]\n").expect("valid config");
+        assert_eq!(config.buckets.labels, [0.8, 0.4, 0.1]);
+        assert_eq!(config.buckets.stars, [0.5, 0.2, 0.05, 0.01]);
+
+        let err = Config::parse("[buckets]\nstars = [0.5, 0.2]\n").expect_err("length");
+        assert_eq!(err, "line 2: `stars` must be an array of 4 numbers");
+    }
+}
@@ -0,0 +1,153 @@
+//! Line execution counts from a test run, read from an lcov tracefile or a
+//! coverage.py (Cobertura) XML report, so rows can show how hot each
+//! definition is at runtime next to how central it is.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::error::CruxlinesError;
+
+/// Execution counts per file and line. Paths are resolved against the repo
+/// root the report was loaded for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    files: HashMap<PathBuf, BTreeMap<usize, u64>>,
+}
+
+impl Coverage {
+    pub fn from_file(path: &Path, repo_root: &Path) -> Result<Self, CruxlinesError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|source| CruxlinesError::ReadFile {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Self::parse(&contents, repo_root).map_err(|message| CruxlinesError::Coverage {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Parses an lcov tracefile, or Cobertura XML when the text starts
+    /// with `<`.
+    pub fn parse(text: &str, repo_root: &Path) -> Result<Self, String> {
+        if text.trim_start().starts_with('<') {
+            Self::parse_cobertura(text, repo_root)
+        } else {
+            Self::parse_lcov(text, repo_root)
+        }
+    }
+
+    /// Number of lines with an execution count.
+    pub fn len(&self) -> usize {
+        self.files.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How often `line` of `path` ran, or `None` when the report has no
+    /// count for it.
+    pub fn line_hits(&self, path: &Path, line: usize) -> Option<u64> {
+        self.files.get(path)?.get(&line).copied()
+    }
+
+    /// The highest count among lines `start..end` of `path`, to the end of
+    /// the file when `end` is `None`.
+    pub(crate) fn span_hits(&self, path: &Path, start: usize, end: Option<usize>) -> Option<u64> {
+        let lines = self.files.get(path)?;
+        let end = end.unwrap_or(usize::MAX).max(start + 1);
+        lines.range(start..end).map(|(_, hits)| *hits).max()
+    }
+
+    fn parse_lcov(text: &str, repo_root: &Path) -> Result<Self, String> {
+        let mut coverage = Self::default();
+        let mut current: Option<PathBuf> = None;
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if let Some(path) = line.strip_prefix("SF:") {
+                current = Some(resolve(repo_root, &[], path));
+            } else if let Some(data) = line.strip_prefix("DA:") {
+                let file = current
+                    .as_ref()
+                    .ok_or_else(|| format!("line {}: DA before SF", index + 1))?;
+                let mut fields = data.split(',');
+                let (Some(Ok(number)), Some(Ok(hits))) = (
+                    fields.next().map(str::parse::<usize>),
+                    fields.next().map(str::parse::<u64>),
+                ) else {
+                    return Err(format!("line {}: malformed DA record", index + 1));
+                };
+                coverage.record(file.clone(), number, hits);
+            } else if line == "end_of_record" {
+                current = None;
+            }
+        }
+        Ok(coverage)
+    }
+
+    fn parse_cobertura(text: &str, repo_root: &Path) -> Result<Self, String> {
+        static TOKENS: OnceLock<Regex> = OnceLock::new();
+        let tokens = TOKENS.get_or_init(|| {
+            Regex::new(
+                r#"<source>([^<]*)</source>|<class\b[^>]*?\bfilename="([^"]*)"|<line\b[^>]*?\bnumber="(\d+)"[^>]*?\bhits="(\d+)""#,
+            )
+            .expect("valid coverage regex")
+        });
+        if !text.contains("<coverage") {
+            return Err("expected a Cobertura <coverage> report".to_string());
+        }
+        let mut coverage = Self::default();
+        let mut sources: Vec<PathBuf> = Vec::new();
+        let mut current: Option<PathBuf> = None;
+        for captures in tokens.captures_iter(text) {
+            if let Some(source) = captures.get(1) {
+                sources.push(repo_root.join(source.as_str().trim()));
+            } else if let Some(filename) = captures.get(2) {
+                current = Some(resolve(repo_root, &sources, &unescape(filename.as_str())));
+            } else if let (Some(file), Some(number), Some(hits)) =
+                (&current, captures.get(3), captures.get(4))
+                && let (Ok(number), Ok(hits)) = (number.as_str().parse(), hits.as_str().parse())
+            {
+                coverage.record(file.clone(), number, hits);
+            }
+        }
+        Ok(coverage)
+    }
+
+    /// Records `hits` for a line, keeping the highest count when a report
+    /// lists the line twice (e.g. under a class and one of its methods).
+    fn record(&mut self, path: PathBuf, line: usize, hits: u64) {
+        let count = self.files.entry(path).or_default().entry(line).or_default();
+        *count = (*count).max(hits);
+    }
+}
+
+/// Resolves a report path: absolute paths are kept, relative ones are
+/// looked up under the report's source directories, then the repo root.
+fn resolve(repo_root: &Path, sources: &[PathBuf], path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let resolved = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        sources
+            .iter()
+            .map(|source| source.join(path))
+            .find(|candidate| candidate.is_file())
+            .unwrap_or_else(|| repo_root.join(path))
+    };
+    // Drop `.` components so paths match the ones found by walking.
+    resolved.components().collect()
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,336 @@
+//! `cruxlines daemon`: keeps the latest analysis in memory and answers
+//! JSON-RPC 2.0 requests on a unix socket, one JSON object per line.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use cruxlines::impact::impacted_definitions;
+use cruxlines::schema::SCHEMA_VERSION;
+use cruxlines::{
+    CruxlinesError, Ecosystem, Explanation, FileSystemSource, Options, OutputRow, ParseSession,
+    ScannedSource, gather_paths,
+};
+use serde_json::{Value, json};
+
+use crate::output::display_path;
+
+/// How often the tree is checked for changed files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Modification time and size of every scanned file.
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+struct Daemon {
+    repo_root: PathBuf,
+    ecosystems: HashSet<Ecosystem>,
+    options: Options,
+    latest: RwLock<Arc<Latest>>,
+}
+
+/// The latest analysis, with the scan it was ranked from for `explain`.
+#[derive(Default)]
+struct Latest {
+    rows: Vec<OutputRow>,
+    scanned: Option<ScannedSource>,
+}
+
+/// Analyzes the repo, then serves requests on `socket` until a `shutdown`
+/// request arrives. The tree is polled every [`POLL_INTERVAL`] and
+/// re-analyzed when a file changes; unchanged files come from the file
+/// cache, so only edited files are parsed again, and a file edited more
+/// than once is reparsed incrementally from its previous tree. Trees of
+/// deleted files are dropped.
+pub(crate) fn run(
+    repo_root: &Path,
+    socket: &Path,
+    ecosystems: HashSet<Ecosystem>,
+    options: Options,
+) -> Result<(), String> {
+    let options = Options {
+        top: None,
+        min_rank: None,
+        sample: None,
+        low_memory: false,
+        parse_session: Some(ParseSession::new()),
+        ..options
+    };
+    let mut daemon = Daemon {
+        repo_root: repo_root.to_path_buf(),
+        ecosystems,
+        options,
+        latest: RwLock::default(),
+    };
+    let mut snapshot = daemon.snapshot().map_err(|err| err.to_string())?;
+    let latest = daemon.analyze().map_err(|err| err.to_string())?;
+    *daemon.latest.get_mut().expect("analysis lock poisoned") = Arc::new(latest);
+    let daemon = Arc::new(daemon);
+
+    if UnixStream::connect(socket).is_ok() {
+        return Err(format!("{} is already in use", socket.display()));
+    }
+    let _ = std::fs::remove_file(socket);
+    let listener = UnixListener::bind(socket)
+        .map_err(|err| format!("failed to bind {}: {err}", socket.display()))?;
+    eprintln!("cruxlines: listening on {}", socket.display());
+
+    let watcher = Arc::clone(&daemon);
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let Ok(current) = watcher.snapshot() else {
+                continue;
+            };
+            if current == snapshot {
+                continue;
+            }
+            if let Some(session) = &watcher.options.parse_session {
+                for deleted in snapshot.keys().filter(|path| !current.contains_key(*path)) {
+                    session.forget(deleted);
+                }
+            }
+            snapshot = current;
+            match watcher.analyze() {
+                Ok(latest) => {
+                    *watcher.latest.write().expect("analysis lock poisoned") = Arc::new(latest);
+                }
+                Err(err) => eprintln!("cruxlines: {err}"),
+            }
+        }
+    });
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let daemon = Arc::clone(&daemon);
+        let socket = socket.to_path_buf();
+        std::thread::spawn(move || daemon.serve(stream, &socket));
+    }
+    Ok(())
+}
+
+impl Daemon {
+    fn snapshot(&self) -> Result<Snapshot, CruxlinesError> {
+        let paths = gather_paths(
+            &self.repo_root,
+            std::slice::from_ref(&self.repo_root),
+            &self.ecosystems,
+            &self.options,
+        )?;
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let metadata = std::fs::metadata(&path).ok();
+                let stamp = (
+                    metadata.as_ref().and_then(|meta| meta.modified().ok()),
+                    metadata.map_or(0, |meta| meta.len()),
+                );
+                (path, stamp)
+            })
+            .collect())
+    }
+
+    fn analyze(&self) -> Result<Latest, CruxlinesError> {
+        let paths = gather_paths(
+            &self.repo_root,
+            std::slice::from_ref(&self.repo_root),
+            &self.ecosystems,
+            &self.options,
+        )?;
+        let (analysis, scanned) = ScannedSource::analyze(
+            &FileSystemSource::new(paths),
+            Some(&self.repo_root),
+            &self.options,
+        )?;
+        for diagnostic in &analysis.diagnostics {
+            eprintln!("cruxlines: skipped: {diagnostic}");
+        }
+        Ok(Latest {
+            rows: analysis.rows,
+            scanned: Some(scanned),
+        })
+    }
+
+    fn latest(&self) -> Arc<Latest> {
+        Arc::clone(&self.latest.read().expect("analysis lock poisoned"))
+    }
+
+    /// Answers each request line on `stream` until the client disconnects.
+    fn serve(&self, stream: UnixStream, socket: &Path) {
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (response, shutdown) = match serde_json::from_str::<Value>(&line) {
+                Ok(request) => {
+                    let id = request.get("id").cloned().unwrap_or(Value::Null);
+                    let method = request.get("method").and_then(Value::as_str);
+                    let params = request.get("params").cloned().unwrap_or(Value::Null);
+                    let result = match method {
+                        Some(method) => self.call(method, &params),
+                        None => Err((INVALID_PARAMS, "missing method".to_string())),
+                    };
+                    (response(id, result), method == Some("shutdown"))
+                }
+                Err(err) => (
+                    response(Value::Null, Err((PARSE_ERROR, err.to_string()))),
+                    false,
+                ),
+            };
+            if writeln!(writer, "{response}").is_err() {
+                return;
+            }
+            if shutdown {
+                let _ = std::fs::remove_file(socket);
+                std::process::exit(0);
+            }
+        }
+    }
+
+    fn call(&self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "top" => {
+                let n = match params.get("n") {
+                    None | Some(Value::Null) => None,
+                    Some(n) => Some(n.as_u64().ok_or_else(|| invalid("n must be a number"))?),
+                };
+                let latest = self.latest();
+                let n = n.map_or(latest.rows.len(), |n| n as usize);
+                let rows: Vec<Value> = latest
+                    .rows
+                    .iter()
+                    .take(n)
+                    .map(|row| self.row_json(row))
+                    .collect();
+                Ok(json!({ "schema_version": SCHEMA_VERSION, "rows": rows }))
+            }
+            "explain" => {
+                let path = self.param_path(params)?;
+                let line = params
+                    .get("line")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| invalid("expected a line number"))?;
+                let latest = self.latest();
+                let scanned = latest
+                    .scanned
+                    .as_ref()
+                    .ok_or_else(|| (INTERNAL_ERROR, "no analysis yet".to_string()))?;
+                let explanations: Vec<Value> = scanned
+                    .explain(&path, line as usize)
+                    .iter()
+                    .map(|explanation| self.explanation_json(explanation))
+                    .collect();
+                Ok(json!({ "schema_version": SCHEMA_VERSION, "explanations": explanations }))
+            }
+            "impact" => {
+                let paths = params
+                    .get("paths")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| invalid("expected a list of paths"))?
+                    .iter()
+                    .map(|path| {
+                        path.as_str()
+                            .map(|path| self.repo_root.join(path))
+                            .ok_or_else(|| invalid("paths must be strings"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let latest = self.latest();
+                let impacted: Vec<Value> = impacted_definitions(&latest.rows, &paths)
+                    .iter()
+                    .map(|impact| {
+                        json!({
+                            "path": display_path(impact.definition.path_str(), &self.repo_root),
+                            "line": impact.definition.line,
+                            "column": impact.definition.column,
+                            "name": impact.definition.name_str(),
+                            "rank": impact.rank,
+                            "depth": impact.depth,
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "schema_version": SCHEMA_VERSION, "impacted": impacted }))
+            }
+            "shutdown" => Ok(Value::Null),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {method}"))),
+        }
+    }
+
+    /// The `path` param, relative to the repo root unless absolute.
+    fn param_path(&self, params: &Value) -> Result<PathBuf, (i64, String)> {
+        params
+            .get("path")
+            .and_then(Value::as_str)
+            .map(|path| self.repo_root.join(path))
+            .ok_or_else(|| invalid("expected a path"))
+    }
+
+    fn row_json(&self, row: &OutputRow) -> Value {
+        json!({
+            "path": display_path(row.definition.path_str(), &self.repo_root),
+            "line": row.definition.line,
+            "column": row.definition.column,
+            "name": row.definition.name_str(),
+            "kind": row.kind.as_str(),
+            "rank": row.rank,
+            "local_score": row.local_score,
+            "file_rank": row.file_rank,
+            "reference_count": row.reference_count,
+            "execution_count": row.execution_count,
+            "owners": row.owners,
+            "is_entry_point": row.is_entry_point,
+        })
+    }
+
+    fn explanation_json(&self, explanation: &Explanation) -> Value {
+        json!({
+            "row": self.row_json(&explanation.row),
+            "references": explanation.references.iter().map(|reference| json!({
+                "path": display_path(reference.location.path_str(), &self.repo_root),
+                "line": reference.location.line,
+                "column": reference.location.column,
+                "file_rank": reference.file_rank,
+                "frecency": reference.frecency,
+                "test_multiplier": reference.test_multiplier,
+                "same_file_multiplier": reference.same_file_multiplier,
+                "external_multiplier": reference.external_multiplier,
+                "file_share": reference.file_share,
+                "weight": reference.weight(),
+            })).collect::<Vec<_>>(),
+            "weighted_references": explanation.weighted_references,
+            "name_count": explanation.name_count,
+            "entry_boost": explanation.entry_boost,
+            "author_boost": explanation.author_boost,
+            "coverage_boost": explanation.coverage_boost,
+        })
+    }
+}
+
+fn invalid(message: &str) -> (i64, String) {
+    (INVALID_PARAMS, message.to_string())
+}
+
+fn response(id: Value, result: Result<Value, (i64, String)>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
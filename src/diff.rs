@@ -0,0 +1,490 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use lasso::Spur;
+
+use crate::analysis::OutputRow;
+use crate::error::CruxlinesError;
+use crate::find_references::{Location, link_name};
+use crate::intern::{intern, resolve};
+use crate::source::{git, resolve_revision};
+
+/// How the rank of one definition changed between two analyses.
+#[derive(Debug, Clone)]
+pub struct RankChange {
+    /// Definition location in the newer analysis, or in the older one when
+    /// the definition was removed.
+    pub definition: Location,
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+    /// The definition in the older analysis, when it had another path or
+    /// name there.
+    pub renamed_from: Option<Location>,
+}
+
+impl RankChange {
+    /// Rank difference, treating a missing side as rank zero.
+    pub fn delta(&self) -> f64 {
+        self.after.unwrap_or(0.0) - self.before.unwrap_or(0.0)
+    }
+}
+
+/// What was renamed between two revisions, so a renamed definition pairs
+/// with its old self instead of showing up as removed and added.
+#[derive(Debug, Clone, Default)]
+pub struct Renames {
+    /// New path of each file renamed between the revisions, by old path.
+    pub files: HashMap<Spur, Spur>,
+    /// Hash of the body of definitions that don't pair by path and name,
+    /// with the definition's own name left out.
+    pub bodies: HashMap<Location, u64>,
+}
+
+impl Renames {
+    /// Finds renamed files with git's rename detection, then hashes the
+    /// bodies of the definitions that still don't pair, read from each
+    /// revision. A body runs from its definition to the next one in the
+    /// file.
+    pub fn detect(
+        repo_root: &Path,
+        before_revision: &str,
+        after_revision: &str,
+        before: &[OutputRow],
+        after: &[OutputRow],
+    ) -> Result<Self, CruxlinesError> {
+        let before_revision = &resolve_revision(repo_root, before_revision)?;
+        let after_revision = &resolve_revision(repo_root, after_revision)?;
+        let output = git(
+            repo_root,
+            after_revision,
+            &[
+                "diff",
+                "-M",
+                "--name-status",
+                "--no-relative",
+                "-z",
+                before_revision,
+                after_revision,
+            ],
+        )?;
+        let output = String::from_utf8_lossy(&output);
+        let mut fields = output.split('\0').filter(|field| !field.is_empty());
+        let mut files = HashMap::new();
+        while let Some(status) = fields.next() {
+            let paths = if status.starts_with(['R', 'C']) { 2 } else { 1 };
+            let paths: Vec<&str> = fields.by_ref().take(paths).collect();
+            if status.starts_with('R')
+                && let [old, new] = paths[..]
+            {
+                files.insert(
+                    intern(&repo_root.join(old).to_string_lossy()),
+                    intern(&repo_root.join(new).to_string_lossy()),
+                );
+            }
+        }
+
+        let mut renames = Self {
+            files,
+            bodies: HashMap::new(),
+        };
+        let (_, before_left, after_left) =
+            pair_by_key(&ranks_by_key(before), &ranks_by_key(after), &renames.files);
+        for (rows, left, revision) in [
+            (before, before_left, before_revision),
+            (after, after_left, after_revision),
+        ] {
+            renames
+                .bodies
+                .extend(body_hashes(repo_root, revision, rows, &left));
+        }
+        Ok(renames)
+    }
+}
+
+/// Pairs definitions by file path and name (line numbers shift between
+/// revisions) and returns those whose rank changed, largest change first.
+/// When a file defines a name more than once, the highest-ranked row wins.
+pub fn rank_changes(before: &[OutputRow], after: &[OutputRow]) -> Vec<RankChange> {
+    rank_changes_with_renames(before, after, &Renames::default())
+}
+
+/// Like [`rank_changes`], but also pairs definitions across `renames`: by
+/// name through a renamed file, then by body within a file, when exactly
+/// one definition on each side has that body. Renamed definitions are
+/// listed even when their rank stayed the same.
+pub fn rank_changes_with_renames(
+    before: &[OutputRow],
+    after: &[OutputRow],
+    renames: &Renames,
+) -> Vec<RankChange> {
+    let before_ranks = ranks_by_key(before);
+    let after_ranks = ranks_by_key(after);
+    let (mut pairs, before_left, after_left) =
+        pair_by_key(&before_ranks, &after_ranks, &renames.files);
+    pairs.extend(pair_by_body(
+        &before_ranks,
+        &after_ranks,
+        &before_left,
+        &after_left,
+        renames,
+    ));
+
+    let mut changes = Vec::new();
+    let mut paired_before = HashSet::new();
+    let mut paired_after = HashSet::new();
+    for (before_key, after_key) in pairs {
+        paired_before.insert(before_key);
+        paired_after.insert(after_key);
+        let (old, before_rank) = before_ranks[&before_key];
+        let (definition, after_rank) = after_ranks[&after_key];
+        let renamed = before_key != after_key;
+        if renamed || before_rank != after_rank {
+            changes.push(RankChange {
+                definition,
+                before: Some(before_rank),
+                after: Some(after_rank),
+                renamed_from: renamed.then_some(old),
+            });
+        }
+    }
+    for (key, (definition, rank)) in &after_ranks {
+        if !paired_after.contains(key) {
+            changes.push(RankChange {
+                definition: *definition,
+                before: None,
+                after: Some(*rank),
+                renamed_from: None,
+            });
+        }
+    }
+    for (key, (definition, rank)) in &before_ranks {
+        if !paired_before.contains(key) {
+            changes.push(RankChange {
+                definition: *definition,
+                before: Some(*rank),
+                after: None,
+                renamed_from: None,
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| {
+        b.delta()
+            .abs()
+            .partial_cmp(&a.delta().abs())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| {
+                let key_a = (a.definition.path_str(), a.definition.name_str());
+                let key_b = (b.definition.path_str(), b.definition.name_str());
+                key_a.cmp(&key_b)
+            })
+    });
+    changes
+}
+
+type Key = (Spur, Spur);
+type Ranks = HashMap<Key, (Location, f64)>;
+
+fn ranks_by_key(rows: &[OutputRow]) -> Ranks {
+    let mut ranks: Ranks = HashMap::new();
+    for row in rows {
+        let key = (row.definition.path, row.definition.name);
+        let entry = ranks.entry(key).or_insert((row.definition, row.rank));
+        if row.rank > entry.1 {
+            *entry = (row.definition, row.rank);
+        }
+    }
+    ranks
+}
+
+/// Pairs keys that are equal, or equal once the old path is renamed, and
+/// returns the pairs and the keys left on each side.
+fn pair_by_key(
+    before: &Ranks,
+    after: &Ranks,
+    files: &HashMap<Spur, Spur>,
+) -> (Vec<(Key, Key)>, Vec<Key>, Vec<Key>) {
+    let mut pairs = Vec::new();
+    let mut paired_after = HashSet::new();
+    let mut before_left = Vec::new();
+    for key in before.keys() {
+        if after.contains_key(key) {
+            pairs.push((*key, *key));
+            paired_after.insert(*key);
+        } else {
+            before_left.push(*key);
+        }
+    }
+    // A file renamed onto a path that also existed before pairs with what
+    // is left of it.
+    before_left.retain(|&(path, name)| {
+        let Some(renamed) = files.get(&path).map(|new_path| (*new_path, name)) else {
+            return true;
+        };
+        if !after.contains_key(&renamed) || !paired_after.insert(renamed) {
+            return true;
+        }
+        pairs.push(((path, name), renamed));
+        false
+    });
+    let after_left = after
+        .keys()
+        .filter(|key| !paired_after.contains(*key))
+        .copied()
+        .collect();
+    (pairs, before_left, after_left)
+}
+
+/// Pairs leftover definitions of the same file whose bodies hash alike,
+/// when the hash is unique to one definition on each side.
+fn pair_by_body(
+    before: &Ranks,
+    after: &Ranks,
+    before_left: &[Key],
+    after_left: &[Key],
+    renames: &Renames,
+) -> Vec<(Key, Key)> {
+    let by_body = |ranks: &Ranks, keys: &[Key], files: Option<&HashMap<Spur, Spur>>| {
+        let mut by_body: HashMap<(Spur, u64), Vec<Key>> = HashMap::new();
+        for key in keys {
+            let (definition, _) = ranks[key];
+            let Some(hash) = renames.bodies.get(&definition) else {
+                continue;
+            };
+            let path = files
+                .and_then(|files| files.get(&key.0))
+                .copied()
+                .unwrap_or(key.0);
+            by_body.entry((path, *hash)).or_default().push(*key);
+        }
+        by_body
+    };
+    let before_bodies = by_body(before, before_left, Some(&renames.files));
+    let after_bodies = by_body(after, after_left, None);
+    before_bodies
+        .iter()
+        .filter_map(
+            |(body, before_keys)| match (&before_keys[..], after_bodies.get(body)) {
+                ([before_key], Some(after_keys)) if after_keys.len() == 1 => {
+                    Some((*before_key, after_keys[0]))
+                }
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+/// Body hashes of the definitions under `keys`, read from `revision`.
+fn body_hashes(
+    repo_root: &Path,
+    revision: &str,
+    rows: &[OutputRow],
+    keys: &[Key],
+) -> HashMap<Location, u64> {
+    let keys: HashSet<&Key> = keys.iter().collect();
+    let mut files: HashMap<Spur, (Vec<usize>, Vec<Location>)> = HashMap::new();
+    for row in rows {
+        let (lines, _) = files.entry(row.definition.path).or_default();
+        lines.push(row.definition.line);
+    }
+    for row in rows {
+        if keys.contains(&(row.definition.path, row.definition.name))
+            && let Some((_, wanted)) = files.get_mut(&row.definition.path)
+        {
+            wanted.push(row.definition);
+        }
+    }
+
+    let mut hashes = HashMap::new();
+    for (path, (mut lines, wanted)) in files {
+        if wanted.is_empty() {
+            continue;
+        }
+        let Some(text) = file_at(repo_root, revision, Path::new(resolve(path))) else {
+            continue;
+        };
+        let text: Vec<&str> = text.lines().collect();
+        lines.sort_unstable();
+        for definition in wanted {
+            let start = definition.line.saturating_sub(1);
+            let end = lines
+                .iter()
+                .find(|line| **line > definition.line)
+                .map_or(text.len(), |line| line - 1)
+                .min(text.len());
+            if start >= end {
+                continue;
+            }
+            // Qualified names such as Java's `User.save` appear in the
+            // source as their last segment.
+            let name = resolve(link_name(definition.name));
+            let mut hasher = DefaultHasher::new();
+            for line in &text[start..end] {
+                let line = line.trim();
+                if !line.is_empty() {
+                    without_identifier(line, name).hash(&mut hasher);
+                }
+            }
+            hashes.insert(definition, hasher.finish());
+        }
+    }
+    hashes
+}
+
+/// `line` without the occurrences of the identifier `name`, so `add` is
+/// dropped from `add(x)` but not from `padded`.
+fn without_identifier(line: &str, name: &str) -> String {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    if name.is_empty() {
+        return line.to_string();
+    }
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(index) = rest.find(name) {
+        let after = &rest[index + name.len()..];
+        let bounded = !rest[..index].ends_with(is_identifier) && !after.starts_with(is_identifier);
+        let end = if bounded { index } else { index + name.len() };
+        out.push_str(&rest[..end]);
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn file_at(repo_root: &Path, revision: &str, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(repo_root).ok()?;
+    let object = format!("{revision}:{}", relative.to_string_lossy());
+    let bytes = git(repo_root, revision, &["show", &object]).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Renames, rank_changes, rank_changes_with_renames, without_identifier};
+    use crate::analysis::{OutputRow, ScoreBreakdown};
+    use crate::find_references::Location;
+    use crate::intern::intern;
+    use crate::languages::{Ecosystem, SymbolKind};
+
+    fn row(path: &str, line: usize, name: &str, rank: f64) -> OutputRow {
+        OutputRow {
+            rank,
+            local_score: rank,
+            file_rank: 1.0,
+            symbol_rank: None,
+            definition: Location {
+                path: intern(path),
+                line,
+                column: 1,
+                name: intern(name),
+            },
+            kind: SymbolKind::Function,
+            ecosystem: Ecosystem::Python,
+            definition_line: String::new(),
+            references: Vec::new(),
+            reference_count: 0,
+            breakdown: ScoreBreakdown::default(),
+            owners: Vec::new(),
+            is_entry_point: false,
+            execution_count: None,
+        }
+    }
+
+    #[test]
+    fn pairs_rows_by_path_and_name() {
+        let before = vec![
+            row("a.py", 1, "moved", 0.2),
+            row("a.py", 5, "steady", 0.1),
+            row("a.py", 9, "removed", 0.05),
+        ];
+        let after = vec![
+            row("a.py", 3, "moved", 0.5),
+            row("a.py", 7, "steady", 0.1),
+            row("b.py", 1, "added", 0.01),
+        ];
+
+        let changes = rank_changes(&before, &after);
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|change| (change.definition.name_str(), change.before, change.after))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("moved", Some(0.2), Some(0.5)),
+                ("removed", Some(0.05), None),
+                ("added", None, Some(0.01)),
+            ]
+        );
+        assert_eq!(changes[0].definition.line, 3);
+    }
+
+    #[test]
+    fn strips_only_whole_identifiers() {
+        assert_eq!(without_identifier("def add(x):", "add"), "def (x):");
+        assert_eq!(
+            without_identifier("padded = add + adder", "add"),
+            "padded =  + adder"
+        );
+        assert_eq!(without_identifier("return x", "add"), "return x");
+    }
+
+    #[test]
+    fn pairs_renamed_files_and_definitions_with_the_same_body() {
+        let before = vec![
+            row("old.py", 1, "kept", 0.3),
+            row("a.py", 1, "parse_old", 0.2),
+            row("a.py", 9, "gone", 0.1),
+            row("a.py", 12, "twin_a", 0.1),
+            row("a.py", 15, "twin_b", 0.1),
+        ];
+        let after = vec![
+            row("new.py", 1, "kept", 0.3),
+            row("a.py", 1, "parse", 0.4),
+            row("a.py", 12, "twin_c", 0.1),
+            row("a.py", 20, "fresh", 0.1),
+        ];
+        let mut renames = Renames::default();
+        renames.files.insert(intern("old.py"), intern("new.py"));
+        // Bodies hash alike for the renamed function, and for two removed
+        // twins, which are then too ambiguous to pair.
+        for (row, hash) in [
+            (&before[1], 7),
+            (&after[1], 7),
+            (&before[3], 8),
+            (&before[4], 8),
+            (&after[2], 8),
+            (&after[3], 9),
+        ] {
+            renames.bodies.insert(row.definition, hash);
+        }
+
+        let changes = rank_changes_with_renames(&before, &after, &renames);
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|change| {
+                (
+                    change.definition.name_str(),
+                    change
+                        .renamed_from
+                        .map(|old| (old.path_str(), old.name_str())),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("parse", Some(("a.py", "parse_old"))),
+                ("fresh", None),
+                ("gone", None),
+                ("twin_a", None),
+                ("twin_b", None),
+                ("twin_c", None),
+                ("kept", Some(("old.py", "kept"))),
+            ]
+        );
+        assert_eq!(rank_changes(&before, &after).len(), 9);
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use crate::error::CruxlinesError;
+use crate::options::Encoding;
+
+/// Characters of Windows-1252 bytes 0x80 to 0x9F, where it differs from
+/// Latin-1. Bytes Windows-1252 leaves undefined keep their Latin-1 control
+/// character.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Decodes the contents of `path` read as `bytes`.
+pub(crate) fn decode(
+    path: &Path,
+    bytes: Vec<u8>,
+    encoding: Encoding,
+) -> Result<String, CruxlinesError> {
+    match encoding {
+        Encoding::Strict => String::from_utf8(bytes).map_err(|_| CruxlinesError::InvalidEncoding {
+            path: path.to_path_buf(),
+        }),
+        Encoding::Lossy => Ok(lossy(bytes)),
+        Encoding::Auto => Ok(detect(bytes)),
+    }
+}
+
+fn lossy(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+}
+
+fn detect(bytes: Vec<u8>) -> String {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return lossy(rest.to_vec());
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return utf16(rest, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return utf16(rest, u16::from_be_bytes);
+    }
+    String::from_utf8(bytes).unwrap_or_else(|err| windows_1252(err.as_bytes()))
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|char| char.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn windows_1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x80..=0x9F => WINDOWS_1252[usize::from(byte - 0x80)],
+            _ => char::from(byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use crate::error::CruxlinesError;
+    use crate::options::Encoding;
+    use std::path::Path;
+
+    #[test]
+    fn decodes_according_to_the_encoding_option() {
+        let path = Path::new("legacy.py");
+        let latin1 = b"# caf\xe9 \x80\ndef f():\n    pass\n".to_vec();
+
+        assert!(matches!(
+            decode(path, latin1.clone(), Encoding::Strict),
+            Err(CruxlinesError::InvalidEncoding { .. })
+        ));
+        assert_eq!(
+            decode(path, latin1.clone(), Encoding::Lossy).expect("lossy"),
+            "# caf\u{FFFD} \u{FFFD}\ndef f():\n    pass\n"
+        );
+        assert_eq!(
+            decode(path, latin1, Encoding::Auto).expect("auto"),
+            "# café €\ndef f():\n    pass\n"
+        );
+
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("def é():".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(
+            decode(path, utf16, Encoding::Auto).expect("utf-16"),
+            "def é():"
+        );
+        assert_eq!(
+            decode(path, b"\xEF\xBB\xBFx = 1".to_vec(), Encoding::Auto).expect("bom"),
+            "x = 1"
+        );
+        assert_eq!(
+            decode(path, "ok é".as_bytes().to_vec(), Encoding::Auto).expect("utf-8"),
+            "ok é"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum CruxlinesError {
+    /// The file could not be read.
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The file is not valid UTF-8.
+    InvalidEncoding { path: PathBuf },
+    /// tree-sitter could not produce a syntax tree for the file.
+    ParseFile { path: PathBuf },
+    /// A `git` invocation failed, e.g. because the revision does not exist.
+    Git { revision: String, message: String },
+    /// A config file could not be parsed.
+    Config { path: PathBuf, message: String },
+    /// An include or exclude glob is malformed.
+    Pattern { pattern: String, message: String },
+    /// A custom language could not be loaded or registered.
+    Language { name: String, message: String },
+    /// A frecency scores file is not a JSON object of path to weight.
+    Frecency { path: PathBuf, message: String },
+    /// A coverage report is neither an lcov tracefile nor Cobertura XML.
+    Coverage { path: PathBuf, message: String },
+}
+
+impl fmt::Display for CruxlinesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CruxlinesError::ReadFile { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            CruxlinesError::InvalidEncoding { path } => {
+                write!(f, "{} is not valid UTF-8", path.display())
+            }
+            CruxlinesError::ParseFile { path } => {
+                write!(f, "failed to parse {}", path.display())
+            }
+            CruxlinesError::Git { revision, message } => {
+                write!(f, "git failed for revision {revision}: {message}")
+            }
+            CruxlinesError::Config { path, message } => {
+                write!(f, "invalid config {}: {message}", path.display())
+            }
+            CruxlinesError::Pattern { pattern, message } => {
+                write!(f, "invalid pattern {pattern}: {message}")
+            }
+            CruxlinesError::Language { name, message } => {
+                write!(f, "failed to load language {name}: {message}")
+            }
+            CruxlinesError::Frecency { path, message } => {
+                write!(f, "invalid frecency file {}: {message}", path.display())
+            }
+            CruxlinesError::Coverage { path, message } => {
+                write!(f, "invalid coverage report {}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CruxlinesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CruxlinesError::ReadFile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl CruxlinesError {
+    /// The file a per-file error is about.
+    fn path(&self) -> Option<&Path> {
+        match self {
+            CruxlinesError::ReadFile { path, .. }
+            | CruxlinesError::InvalidEncoding { path }
+            | CruxlinesError::ParseFile { path } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// How much of a file made it into the results.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseStatus {
+    Ok,
+    /// The file has syntax errors. Definitions inside the broken parts were
+    /// skipped, the rest of the file was used.
+    Partial,
+    /// The file was skipped entirely, see [`Diagnostics::iter`].
+    Failed,
+}
+
+/// Number of scanned files with each [`ParseStatus`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseCounts {
+    pub ok: usize,
+    pub partial: usize,
+    pub failed: usize,
+}
+
+/// Per-file problems that were skipped over instead of aborting the run.
+/// Iterating yields the errors of files that were skipped; files that were
+/// only partially parsed are listed by [`Diagnostics::partial`].
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    errors: Vec<CruxlinesError>,
+    partial: Vec<PathBuf>,
+    scanned: usize,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, error: CruxlinesError) {
+        self.errors.push(error);
+    }
+
+    pub(crate) fn push_partial(&mut self, path: PathBuf) {
+        self.partial.push(path);
+    }
+
+    pub(crate) fn set_scanned(&mut self, scanned: usize) {
+        self.scanned = scanned;
+    }
+
+    /// Files parsed with syntax errors.
+    pub fn partial(&self) -> &[PathBuf] {
+        &self.partial
+    }
+
+    /// Status of a scanned file; files that were never scanned count as
+    /// `Ok`.
+    pub fn parse_status(&self, path: &Path) -> ParseStatus {
+        if self.errors.iter().any(|error| error.path() == Some(path)) {
+            ParseStatus::Failed
+        } else if self.partial.iter().any(|partial| partial == path) {
+            ParseStatus::Partial
+        } else {
+            ParseStatus::Ok
+        }
+    }
+
+    pub fn parse_counts(&self) -> ParseCounts {
+        let failed = self
+            .errors
+            .iter()
+            .filter(|error| error.path().is_some())
+            .count();
+        ParseCounts {
+            ok: self.scanned.saturating_sub(failed + self.partial.len()),
+            partial: self.partial.len(),
+            failed,
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, CruxlinesError> {
+        self.errors.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = CruxlinesError;
+    type IntoIter = std::vec::IntoIter<CruxlinesError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a CruxlinesError;
+    type IntoIter = std::slice::Iter<'a, CruxlinesError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+
+use crate::error::CruxlinesError;
+use crate::find_references::Location;
+use crate::languages::SymbolKind;
+use crate::options::{NamePattern, Options};
+
+/// Compiled form of the definition filters in [`Options`].
+pub(crate) struct DefinitionFilter {
+    kinds: Vec<SymbolKind>,
+    name: Option<NameMatcher>,
+    paths: Option<Vec<PathBuf>>,
+}
+
+enum NameMatcher {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl DefinitionFilter {
+    pub(crate) fn new(options: &Options) -> Result<Self, CruxlinesError> {
+        let name = match &options.name {
+            None => None,
+            Some(NamePattern::Glob(pattern)) => Some(NameMatcher::Glob(
+                Glob::new(pattern)
+                    .map_err(|err| pattern_error(pattern, err.kind().to_string()))?
+                    .compile_matcher(),
+            )),
+            Some(NamePattern::Regex(pattern)) => Some(NameMatcher::Regex(
+                Regex::new(pattern).map_err(|err| pattern_error(pattern, err.to_string()))?,
+            )),
+        };
+        Ok(Self {
+            kinds: options.kinds.clone(),
+            name,
+            paths: options.definition_paths.clone(),
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.kinds.is_empty() && self.name.is_none() && self.paths.is_none()
+    }
+
+    pub(crate) fn keeps(
+        &self,
+        definition: &Location,
+        definition_kinds: &HashMap<Location, SymbolKind>,
+    ) -> bool {
+        if !self.kinds.is_empty() {
+            let kind = definition_kinds
+                .get(definition)
+                .copied()
+                .unwrap_or_default();
+            if !self.kinds.contains(&kind) {
+                return false;
+            }
+        }
+        if let Some(paths) = &self.paths {
+            let path = Path::new(definition.path_str());
+            if !paths.iter().any(|kept| path.starts_with(kept)) {
+                return false;
+            }
+        }
+        match &self.name {
+            None => true,
+            Some(NameMatcher::Glob(glob)) => glob.is_match(definition.name_str()),
+            Some(NameMatcher::Regex(regex)) => regex.is_match(definition.name_str()),
+        }
+    }
+}
+
+fn pattern_error(pattern: &str, message: String) -> CruxlinesError {
+    CruxlinesError::Pattern {
+        pattern: pattern.to_string(),
+        message,
+    }
+}
@@ -1,14 +1,24 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use lasso::Spur;
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
-use tree_sitter::{Node, Parser, Tree};
+use tree_sitter::{InputEdit, Node, Parser, Point, Tree};
 
 use crate::cache::FileCache;
+use crate::encoding::decode;
+use crate::error::{CruxlinesError, Diagnostics};
 use crate::intern::{intern, resolve};
+use crate::languages::SymbolKind;
+use crate::namespaces::Namespaces;
+use crate::options::{Options, ReferenceDedup, ReferenceKinds};
+use crate::pipelines::{PipelineTargets, PipelineUsage, pipeline_usages};
+use crate::progress::Phase;
+use crate::read_ahead::read_ahead;
+use crate::source::SourceProvider;
 
 /// A source code location with interned path and name for efficiency.
 /// Use `path_str()` and `name_str()` to get string values.
@@ -71,6 +81,123 @@ impl From<SerializedLocation> for Location {
     }
 }
 
+/// A name bound by an import statement, e.g. `import { add as plus } from "./math"`
+/// binds `plus` in `path` to `add` exported by the module `specifier`.
+/// Re-exports (`export { add } from "./math"`) bind the same way, while
+/// `export * from "./math"` and `#include "math.h"` bind [`IMPORT_ALL`] to
+/// itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportBinding {
+    pub path: Spur,
+    pub local: Spur,
+    pub imported: Spur,
+    pub specifier: String,
+    /// Line and column of the one reference the binding applies to, such as
+    /// the `User` of a Rust `models::User`. `None` binds `local` throughout
+    /// the file.
+    pub site: Option<(usize, usize)>,
+}
+
+/// Name bound by `export * from "..."` and `#include "..."`, which bring in
+/// every name of the module.
+pub const IMPORT_ALL: &str = "*";
+
+/// Name bound by a Java or Kotlin `package` declaration, whose specifier is
+/// the file's package. `package` is a keyword, so no import can bind it.
+pub const PACKAGE: &str = "package";
+
+/// Re-export hops followed from an import before giving up (guards cycles).
+const MAX_REEXPORT_HOPS: usize = 8;
+
+/// Serializable version of ImportBinding for cache storage
+#[derive(Serialize, Deserialize)]
+pub struct SerializedImportBinding {
+    pub path: String,
+    pub local: String,
+    pub imported: String,
+    pub specifier: String,
+    pub site: Option<(usize, usize)>,
+}
+
+impl From<&ImportBinding> for SerializedImportBinding {
+    fn from(binding: &ImportBinding) -> Self {
+        Self {
+            path: resolve(binding.path).to_string(),
+            local: resolve(binding.local).to_string(),
+            imported: resolve(binding.imported).to_string(),
+            specifier: binding.specifier.clone(),
+            site: binding.site,
+        }
+    }
+}
+
+impl From<SerializedImportBinding> for ImportBinding {
+    fn from(binding: SerializedImportBinding) -> Self {
+        Self {
+            path: intern(&binding.path),
+            local: intern(&binding.local),
+            imported: intern(&binding.imported),
+            specifier: binding.specifier,
+            site: binding.site,
+        }
+    }
+}
+
+/// A Rust definition exposed to another ecosystem through FFI bindings, e.g.
+/// `#[pyfunction] fn add` is callable from Python as `add`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FfiExport {
+    pub definition: Location,
+    /// Name the definition has on the other side of the boundary.
+    pub exported: Spur,
+    pub ecosystem: crate::languages::Ecosystem,
+}
+
+/// Serializable version of FfiExport for cache storage
+#[derive(Serialize, Deserialize)]
+pub struct SerializedFfiExport {
+    pub definition: SerializedLocation,
+    pub exported: String,
+    pub ecosystem: crate::languages::Ecosystem,
+}
+
+impl From<&FfiExport> for SerializedFfiExport {
+    fn from(export: &FfiExport) -> Self {
+        Self {
+            definition: SerializedLocation::from(&export.definition),
+            exported: resolve(export.exported).to_string(),
+            ecosystem: export.ecosystem,
+        }
+    }
+}
+
+impl From<SerializedFfiExport> for FfiExport {
+    fn from(export: SerializedFfiExport) -> Self {
+        Self {
+            definition: Location::from(export.definition),
+            exported: intern(&export.exported),
+            ecosystem: export.ecosystem,
+        }
+    }
+}
+
+/// An import binding whose module specifier resolved to a scanned file.
+#[derive(Clone, Copy)]
+struct ResolvedImport {
+    target: Spur,
+    imported: Spur,
+}
+
+/// Resolved import bindings of an ecosystem.
+#[derive(Default)]
+struct ResolvedImports {
+    /// Keyed by (importing file, local name).
+    by_name: FxHashMap<(Spur, Spur), ResolvedImport>,
+    /// Bindings of a single reference, keyed by (file, line, column). `None`
+    /// marks a site whose module didn't resolve; it still ignores `by_name`.
+    by_site: FxHashMap<(Spur, usize, usize), Option<ResolvedImport>>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ReferenceEdge {
     pub definition: Location,
@@ -78,9 +205,58 @@ pub struct ReferenceEdge {
     pub ecosystem: crate::languages::Ecosystem,
 }
 
+/// What a scan found, for sanity-checking coverage on a new repo.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    /// Files read and parsed, per ecosystem.
+    pub files: HashMap<crate::languages::Ecosystem, usize>,
+    pub definitions: usize,
+    /// Links from a reference to a definition. A reference whose name has
+    /// several candidate definitions links to each of them.
+    pub reference_edges: usize,
+    /// References that matched no definition, such as calls into libraries.
+    pub unresolved_references: usize,
+    /// Files the walk left out for exceeding `ScanOptions::max_filesize`.
+    pub skipped_large_files: usize,
+    /// Files the walk left out once `ScanOptions::max_files` was reached.
+    pub skipped_excess_files: usize,
+    /// Files skipped for not being UTF-8 under `Encoding::Strict`.
+    pub skipped_invalid_encoding: usize,
+}
+
 pub struct ReferenceScan {
     pub edges: Vec<ReferenceEdge>,
     pub definition_lines: HashMap<Location, String>,
+    pub definition_kinds: HashMap<Location, SymbolKind>,
+    /// Line ranges of test-only code (such as `#[cfg(test)]` modules) by path.
+    pub test_ranges: FxHashMap<Spur, Vec<(usize, usize)>>,
+    /// Definitions a program starts from, such as Rust's `fn main` or the
+    /// files a `package.json` names as `main` or `bin`.
+    pub entry_points: FxHashSet<Location>,
+    /// (definition file, usage file) pairs added to an ecosystem's file
+    /// graph without a reference behind them, such as Cargo dependencies
+    /// between the crates of a workspace.
+    pub file_edges: HashMap<crate::languages::Ecosystem, Vec<(Spur, Spur)>>,
+    pub diagnostics: Diagnostics,
+    pub stats: ScanStats,
+}
+
+/// Usage counts per definition, gathered by [`count_references`] without
+/// keeping reference locations.
+pub struct ReferenceCounts {
+    /// Every definition with its ecosystem; `counts` refers to them by index.
+    pub definitions: Vec<(Location, crate::languages::Ecosystem)>,
+    /// Usages per (definition index, usage file, usage inside test-only code
+    /// such as a `#[cfg(test)]` module).
+    pub counts: FxHashMap<(u32, Spur, bool), u32>,
+    pub definition_lines: HashMap<Location, String>,
+    pub definition_kinds: HashMap<Location, SymbolKind>,
+    /// See [`ReferenceScan::entry_points`].
+    pub entry_points: FxHashSet<Location>,
+    /// See [`ReferenceScan::file_edges`].
+    pub file_edges: HashMap<crate::languages::Ecosystem, Vec<(Spur, Spur)>>,
+    pub diagnostics: Diagnostics,
+    pub stats: ScanStats,
 }
 
 struct EcosystemSymbols {
@@ -88,105 +264,421 @@ struct EcosystemSymbols {
     definition_positions: FxHashSet<(Spur, usize, usize)>,
     references: Vec<Location>,
     definition_lines: FxHashMap<Location, String>,
+    definition_kinds: FxHashMap<Location, SymbolKind>,
+    imports: Vec<ImportBinding>,
 }
 
 /// Results from processing a single file
-struct FileResult {
-    ecosystem: crate::languages::Ecosystem,
-    definitions: Vec<Location>,
-    references: Vec<Location>,
-    definition_lines: FxHashMap<Location, String>,
+#[derive(Clone)]
+pub(crate) struct FileResult {
+    pub(crate) ecosystem: crate::languages::Ecosystem,
+    pub(crate) definitions: Vec<Location>,
+    pub(crate) references: Vec<Location>,
+    pub(crate) definition_lines: FxHashMap<Location, String>,
+    pub(crate) definition_kinds: FxHashMap<Location, SymbolKind>,
+    pub(crate) imports: Vec<ImportBinding>,
+    /// Inclusive 1-based line ranges of test-only code.
+    pub(crate) test_ranges: Vec<(usize, usize)>,
+    pub(crate) ffi_exports: Vec<FfiExport>,
+    /// Definitions a program starts from, see [`entry_points`].
+    pub(crate) entry_points: Vec<Location>,
+    /// The syntax tree has errors; definitions inside them were dropped.
+    pub(crate) partial: bool,
 }
 
-pub fn find_references<I, P>(files: I) -> Result<ReferenceScan, crate::io::CruxlinesError>
-where
-    I: IntoIterator<Item = Result<(P, String), crate::io::CruxlinesError>>,
-    P: Into<PathBuf>,
-{
-    // Collect files first (need Vec for parallel iteration)
-    let files: Vec<(PathBuf, String)> = files
-        .into_iter()
-        .filter_map(|item| item.ok())
-        .map(|(p, s)| (p.into(), s))
-        .collect();
-
-    // Process files in parallel
-    let file_results: Vec<FileResult> = files
-        .par_iter()
-        .filter_map(|(path, source)| process_file(path, source))
-        .collect();
-
-    // Merge results by ecosystem
-    let mut symbols_by_ecosystem: HashMap<crate::languages::Ecosystem, EcosystemSymbols> =
-        HashMap::new();
-
-    for result in file_results {
-        let entry = symbols_by_ecosystem
-            .entry(result.ecosystem)
-            .or_insert_with(|| EcosystemSymbols {
-                definitions: FxHashMap::default(),
-                definition_positions: FxHashSet::default(),
-                references: Vec::new(),
-                definition_lines: FxHashMap::default(),
-            });
-
-        for location in result.definitions {
-            record_definition(
-                location,
-                &mut entry.definitions,
-                &mut entry.definition_positions,
-            );
-        }
-        entry.references.extend(result.references);
-        entry.definition_lines.extend(result.definition_lines);
-    }
+/// Scans every file of `source`, serving unchanged files from `cache` when
+/// one is given. Files that cannot be read, decoded or parsed are skipped
+/// and reported in the scan's diagnostics. With `Options::ffi_edges`,
+/// references in other ecosystems also link to Rust definitions exported to
+/// them. Files in one of `namespaces` only link within it.
+pub fn find_references(
+    source: &dyn SourceProvider,
+    repo_root: Option<&Path>,
+    cache: Option<&FileCache>,
+    options: &Options,
+    namespaces: &Namespaces,
+) -> ReferenceScan {
+    let scanned = scan_files(source, repo_root, cache, options, namespaces, true);
+    let common_names = CommonNameSet::new(options);
 
     let mut edges = Vec::new();
-    let mut definition_lines = HashMap::new();
-    for (ecosystem, symbols) in &symbols_by_ecosystem {
-        let ecosystem_edges: Vec<ReferenceEdge> = symbols
+    // Whether each reference linked to a definition (or is one), per ecosystem.
+    let mut resolved: HashMap<crate::languages::Ecosystem, Vec<bool>> = HashMap::new();
+    for (ecosystem, symbols) in &scanned.symbols {
+        let imports = resolve_imports(*ecosystem, symbols);
+        let packages = PackageScopes::new(&symbols.imports);
+        let linked: Vec<Vec<ReferenceEdge>> = symbols
             .references
             .par_iter()
-            .flat_map(|reference| {
+            .map(|reference| {
                 make_edges(
                     reference,
                     *ecosystem,
                     &symbols.definitions,
                     &symbols.definition_positions,
+                    &imports,
+                    &packages,
+                    &common_names,
                 )
             })
             .collect();
-        edges.extend(ecosystem_edges);
-
-        for (location, line) in &symbols.definition_lines {
-            definition_lines
-                .entry(*location)
-                .or_insert_with(|| line.clone());
+        resolved.insert(
+            *ecosystem,
+            linked
+                .iter()
+                .zip(&symbols.references)
+                .map(|(edges, reference)| {
+                    !edges.is_empty()
+                        || is_definition_site(reference, &symbols.definition_positions)
+                })
+                .collect(),
+        );
+        edges.extend(linked.into_iter().flatten());
+    }
+    if let Some(ffi) = &scanned.ffi {
+        for (ecosystem, symbols) in &scanned.symbols {
+            let resolved = resolved
+                .get_mut(ecosystem)
+                .expect("every ecosystem was linked");
+            for (index, reference) in symbols.references.iter().enumerate() {
+                let ffi_edges = ffi.edges(reference, *ecosystem, &symbols.definition_positions);
+                resolved[index] |= !ffi_edges.is_empty();
+                edges.extend(ffi_edges);
+            }
         }
     }
+    if !scanned.external_usages.is_empty() {
+        let targets = PipelineTargets::new(
+            scanned.symbols.iter().flat_map(|(ecosystem, symbols)| {
+                symbols
+                    .definitions
+                    .values()
+                    .flatten()
+                    .map(|definition| (*definition, *ecosystem))
+            }),
+            &scanned.entry_points,
+        );
+        for usage in &scanned.external_usages {
+            edges.extend(targets.edges(usage));
+        }
+    }
+
+    let mut seen = SeenEdges::new(options.reference_dedup);
+    edges.retain(|edge| seen.insert(edge));
 
-    Ok(ReferenceScan {
+    let mut stats = scanned.stats();
+    stats.reference_edges = edges.len();
+    stats.unresolved_references = resolved
+        .values()
+        .flatten()
+        .filter(|linked| !**linked)
+        .count();
+    let (definition_lines, definition_kinds) = scanned.definition_details();
+    ReferenceScan {
         edges,
         definition_lines,
-    })
+        definition_kinds,
+        test_ranges: scanned.test_ranges,
+        entry_points: scanned.entry_points,
+        file_edges: scanned.file_edges,
+        diagnostics: scanned.diagnostics,
+        stats,
+    }
+}
+
+/// Two-pass variant of [`find_references`] for very large trees. The first
+/// pass keeps only definitions and imports. The second reads every file
+/// again, from `cache` when possible, and folds its references into counts,
+/// so reference locations are never held for the whole tree at once.
+pub fn count_references(
+    source: &dyn SourceProvider,
+    repo_root: Option<&Path>,
+    cache: Option<&FileCache>,
+    options: &Options,
+    namespaces: &Namespaces,
+) -> ReferenceCounts {
+    let scanned = scan_files(source, repo_root, cache, options, namespaces, false);
+    let common_names = CommonNameSet::new(options);
+    let imports: HashMap<crate::languages::Ecosystem, _> = scanned
+        .symbols
+        .iter()
+        .map(|(ecosystem, symbols)| {
+            let imports = resolve_imports(*ecosystem, symbols);
+            (*ecosystem, (imports, PackageScopes::new(&symbols.imports)))
+        })
+        .collect();
+
+    let mut definitions = Vec::new();
+    let mut ids: FxHashMap<Location, u32> = FxHashMap::default();
+    for (ecosystem, symbols) in &scanned.symbols {
+        for definition in symbols.definitions.values().flatten() {
+            ids.insert(*definition, definitions.len() as u32);
+            definitions.push((*definition, *ecosystem));
+        }
+    }
+
+    let paths = source.paths();
+    let parsed = ParseProgress::new(paths.len(), options);
+    // Usage counts, plus the number of edges and of unresolved references.
+    let load = |path| load_source_file(path, source, cache);
+    let (mut counts, mut reference_edges, unresolved_references) =
+        read_ahead(&paths, options.scan.read_threads, load, |files| {
+            files
+                .fold(
+                    || (FxHashMap::default(), 0, 0),
+                    |(mut counts, mut edge_count, mut unresolved), (_, path, loaded)| {
+                        let outcome = parse_source_file(path, loaded, source, cache, options);
+                        parsed.advance();
+                        // Files that failed were already reported by the first pass.
+                        let Ok(Some(mut result)) = outcome else {
+                            return (counts, edge_count, unresolved);
+                        };
+                        result.ecosystem = namespaces.ecosystem(path, result.ecosystem);
+                        let (Some(symbols), Some((imports, packages))) = (
+                            scanned.symbols.get(&result.ecosystem),
+                            imports.get(&result.ecosystem),
+                        ) else {
+                            return (counts, edge_count, unresolved);
+                        };
+                        let mut seen = SeenEdges::new(options.reference_dedup);
+                        for reference in &result.references {
+                            let in_test_code = result
+                                .test_ranges
+                                .iter()
+                                .any(|(start, end)| (*start..=*end).contains(&reference.line));
+                            let edges = make_edges(
+                                reference,
+                                result.ecosystem,
+                                &symbols.definitions,
+                                &symbols.definition_positions,
+                                imports,
+                                packages,
+                                &common_names,
+                            );
+                            let ffi_edges = scanned.ffi.iter().flat_map(|ffi| {
+                                ffi.edges(
+                                    reference,
+                                    result.ecosystem,
+                                    &symbols.definition_positions,
+                                )
+                            });
+                            let mut linked =
+                                is_definition_site(reference, &symbols.definition_positions);
+                            for edge in edges.into_iter().chain(ffi_edges) {
+                                linked = true;
+                                if !seen.insert(&edge) {
+                                    continue;
+                                }
+                                edge_count += 1;
+                                if let Some(id) = ids.get(&edge.definition) {
+                                    *counts
+                                        .entry((*id, reference.path, in_test_code))
+                                        .or_default() += 1;
+                                }
+                            }
+                            unresolved += usize::from(!linked);
+                        }
+                        (counts, edge_count, unresolved)
+                    },
+                )
+                .reduce(
+                    || (FxHashMap::default(), 0, 0),
+                    |(mut merged, edges, unresolved), (counts, more_edges, more_unresolved)| {
+                        for (key, count) in counts {
+                            *merged.entry(key).or_default() += count;
+                        }
+                        (merged, edges + more_edges, unresolved + more_unresolved)
+                    },
+                )
+        });
+    if !scanned.external_usages.is_empty() {
+        let targets = PipelineTargets::new(definitions.iter().copied(), &scanned.entry_points);
+        let mut seen = SeenEdges::new(options.reference_dedup);
+        for edge in scanned
+            .external_usages
+            .iter()
+            .flat_map(|usage| targets.edges(usage))
+            .filter(|edge| seen.insert(edge))
+        {
+            reference_edges += 1;
+            if let Some(id) = ids.get(&edge.definition) {
+                *counts.entry((*id, edge.usage.path, false)).or_default() += 1;
+            }
+        }
+    }
+
+    let mut stats = scanned.stats();
+    stats.reference_edges = reference_edges;
+    stats.unresolved_references = unresolved_references;
+    let (definition_lines, definition_kinds) = scanned.definition_details();
+    ReferenceCounts {
+        definitions,
+        counts,
+        definition_lines,
+        definition_kinds,
+        entry_points: scanned.entry_points,
+        file_edges: scanned.file_edges,
+        diagnostics: scanned.diagnostics,
+        stats,
+    }
+}
+
+/// Per-file results merged by ecosystem, before references are linked.
+struct ScannedFiles {
+    symbols: HashMap<crate::languages::Ecosystem, EcosystemSymbols>,
+    ffi: Option<FfiTargets>,
+    test_ranges: FxHashMap<Spur, Vec<(usize, usize)>>,
+    entry_points: FxHashSet<Location>,
+    /// Code named by package manifests, and by pipeline YAML with
+    /// `Options::pipeline_references`.
+    external_usages: Vec<PipelineUsage>,
+    file_edges: HashMap<crate::languages::Ecosystem, Vec<(Spur, Spur)>>,
+    diagnostics: Diagnostics,
+    /// Files parsed per ecosystem.
+    files: HashMap<crate::languages::Ecosystem, usize>,
+}
+
+impl ScannedFiles {
+    /// File and definition counts; reference counts are filled in once
+    /// references are linked.
+    fn stats(&self) -> ScanStats {
+        ScanStats {
+            files: self.files.clone(),
+            definitions: self
+                .symbols
+                .values()
+                .map(|symbols| symbols.definitions.values().map(Vec::len).sum::<usize>())
+                .sum(),
+            skipped_invalid_encoding: self
+                .diagnostics
+                .iter()
+                .filter(|error| matches!(error, CruxlinesError::InvalidEncoding { .. }))
+                .count(),
+            ..ScanStats::default()
+        }
+    }
+
+    fn definition_details(&self) -> (HashMap<Location, String>, HashMap<Location, SymbolKind>) {
+        let mut definition_lines = HashMap::new();
+        let mut definition_kinds = HashMap::new();
+        for symbols in self.symbols.values() {
+            for (location, line) in &symbols.definition_lines {
+                definition_lines
+                    .entry(*location)
+                    .or_insert_with(|| line.clone());
+            }
+            for (location, kind) in &symbols.definition_kinds {
+                definition_kinds.entry(*location).or_insert(*kind);
+            }
+        }
+        (definition_lines, definition_kinds)
+    }
+}
+
+/// Reports [`Phase::Parse`] progress as files are processed.
+struct ParseProgress<'a> {
+    options: &'a Options,
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl<'a> ParseProgress<'a> {
+    fn new(total: usize, options: &'a Options) -> Self {
+        Self {
+            options,
+            done: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    fn advance(&self) {
+        if let Some(progress) = &self.options.progress {
+            let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+            progress.report(Phase::Parse, done, Some(self.total));
+        }
+    }
 }
 
-/// Find references with caching support. Only reads and parses files that aren't cached.
-pub fn find_references_cached(
-    paths: Vec<PathBuf>,
-    cache: &FileCache,
-) -> Result<ReferenceScan, crate::io::CruxlinesError> {
+/// Processes every file in parallel and merges the results by ecosystem.
+/// Without `keep_references`, each file's references are dropped as soon as
+/// it is processed.
+fn scan_files(
+    source: &dyn SourceProvider,
+    repo_root: Option<&Path>,
+    cache: Option<&FileCache>,
+    options: &Options,
+    namespaces: &Namespaces,
+    keep_references: bool,
+) -> ScannedFiles {
     // Process files in parallel - check cache first, parse on miss
-    let file_results: Vec<FileResult> = paths
-        .par_iter()
-        .filter_map(|path| process_file_cached(path, cache))
+    let paths = source.paths();
+    let parsed = ParseProgress::new(paths.len(), options);
+    let load = |path| load_source_file(path, source, cache);
+    let mut outcomes: Vec<(usize, Spur, Result<FileResult, CruxlinesError>)> =
+        read_ahead(&paths, options.scan.read_threads, load, |files| {
+            files
+                .filter_map(|(index, path, loaded)| {
+                    let outcome = parse_source_file(path, loaded, source, cache, options);
+                    parsed.advance();
+                    let mut outcome = outcome.transpose()?;
+                    if let Ok(result) = &mut outcome {
+                        result.ecosystem = namespaces.ecosystem(path, result.ecosystem);
+                        if !keep_references {
+                            result.references = Vec::new();
+                        }
+                    }
+                    Some((index, intern(&path.to_string_lossy()), outcome))
+                })
+                .collect()
+        });
+    // Reads finish in any order; keep the results in path order.
+    outcomes.sort_unstable_by_key(|(index, _, _)| *index);
+    let outcomes: Vec<(Spur, Result<FileResult, CruxlinesError>)> = outcomes
+        .into_iter()
+        .map(|(_, path, outcome)| (path, outcome))
         .collect();
 
-    // Rest is same as find_references
+    let mut diagnostics = Diagnostics::default();
+    diagnostics.set_scanned(outcomes.len());
+    let mut test_ranges = FxHashMap::default();
+    let mut file_results = Vec::with_capacity(outcomes.len());
+    let mut ffi_exports = Vec::new();
+    let package_entries = package_entry_files(source, repo_root, &outcomes);
+    let cargo_edges = cargo_file_edges(source, repo_root, &outcomes);
+    let mut external_usages = manifest_usages(source, repo_root, &outcomes);
+    if options.pipeline_references {
+        external_usages.extend(pipeline_usages(source));
+    }
+    let mut entry_points = FxHashSet::default();
+    for (path, outcome) in outcomes {
+        match outcome {
+            Ok(mut result) => {
+                if package_entries.contains(&path) {
+                    entry_points.extend(result.definitions.iter().copied());
+                }
+                entry_points.extend(result.entry_points.drain(..));
+                if result.partial {
+                    diagnostics.push_partial(PathBuf::from(resolve(path)));
+                }
+                if options.ffi_edges {
+                    ffi_exports.append(&mut result.ffi_exports);
+                }
+                if !result.test_ranges.is_empty() {
+                    test_ranges.insert(path, std::mem::take(&mut result.test_ranges));
+                }
+                file_results.push(result);
+            }
+            Err(error) => diagnostics.push(error),
+        }
+    }
+
+    // Merge results by ecosystem
     let mut symbols_by_ecosystem: HashMap<crate::languages::Ecosystem, EcosystemSymbols> =
         HashMap::new();
+    let mut files = HashMap::new();
 
     for result in file_results {
+        *files.entry(result.ecosystem).or_default() += 1;
         let entry = symbols_by_ecosystem
             .entry(result.ecosystem)
             .or_insert_with(|| EcosystemSymbols {
@@ -194,6 +686,8 @@ pub fn find_references_cached(
                 definition_positions: FxHashSet::default(),
                 references: Vec::new(),
                 definition_lines: FxHashMap::default(),
+                definition_kinds: FxHashMap::default(),
+                imports: Vec::new(),
             });
 
         for location in result.definitions {
@@ -205,64 +699,309 @@ pub fn find_references_cached(
         }
         entry.references.extend(result.references);
         entry.definition_lines.extend(result.definition_lines);
+        entry.definition_kinds.extend(result.definition_kinds);
+        entry.imports.extend(result.imports);
     }
 
-    let mut edges = Vec::new();
-    let mut definition_lines = HashMap::new();
-    for (ecosystem, symbols) in &symbols_by_ecosystem {
-        let ecosystem_edges: Vec<ReferenceEdge> = symbols
-            .references
-            .par_iter()
-            .flat_map(|reference| {
-                make_edges(
-                    reference,
-                    *ecosystem,
-                    &symbols.definitions,
-                    &symbols.definition_positions,
-                )
-            })
-            .collect();
-        edges.extend(ecosystem_edges);
-
-        for (location, line) in &symbols.definition_lines {
-            definition_lines
-                .entry(*location)
-                .or_insert_with(|| line.clone());
+    for (ecosystem, symbols) in &mut symbols_by_ecosystem {
+        if ecosystem.base() == crate::languages::Ecosystem::C {
+            collapse_header_declarations(symbols);
         }
     }
 
-    Ok(ReferenceScan {
-        edges,
-        definition_lines,
+    ScannedFiles {
+        symbols: symbols_by_ecosystem,
+        ffi: (!ffi_exports.is_empty()).then(|| FfiTargets::new(&ffi_exports)),
+        test_ranges,
+        entry_points,
+        external_usages,
+        file_edges: HashMap::from([(crate::languages::Ecosystem::Rust, cargo_edges)]),
+        diagnostics,
+        files,
+    }
+}
+
+/// The directories above `file`, nearest first, stopping at `repo_root`
+/// when `file` is under it so manifests outside the repo are never read.
+fn manifest_directories<'a>(
+    file: &'a Path,
+    repo_root: Option<&'a Path>,
+) -> impl Iterator<Item = &'a Path> {
+    let mut above_root = false;
+    file.ancestors().skip(1).take_while(move |directory| {
+        let inside = !above_root;
+        above_root |= repo_root == Some(*directory);
+        inside
     })
 }
 
-/// Process a file with cache support - returns cached result or parses fresh
-fn process_file_cached(path: &Path, cache: &FileCache) -> Option<FileResult> {
-    // Try cache first
-    if let Some(cached) = cache.get(path) {
-        return Some(FileResult {
-            ecosystem: cached.ecosystem,
-            definitions: cached.definitions,
-            references: cached.references,
-            definition_lines: cached.definition_lines,
+/// Usages of the public API that the `package.json` and `pyproject.toml`
+/// above scanned JavaScript/TypeScript and Python files declare.
+fn manifest_usages(
+    source: &dyn SourceProvider,
+    repo_root: Option<&Path>,
+    outcomes: &[(Spur, Result<FileResult, CruxlinesError>)],
+) -> Vec<PipelineUsage> {
+    use crate::languages::Ecosystem;
+
+    let scanned: FxHashMap<&str, Ecosystem> = outcomes
+        .iter()
+        .filter_map(|(path, outcome)| {
+            let path = resolve(*path);
+            let language = crate::languages::language_for_path(Path::new(path))?;
+            outcome
+                .is_ok()
+                .then(|| (path, crate::languages::ecosystem_for_language(language)))
+        })
+        .collect();
+    let mut directories: FxHashSet<(&Path, Ecosystem)> = FxHashSet::default();
+    let mut usages = Vec::new();
+    for (file, ecosystem) in &scanned {
+        let manifest_name = match ecosystem {
+            Ecosystem::JavaScript => "package.json",
+            Ecosystem::Python => "pyproject.toml",
+            _ => continue,
+        };
+        for directory in manifest_directories(Path::new(file), repo_root) {
+            if !directories.insert((directory, *ecosystem)) {
+                break;
+            }
+            let manifest = directory.join(manifest_name);
+            let Ok(Some(contents)) = source.read(&manifest) else {
+                continue;
+            };
+            let manifest_path = manifest.to_string_lossy();
+            let path = intern(&manifest_path);
+            if *ecosystem == Ecosystem::Python {
+                usages.extend(
+                    crate::languages::python::pyproject_entry_points(&contents)
+                        .into_iter()
+                        .filter_map(|(line, column, entry_point)| {
+                            PipelineUsage::function(path, line, column, entry_point)
+                        }),
+                );
+                continue;
+            }
+            for (specifier, resolved) in crate::languages::javascript::package_exports(
+                &manifest_path,
+                &contents,
+                |candidate| scanned.get(candidate) == Some(&Ecosystem::JavaScript),
+            ) {
+                let (line, column) = crate::pipelines::string_position(&contents, &specifier);
+                usages.push(PipelineUsage::file(path, line, column, intern(&resolved)));
+            }
+        }
+    }
+    usages
+}
+
+/// Scanned JavaScript/TypeScript files that a `package.json` in one of their
+/// directories names as `main` or `bin`.
+fn package_entry_files(
+    source: &dyn SourceProvider,
+    repo_root: Option<&Path>,
+    outcomes: &[(Spur, Result<FileResult, CruxlinesError>)],
+) -> FxHashSet<Spur> {
+    let scripts: FxHashSet<&str> = outcomes
+        .iter()
+        .filter(|(_, outcome)| outcome.is_ok())
+        .map(|(path, _)| resolve(*path))
+        .filter(|path| {
+            crate::languages::language_for_path(Path::new(path)).is_some_and(|language| {
+                crate::languages::ecosystem_for_language(language)
+                    == crate::languages::Ecosystem::JavaScript
+            })
+        })
+        .collect();
+    let mut directories: FxHashSet<&Path> = FxHashSet::default();
+    let mut entries = FxHashSet::default();
+    for script in &scripts {
+        for directory in manifest_directories(Path::new(script), repo_root) {
+            if !directories.insert(directory) {
+                break;
+            }
+            let manifest = directory.join("package.json");
+            let Ok(Some(contents)) = source.read(&manifest) else {
+                continue;
+            };
+            let manifest = manifest.to_string_lossy();
+            for entry in crate::languages::javascript::package_entry_files(
+                &manifest,
+                &contents,
+                |candidate| scripts.contains(candidate),
+            ) {
+                entries.insert(intern(&entry));
+            }
+        }
+    }
+    entries
+}
+
+/// File graph pairs from the `Cargo.toml` of scanned Rust crates: each
+/// file of a crate is used by the root (`src/lib.rs` or `src/main.rs`) of
+/// every crate that depends on it through a path, a workspace dependency or
+/// the name of another scanned crate. PageRank then sees crate dependencies
+/// that name matching misses.
+fn cargo_file_edges(
+    source: &dyn SourceProvider,
+    repo_root: Option<&Path>,
+    outcomes: &[(Spur, Result<FileResult, CruxlinesError>)],
+) -> Vec<(Spur, Spur)> {
+    use crate::languages::rust::{CargoManifest, cargo_manifest};
+
+    let files: Vec<&'static str> = outcomes
+        .iter()
+        .filter(|(_, outcome)| {
+            outcome
+                .as_ref()
+                .is_ok_and(|result| result.ecosystem == crate::languages::Ecosystem::Rust)
+        })
+        .map(|(path, _)| resolve(*path))
+        .collect();
+    let mut manifests: FxHashMap<&Path, Option<CargoManifest>> = FxHashMap::default();
+    for file in &files {
+        for directory in manifest_directories(Path::new(file), repo_root) {
+            if manifests.contains_key(directory) {
+                break;
+            }
+            let manifest = source
+                .read(&directory.join("Cargo.toml"))
+                .ok()
+                .flatten()
+                .map(|contents| cargo_manifest(&contents));
+            manifests.insert(directory, manifest);
+        }
+    }
+    let manifest = |directory: &Path| manifests.get(directory).and_then(Option::as_ref);
+
+    // Files by the directory of the nearest package manifest above them.
+    let mut crates: FxHashMap<&Path, Vec<&'static str>> = FxHashMap::default();
+    for file in &files {
+        if let Some(directory) = Path::new(file)
+            .ancestors()
+            .skip(1)
+            .find(|directory| manifest(directory).is_some_and(|manifest| manifest.name.is_some()))
+        {
+            crates.entry(directory).or_default().push(file);
+        }
+    }
+    let by_name: FxHashMap<&str, &Path> = crates
+        .keys()
+        .filter_map(|directory| Some((manifest(directory)?.name.as_deref()?, *directory)))
+        .collect();
+
+    let mut pairs = Vec::new();
+    for (directory, crate_files) in &crates {
+        let Some(root) = ["src/lib.rs", "src/main.rs"].iter().find_map(|root| {
+            let root = directory.join(root);
+            crate_files
+                .iter()
+                .find(|file| Path::new(file) == root)
+                .map(|file| intern(file))
+        }) else {
+            continue;
+        };
+        let Some(package) = manifest(directory) else {
+            continue;
+        };
+        let workspace = directory.ancestors().find_map(|ancestor| {
+            let manifest = manifest(ancestor)?;
+            (!manifest.workspace_dependencies.is_empty()).then_some((ancestor, manifest))
         });
+        for dependency in &package.dependencies {
+            let (base, dependency) = match (&dependency.path, workspace) {
+                (None, Some((workspace_directory, workspace))) if dependency.workspace => {
+                    let Some(inherited) = workspace
+                        .workspace_dependencies
+                        .iter()
+                        .find(|inherited| inherited.key == dependency.key)
+                    else {
+                        continue;
+                    };
+                    (workspace_directory, inherited)
+                }
+                _ => (*directory, dependency),
+            };
+            let target = match &dependency.path {
+                Some(path) => crate::languages::normalize_path(&base.join(path)),
+                None => match by_name.get(dependency.package.as_str()) {
+                    Some(target) => target.to_path_buf(),
+                    None => continue,
+                },
+            };
+            if target.as_path() == *directory {
+                continue;
+            }
+            if let Some(used) = crates.get(target.as_path()) {
+                pairs.extend(used.iter().map(|file| (intern(file), root)));
+            }
+        }
+    }
+    pairs
+}
+
+/// A file as the read stage hands it to the parsers.
+enum Loaded {
+    /// An unchanged file served from the cache.
+    Cached(FileResult),
+    /// The raw contents of a file to decode and parse.
+    Read(Vec<u8>),
+}
+
+/// Reads a file for [`parse_source_file`], or takes its result from the
+/// cache. `Ok(None)` means the file is unsupported or skipped by the source.
+fn load_source_file(
+    path: &Path,
+    source: &dyn SourceProvider,
+    cache: Option<&FileCache>,
+) -> Result<Option<Loaded>, CruxlinesError> {
+    if crate::languages::language_for_path(path).is_none() {
+        return Ok(None);
+    }
+    if let Some(cache) = cache.filter(|_| source.is_cacheable(path))
+        && let Some(cached) = cache.get(path)
+    {
+        return Ok(Some(Loaded::Cached(cached)));
     }
+    Ok(source.read_bytes(path)?.map(Loaded::Read))
+}
 
-    // Cache miss - read and parse file
-    let source = std::fs::read_to_string(path).ok()?;
-    let result = process_file(path, &source)?;
+/// Decodes and parses a file read by [`load_source_file`], saving the result
+/// to the cache.
+fn parse_source_file(
+    path: &Path,
+    loaded: Result<Option<Loaded>, CruxlinesError>,
+    source: &dyn SourceProvider,
+    cache: Option<&FileCache>,
+    options: &Options,
+) -> Result<Option<FileResult>, CruxlinesError> {
+    let bytes = match loaded? {
+        None => return Ok(None),
+        Some(Loaded::Cached(result)) => return Ok(Some(result)),
+        Some(Loaded::Read(bytes)) => bytes,
+    };
+    let contents = decode(path, bytes, options.scan.encoding)?;
+    let result = match &options.parse_session {
+        Some(session) => session.parse(path, contents, &options.reference_kinds),
+        None => process_file(path, &contents, &options.reference_kinds),
+    }
+    .ok_or_else(|| CruxlinesError::ParseFile {
+        path: path.to_path_buf(),
+    })?;
 
     // Save to cache (ignore errors)
-    let _ = cache.set(
-        path,
-        result.ecosystem,
-        &result.definitions,
-        &result.references,
-        &result.definition_lines,
-    );
+    if let Some(cache) = cache.filter(|_| source.is_cacheable(path)) {
+        let _ = cache.set(path, &result);
+    }
 
-    Some(result)
+    Ok(Some(result))
+}
+
+#[derive(Default)]
+struct CollectedDefinitions {
+    definitions: Vec<Location>,
+    lines: FxHashMap<Location, String>,
+    kinds: FxHashMap<Location, SymbolKind>,
 }
 
 fn collect_definitions(
@@ -270,154 +1009,319 @@ fn collect_definitions(
     source: &str,
     tree: &Tree,
     language: crate::languages::Language,
-) -> (Vec<Location>, FxHashMap<Location, String>) {
-    let mut definitions = Vec::new();
-    let mut definition_lines = FxHashMap::default();
-
-    let emit_def =
-        |loc: Location, defs: &mut Vec<Location>, lines: &mut FxHashMap<Location, String>| {
-            record_definition_line(&loc, source, lines);
-            defs.push(loc);
-        };
+) -> CollectedDefinitions {
+    let mut collected = CollectedDefinitions::default();
+    let errors = error_ranges(tree);
+    let mut emit_def = |loc: Location, kind: SymbolKind| {
+        let point = (loc.line - 1, loc.column - 1);
+        if errors
+            .iter()
+            .any(|(start, end)| *start <= point && point < *end)
+        {
+            return;
+        }
+        record_definition_line(&loc, source, &mut collected.lines);
+        collected.kinds.entry(loc).or_insert(kind);
+        collected.definitions.push(loc);
+    };
 
     match language {
         crate::languages::Language::C => {
-            crate::languages::c::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
+            crate::languages::c::emit_definitions(path, source, tree, &mut emit_def);
         }
         crate::languages::Language::Cpp => {
-            crate::languages::cpp::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
-        }
-        crate::languages::Language::CSharp => {
-            crate::languages::csharp::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
-        }
-        crate::languages::Language::Go => {
-            crate::languages::go::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
-        }
-        crate::languages::Language::Java => {
-            crate::languages::java::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
-        }
-        crate::languages::Language::Kotlin => {
-            crate::languages::kotlin::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
-        }
-        crate::languages::Language::Php => {
-            crate::languages::php::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
-        }
-        crate::languages::Language::Python => {
-            crate::languages::python::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
+            crate::languages::cpp::emit_definitions(path, source, tree, &mut emit_def);
         }
         crate::languages::Language::JavaScript
         | crate::languages::Language::TypeScript
         | crate::languages::Language::TypeScriptReact => {
-            crate::languages::javascript::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
-            });
+            crate::languages::javascript::emit_definitions(path, source, tree, &mut emit_def);
         }
-        crate::languages::Language::Rust => {
-            crate::languages::rust::emit_definitions(path, source, tree, |loc| {
-                emit_def(loc, &mut definitions, &mut definition_lines);
+        _ => {
+            let queries = crate::languages::query::queries(language);
+            queries.emit_definitions(source, tree, |name, kind| {
+                let Some(mut location) = location_from_node(path, source, name) else {
+                    return;
+                };
+                if language == crate::languages::Language::Java
+                    && let Some(qualified) = crate::languages::java::qualified_name(source, name)
+                {
+                    location.name = intern(&qualified);
+                }
+                emit_def(location, kind);
             });
         }
     }
 
-    (definitions, definition_lines)
+    collected
+}
+
+/// (row, column) spans of the outermost `ERROR` nodes, whose contents
+/// tree-sitter could only guess at.
+fn error_ranges(tree: &Tree) -> Vec<((usize, usize), (usize, usize))> {
+    let mut ranges = Vec::new();
+    if !tree.root_node().has_error() {
+        return ranges;
+    }
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.is_error() {
+            let (start, end) = (node.start_position(), node.end_position());
+            ranges.push(((start.row, start.column), (end.row, end.column)));
+            continue;
+        }
+        if !node.has_error() {
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    ranges
 }
 
 /// Process a single file: parse and extract definitions/references
-fn process_file(path: &Path, source: &str) -> Option<FileResult> {
+fn process_file(
+    path: &Path,
+    source: &str,
+    reference_kinds: &HashMap<crate::languages::Language, ReferenceKinds>,
+) -> Option<FileResult> {
+    process_file_incremental(path, source, reference_kinds, None).map(|(result, _)| result)
+}
+
+/// [`process_file`], reparsing only what changed since `previous`, an
+/// earlier text of the file and its tree. Also returns the new tree, or
+/// `None` for files whose scripts are extracted from markup, which are
+/// always parsed from scratch.
+pub(crate) fn process_file_incremental(
+    path: &Path,
+    source: &str,
+    reference_kinds: &HashMap<crate::languages::Language, ReferenceKinds>,
+    previous: Option<(&str, Tree)>,
+) -> Option<(FileResult, Option<Tree>)> {
     let language = crate::languages::language_for_path(path)?;
-    let tree = parse_tree(&language, source)?;
+    let embedded = crate::languages::javascript::has_embedded_scripts(path);
+    let scripts;
+    let (source, previous) = if embedded {
+        scripts = crate::languages::javascript::embedded_scripts(source);
+        (scripts.as_str(), None)
+    } else {
+        (source, previous)
+    };
+    let tree = match previous {
+        Some((old_source, mut old_tree)) => {
+            old_tree.edit(&input_edit(old_source, source));
+            parse_tree_from(&language, source, Some(&old_tree))?
+        }
+        None => parse_tree(&language, source)?,
+    };
     let ecosystem = crate::languages::ecosystem_for_language(language);
 
-    let (definitions, definition_lines) = collect_definitions(path, source, &tree, language);
+    let CollectedDefinitions {
+        definitions,
+        lines: definition_lines,
+        kinds: definition_kinds,
+    } = collect_definitions(path, source, &tree, language);
+
+    let references = reference_nodes(source, &tree, language, reference_kinds.get(&language))
+        .into_iter()
+        .filter_map(|node| location_from_node(path, source, node))
+        .collect();
 
-    let mut references = Vec::new();
+    let mut imports = Vec::new();
     match language {
-        crate::languages::Language::C => {
-            crate::languages::c::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
-        crate::languages::Language::Cpp => {
-            crate::languages::cpp::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
-        crate::languages::Language::CSharp => {
-            crate::languages::csharp::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
-        crate::languages::Language::Go => {
-            crate::languages::go::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
-        crate::languages::Language::Java => {
-            crate::languages::java::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
-        crate::languages::Language::Kotlin => {
-            crate::languages::kotlin::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
-        crate::languages::Language::Php => {
-            crate::languages::php::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
-        crate::languages::Language::Python => {
-            crate::languages::python::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
-            });
-        }
         crate::languages::Language::JavaScript
         | crate::languages::Language::TypeScript
         | crate::languages::Language::TypeScriptReact => {
-            crate::languages::javascript::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
+            crate::languages::javascript::emit_imports(path, source, &tree, |binding| {
+                imports.push(binding);
+            });
+        }
+        crate::languages::Language::C | crate::languages::Language::Cpp => {
+            crate::languages::c::emit_includes(path, source, &tree, |binding| {
+                imports.push(binding);
             });
         }
         crate::languages::Language::Rust => {
-            crate::languages::rust::emit_references(path, source, &tree, |loc| {
-                references.push(loc);
+            crate::languages::rust::emit_imports(path, source, &tree, |binding| {
+                imports.push(binding);
+            });
+        }
+        crate::languages::Language::Java | crate::languages::Language::Kotlin => {
+            crate::languages::java::emit_package_imports(path, source, &tree, |binding| {
+                imports.push(binding);
             });
         }
+        _ => {}
+    }
+
+    let mut test_ranges = Vec::new();
+    let mut ffi_exports = Vec::new();
+    if let crate::languages::Language::Rust = language {
+        crate::languages::rust::emit_test_ranges(source, &tree, |range| {
+            test_ranges.push(range);
+        });
+        crate::languages::rust::emit_ffi_exports(path, source, &tree, |export| {
+            ffi_exports.push(export);
+        });
     }
 
-    Some(FileResult {
+    let entry_points = entry_points(
+        path,
+        source,
+        &tree,
+        language,
+        &definitions,
+        &definition_kinds,
+        &definition_lines,
+    );
+
+    let result = FileResult {
         ecosystem,
         definitions,
         references,
         definition_lines,
-    })
+        definition_kinds,
+        imports,
+        test_ranges,
+        ffi_exports,
+        entry_points,
+        partial: tree.root_node().has_error(),
+    };
+    Some((result, (!embedded).then_some(tree)))
+}
+
+/// The edit turning `old` into `new`: everything between their common
+/// prefix and common suffix was replaced.
+fn input_edit(old: &str, new: &str) -> InputEdit {
+    let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+    let prefix = old_bytes
+        .iter()
+        .zip(new_bytes)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_bytes[prefix..]
+        .iter()
+        .rev()
+        .zip(new_bytes[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    InputEdit {
+        start_byte: prefix,
+        old_end_byte: old_end,
+        new_end_byte: new_end,
+        start_position: point_at(old_bytes, prefix),
+        old_end_position: point_at(old_bytes, old_end),
+        new_end_position: point_at(new_bytes, new_end),
+    }
+}
+
+/// Row and byte column of `offset` in `text`.
+fn point_at(text: &[u8], offset: usize) -> Point {
+    let before = &text[..offset];
+    let line_start = before
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |index| index + 1);
+    Point::new(
+        before.iter().filter(|byte| **byte == b'\n').count(),
+        offset - line_start,
+    )
+}
+
+/// Definitions a program starts from: Rust's `fn main`, Java's
+/// `static void main`, and in Python the definitions a
+/// `if __name__ == "__main__":` block uses, or all of a `__main__.py`.
+/// JavaScript entry points come from `package.json` instead.
+fn entry_points(
+    path: &Path,
+    source: &str,
+    tree: &Tree,
+    language: crate::languages::Language,
+    definitions: &[Location],
+    kinds: &FxHashMap<Location, SymbolKind>,
+    lines: &FxHashMap<Location, String>,
+) -> Vec<Location> {
+    let is_main = |definition: &&Location, kind: SymbolKind| {
+        definition.name_str() == "main" && kinds.get(*definition) == Some(&kind)
+    };
+    match language {
+        crate::languages::Language::Rust => definitions
+            .iter()
+            .filter(|definition| is_main(definition, SymbolKind::Function))
+            .copied()
+            .collect(),
+        crate::languages::Language::Java => definitions
+            .iter()
+            .filter(|definition| {
+                is_main(definition, SymbolKind::Method)
+                    && lines
+                        .get(*definition)
+                        .is_some_and(|line| line.split_whitespace().any(|word| word == "static"))
+            })
+            .copied()
+            .collect(),
+        crate::languages::Language::Python => {
+            if path.file_name().is_some_and(|name| name == "__main__.py") {
+                return definitions.to_vec();
+            }
+            let names = crate::languages::python::main_guard_names(source, tree);
+            definitions
+                .iter()
+                .filter(|definition| names.contains(&definition.name_str()))
+                .copied()
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Nodes captured by the language's references query, adjusted by the
+/// configured node kinds to add or remove.
+fn reference_nodes<'tree>(
+    source: &str,
+    tree: &'tree Tree,
+    language: crate::languages::Language,
+    overrides: Option<&ReferenceKinds>,
+) -> Vec<Node<'tree>> {
+    let mut nodes = Vec::new();
+    crate::languages::query::queries(language).emit_references(source, tree, |node| {
+        nodes.push(node);
+    });
+    let Some(overrides) = overrides else {
+        return nodes;
+    };
+    let captured: FxHashSet<usize> = nodes.iter().map(Node::id).collect();
+    nodes.retain(|node| !overrides.removes(node.kind()));
+    if !overrides.add.is_empty() {
+        walk_tree(tree, |node| {
+            if overrides.adds(node.kind()) && !captured.contains(&node.id()) {
+                nodes.push(node);
+            }
+        });
+        nodes.sort_by_key(Node::start_byte);
+    }
+    nodes
 }
 
 fn parse_tree(language: &crate::languages::Language, source: &str) -> Option<Tree> {
+    parse_tree_from(language, source, None)
+}
+
+/// Parses `source`, reusing the unchanged parts of `old_tree`, which must
+/// already have been edited to match.
+fn parse_tree_from(
+    language: &crate::languages::Language,
+    source: &str,
+    old_tree: Option<&Tree>,
+) -> Option<Tree> {
     let mut parser = Parser::new();
     let ts_language = crate::languages::tree_sitter_language(*language);
     parser.set_language(&ts_language).ok()?;
-    parser.parse(source, None)
+    parser.parse(source, old_tree)
 }
 
-pub(crate) fn walk_tree(tree: &Tree, mut visit: impl FnMut(Node)) {
+pub(crate) fn walk_tree<'tree>(tree: &'tree Tree, mut visit: impl FnMut(Node<'tree>)) {
     let root = tree.root_node();
     let mut stack = vec![root];
     while let Some(node) = stack.pop() {
@@ -464,12 +1368,21 @@ pub(crate) fn location_from_node(path: &Path, source: &str, node: Node) -> Optio
     })
 }
 
+/// The name references use for a definition: the member of a qualified name
+/// such as Java's `User.save`, or `name` itself.
+pub(crate) fn link_name(name: Spur) -> Spur {
+    match resolve(name).rsplit_once('.') {
+        Some((_, member)) => intern(member),
+        None => name,
+    }
+}
+
 fn record_definition(
     location: Location,
     definitions: &mut FxHashMap<Spur, Vec<Location>>,
     definition_positions: &mut FxHashSet<(Spur, usize, usize)>,
 ) {
-    let key = location.name;
+    let key = link_name(location.name);
     let entry = definitions.entry(key).or_default();
     if !entry.iter().any(|item| {
         item.path == location.path && item.line == location.line && item.column == location.column
@@ -479,17 +1392,236 @@ fn record_definition(
     definition_positions.insert((location.path, location.line, location.column));
 }
 
+/// Resolves import specifiers against the files that define or import
+/// symbols in this ecosystem. Imports of a re-exported name resolve to the
+/// file that defines it, so importing through a barrel `index.ts` links to
+/// the original definition.
+fn resolve_imports(
+    ecosystem: crate::languages::Ecosystem,
+    symbols: &EcosystemSymbols,
+) -> ResolvedImports {
+    let mut resolved = FxHashMap::default();
+    let mut by_site = FxHashMap::default();
+    if symbols.imports.is_empty() {
+        return ResolvedImports::default();
+    }
+    let known_paths = known_paths(symbols);
+    let import_all = intern(IMPORT_ALL);
+    let mut wildcards: FxHashMap<Spur, Vec<Spur>> = FxHashMap::default();
+    for binding in &symbols.imports {
+        // Namespaced files resolve imports like the rest of their language.
+        let target = crate::languages::resolve_import(
+            ecosystem.base(),
+            resolve(binding.path),
+            &binding.specifier,
+            |candidate| known_paths.contains(candidate),
+        );
+        if let Some((line, column)) = binding.site {
+            let import = target.map(|target| ResolvedImport {
+                target: intern(&target),
+                imported: binding.imported,
+            });
+            by_site.insert((binding.path, line, column), import);
+            continue;
+        }
+        let Some(target) = target else {
+            continue;
+        };
+        let target = intern(&target);
+        if binding.local == import_all {
+            wildcards.entry(binding.path).or_default().push(target);
+            continue;
+        }
+        resolved.insert(
+            (binding.path, binding.local),
+            ResolvedImport {
+                target,
+                imported: binding.imported,
+            },
+        );
+    }
+
+    let reexports = Reexports {
+        defined: symbols
+            .definitions
+            .values()
+            .flatten()
+            .map(|definition| (definition.path, definition.name))
+            .collect(),
+        resolved: &resolved,
+        wildcards: &wildcards,
+    };
+    let origins: Vec<((Spur, Spur), (Spur, Spur))> = resolved
+        .iter()
+        .filter_map(|(key, import)| {
+            let origin = reexports.origin(import.target, import.imported, MAX_REEXPORT_HOPS)?;
+            (origin != (import.target, import.imported)).then_some((*key, origin))
+        })
+        .collect();
+    for import in by_site.values_mut().flatten() {
+        if let Some((target, imported)) =
+            reexports.origin(import.target, import.imported, MAX_REEXPORT_HOPS)
+        {
+            *import = ResolvedImport { target, imported };
+        }
+    }
+    for (key, (target, imported)) in origins {
+        resolved.insert(key, ResolvedImport { target, imported });
+    }
+    ResolvedImports {
+        by_name: resolved,
+        by_site,
+    }
+}
+
+/// Files that define or import symbols, which import specifiers may name.
+fn known_paths(symbols: &EcosystemSymbols) -> FxHashSet<&'static str> {
+    symbols
+        .definitions
+        .values()
+        .flatten()
+        .map(|definition| definition.path_str())
+        .chain(symbols.imports.iter().map(|binding| resolve(binding.path)))
+        .collect()
+}
+
+/// Collapses C/C++ header declarations onto the definition in the paired
+/// source file, so a function or global declared in `util.h` and defined in
+/// `util.c` ranks as one symbol instead of splitting its score. A source
+/// file pairs with a header that shares its file stem or that it includes.
+/// Only functions and variables collapse; types defined in a header are the
+/// definition. Headers keep their declarations when no paired source file
+/// defines the name, such as for APIs implemented elsewhere.
+fn collapse_header_declarations(symbols: &mut EcosystemSymbols) {
+    let import_all = intern(IMPORT_ALL);
+    let known_paths = known_paths(symbols);
+    let includes: FxHashSet<(Spur, Spur)> = symbols
+        .imports
+        .iter()
+        .filter(|binding| binding.local == import_all)
+        .filter_map(|binding| {
+            let header = crate::languages::resolve_import(
+                crate::languages::Ecosystem::C,
+                resolve(binding.path),
+                &binding.specifier,
+                |candidate| known_paths.contains(candidate),
+            )?;
+            Some((binding.path, intern(&header)))
+        })
+        .collect();
+    let kinds = &symbols.definition_kinds;
+    let is_declaration = |location: &Location| {
+        crate::languages::c::is_header(Path::new(location.path_str()))
+            && matches!(
+                kinds.get(location),
+                Some(SymbolKind::Function | SymbolKind::Method | SymbolKind::Variable)
+            )
+    };
+    let is_header =
+        |location: &Location| crate::languages::c::is_header(Path::new(location.path_str()));
+    let pairs = |source: &Location, header: &Location| {
+        includes.contains(&(source.path, header.path))
+            || Path::new(source.path_str()).file_stem() == Path::new(header.path_str()).file_stem()
+    };
+    for definitions in symbols.definitions.values_mut() {
+        if definitions.len() < 2 {
+            continue;
+        }
+        let collapsed: Vec<Location> = definitions
+            .iter()
+            .filter(|header| {
+                !is_declaration(header)
+                    || !definitions
+                        .iter()
+                        .any(|source| !is_header(source) && pairs(source, header))
+            })
+            .copied()
+            .collect();
+        *definitions = collapsed;
+    }
+}
+
+/// Re-export bindings of an ecosystem, for following imports to the file
+/// that defines a name.
+struct Reexports<'a> {
+    defined: FxHashSet<(Spur, Spur)>,
+    resolved: &'a FxHashMap<(Spur, Spur), ResolvedImport>,
+    wildcards: &'a FxHashMap<Spur, Vec<Spur>>,
+}
+
+impl Reexports<'_> {
+    /// The (file, name) that `name` exported by `file` refers to, or `None`
+    /// if the chain doesn't reach a definition within `hops` re-exports.
+    fn origin(&self, file: Spur, name: Spur, hops: usize) -> Option<(Spur, Spur)> {
+        if self.defined.contains(&(file, name)) {
+            return Some((file, name));
+        }
+        let hops = hops.checked_sub(1)?;
+        if let Some(next) = self.resolved.get(&(file, name)) {
+            return self.origin(next.target, next.imported, hops);
+        }
+        self.wildcards
+            .get(&file)?
+            .iter()
+            .find_map(|target| self.origin(*target, name, hops))
+    }
+}
+
+/// Whether `location` is the name of a definition rather than a use of one.
+fn is_definition_site(
+    location: &Location,
+    definition_positions: &FxHashSet<(Spur, usize, usize)>,
+) -> bool {
+    definition_positions.contains(&(location.path, location.line, location.column))
+}
+
 /// Returns edges for a reference (used in parallel processing)
 fn make_edges(
     location: &Location,
     ecosystem: crate::languages::Ecosystem,
     definitions: &FxHashMap<Spur, Vec<Location>>,
     definition_positions: &FxHashSet<(Spur, usize, usize)>,
+    imports: &ResolvedImports,
+    packages: &PackageScopes,
+    common_names: &CommonNameSet,
 ) -> Vec<ReferenceEdge> {
-    if definition_positions.contains(&(location.path, location.line, location.column)) {
+    if is_definition_site(location, definition_positions) {
         return Vec::new();
     }
+    // Names imported from a resolved file only link to that file's
+    // definitions. A binding of this very reference, such as a qualified
+    // path, wins over one of its name.
+    let import = match imports
+        .by_site
+        .get(&(location.path, location.line, location.column))
+    {
+        Some(import) => import.as_ref(),
+        None => imports.by_name.get(&(location.path, location.name)),
+    };
+    if let Some(import) = import
+        && let Some(defs) = definitions.get(&import.imported)
+    {
+        let targeted: Vec<ReferenceEdge> = defs
+            .iter()
+            .filter(|def| def.path == import.target)
+            .map(|def| ReferenceEdge {
+                definition: *def,
+                usage: *location,
+                ecosystem,
+            })
+            .collect();
+        if !targeted.is_empty() {
+            return targeted;
+        }
+    }
     if let Some(defs) = definitions.get(&location.name) {
+        let visible = packages.visible(location, defs);
+        let defs = visible.as_deref().unwrap_or(defs);
+        // A common name defined once is that definition; defined several
+        // times, it is more likely a builtin or a method of another type.
+        if defs.len() > 1 && common_names.contains(location) {
+            return Vec::new();
+        }
         defs.iter()
             .map(|def| ReferenceEdge {
                 definition: *def,
@@ -502,6 +1634,209 @@ fn make_edges(
     }
 }
 
+/// Usages recorded so far, for dropping the edges `Options::reference_dedup`
+/// collapses into an earlier edge. A usage keeps the first definition it
+/// links to, so one with several candidates still counts once.
+struct SeenEdges {
+    dedup: ReferenceDedup,
+    seen: FxHashSet<Location>,
+}
+
+impl SeenEdges {
+    fn new(dedup: ReferenceDedup) -> Self {
+        Self {
+            dedup,
+            seen: FxHashSet::default(),
+        }
+    }
+
+    /// Records `edge`, returning whether it should be kept.
+    fn insert(&mut self, edge: &ReferenceEdge) -> bool {
+        let column = match self.dedup {
+            ReferenceDedup::None => return true,
+            ReferenceDedup::Location => edge.usage.column,
+            ReferenceDedup::Line => 0,
+        };
+        self.seen.insert(Location {
+            column,
+            ..edge.usage
+        })
+    }
+}
+
+/// Java and Kotlin package declarations and imports, which decide which of
+/// several same-named definitions a reference can see.
+struct PackageScopes {
+    /// Declared package of each file.
+    packages: FxHashMap<Spur, &'static str>,
+    /// Qualified names each file imports; wildcard imports end in `.*`.
+    imports: FxHashMap<Spur, Vec<String>>,
+}
+
+impl PackageScopes {
+    fn new(bindings: &[ImportBinding]) -> Self {
+        let (package, import_all) = (intern(PACKAGE), intern(IMPORT_ALL));
+        let mut packages = FxHashMap::default();
+        let mut imports: FxHashMap<Spur, Vec<String>> = FxHashMap::default();
+        for binding in bindings {
+            let is_java = crate::languages::language_for_path(Path::new(resolve(binding.path)))
+                .is_some_and(|language| {
+                    crate::languages::ecosystem_for_language(language)
+                        == crate::languages::Ecosystem::Java
+                });
+            if !is_java {
+                continue;
+            }
+            if binding.local == package {
+                packages.insert(binding.path, resolve(intern(&binding.specifier)));
+            } else if binding.local == import_all {
+                imports
+                    .entry(binding.path)
+                    .or_default()
+                    .push(format!("{}.*", binding.specifier));
+            } else {
+                imports
+                    .entry(binding.path)
+                    .or_default()
+                    .push(binding.specifier.clone());
+            }
+        }
+        Self { packages, imports }
+    }
+
+    /// The definitions among `defs` that `reference` sees through its own
+    /// package or its imports. `None` keeps every definition: the
+    /// reference's file declares neither a package nor imports, the name is
+    /// defined once, or no definition is visible and the package is
+    /// probably referenced by its qualified name instead.
+    fn visible(&self, reference: &Location, defs: &[Location]) -> Option<Vec<Location>> {
+        if defs.len() < 2 {
+            return None;
+        }
+        let own_package = self.packages.get(&reference.path);
+        let imports = self.imports.get(&reference.path);
+        if own_package.is_none() && imports.is_none() {
+            return None;
+        }
+        let imports = imports.map_or(&[][..], Vec::as_slice);
+        let name = reference.name_str();
+        // A single-type import of the name shadows same-package definitions.
+        let imported_by_name = imports
+            .iter()
+            .any(|import| import.rsplit('.').next() == Some(name));
+        let visible: Vec<Location> = defs
+            .iter()
+            .filter(|def| {
+                let package = self.packages.get(&def.path).copied().unwrap_or("");
+                let file_type = Path::new(def.path_str())
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or_default();
+                let qualified = |name: &str| {
+                    if package.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{package}.{name}")
+                    }
+                };
+                let (by_name, by_type) = (qualified(name), qualified(file_type));
+                let imported = imports
+                    .iter()
+                    .any(|import| match import.strip_suffix(".*") {
+                        Some(wildcard) => wildcard == package || wildcard == by_type,
+                        None => {
+                            *import == by_name
+                                || *import == by_type
+                                || import
+                                    .strip_prefix(&by_type)
+                                    .is_some_and(|member| member.starts_with('.'))
+                        }
+                    });
+                imported || (!imported_by_name && own_package.copied().unwrap_or("") == package)
+            })
+            .copied()
+            .collect();
+        (!visible.is_empty()).then_some(visible)
+    }
+}
+
+/// Names that don't link by name when several definitions share them, with
+/// the languages whose files they are common in.
+struct CommonNameSet(FxHashMap<Spur, Vec<crate::languages::Language>>);
+
+impl CommonNameSet {
+    fn new(options: &Options) -> Self {
+        let mut names: FxHashMap<Spur, Vec<_>> = FxHashMap::default();
+        let no_changes = crate::options::CommonNames::default();
+        for language in crate::languages::Language::ALL {
+            let ecosystem = crate::languages::ecosystem_for_language(language);
+            let changes = options.common_names.get(&ecosystem).unwrap_or(&no_changes);
+            for name in changes.apply(language) {
+                names.entry(intern(name)).or_default().push(language);
+            }
+        }
+        Self(names)
+    }
+
+    /// Whether `reference` uses a name that is common in its file's language.
+    fn contains(&self, reference: &Location) -> bool {
+        self.0.get(&reference.name).is_some_and(|languages| {
+            crate::languages::language_for_path(Path::new(reference.path_str()))
+                .is_some_and(|language| languages.contains(&language))
+        })
+    }
+}
+
+/// Rust definitions behind FFI bindings, by the ecosystem that calls them
+/// and the name they are exported under.
+struct FfiTargets {
+    by_target: HashMap<crate::languages::Ecosystem, FxHashMap<Spur, Vec<Location>>>,
+}
+
+impl FfiTargets {
+    fn new(exports: &[FfiExport]) -> Self {
+        let mut by_target: HashMap<_, FxHashMap<Spur, Vec<Location>>> = HashMap::new();
+        for export in exports {
+            by_target
+                .entry(export.ecosystem)
+                .or_default()
+                .entry(export.exported)
+                .or_default()
+                .push(export.definition);
+        }
+        Self { by_target }
+    }
+
+    /// Links a reference in `ecosystem`, or a namespace of it, to the Rust
+    /// definitions exported to it under the reference's name. Edges belong
+    /// to the Rust ecosystem, so the calling files join the Rust file graph.
+    fn edges(
+        &self,
+        reference: &Location,
+        ecosystem: crate::languages::Ecosystem,
+        definition_positions: &FxHashSet<(Spur, usize, usize)>,
+    ) -> Vec<ReferenceEdge> {
+        let Some(definitions) = self
+            .by_target
+            .get(&ecosystem.base())
+            .and_then(|exported| exported.get(&reference.name))
+        else {
+            return Vec::new();
+        };
+        if definition_positions.contains(&(reference.path, reference.line, reference.column)) {
+            return Vec::new();
+        }
+        definitions
+            .iter()
+            .map(|definition| ReferenceEdge {
+                definition: *definition,
+                usage: *reference,
+                ecosystem: crate::languages::Ecosystem::Rust,
+            })
+            .collect()
+    }
+}
+
 fn position(node: Node) -> (usize, usize) {
     let pos = node.start_position();
     (pos.row + 1, pos.column + 1)
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use lasso::Spur;
+
+use crate::error::CruxlinesError;
+use crate::intern::intern;
+
+/// Supplies per-file recency scores, such as an editor's recently opened
+/// files. Scores multiply the weight of references made from each file;
+/// files without a score count as `1.0`.
+pub trait FrecencyProvider: Send + Sync {
+    /// Scores keyed by the same paths the analyzed source reports.
+    fn scores(&self, repo_root: Option<&Path>) -> HashMap<PathBuf, f64>;
+}
+
+/// Where frecency scores come from.
+#[derive(Clone, Default)]
+pub enum Frecency {
+    /// Git history of the repo root, via `frecenfile`. Without the `native`
+    /// feature this behaves like [`Frecency::Disabled`].
+    #[default]
+    Git,
+    /// No frecency; every file counts as `1.0`. Keeps output reproducible.
+    Disabled,
+    Custom(Arc<dyn FrecencyProvider>),
+}
+
+impl fmt::Debug for Frecency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frecency::Git => f.write_str("Git"),
+            Frecency::Disabled => f.write_str("Disabled"),
+            Frecency::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Frecency {
+    pub(crate) fn scores(&self, repo_root: Option<&Path>) -> HashMap<Spur, f64> {
+        self.path_scores(repo_root)
+            .into_iter()
+            .map(|(path, score)| (intern(&path.to_string_lossy()), score))
+            .collect()
+    }
+
+    fn path_scores(&self, repo_root: Option<&Path>) -> HashMap<PathBuf, f64> {
+        match self {
+            Frecency::Git => git_scores(repo_root),
+            Frecency::Disabled => HashMap::new(),
+            Frecency::Custom(provider) => provider.scores(repo_root),
+        }
+    }
+}
+
+/// Scores read from a JSON object mapping paths to weights, such as an
+/// export of an editor's recently opened files:
+/// `{"src/app.py": 3.5, "src/db.py": 1.2}`. Relative paths are resolved
+/// against the repo root.
+#[derive(Debug, Clone, Default)]
+pub struct JsonFrecency {
+    scores: HashMap<PathBuf, f64>,
+}
+
+impl JsonFrecency {
+    pub fn from_file(path: &Path) -> Result<Self, CruxlinesError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|source| CruxlinesError::ReadFile {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Self::parse(&contents).map_err(|message| CruxlinesError::Frecency {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Parses the JSON object; every value must be a non-negative number.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| "expected an object of path to weight".to_string())?;
+        let mut scores = HashMap::with_capacity(object.len());
+        for (path, weight) in object {
+            let weight = weight
+                .as_f64()
+                .filter(|weight| *weight >= 0.0)
+                .ok_or_else(|| format!("weight of {path} is not a non-negative number"))?;
+            scores.insert(PathBuf::from(path), weight);
+        }
+        Ok(Self { scores })
+    }
+}
+
+impl FrecencyProvider for JsonFrecency {
+    fn scores(&self, repo_root: Option<&Path>) -> HashMap<PathBuf, f64> {
+        self.scores
+            .iter()
+            .map(|(path, weight)| match repo_root {
+                Some(root) if path.is_relative() => (root.join(path), *weight),
+                _ => (path.clone(), *weight),
+            })
+            .collect()
+    }
+}
+
+/// Several frecency sources merged into one. A file's score is the mean of
+/// its scores in each source, weighted by the source's blend weight; a
+/// source without a score for the file counts it as `1.0`, like the
+/// analysis does.
+#[derive(Clone, Default)]
+pub struct BlendedFrecency {
+    sources: Vec<(Frecency, f64)>,
+}
+
+impl BlendedFrecency {
+    /// `sources` pairs each source with its blend weight.
+    pub fn new(sources: impl IntoIterator<Item = (Frecency, f64)>) -> Self {
+        Self {
+            sources: sources.into_iter().collect(),
+        }
+    }
+}
+
+impl fmt::Debug for BlendedFrecency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.sources).finish()
+    }
+}
+
+impl FrecencyProvider for BlendedFrecency {
+    fn scores(&self, repo_root: Option<&Path>) -> HashMap<PathBuf, f64> {
+        let total: f64 = self.sources.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return HashMap::new();
+        }
+        let scores: Vec<(HashMap<PathBuf, f64>, f64)> = self
+            .sources
+            .iter()
+            .map(|(source, weight)| (source.path_scores(repo_root), *weight))
+            .collect();
+        let mut blended: HashMap<PathBuf, f64> = HashMap::new();
+        for (source_scores, _) in &scores {
+            for path in source_scores.keys() {
+                if blended.contains_key(path) {
+                    continue;
+                }
+                let sum: f64 = scores
+                    .iter()
+                    .map(|(scores, weight)| weight * scores.get(path).copied().unwrap_or(1.0))
+                    .sum();
+                blended.insert(path.clone(), sum / total);
+            }
+        }
+        blended
+    }
+}
+
+#[cfg(feature = "native")]
+fn git_scores(repo_root: Option<&Path>) -> HashMap<PathBuf, f64> {
+    let Some(repo_root) = repo_root else {
+        return HashMap::new();
+    };
+    // libgit2 doesn't read GIT_DIR, so open the metadata directory itself.
+    let Some(git_dir) = crate::repo::git_dir(repo_root) else {
+        return HashMap::new();
+    };
+    let Ok(scores) = frecenfile::analyze_repo(&git_dir, None, None) else {
+        return HashMap::new();
+    };
+    scores
+        .into_iter()
+        .map(|(path, score)| (repo_root.join(path), score))
+        .collect()
+}
+
+#[cfg(not(feature = "native"))]
+fn git_scores(_repo_root: Option<&Path>) -> HashMap<PathBuf, f64> {
+    HashMap::new()
+}
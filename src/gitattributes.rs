@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+const SKIPPED_ATTRIBUTES: &[&str] = &["linguist-generated", "linguist-vendored"];
+
+/// Answers whether files are marked `linguist-generated` or
+/// `linguist-vendored` by `.gitattributes` files inside the repo.
+pub(crate) struct GeneratedFiles {
+    repo_root: PathBuf,
+    matchers: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl GeneratedFiles {
+    pub(crate) fn new(repo_root: &Path) -> Self {
+        Self {
+            repo_root: repo_root.to_path_buf(),
+            matchers: HashMap::new(),
+        }
+    }
+
+    /// Checks `.gitattributes` from the file's directory up to the repo root;
+    /// the deepest file with a matching pattern decides.
+    pub(crate) fn is_generated(&mut self, path: &Path) -> bool {
+        if !path.starts_with(&self.repo_root) {
+            return false;
+        }
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if let Some(matcher) = self.matcher(current) {
+                match matcher.matched(path, false) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            if current == self.repo_root {
+                break;
+            }
+            dir = current.parent();
+        }
+        false
+    }
+
+    fn matcher(&mut self, dir: &Path) -> Option<&Gitignore> {
+        self.matchers
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| load_matcher(dir))
+            .as_ref()
+    }
+}
+
+/// Translates attribute lines into gitignore patterns: setting a skipped
+/// attribute adds the pattern, unsetting it adds a negated pattern. Both
+/// formats use last-match-wins, so precedence carries over.
+fn load_matcher(dir: &Path) -> Option<Gitignore> {
+    let contents = std::fs::read_to_string(dir.join(".gitattributes")).ok()?;
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut has_patterns = false;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        for attribute in fields {
+            let Some(skip) = skip_value(attribute) else {
+                continue;
+            };
+            let pattern = if skip {
+                pattern.to_string()
+            } else {
+                format!("!{pattern}")
+            };
+            if builder.add_line(None, &pattern).is_ok() {
+                has_patterns = true;
+            }
+        }
+    }
+    if !has_patterns {
+        return None;
+    }
+    builder.build().ok()
+}
+
+fn skip_value(attribute: &str) -> Option<bool> {
+    let (name, value) = match attribute.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (attribute, None),
+    };
+    let (name, unset) = match name.strip_prefix(['-', '!']) {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    if !SKIPPED_ATTRIBUTES.contains(&name) {
+        return None;
+    }
+    if unset {
+        return Some(false);
+    }
+    Some(!matches!(value, Some("false" | "0")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::skip_value;
+
+    #[test]
+    fn parses_linguist_attribute_values() {
+        assert_eq!(skip_value("linguist-generated"), Some(true));
+        assert_eq!(skip_value("linguist-vendored=true"), Some(true));
+        assert_eq!(skip_value("linguist-generated=false"), Some(false));
+        assert_eq!(skip_value("-linguist-vendored"), Some(false));
+        assert_eq!(skip_value("text"), None);
+    }
+}
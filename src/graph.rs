@@ -1,27 +1,306 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 
 use lasso::Spur;
 use petgraph::graph::{Graph, NodeIndex};
+use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 
+use crate::analysis::OutputRow;
 use crate::find_references::Location;
+use crate::intern::resolve;
 
-pub fn build_file_graph(
-    grouped: &HashMap<Location, Vec<Location>>,
+/// Granularity of an exported reference graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GraphLevel {
+    /// One node per file, edges from usage file to definition file.
+    File,
+    /// One node per definition, edges from the enclosing definition of a
+    /// usage to the referenced definition.
+    Symbol,
+}
+
+/// The file-level reference graph behind a set of rows, for running graph
+/// algorithms cruxlines does not ship (betweenness, community detection)
+/// on its extraction. Nodes are sorted by path, and edges by their nodes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileGraph {
+    pub nodes: Vec<FileNode>,
+    /// Edges from the file a reference is in to the file it refers to.
+    /// Same-file references are left out.
+    pub edges: Vec<FileEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileNode {
+    pub path: PathBuf,
+    /// File PageRank of files that define one of the rows; `None` for
+    /// files that only reference other files.
+    pub rank: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEdge {
+    /// Index in [`FileGraph::nodes`] of the referencing file.
+    pub from: usize,
+    /// Index in [`FileGraph::nodes`] of the defining file.
+    pub to: usize,
+    /// Number of references from `from` to definitions in `to`.
+    pub references: usize,
+}
+
+impl FileGraph {
+    /// Builds the graph from `rows`. Rows analyzed with
+    /// `Options::low_memory` have no reference locations, so they only
+    /// contribute nodes.
+    pub fn from_rows(rows: &[OutputRow]) -> Self {
+        let mut ranks: BTreeMap<&'static Path, Option<f64>> = BTreeMap::new();
+        let mut counts: BTreeMap<(&'static Path, &'static Path), usize> = BTreeMap::new();
+        for row in rows {
+            let definition_path = Path::new(row.definition.path_str());
+            ranks.insert(definition_path, Some(row.file_rank));
+            for reference in &row.references {
+                if reference.path == row.definition.path {
+                    continue;
+                }
+                let reference_path = Path::new(reference.path_str());
+                ranks.entry(reference_path).or_insert(None);
+                *counts.entry((reference_path, definition_path)).or_default() += 1;
+            }
+        }
+        let index: HashMap<&Path, usize> = ranks
+            .keys()
+            .enumerate()
+            .map(|(index, path)| (*path, index))
+            .collect();
+        Self {
+            nodes: ranks
+                .into_iter()
+                .map(|(path, rank)| FileNode {
+                    path: path.to_path_buf(),
+                    rank,
+                })
+                .collect(),
+            edges: counts
+                .into_iter()
+                .map(|((from, to), references)| FileEdge {
+                    from: index[from],
+                    to: index[to],
+                    references,
+                })
+                .collect(),
+        }
+    }
+
+    /// Index in [`FileGraph::nodes`] of `path`.
+    pub fn node(&self, path: &Path) -> Option<usize> {
+        self.nodes
+            .binary_search_by(|node| node.path.as_path().cmp(path))
+            .ok()
+    }
+
+    /// The same graph as a petgraph [`Graph`] weighted by reference counts,
+    /// with node indices matching [`FileGraph::nodes`].
+    pub fn to_petgraph(&self) -> Graph<PathBuf, usize> {
+        let mut graph = Graph::with_capacity(self.nodes.len(), self.edges.len());
+        for node in &self.nodes {
+            graph.add_node(node.path.clone());
+        }
+        for edge in &self.edges {
+            graph.add_edge(
+                NodeIndex::new(edge.from),
+                NodeIndex::new(edge.to),
+                edge.references,
+            );
+        }
+        graph
+    }
+}
+
+/// Renders the reference graph behind `rows` as GraphViz DOT. Node labels are
+/// paths relative to `repo_root` when given, and edge labels count references.
+pub fn to_dot(rows: &[OutputRow], level: GraphLevel, repo_root: Option<&Path>) -> String {
+    let grouped: HashMap<Location, Vec<Location>> = rows
+        .iter()
+        .map(|row| (row.definition, row.references.clone()))
+        .collect();
+    let label =
+        |path: &str| match repo_root.and_then(|root| Path::new(path).strip_prefix(root).ok()) {
+            Some(rel) => rel.display().to_string(),
+            None => path.to_string(),
+        };
+
+    let mut nodes: BTreeMap<String, String> = BTreeMap::new();
+    let mut edges: BTreeMap<(String, String), usize> = BTreeMap::new();
+    match level {
+        GraphLevel::File => {
+            for (definition, usages) in &grouped {
+                let def_id = label(definition.path_str());
+                nodes.insert(def_id.clone(), def_id.clone());
+                for usage in usages {
+                    if usage.path == definition.path {
+                        continue;
+                    }
+                    let use_id = label(usage.path_str());
+                    nodes.insert(use_id.clone(), use_id.clone());
+                    *edges.entry((use_id, def_id.clone())).or_default() += 1;
+                }
+            }
+        }
+        GraphLevel::Symbol => {
+            let definitions_by_path = definitions_by_path(&grouped);
+            let symbol_id = |location: &Location| {
+                let id = format!(
+                    "{}:{}:{}",
+                    label(location.path_str()),
+                    location.line,
+                    location.column
+                );
+                let text = format!("{}\\n{}", location.name_str(), id);
+                (id, text)
+            };
+            for (definition, usages) in &grouped {
+                let (def_id, def_label) = symbol_id(definition);
+                nodes.insert(def_id.clone(), def_label);
+                for usage in usages {
+                    let Some(enclosing) = enclosing_definition(&definitions_by_path, usage) else {
+                        continue;
+                    };
+                    if enclosing == *definition {
+                        continue;
+                    }
+                    let (use_id, use_label) = symbol_id(&enclosing);
+                    nodes.insert(use_id.clone(), use_label);
+                    *edges.entry((use_id, def_id.clone())).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let mut out = String::from("digraph cruxlines {\n");
+    for (id, text) in &nodes {
+        let _ = writeln!(
+            out,
+            "  \"{}\" [label=\"{}\"];",
+            escape(id),
+            escape_label(text)
+        );
+    }
+    for ((from, to), count) in &edges {
+        let _ = writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [label=\"{}\"];",
+            escape(from),
+            escape(to),
+            count
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Like `escape`, but keeps `\n` line breaks intact.
+fn escape_label(value: &str) -> String {
+    value
+        .split("\\n")
+        .map(escape)
+        .collect::<Vec<_>>()
+        .join("\\n")
+}
+
+/// Builds the file graph from (definition file, usage file) pairs, one or
+/// more per referenced definition. Every definition file becomes a node;
+/// same-file pairs add no edge.
+pub(crate) fn build_file_graph(
+    pairs: impl IntoIterator<Item = (Spur, Spur)>,
 ) -> (Graph<Spur, ()>, FxHashMap<Spur, NodeIndex>) {
     let mut graph: Graph<Spur, ()> = Graph::new();
     let mut indices: FxHashMap<Spur, NodeIndex> = FxHashMap::default();
     // Track existing edges to avoid duplicates
     let mut existing_edges: FxHashSet<(NodeIndex, NodeIndex)> = FxHashSet::default();
 
+    // Nodes are added in path order: PageRank sums in node order, and its
+    // floating-point result should not depend on hash map iteration or on
+    // the order in which paths were interned by parallel workers.
+    let mut pairs: Vec<(Spur, Spur)> = pairs.into_iter().collect();
+    pairs.sort_unstable_by(|a, b| (resolve(a.0), resolve(a.1)).cmp(&(resolve(b.0), resolve(b.1))));
+
+    for (definition_path, usage_path) in pairs {
+        let def_idx = node_index(&mut graph, &mut indices, definition_path);
+        if usage_path == definition_path {
+            continue;
+        }
+        let use_idx = node_index(&mut graph, &mut indices, usage_path);
+        // Only add edge if it doesn't already exist
+        if existing_edges.insert((use_idx, def_idx)) {
+            graph.add_edge(use_idx, def_idx, ());
+        }
+    }
+    (graph, indices)
+}
+
+/// Identifies the graph [`build_file_graph`] builds from `pairs`: its files
+/// and deduplicated edges, independent of the order they come in.
+pub(crate) fn file_graph_hash(pairs: &[(Spur, Spur)]) -> u64 {
+    use std::collections::BTreeSet;
+    use std::hash::{Hash, Hasher};
+
+    let mut nodes = BTreeSet::new();
+    let mut edges = BTreeSet::new();
+    for (definition_path, usage_path) in pairs {
+        let definition_path = resolve(*definition_path);
+        let usage_path = resolve(*usage_path);
+        nodes.insert(definition_path);
+        if usage_path != definition_path {
+            nodes.insert(usage_path);
+            edges.insert((usage_path, definition_path));
+        }
+    }
+    let mut hasher = rustc_hash::FxHasher::default();
+    nodes.hash(&mut hasher);
+    edges.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Builds a definition-level graph. Each usage is attributed to the closest
+/// definition above it in the same file, giving an edge from that enclosing
+/// definition to the referenced one. Usages above the first definition in a
+/// file have no enclosing symbol and are skipped.
+pub(crate) fn build_reference_graph(
+    grouped: &HashMap<Location, Vec<Location>>,
+) -> (Graph<Location, ()>, FxHashMap<Location, NodeIndex>) {
+    let mut graph: Graph<Location, ()> = Graph::new();
+    let mut indices: FxHashMap<Location, NodeIndex> = FxHashMap::default();
+    let mut existing_edges: FxHashSet<(NodeIndex, NodeIndex)> = FxHashSet::default();
+
+    let definitions_by_path = definitions_by_path(grouped);
+
+    // In location order for the same reason as in `build_file_graph`.
+    let mut grouped: Vec<_> = grouped.iter().collect();
+    grouped.sort_unstable_by_key(|(definition, _)| {
+        (
+            definition.path_str(),
+            definition.line,
+            definition.column,
+            definition.name_str(),
+        )
+    });
+
     for (definition, usages) in grouped {
-        let def_idx = node_index(&mut graph, &mut indices, definition.path);
+        let def_idx = symbol_index(&mut graph, &mut indices, *definition);
         for usage in usages {
-            if usage.path == definition.path {
+            let Some(enclosing) = enclosing_definition(&definitions_by_path, usage) else {
+                continue;
+            };
+            if enclosing == *definition {
                 continue;
             }
-            let use_idx = node_index(&mut graph, &mut indices, usage.path);
-            // Only add edge if it doesn't already exist
+            let use_idx = symbol_index(&mut graph, &mut indices, enclosing);
             if existing_edges.insert((use_idx, def_idx)) {
                 graph.add_edge(use_idx, def_idx, ());
             }
@@ -30,6 +309,245 @@ pub fn build_file_graph(
     (graph, indices)
 }
 
+/// Definitions reachable from `roots` by following references: a definition
+/// reaches every definition referenced inside it. `definitions` lists every
+/// definition, referenced or not, so that references inside unreferenced
+/// ones like `fn main` are attributed to them. Roots are always included.
+pub(crate) fn reachable_definitions<'a>(
+    grouped: impl Iterator<Item = (&'a Location, &'a Vec<Location>)>,
+    definitions: impl Iterator<Item = &'a Location>,
+    roots: impl Iterator<Item = Location>,
+) -> FxHashSet<Location> {
+    let definitions_by_path = sorted_by_path(definitions);
+    let mut referenced: FxHashMap<Location, Vec<Location>> = FxHashMap::default();
+    for (definition, usages) in grouped {
+        for usage in usages {
+            if let Some(enclosing) = enclosing_definition(&definitions_by_path, usage)
+                && enclosing != *definition
+            {
+                referenced.entry(enclosing).or_default().push(*definition);
+            }
+        }
+    }
+    let mut reached: FxHashSet<Location> = FxHashSet::default();
+    let mut pending: Vec<Location> = roots.collect();
+    while let Some(definition) = pending.pop() {
+        if reached.insert(definition)
+            && let Some(targets) = referenced.get(&definition)
+        {
+            pending.extend(targets.iter().filter(|target| !reached.contains(target)));
+        }
+    }
+    reached
+}
+
+fn definitions_by_path(
+    grouped: &HashMap<Location, Vec<Location>>,
+) -> FxHashMap<Spur, Vec<Location>> {
+    sorted_by_path(grouped.keys())
+}
+
+/// `definitions` by path, each list in position order.
+fn sorted_by_path<'a>(
+    definitions: impl Iterator<Item = &'a Location>,
+) -> FxHashMap<Spur, Vec<Location>> {
+    let mut definitions_by_path: FxHashMap<Spur, Vec<Location>> = FxHashMap::default();
+    for definition in definitions {
+        definitions_by_path
+            .entry(definition.path)
+            .or_default()
+            .push(*definition);
+    }
+    for definitions in definitions_by_path.values_mut() {
+        definitions.sort_by_key(|definition| (definition.line, definition.column));
+    }
+    definitions_by_path
+}
+
+fn enclosing_definition(
+    definitions_by_path: &FxHashMap<Spur, Vec<Location>>,
+    usage: &Location,
+) -> Option<Location> {
+    let definitions = definitions_by_path.get(&usage.path)?;
+    let position = (usage.line, usage.column);
+    let after =
+        definitions.partition_point(|definition| (definition.line, definition.column) <= position);
+    after.checked_sub(1).map(|index| definitions[index])
+}
+
+fn symbol_index(
+    graph: &mut Graph<Location, ()>,
+    indices: &mut FxHashMap<Location, NodeIndex>,
+    definition: Location,
+) -> NodeIndex {
+    *indices
+        .entry(definition)
+        .or_insert_with(|| graph.add_node(definition))
+}
+
+/// PageRank with `iterations` rounds, stopping early once the ranks move
+/// less than petgraph's default tolerance. Random jumps, and the rank of
+/// nodes without outgoing edges, go to every node evenly, or in proportion
+/// to `personalization` when given (one weight per node, summing to one).
+/// Unlike `petgraph::algo::page_rank::parallel_page_rank`, every sum is
+/// taken in node order, so the result is the same whatever the thread
+/// count.
+pub(crate) fn page_rank<N>(
+    graph: &Graph<N, ()>,
+    damping: f64,
+    iterations: usize,
+    personalization: Option<&[f64]>,
+) -> Vec<f64>
+where
+    N: Sync,
+{
+    let node_count = graph.node_count();
+    if node_count == 0 {
+        return Vec::new();
+    }
+    let nb = node_count as f64;
+    let jump = |node: usize| personalization.map_or(1.0 / nb, |weights| weights[node]);
+    let out_degrees: Vec<f64> = graph
+        .node_indices()
+        .map(|node| graph.neighbors(node).count() as f64)
+        .collect();
+    let mut incoming: Vec<Vec<usize>> = vec![Vec::new(); node_count];
+    for edge in graph.raw_edges() {
+        incoming[edge.target().index()].push(edge.source().index());
+    }
+    for sources in &mut incoming {
+        sources.sort_unstable();
+    }
+
+    let mut ranks = vec![1.0 / nb; node_count];
+    for _ in 0..iterations {
+        // Rank handed out by jumping: random jumps, plus all the rank of
+        // nodes without outgoing edges.
+        let jumping: f64 = ranks
+            .iter()
+            .zip(&out_degrees)
+            .map(|(rank, out_degree)| {
+                if *out_degree == 0.0 {
+                    damping * rank
+                } else {
+                    (1.0 - damping) * rank
+                }
+            })
+            .sum();
+        let pi: Vec<f64> = incoming
+            .par_iter()
+            .enumerate()
+            .map(|(node, sources)| {
+                let jump = jump(node);
+                sources.iter().fold(jumping * jump, |total, source| {
+                    let rank = ranks[*source];
+                    total + damping * rank / out_degrees[*source] - (1.0 - damping) * rank * jump
+                })
+            })
+            .collect();
+        let sum: f64 = pi.iter().sum();
+        let new_ranks: Vec<f64> = pi.iter().map(|rank| rank / sum).collect();
+        let squared_norm: f64 = new_ranks
+            .iter()
+            .zip(&ranks)
+            .map(|(new, old)| (new - old) * (new - old))
+            .sum();
+        if squared_norm <= 1e-6 {
+            return ranks;
+        }
+        ranks = new_ranks;
+    }
+    ranks
+}
+
+/// Brandes' betweenness centrality: for each node, how many shortest paths
+/// between two other nodes pass through it, paths with ties counting
+/// fractionally. Sources run in parallel a batch at a time, keeping one
+/// dependency vector per thread in memory, and contributions are summed in
+/// source order, so the result is the same whatever the thread count.
+pub(crate) fn betweenness<N>(graph: &Graph<N, ()>) -> Vec<f64>
+where
+    N: Sync,
+{
+    let node_count = graph.node_count();
+    let outgoing: Vec<Vec<usize>> = graph
+        .node_indices()
+        .map(|node| graph.neighbors(node).map(|next| next.index()).collect())
+        .collect();
+    let batch = rayon::current_num_threads().max(1);
+    let mut centrality = vec![0.0; node_count];
+    for start in (0..node_count).step_by(batch) {
+        let contributions: Vec<Vec<f64>> = (start..node_count.min(start + batch))
+            .into_par_iter()
+            .map(|source| dependencies(source, &outgoing))
+            .collect();
+        for dependency in contributions {
+            for (total, value) in centrality.iter_mut().zip(dependency) {
+                *total += value;
+            }
+        }
+    }
+    centrality
+}
+
+/// How much each node lies on the shortest paths from `source` to the
+/// others: one Brandes pass.
+fn dependencies(source: usize, outgoing: &[Vec<usize>]) -> Vec<f64> {
+    let node_count = outgoing.len();
+    // Breadth-first search from `source`, counting shortest paths.
+    let mut order = Vec::with_capacity(node_count);
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); node_count];
+    let mut paths = vec![0.0_f64; node_count];
+    let mut distance = vec![usize::MAX; node_count];
+    paths[source] = 1.0;
+    distance[source] = 0;
+    let mut queue = std::collections::VecDeque::from([source]);
+    while let Some(node) = queue.pop_front() {
+        order.push(node);
+        for &next in &outgoing[node] {
+            if distance[next] == usize::MAX {
+                distance[next] = distance[node] + 1;
+                queue.push_back(next);
+            }
+            if distance[next] == distance[node] + 1 {
+                paths[next] += paths[node];
+                predecessors[next].push(node);
+            }
+        }
+    }
+    // Back-propagate dependencies from the farthest nodes.
+    let mut dependency = vec![0.0_f64; node_count];
+    for &node in order.iter().rev() {
+        for &previous in &predecessors[node] {
+            dependency[previous] += paths[previous] / paths[node] * (1.0 + dependency[node]);
+        }
+    }
+    dependency[source] = 0.0;
+    dependency
+}
+
+/// The number of edges pointing at each node.
+pub(crate) fn in_degrees<N>(graph: &Graph<N, ()>) -> Vec<f64> {
+    let mut degrees = vec![0.0; graph.node_count()];
+    for edge in graph.raw_edges() {
+        degrees[edge.target().index()] += 1.0;
+    }
+    degrees
+}
+
+/// Scales `values` to sum to one, like PageRank. All zeros, e.g. the
+/// betweenness of a graph without paths of two edges, become uniform.
+pub(crate) fn normalized(mut values: Vec<f64>) -> Vec<f64> {
+    let total: f64 = values.iter().sum();
+    if total > 0.0 {
+        values.iter_mut().for_each(|value| *value /= total);
+    } else {
+        let uniform = 1.0 / values.len().max(1) as f64;
+        values.iter_mut().for_each(|value| *value = uniform);
+    }
+    values
+}
+
 fn node_index(
     graph: &mut Graph<Spur, ()>,
     indices: &mut FxHashMap<Spur, NodeIndex>,
@@ -46,10 +564,15 @@ fn node_index(
 
 #[cfg(test)]
 mod tests {
-    use super::build_file_graph;
+    use super::{
+        GraphLevel, betweenness, build_file_graph, build_reference_graph, in_degrees, normalized,
+        page_rank, to_dot,
+    };
+    use crate::analysis::cruxlines_from_inputs;
     use crate::find_references::Location;
     use crate::intern::intern;
     use std::collections::HashMap;
+    use std::path::PathBuf;
 
     #[test]
     fn builds_file_graph_with_cross_file_edges() {
@@ -65,12 +588,129 @@ mod tests {
             column: 1,
             name: intern("foo"),
         };
-        let mut grouped: HashMap<Location, Vec<Location>> = HashMap::new();
-        grouped.insert(def, vec![usage]);
-
-        let (graph, indices) = build_file_graph(&grouped);
+        let (graph, indices) = build_file_graph([(def.path, usage.path)]);
         let def_idx = indices.get(&def.path).expect("def node");
         let use_idx = indices.get(&usage.path).expect("use node");
         assert!(graph.contains_edge(*use_idx, *def_idx));
     }
+
+    #[test]
+    fn page_rank_matches_petgraph_and_ignores_pair_order() {
+        let pairs = [
+            ("a.py", "b.py"),
+            ("a.py", "c.py"),
+            ("b.py", "c.py"),
+            ("d.py", "c.py"),
+        ]
+        .map(|(definition, usage)| (intern(definition), intern(usage)));
+        let (graph, indices) = build_file_graph(pairs);
+        let ranks = page_rank(&graph, 0.85, 5, None);
+        let expected = petgraph::algo::page_rank::parallel_page_rank(&graph, 0.85, 5, None);
+        for (rank, expected) in ranks.iter().zip(&expected) {
+            assert!((rank - expected).abs() < 1e-12, "{ranks:?} vs {expected:?}");
+        }
+
+        let mut reversed = pairs;
+        reversed.reverse();
+        let (reversed_graph, reversed_indices) = build_file_graph(reversed);
+        let reversed_ranks = page_rank(&reversed_graph, 0.85, 5, None);
+        for (path, index) in &indices {
+            assert_eq!(
+                ranks[index.index()].to_bits(),
+                reversed_ranks[reversed_indices[path].index()].to_bits()
+            );
+        }
+    }
+
+    #[test]
+    fn betweenness_favors_the_bridge_of_a_chain() {
+        // app -> mid -> base, plus app -> base directly and tool -> mid.
+        let pairs = [
+            ("mid.py", "app.py"),
+            ("base.py", "mid.py"),
+            ("base.py", "app.py"),
+            ("mid.py", "tool.py"),
+        ]
+        .map(|(definition, usage)| (intern(definition), intern(usage)));
+        let (graph, indices) = build_file_graph(pairs);
+        let at = |values: &[f64], path: &str| values[indices[&intern(path)].index()];
+
+        let between = betweenness(&graph);
+        // Only tool -> base passes through mid; app reaches base directly.
+        assert_eq!(at(&between, "mid.py"), 1.0);
+        assert_eq!(at(&between, "app.py"), 0.0);
+        assert_eq!(at(&between, "base.py"), 0.0);
+
+        let degrees = in_degrees(&graph);
+        assert_eq!(at(&degrees, "base.py"), 2.0);
+        assert_eq!(at(&degrees, "mid.py"), 2.0);
+        assert_eq!(at(&degrees, "tool.py"), 0.0);
+
+        assert_eq!(normalized(vec![1.0, 3.0]), vec![0.25, 0.75]);
+        assert_eq!(normalized(vec![0.0, 0.0]), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn builds_reference_graph_from_enclosing_definitions() {
+        let location = |path: &str, line: usize, name: &str| Location {
+            path: intern(path),
+            line,
+            column: 5,
+            name: intern(name),
+        };
+        let helper = location("a.py", 1, "helper");
+        let caller = location("b.py", 1, "caller");
+        let helper_use = location("b.py", 2, "helper");
+        let caller_use = location("c.py", 3, "caller");
+        let mut grouped: HashMap<Location, Vec<Location>> = HashMap::new();
+        grouped.insert(helper, vec![helper_use]);
+        grouped.insert(caller, vec![caller_use]);
+
+        let (graph, indices) = build_reference_graph(&grouped);
+        let helper_idx = indices.get(&helper).expect("helper node");
+        let caller_idx = indices.get(&caller).expect("caller node");
+        assert!(graph.contains_edge(*caller_idx, *helper_idx));
+        assert_eq!(graph.edge_count(), 1);
+    }
+
+    #[test]
+    fn renders_file_graph_as_dot_with_edge_counts() {
+        let inputs = vec![
+            (
+                PathBuf::from("/repo/a.py"),
+                "def foo():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("/repo/b.py"),
+                "from a import foo\n\nfoo()\n".to_string(),
+            ),
+        ];
+        let rows = cruxlines_from_inputs(inputs, None);
+        let dot = to_dot(&rows, GraphLevel::File, Some(std::path::Path::new("/repo")));
+        assert!(dot.starts_with("digraph cruxlines {"));
+        assert!(dot.contains("\"a.py\" [label=\"a.py\"];"), "{dot}");
+        assert!(dot.contains("\"b.py\" -> \"a.py\" [label=\"2\"];"), "{dot}");
+    }
+
+    #[test]
+    fn renders_symbol_graph_as_dot() {
+        let inputs = vec![
+            (PathBuf::from("a.py"), "def foo():\n    pass\n".to_string()),
+            (
+                PathBuf::from("b.py"),
+                "from a import foo\n\ndef bar():\n    foo()\n".to_string(),
+            ),
+            (
+                PathBuf::from("c.py"),
+                "from b import bar\n\ndef baz():\n    bar()\n".to_string(),
+            ),
+        ];
+        let rows = cruxlines_from_inputs(inputs, None);
+        let dot = to_dot(&rows, GraphLevel::Symbol, None);
+        assert!(
+            dot.contains("\"b.py:3:5\" -> \"a.py:1:5\" [label=\"1\"];"),
+            "{dot}"
+        );
+        assert!(dot.contains("[label=\"foo\\na.py:1:5\"]"), "{dot}");
+    }
 }
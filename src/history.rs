@@ -0,0 +1,94 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::analysis::OutputRow;
+use crate::diff::{Renames, rank_changes_with_renames};
+use crate::error::CruxlinesError;
+use crate::find_references::Location;
+use crate::source::git;
+
+/// A commit picked to stand for one point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub commit: String,
+    /// Committer date, `YYYY-MM-DD`.
+    pub date: String,
+}
+
+/// Picks the commit on `HEAD` that was current every `every`, going back
+/// `last` from the newest commit, oldest first. Points where the same commit
+/// was still current are kept once, and sampling stops at the first commit.
+pub fn sample_history(
+    repo_root: &Path,
+    every: Duration,
+    last: Duration,
+) -> Result<Vec<Sample>, CruxlinesError> {
+    let every = every.as_secs().max(1);
+    let newest = log(repo_root, &["--format=%ct", "HEAD"])?;
+    let newest: u64 = newest.trim().parse().map_err(|_| CruxlinesError::Git {
+        revision: "HEAD".to_string(),
+        message: format!("unexpected commit time {newest:?}"),
+    })?;
+
+    let mut samples: Vec<Sample> = Vec::new();
+    for step in 0..=last.as_secs() / every {
+        let Some(at) = newest.checked_sub(step * every) else {
+            break;
+        };
+        let before = format!("--before=@{at}");
+        let line = log(repo_root, &["--format=%H %cs", &before, "HEAD"])?;
+        let Some((commit, date)) = line.trim().split_once(' ') else {
+            break;
+        };
+        if samples.last().is_some_and(|sample| sample.commit == commit) {
+            continue;
+        }
+        samples.push(Sample {
+            commit: commit.to_string(),
+            date: date.to_string(),
+        });
+    }
+    samples.reverse();
+    Ok(samples)
+}
+
+/// Rank of the definition named `name` in the file at `path`. When the file
+/// defines the name more than once, the highest-ranked row wins.
+pub fn symbol_rank(rows: &[OutputRow], path: &Path, name: &str) -> Option<f64> {
+    rows.iter()
+        .filter(|row| {
+            Path::new(row.definition.path_str()) == path && row.definition.name_str() == name
+        })
+        .map(|row| row.rank)
+        .reduce(f64::max)
+}
+
+/// The definition that the one named `name` in the file at `path` was
+/// renamed from between `before_revision` and `after_revision`, whose rows
+/// are `before` and `after`. `None` when it kept its path and name, or no
+/// earlier definition pairs with it.
+pub fn renamed_from(
+    repo_root: &Path,
+    before_revision: &str,
+    after_revision: &str,
+    before: &[OutputRow],
+    after: &[OutputRow],
+    path: &Path,
+    name: &str,
+) -> Result<Option<Location>, CruxlinesError> {
+    let renames = Renames::detect(repo_root, before_revision, after_revision, before, after)?;
+    Ok(rank_changes_with_renames(before, after, &renames)
+        .into_iter()
+        .find(|change| {
+            Path::new(change.definition.path_str()) == path && change.definition.name_str() == name
+        })
+        .and_then(|change| change.renamed_from))
+}
+
+/// Output of `git log -1` with `args`.
+fn log(repo_root: &Path, args: &[&str]) -> Result<String, CruxlinesError> {
+    let mut log_args = vec!["log", "-1"];
+    log_args.extend_from_slice(args);
+    let output = git(repo_root, "HEAD", &log_args)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
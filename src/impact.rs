@@ -0,0 +1,125 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use petgraph::Direction;
+use rustc_hash::FxHashMap;
+
+use crate::analysis::OutputRow;
+use crate::find_references::Location;
+use crate::graph::build_reference_graph;
+
+/// A definition that depends, directly or transitively, on a definition in
+/// one of the changed files.
+#[derive(Debug, Clone)]
+pub struct ImpactedDefinition {
+    pub definition: Location,
+    pub rank: f64,
+    /// Reference hops from the nearest changed definition; `1` means it
+    /// references a changed definition directly.
+    pub depth: usize,
+}
+
+/// Walks the symbol-level reference graph behind `rows` backwards from every
+/// definition in `changed_paths` (files or directories) and returns the
+/// definitions outside them that reach one, highest rank first.
+///
+/// Usages are attributed to their enclosing definition as in
+/// [`GraphLevel::Symbol`](crate::graph::GraphLevel::Symbol), so code above a
+/// file's first definition doesn't propagate the impact.
+pub fn impacted_definitions(
+    rows: &[OutputRow],
+    changed_paths: &[PathBuf],
+) -> Vec<ImpactedDefinition> {
+    let is_changed = |definition: &Location| {
+        let path = Path::new(definition.path_str());
+        changed_paths
+            .iter()
+            .any(|changed| path.starts_with(changed))
+    };
+    let grouped: HashMap<Location, Vec<Location>> = rows
+        .iter()
+        .map(|row| (row.definition, row.references.clone()))
+        .collect();
+    let (graph, indices) = build_reference_graph(&grouped);
+
+    let mut depths = FxHashMap::default();
+    let mut queue = VecDeque::new();
+    for (definition, index) in &indices {
+        if is_changed(definition) {
+            depths.insert(*index, 0);
+            queue.push_back(*index);
+        }
+    }
+    while let Some(index) = queue.pop_front() {
+        let depth = depths[&index] + 1;
+        for dependent in graph.neighbors_directed(index, Direction::Incoming) {
+            depths.entry(dependent).or_insert_with(|| {
+                queue.push_back(dependent);
+                depth
+            });
+        }
+    }
+
+    let ranks: HashMap<Location, f64> = rows.iter().map(|row| (row.definition, row.rank)).collect();
+    let mut impacted: Vec<ImpactedDefinition> = depths
+        .into_iter()
+        .filter(|(_, depth)| *depth > 0)
+        .map(|(index, depth)| {
+            let definition = graph[index];
+            ImpactedDefinition {
+                definition,
+                rank: ranks.get(&definition).copied().unwrap_or(0.0),
+                depth,
+            }
+        })
+        .filter(|impact| !is_changed(&impact.definition))
+        .collect();
+    impacted.sort_by(|a, b| {
+        b.rank
+            .partial_cmp(&a.rank)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| {
+                let key_a = (a.definition.path_str(), a.definition.line);
+                let key_b = (b.definition.path_str(), b.definition.line);
+                key_a.cmp(&key_b)
+            })
+    });
+    impacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::impacted_definitions;
+    use crate::analysis::cruxlines_from_inputs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn follows_references_transitively_from_changed_files() {
+        let rows = cruxlines_from_inputs(
+            vec![
+                (PathBuf::from("a.py"), "def foo():\n    pass\n".to_string()),
+                (
+                    PathBuf::from("b.py"),
+                    "from a import foo\n\ndef bar():\n    foo()\n".to_string(),
+                ),
+                (
+                    PathBuf::from("c.py"),
+                    "from b import bar\n\ndef baz():\n    bar()\n\nbaz()\n".to_string(),
+                ),
+                (
+                    PathBuf::from("d.py"),
+                    "def unrelated():\n    pass\n\nunrelated()\n".to_string(),
+                ),
+            ],
+            None,
+        );
+
+        let impacted = impacted_definitions(&rows, &[PathBuf::from("a.py")]);
+        let mut summary: Vec<_> = impacted
+            .iter()
+            .map(|impact| (impact.definition.name_str(), impact.depth))
+            .collect();
+        summary.sort();
+        assert_eq!(summary, vec![("bar", 1), ("baz", 2)]);
+    }
+}
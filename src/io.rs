@@ -1,21 +1,87 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
 
 use ignore::WalkBuilder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
 
 use crate::Ecosystem;
+use crate::error::CruxlinesError;
+use crate::gitattributes::GeneratedFiles;
+use crate::options::{Options, ScanOptions};
+use crate::progress::Phase;
+use crate::repo::common_dir;
 
-#[derive(Debug)]
-pub enum CruxlinesError {
-    ReadFile {
-        path: PathBuf,
-        source: std::io::Error,
-    },
+/// Walks `roots` (files or directories) and collects source files for the
+/// selected ecosystems, honoring ignore files, hidden files and symlinks as
+/// `options.scan` says.
+/// Files marked generated or vendored in `.gitattributes` are skipped unless
+/// `options.include_generated` is set, and `options.include`/`exclude` globs
+/// narrow the walk further.
+pub fn gather_paths(
+    repo_root: &Path,
+    roots: &[PathBuf],
+    ecosystems: &HashSet<Ecosystem>,
+    options: &Options,
+) -> Result<Vec<PathBuf>, CruxlinesError> {
+    gather_paths_counted(repo_root, roots, ecosystems, options).map(|(paths, _)| paths)
 }
 
-pub fn gather_paths(repo_root: &PathBuf, ecosystems: &HashSet<Ecosystem>) -> Vec<PathBuf> {
-    let builder = WalkBuilder::new(repo_root);
+/// Files [`gather_paths`] left out because of the `ScanOptions` limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SkippedFiles {
+    /// Larger than `max_filesize`.
+    pub large: usize,
+    /// Found after `max_files` were collected.
+    pub excess: usize,
+}
 
+/// [`gather_paths`], also counting the files skipped by the size and count
+/// limits.
+pub fn gather_paths_counted(
+    repo_root: &Path,
+    roots: &[PathBuf],
+    ecosystems: &HashSet<Ecosystem>,
+    options: &Options,
+) -> Result<(Vec<PathBuf>, SkippedFiles), CruxlinesError> {
+    let mut skipped = SkippedFiles::default();
+    let Some((first, rest)) = roots.split_first() else {
+        return Ok((Vec::new(), skipped));
+    };
+    let mut builder = WalkBuilder::new(first);
+    for root in rest {
+        builder.add(root);
+    }
+    let scan = options.scan;
+    builder
+        .git_ignore(scan.respect_gitignore)
+        .git_exclude(scan.respect_gitignore)
+        .ignore(scan.respect_gitignore)
+        .parents(scan.respect_gitignore)
+        .git_global(scan.respect_global_gitignore)
+        .require_git(false)
+        .hidden(!scan.hidden)
+        .follow_links(scan.follow_symlinks);
+    if scan.max_files.is_some() {
+        builder.sort_by_file_name(|a, b| a.cmp(b));
+    }
+    // With symlinks followed, several paths can lead to one directory or
+    // file, and links can form cycles; visit each device and inode once.
+    let visited = scan.follow_symlinks.then(|| Mutex::new(HashSet::new()));
+    builder.filter_entry(move |entry| {
+        entry.file_name() != ".git"
+            && visited
+                .as_ref()
+                .is_none_or(|visited| first_visit(visited, entry.path()))
+    });
+    // Excludes go through the walker so whole directories are pruned.
+    // Includes are checked per file instead, because a whitelisting override
+    // would also bypass .gitignore.
+    builder.overrides(build_override(repo_root, &options.exclude, "!")?);
+    let includes = build_override(repo_root, &options.include, "")?;
+
+    let mut generated = (!options.include_generated).then(|| GeneratedFiles::new(repo_root));
     let mut paths = Vec::new();
     for entry in builder.build() {
         let entry = match entry {
@@ -30,14 +96,175 @@ pub fn gather_paths(repo_root: &PathBuf, ecosystems: &HashSet<Ecosystem>) -> Vec
             continue;
         }
         let path = entry.path();
-        let Some(ecosystem) = crate::ecosystem_for_path(path) else {
+        let pipeline = options.pipeline_references && crate::pipelines::is_pipeline_file(path);
+        if !pipeline
+            && !crate::ecosystem_for_path(path)
+                .is_some_and(|ecosystem| ecosystems.contains(&ecosystem))
+        {
             continue;
-        };
-        if !ecosystems.contains(&ecosystem) {
+        }
+        if !includes.is_empty() && !includes.matched(path, false).is_whitelist() {
+            continue;
+        }
+        if let Some(generated) = generated.as_mut()
+            && generated.is_generated(path)
+        {
+            continue;
+        }
+        if let Some(max_filesize) = scan.max_filesize
+            && entry
+                .metadata()
+                .is_ok_and(|metadata| metadata.len() > max_filesize)
+        {
+            skipped.large += 1;
+            continue;
+        }
+        // The rest of the walk only counts what is left out.
+        if scan
+            .max_files
+            .is_some_and(|max_files| paths.len() >= max_files)
+        {
+            skipped.excess += 1;
             continue;
         }
         paths.push(path.to_path_buf());
+        if let Some(progress) = &options.progress {
+            progress.report(Phase::Scan, paths.len(), None);
+        }
+    }
+
+    Ok((paths, skipped))
+}
+
+/// The ignore rules of [`gather_paths`], for paths that do not come from a
+/// walk, such as the inputs of
+/// [`cruxlines_from_inputs`](crate::cruxlines_from_inputs). Each
+/// directory's ignore files are read on first use.
+pub(crate) struct IgnoreRules {
+    repo_root: PathBuf,
+    scan: ScanOptions,
+    directories: HashMap<PathBuf, Option<Gitignore>>,
+    exclude: Option<Gitignore>,
+    global: Option<Gitignore>,
+}
+
+impl IgnoreRules {
+    pub(crate) fn new(repo_root: &Path, scan: ScanOptions) -> Self {
+        let exclude = scan
+            .respect_gitignore
+            .then(|| {
+                let common_dir = common_dir(repo_root).unwrap_or_else(|| repo_root.join(".git"));
+                ignore_file(repo_root, &[common_dir.join("info/exclude")])
+            })
+            .flatten();
+        let global = scan
+            .respect_global_gitignore
+            .then(|| Gitignore::global().0)
+            .filter(|global| !global.is_empty());
+        Self {
+            repo_root: repo_root.to_path_buf(),
+            scan,
+            directories: HashMap::new(),
+            exclude,
+            global,
+        }
     }
 
-    paths
+    /// Whether a walk from the repo root would skip `path`, which is
+    /// absolute or relative to the repo root.
+    pub(crate) fn is_ignored(&mut self, path: &Path) -> bool {
+        let path = self.repo_root.join(path);
+        let Ok(relative) = path.strip_prefix(&self.repo_root) else {
+            return false;
+        };
+        let hidden = relative.components().any(|component| {
+            matches!(component, Component::Normal(name) if name.to_string_lossy().starts_with('.'))
+        });
+        if hidden && !self.scan.hidden {
+            return true;
+        }
+        if self.scan.respect_gitignore {
+            // The deepest directory with a matching rule decides.
+            let directories: Vec<PathBuf> = path
+                .ancestors()
+                .skip(1)
+                .take_while(|directory| directory.starts_with(&self.repo_root))
+                .map(Path::to_path_buf)
+                .collect();
+            for directory in directories {
+                let rules = self
+                    .directories
+                    .entry(directory)
+                    .or_insert_with_key(|directory| {
+                        ignore_file(
+                            directory,
+                            &[directory.join(".gitignore"), directory.join(".ignore")],
+                        )
+                    });
+                if let Some(rules) = rules {
+                    let matched = rules.matched_path_or_any_parents(&path, false);
+                    if !matched.is_none() {
+                        return matched.is_ignore();
+                    }
+                }
+            }
+        }
+        [&self.exclude, &self.global]
+            .into_iter()
+            .flatten()
+            .map(|rules| rules.matched_path_or_any_parents(&path, false))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+}
+
+/// The rules of those `files` that exist, rooted at `directory`.
+fn ignore_file(directory: &Path, files: &[PathBuf]) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(directory);
+    let mut found = false;
+    for file in files.iter().filter(|file| file.is_file()) {
+        found |= builder.add(file).is_none();
+    }
+    found.then(|| builder.build().ok()).flatten()
+}
+
+/// Records the device and inode `path` resolves to, and whether they were
+/// new. Paths whose metadata cannot be read count as new.
+#[cfg(unix)]
+fn first_visit(visited: &Mutex<HashSet<(u64, u64)>>, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return true;
+    };
+    visited.lock().map_or(true, |mut visited| {
+        visited.insert((metadata.dev(), metadata.ino()))
+    })
+}
+
+/// Without inodes to compare, only the walker's own loop detection applies.
+#[cfg(not(unix))]
+fn first_visit(_visited: &Mutex<HashSet<(u64, u64)>>, _path: &Path) -> bool {
+    true
+}
+
+/// Builds an override from `patterns`, each prefixed with `prefix` (`!` turns
+/// a pattern into an exclusion).
+pub(crate) fn build_override(
+    repo_root: &Path,
+    patterns: &[String],
+    prefix: &str,
+) -> Result<Override, CruxlinesError> {
+    let mut builder = OverrideBuilder::new(repo_root);
+    for pattern in patterns {
+        builder
+            .add(&format!("{prefix}{pattern}"))
+            .map_err(|err| CruxlinesError::Pattern {
+                pattern: pattern.clone(),
+                message: err.to_string(),
+            })?;
+    }
+    builder.build().map_err(|err| CruxlinesError::Pattern {
+        pattern: patterns.join(" "),
+        message: err.to_string(),
+    })
 }
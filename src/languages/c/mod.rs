@@ -2,10 +2,19 @@ use std::path::Path;
 
 use tree_sitter::Node;
 
-use crate::find_references::{Location, location_from_node, walk_tree};
+use crate::find_references::{IMPORT_ALL, ImportBinding, Location, location_from_node, walk_tree};
+use crate::intern::intern;
+use crate::languages::{SymbolKind, normalize_path};
 
 pub(crate) const EXTENSIONS: &[&str] = &["c", "h"];
-pub(crate) const REFERENCE_KINDS: &[&str] = &["identifier", "type_identifier", "field_identifier"];
+/// C and C++ header extensions, whose function prototypes are declarations.
+const HEADER_EXTENSIONS: &[&str] = &["h", "hpp", "hh", "hxx"];
+pub(crate) const REFERENCES_QUERY: &str = include_str!("references.scm");
+/// C standard library functions.
+pub(crate) const COMMON_NAMES: &[&str] = &[
+    "main", "printf", "fprintf", "sprintf", "snprintf", "malloc", "calloc", "realloc", "free",
+    "memcpy", "memset", "strlen", "strcmp", "init", "size",
+];
 
 pub(crate) fn language() -> tree_sitter::Language {
     tree_sitter_c::LANGUAGE.into()
@@ -15,50 +24,56 @@ pub(crate) fn emit_definitions(
     path: &Path,
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut(Location, SymbolKind),
 ) {
     walk_tree(tree, |node| match node.kind() {
         "function_definition" => {
-            if is_top_level(node) {
-                if let Some(declarator) = node.child_by_field_name("declarator") {
-                    if let Some(name) = find_identifier_in_declarator(declarator) {
-                        if let Some(location) = location_from_node(path, source, name) {
-                            emit(location);
-                        }
-                    }
-                }
+            if is_top_level(node)
+                && let Some(declarator) = node.child_by_field_name("declarator")
+                && let Some(name) = find_identifier_in_declarator(declarator)
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                emit(location, SymbolKind::Function);
             }
         }
         "struct_specifier" | "enum_specifier" | "union_specifier" => {
-            if is_top_level_type_specifier(node) {
-                if let Some(name) = node.child_by_field_name("name") {
-                    if let Some(location) = location_from_node(path, source, name) {
-                        emit(location);
-                    }
-                }
+            // Without a body this is a forward declaration or a use.
+            if node.child_by_field_name("body").is_some()
+                && is_top_level_type_specifier(node)
+                && let Some(name) = node.child_by_field_name("name")
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                emit(location, type_specifier_kind(node));
             }
         }
         "type_definition" => {
-            if is_top_level(node) {
-                if let Some(declarator) = node.child_by_field_name("declarator") {
-                    if let Some(name) = find_identifier_in_declarator(declarator) {
-                        if let Some(location) = location_from_node(path, source, name) {
-                            emit(location);
-                        }
-                    }
+            if is_top_level(node)
+                && let Some(declarator) = node.child_by_field_name("declarator")
+                && let Some(name) = find_identifier_in_declarator(declarator)
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                emit(location, SymbolKind::Type);
+            }
+        }
+        // Function prototypes declare the API a header exports.
+        "declaration" if is_top_level(node) && is_function_declaration(node) && is_header(path) => {
+            let mut cursor = node.walk();
+            for child in node.children_by_field_name("declarator", &mut cursor) {
+                if let Some(name) = find_identifier_in_declarator(child)
+                    && let Some(location) = location_from_node(path, source, name)
+                {
+                    emit(location, SymbolKind::Function);
                 }
             }
         }
-        "declaration" => {
-            // Global variable declarations (can have multiple declarators like `int a, b, c;`)
-            if is_top_level(node) && !is_function_declaration(node) {
-                let mut cursor = node.walk();
-                for child in node.children_by_field_name("declarator", &mut cursor) {
-                    if let Some(name) = find_identifier_in_declarator(child) {
-                        if let Some(location) = location_from_node(path, source, name) {
-                            emit(location);
-                        }
-                    }
+        // Global variable declarations (can have multiple declarators like `int a, b, c;`)
+        "declaration" if is_top_level(node) && !is_function_declaration(node) => {
+            let mut cursor = node.walk();
+            for child in node.children_by_field_name("declarator", &mut cursor) {
+                if let Some(name) = find_identifier_in_declarator(child)
+                    && let Some(location) = location_from_node(path, source, name)
+                {
+                    emit(location, SymbolKind::Variable);
                 }
             }
         }
@@ -66,21 +81,63 @@ pub(crate) fn emit_definitions(
     });
 }
 
-pub(crate) fn emit_references(
+/// Emits a binding of every name for each quoted `#include "x.h"`. System
+/// includes (`<stdio.h>`) are skipped.
+pub(crate) fn emit_includes(
     path: &Path,
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut(ImportBinding),
 ) {
+    let file = intern(&path.to_string_lossy());
     walk_tree(tree, |node| {
-        if REFERENCE_KINDS.contains(&node.kind())
-            && let Some(location) = location_from_node(path, source, node)
+        if node.kind() == "preproc_include"
+            && let Some(included) = node.child_by_field_name("path")
+            && included.kind() == "string_literal"
+            && let Ok(text) = included.utf8_text(source.as_bytes())
         {
-            emit(location);
+            emit(ImportBinding {
+                path: file,
+                local: intern(IMPORT_ALL),
+                imported: intern(IMPORT_ALL),
+                specifier: text.trim_matches('"').to_string(),
+                site: None,
+            });
         }
     });
 }
 
+/// Resolves an `#include` path against the including file's directory, then
+/// against the scan root.
+pub(crate) fn resolve_include(
+    from: &str,
+    specifier: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Option<String> {
+    let relative = Path::new(from).parent()?.join(specifier);
+    [
+        normalize_path(&relative),
+        normalize_path(Path::new(specifier)),
+    ]
+    .into_iter()
+    .map(|candidate| candidate.to_string_lossy().to_string())
+    .find(|candidate| exists(candidate))
+}
+
+pub(crate) fn is_header(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| HEADER_EXTENSIONS.contains(&ext))
+}
+
+fn type_specifier_kind(node: Node) -> SymbolKind {
+    if node.kind() == "enum_specifier" {
+        SymbolKind::Enum
+    } else {
+        SymbolKind::Struct
+    }
+}
+
 fn is_top_level(node: Node) -> bool {
     node.parent()
         .is_some_and(|parent| parent.kind() == "translation_unit")
@@ -117,10 +174,10 @@ fn has_parameter_list(node: Node) -> bool {
         return true;
     }
     for i in 0..node.child_count() {
-        if let Some(child) = node.child(i) {
-            if has_parameter_list(child) {
-                return true;
-            }
+        if let Some(child) = node.child(i)
+            && has_parameter_list(child)
+        {
+            return true;
         }
     }
     false
@@ -141,10 +198,10 @@ fn find_identifier_in_declarator(node: Node) -> Option<Node> {
             } else {
                 // For parenthesized_declarator, sometimes we need to search children
                 for i in 0..node.child_count() {
-                    if let Some(child) = node.child(i) {
-                        if let Some(found) = find_identifier_in_declarator(child) {
-                            return Some(found);
-                        }
+                    if let Some(child) = node.child(i)
+                        && let Some(found) = find_identifier_in_declarator(child)
+                    {
+                        return Some(found);
                     }
                 }
                 None
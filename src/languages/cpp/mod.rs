@@ -3,13 +3,28 @@ use std::path::Path;
 use tree_sitter::Node;
 
 use crate::find_references::{Location, location_from_node, walk_tree};
+use crate::languages::SymbolKind;
 
 pub(crate) const EXTENSIONS: &[&str] = &["cpp", "cc", "cxx", "hpp", "hh", "hxx"];
-pub(crate) const REFERENCE_KINDS: &[&str] = &[
-    "identifier",
-    "type_identifier",
-    "field_identifier",
-    "qualified_identifier",
+pub(crate) const REFERENCES_QUERY: &str = include_str!("references.scm");
+/// Standard library names and member functions most containers share.
+pub(crate) const COMMON_NAMES: &[&str] = &[
+    "main",
+    "printf",
+    "malloc",
+    "free",
+    "memcpy",
+    "memset",
+    "strlen",
+    "std",
+    "string",
+    "vector",
+    "size",
+    "begin",
+    "end",
+    "get",
+    "init",
+    "push_back",
 ];
 
 pub(crate) fn language() -> tree_sitter::Language {
@@ -20,98 +35,92 @@ pub(crate) fn emit_definitions(
     path: &Path,
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut(Location, SymbolKind),
 ) {
     walk_tree(tree, |node| match node.kind() {
         "function_definition" => {
-            if is_top_level(node) {
-                if let Some(declarator) = node.child_by_field_name("declarator") {
-                    if let Some(name) = find_identifier_in_declarator(declarator) {
-                        if let Some(location) = location_from_node(path, source, name) {
-                            emit(location);
-                        }
-                    }
-                }
+            if is_top_level(node)
+                && let Some(declarator) = node.child_by_field_name("declarator")
+                && let Some(name) = find_identifier_in_declarator(declarator)
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                emit(location, function_kind(name));
             }
         }
         "class_specifier" | "struct_specifier" | "enum_specifier" | "union_specifier" => {
-            if is_top_level_type_specifier(node) {
-                if let Some(name) = node.child_by_field_name("name") {
-                    if let Some(location) = location_from_node(path, source, name) {
-                        emit(location);
-                    }
-                }
+            // Without a body this is a forward declaration or a use.
+            if node.child_by_field_name("body").is_some()
+                && is_top_level_type_specifier(node)
+                && let Some(name) = node.child_by_field_name("name")
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                emit(location, type_specifier_kind(node));
             }
         }
         "type_definition" => {
-            if is_top_level(node) {
-                if let Some(declarator) = node.child_by_field_name("declarator") {
-                    if let Some(name) = find_identifier_in_declarator(declarator) {
-                        if let Some(location) = location_from_node(path, source, name) {
-                            emit(location);
-                        }
-                    }
-                }
+            if is_top_level(node)
+                && let Some(declarator) = node.child_by_field_name("declarator")
+                && let Some(name) = find_identifier_in_declarator(declarator)
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                emit(location, SymbolKind::Type);
             }
         }
         "namespace_definition" => {
-            if is_top_level(node) {
-                if let Some(name) = node.child_by_field_name("name") {
-                    if let Some(location) = location_from_node(path, source, name) {
-                        emit(location);
-                    }
-                }
+            if is_top_level(node)
+                && let Some(name) = node.child_by_field_name("name")
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                emit(location, SymbolKind::Module);
             }
         }
         "declaration" => {
-            // Global variable declarations (not function declarations)
-            if is_top_level(node) && !is_function_declaration(node) {
-                if let Some(declarator) = node.child_by_field_name("declarator") {
-                    if let Some(name) = find_identifier_in_declarator(declarator) {
-                        if let Some(location) = location_from_node(path, source, name) {
-                            emit(location);
-                        }
-                    }
-                }
+            // Global variables, and function prototypes in headers
+            if is_top_level(node)
+                && (!is_function_declaration(node) || crate::languages::c::is_header(path))
+                && let Some(declarator) = node.child_by_field_name("declarator")
+                && let Some(name) = find_identifier_in_declarator(declarator)
+                && let Some(location) = location_from_node(path, source, name)
+            {
+                let kind = if is_function_declaration(node) {
+                    function_kind(name)
+                } else {
+                    SymbolKind::Variable
+                };
+                emit(location, kind);
             }
         }
-        "template_declaration" => {
-            // Template classes, structs, and functions
-            if is_top_level(node) {
-                // Find the actual declaration inside the template
-                for i in 0..node.child_count() {
-                    if let Some(child) = node.child(i) {
-                        match child.kind() {
-                            "class_specifier" | "struct_specifier" => {
-                                if let Some(name) = child.child_by_field_name("name") {
-                                    if let Some(location) = location_from_node(path, source, name) {
-                                        emit(location);
-                                    }
-                                }
+        // Template classes, structs, and functions
+        "template_declaration" if is_top_level(node) => {
+            // Find the actual declaration inside the template
+            for i in 0..node.child_count() {
+                if let Some(child) = node.child(i) {
+                    match child.kind() {
+                        "class_specifier" | "struct_specifier" => {
+                            if let Some(name) = child.child_by_field_name("name")
+                                && let Some(location) = location_from_node(path, source, name)
+                            {
+                                emit(location, type_specifier_kind(child));
                             }
-                            "function_definition" => {
-                                if let Some(declarator) = child.child_by_field_name("declarator") {
-                                    if let Some(name) = find_identifier_in_declarator(declarator) {
-                                        if let Some(location) = location_from_node(path, source, name)
-                                        {
-                                            emit(location);
-                                        }
-                                    }
-                                }
+                        }
+                        "function_definition" => {
+                            if let Some(declarator) = child.child_by_field_name("declarator")
+                                && let Some(name) = find_identifier_in_declarator(declarator)
+                                && let Some(location) = location_from_node(path, source, name)
+                            {
+                                emit(location, function_kind(name));
                             }
-                            "declaration" => {
-                                // Template function declaration (not definition)
-                                if let Some(declarator) = child.child_by_field_name("declarator") {
-                                    if let Some(name) = find_identifier_in_declarator(declarator) {
-                                        if let Some(location) = location_from_node(path, source, name)
-                                        {
-                                            emit(location);
-                                        }
-                                    }
-                                }
+                        }
+                        "declaration" => {
+                            // Template function declaration (not definition)
+                            if let Some(declarator) = child.child_by_field_name("declarator")
+                                && let Some(name) = find_identifier_in_declarator(declarator)
+                                && let Some(location) = location_from_node(path, source, name)
+                            {
+                                emit(location, function_kind(name));
                             }
-                            _ => {}
                         }
+                        _ => {}
                     }
                 }
             }
@@ -120,19 +129,24 @@ pub(crate) fn emit_definitions(
     });
 }
 
-pub(crate) fn emit_references(
-    path: &Path,
-    source: &str,
-    tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
-) {
-    walk_tree(tree, |node| {
-        if REFERENCE_KINDS.contains(&node.kind())
-            && let Some(location) = location_from_node(path, source, node)
-        {
-            emit(location);
-        }
-    });
+fn type_specifier_kind(node: Node) -> SymbolKind {
+    match node.kind() {
+        "class_specifier" => SymbolKind::Class,
+        "enum_specifier" => SymbolKind::Enum,
+        _ => SymbolKind::Struct,
+    }
+}
+
+/// Out-of-line member definitions like `Widget::draw` are methods.
+fn function_kind(name: Node) -> SymbolKind {
+    if name
+        .parent()
+        .is_some_and(|parent| parent.kind() == "qualified_identifier")
+    {
+        SymbolKind::Method
+    } else {
+        SymbolKind::Function
+    }
 }
 
 fn is_top_level(node: Node) -> bool {
@@ -153,12 +167,11 @@ fn is_top_level(node: Node) -> bool {
     }
 
     // Inside a declaration_list within a namespace
-    if parent_kind == "declaration_list" {
-        if let Some(grandparent) = parent.parent() {
-            if grandparent.kind() == "namespace_definition" {
-                return true;
-            }
-        }
+    if parent_kind == "declaration_list"
+        && let Some(grandparent) = parent.parent()
+        && grandparent.kind() == "namespace_definition"
+    {
+        return true;
     }
 
     false
@@ -181,12 +194,11 @@ fn is_top_level_type_specifier(node: Node) -> bool {
     }
 
     // Inside a declaration_list within a namespace
-    if parent_kind == "declaration_list" {
-        if let Some(grandparent) = parent.parent() {
-            if grandparent.kind() == "namespace_definition" {
-                return true;
-            }
-        }
+    if parent_kind == "declaration_list"
+        && let Some(grandparent) = parent.parent()
+        && grandparent.kind() == "namespace_definition"
+    {
+        return true;
     }
 
     // Check if parent is a type_definition or declaration at top level
@@ -209,10 +221,10 @@ fn has_function_declarator(node: Node) -> bool {
         return true;
     }
     for i in 0..node.child_count() {
-        if let Some(child) = node.child(i) {
-            if has_function_declarator(child) {
-                return true;
-            }
+        if let Some(child) = node.child(i)
+            && has_function_declarator(child)
+        {
+            return true;
         }
     }
     false
@@ -235,11 +247,7 @@ fn find_identifier_in_declarator(node: Node) -> Option<Node> {
         }
         "qualified_identifier" => {
             // For qualified identifiers like MyClass::method, get the name part
-            if let Some(name) = node.child_by_field_name("name") {
-                Some(name)
-            } else {
-                None
-            }
+            node.child_by_field_name("name")
         }
         _ => None,
     }
@@ -0,0 +1,7 @@
+; Identifiers linked to definitions of the same name.
+[
+  (identifier)
+  (type_identifier)
+  (field_identifier)
+  (qualified_identifier)
+] @reference
@@ -0,0 +1,22 @@
+; Types declared at the top level of a file or directly inside a namespace.
+
+(compilation_unit
+  [
+    (class_declaration name: (identifier) @definition.class)
+    (record_declaration name: (identifier) @definition.class)
+    (interface_declaration name: (identifier) @definition.interface)
+    (struct_declaration name: (identifier) @definition.struct)
+    (enum_declaration name: (identifier) @definition.enum)
+    (delegate_declaration name: (identifier) @definition.type)
+  ])
+
+(namespace_declaration
+  body: (declaration_list
+    [
+      (class_declaration name: (identifier) @definition.class)
+      (record_declaration name: (identifier) @definition.class)
+      (interface_declaration name: (identifier) @definition.interface)
+      (struct_declaration name: (identifier) @definition.struct)
+      (enum_declaration name: (identifier) @definition.enum)
+      (delegate_declaration name: (identifier) @definition.type)
+    ]))
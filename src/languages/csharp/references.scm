@@ -0,0 +1,5 @@
+; Identifiers linked to definitions of the same name.
+[
+  (identifier)
+  (generic_name)
+] @reference
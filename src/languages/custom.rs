@@ -0,0 +1,238 @@
+//! Languages registered at runtime from an external tree-sitter grammar and
+//! a pair of query files, see [`register_language`] and [`load_language`].
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::error::CruxlinesError;
+use crate::languages::Language;
+use crate::languages::query::LanguageQueries;
+
+/// A language described by a grammar and its queries rather than a module
+/// of this crate.
+pub struct CustomLanguage {
+    /// Name used in config files and error messages, e.g. `zig`.
+    pub name: String,
+    /// File extensions without the leading dot.
+    pub extensions: Vec<String>,
+    pub grammar: tree_sitter::Language,
+    /// Contents of a `definitions.scm`, capturing names as
+    /// `@definition.<kind>`.
+    pub definitions: String,
+    /// Contents of a `references.scm`, capturing identifiers as `@reference`.
+    pub references: String,
+}
+
+struct Registered {
+    name: &'static str,
+    extensions: Vec<String>,
+    grammar: tree_sitter::Language,
+    queries: LanguageQueries,
+    /// Query sources, part of the cache namespace so that editing them
+    /// invalidates cached results.
+    sources: String,
+}
+
+// Entries are leaked so that names and queries can be handed out as
+// `'static`, like those of the built-in languages. Registration happens a
+// handful of times per process.
+static REGISTRY: RwLock<Vec<&'static Registered>> = RwLock::new(Vec::new());
+
+fn registered(index: u16) -> &'static Registered {
+    REGISTRY.read().expect("language registry poisoned")[index as usize]
+}
+
+/// Makes `language` available to every scan in this process, as its own
+/// [`Ecosystem`](crate::Ecosystem). Fails when its name or an extension is
+/// already taken, or when its queries don't compile against the grammar.
+pub fn register_language(language: CustomLanguage) -> Result<Language, CruxlinesError> {
+    let error = |message: String| CruxlinesError::Language {
+        name: language.name.clone(),
+        message,
+    };
+    if language.extensions.is_empty() {
+        return Err(error("no file extensions given".to_string()));
+    }
+    if Language::from_name(&language.name).is_some() {
+        return Err(error(
+            "a language with this name already exists".to_string(),
+        ));
+    }
+    for extension in &language.extensions {
+        let path = PathBuf::from(format!("file.{extension}"));
+        if let Some(existing) = crate::languages::language_for_path(&path) {
+            return Err(error(format!(
+                "extension .{extension} is already used by {}",
+                existing.name()
+            )));
+        }
+    }
+    let queries = LanguageQueries::new(
+        &language.grammar,
+        Some(&language.definitions),
+        &language.references,
+    )
+    .map_err(error)?;
+
+    let mut registry = REGISTRY.write().expect("language registry poisoned");
+    let index = u16::try_from(registry.len())
+        .map_err(|_| error("too many custom languages".to_string()))?;
+    registry.push(Box::leak(Box::new(Registered {
+        name: Box::leak(language.name.into_boxed_str()),
+        extensions: language.extensions,
+        grammar: language.grammar,
+        queries,
+        sources: format!("{}\n{}", language.definitions, language.references),
+    })));
+    Ok(Language::Custom(index))
+}
+
+/// Loads the grammar compiled into the shared library at `library` and the
+/// `definitions.scm` and `references.scm` in `queries`, then registers it for
+/// files with the given `extensions`.
+///
+/// The language is named after the library file, so
+/// `libtree-sitter-zig.so` is `zig` and must export `tree_sitter_zig`.
+pub fn load_language(
+    library: &Path,
+    extensions: Vec<String>,
+    queries: &Path,
+) -> Result<Language, CruxlinesError> {
+    let name = library_language_name(library).ok_or_else(|| CruxlinesError::Language {
+        name: library.display().to_string(),
+        message: "cannot derive a language name from the file name".to_string(),
+    })?;
+    let error = |message: String| CruxlinesError::Language {
+        name: name.clone(),
+        message,
+    };
+    let read_query = |file: &str| {
+        let path = queries.join(file);
+        std::fs::read_to_string(&path)
+            .map_err(|err| error(format!("failed to read {}: {err}", path.display())))
+    };
+    let definitions = read_query("definitions.scm")?;
+    let references = read_query("references.scm")?;
+    let grammar = open_grammar(library, &format!("tree_sitter_{name}")).map_err(error)?;
+    if !(tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION)
+        .contains(&grammar.abi_version())
+    {
+        return Err(error(format!(
+            "grammar ABI version {} is not supported, expected {} to {}",
+            grammar.abi_version(),
+            tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION,
+            tree_sitter::LANGUAGE_VERSION
+        )));
+    }
+    register_language(CustomLanguage {
+        name,
+        extensions,
+        grammar,
+        definitions,
+        references,
+    })
+}
+
+/// `libtree-sitter-foo-bar.so` -> `foo_bar`.
+fn library_language_name(library: &Path) -> Option<String> {
+    let stem = library.file_name()?.to_str()?.split('.').next()?;
+    let stem = stem.strip_prefix("lib").unwrap_or(stem);
+    let stem = stem.strip_prefix("tree-sitter-").unwrap_or(stem);
+    (!stem.is_empty()).then(|| stem.replace('-', "_"))
+}
+
+#[cfg(unix)]
+fn open_grammar(library: &Path, symbol: &str) -> Result<tree_sitter::Language, String> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let dlerror = || {
+        // SAFETY: dlerror returns null or a valid C string.
+        let message = unsafe { libc::dlerror() };
+        if message.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    };
+    let path = CString::new(library.as_os_str().as_bytes())
+        .map_err(|_| "library path contains a NUL byte".to_string())?;
+    let symbol_name = CString::new(symbol).map_err(|_| "invalid symbol name".to_string())?;
+    // SAFETY: both strings are NUL-terminated. The handle is never closed,
+    // since the grammar is used until the process exits.
+    let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(dlerror());
+    }
+    let constructor = unsafe { libc::dlsym(handle, symbol_name.as_ptr()) };
+    if constructor.is_null() {
+        return Err(format!("symbol {symbol} not found: {}", dlerror()));
+    }
+    // SAFETY: tree-sitter grammars export `const TSLanguage *tree_sitter_<name>(void)`.
+    let constructor = unsafe {
+        std::mem::transmute::<*mut libc::c_void, unsafe extern "C" fn() -> *const ()>(constructor)
+    };
+    let builder = unsafe { tree_sitter_language::LanguageFn::from_raw(constructor) };
+    Ok(tree_sitter::Language::new(builder))
+}
+
+#[cfg(not(unix))]
+fn open_grammar(_library: &Path, _symbol: &str) -> Result<tree_sitter::Language, String> {
+    Err("loading grammars from shared libraries is only supported on Unix".to_string())
+}
+
+/// Number of registered languages.
+pub(crate) fn count() -> usize {
+    REGISTRY.read().expect("language registry poisoned").len()
+}
+
+pub(crate) fn name(index: u16) -> &'static str {
+    registered(index).name
+}
+
+pub(crate) fn from_name(name: &str) -> Option<Language> {
+    let registry = REGISTRY.read().expect("language registry poisoned");
+    let index = registry.iter().position(|entry| entry.name == name)?;
+    Some(Language::Custom(index as u16))
+}
+
+pub(crate) fn for_extension(extension: &str) -> Option<Language> {
+    let registry = REGISTRY.read().expect("language registry poisoned");
+    let index = registry
+        .iter()
+        .position(|entry| entry.extensions.iter().any(|known| known == extension))?;
+    Some(Language::Custom(index as u16))
+}
+
+pub(crate) fn grammar(index: u16) -> tree_sitter::Language {
+    registered(index).grammar.clone()
+}
+
+pub(crate) fn queries(index: u16) -> &'static LanguageQueries {
+    &registered(index).queries
+}
+
+/// Identifies the registered languages for the cache, or `None` when there
+/// are none.
+pub(crate) fn fingerprint() -> Option<String> {
+    let registry = REGISTRY.read().expect("language registry poisoned");
+    if registry.is_empty() {
+        return None;
+    }
+    Some(
+        registry
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{}:{}:{}",
+                    entry.name,
+                    entry.extensions.join(","),
+                    entry.sources
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(";"),
+    )
+}
@@ -0,0 +1,47 @@
+; Top-level functions, methods, types, constants and variables. Grouped
+; declarations (`const ( ... )`) declare one spec per line; only the first
+; name of a spec is captured.
+
+(source_file
+  (function_declaration
+    name: (identifier) @definition.function))
+
+(source_file
+  (method_declaration
+    name: (field_identifier) @definition.method))
+
+(source_file
+  (type_declaration
+    (type_spec
+      name: (type_identifier) @definition.struct
+      type: (struct_type))))
+
+(source_file
+  (type_declaration
+    (type_spec
+      name: (type_identifier) @definition.interface
+      type: (interface_type))))
+
+(source_file
+  (type_declaration
+    (type_spec
+      name: (type_identifier) @definition.type)))
+
+(source_file
+  (const_declaration
+    (const_spec
+      .
+      name: (identifier) @definition.constant)))
+
+(source_file
+  (var_declaration
+    (var_spec
+      .
+      name: (identifier) @definition.variable)))
+
+(source_file
+  (var_declaration
+    (var_spec_list
+      (var_spec
+        .
+        name: (identifier) @definition.variable))))
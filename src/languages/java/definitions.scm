@@ -0,0 +1,66 @@
+; Top-level types and the members declared directly in their bodies.
+; Members of nested types are left out.
+
+(program
+  [
+    (class_declaration name: (identifier) @definition.class)
+    (record_declaration name: (identifier) @definition.class)
+    (interface_declaration name: (identifier) @definition.interface)
+    (annotation_type_declaration name: (identifier) @definition.interface)
+    (enum_declaration name: (identifier) @definition.enum)
+  ])
+
+(program
+  (enum_declaration
+    body: (enum_body
+      (enum_constant name: (identifier) @definition.constant))))
+
+; Members of class, interface and annotation bodies, then of enum bodies.
+; `static final` fields are constants; their patterns come before the plain
+; field patterns so they win.
+
+(program
+  (_
+    body: (_
+      [
+        (class_declaration name: (identifier) @definition.class)
+        (record_declaration name: (identifier) @definition.class)
+        (interface_declaration name: (identifier) @definition.interface)
+        (annotation_type_declaration name: (identifier) @definition.interface)
+        (enum_declaration name: (identifier) @definition.enum)
+        (method_declaration name: (identifier) @definition.method)
+        (constant_declaration
+          declarator: (variable_declarator name: (identifier) @definition.constant))
+        (field_declaration
+          (modifiers ["static" "final"] ["static" "final"])
+          declarator: (variable_declarator name: (identifier) @definition.constant))
+      ])))
+
+(program
+  (enum_declaration
+    body: (enum_body
+      (enum_body_declarations
+        [
+          (class_declaration name: (identifier) @definition.class)
+          (record_declaration name: (identifier) @definition.class)
+          (interface_declaration name: (identifier) @definition.interface)
+          (annotation_type_declaration name: (identifier) @definition.interface)
+          (enum_declaration name: (identifier) @definition.enum)
+          (method_declaration name: (identifier) @definition.method)
+          (field_declaration
+            (modifiers ["static" "final"] ["static" "final"])
+            declarator: (variable_declarator name: (identifier) @definition.constant))
+        ]))))
+
+(program
+  (_
+    body: (_
+      (field_declaration
+        declarator: (variable_declarator name: (identifier) @definition.field)))))
+
+(program
+  (enum_declaration
+    body: (enum_body
+      (enum_body_declarations
+        (field_declaration
+          declarator: (variable_declarator name: (identifier) @definition.field))))))
@@ -2,55 +2,106 @@ use std::path::Path;
 
 use tree_sitter::Node;
 
-use crate::find_references::{Location, location_from_node, walk_tree};
+use crate::find_references::{IMPORT_ALL, ImportBinding, PACKAGE};
+use crate::intern::intern;
 
 pub(crate) const EXTENSIONS: &[&str] = &["java"];
-pub(crate) const REFERENCE_KINDS: &[&str] = &["identifier", "type_identifier"];
+pub(crate) const DEFINITIONS_QUERY: &str = include_str!("definitions.scm");
+pub(crate) const REFERENCES_QUERY: &str = include_str!("references.scm");
+/// `java.lang` types and the methods every `Object` has.
+pub(crate) const COMMON_NAMES: &[&str] = &[
+    "main", "toString", "equals", "hashCode", "get", "set", "close", "size", "String", "Object",
+    "Integer", "Long", "Boolean", "List", "Map", "Override", "System", "println",
+];
 
 pub(crate) fn language() -> tree_sitter::Language {
     tree_sitter_java::LANGUAGE.into()
 }
 
-pub(crate) fn emit_definitions(
-    path: &Path,
-    source: &str,
-    tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
-) {
-    walk_tree(tree, |node| match node.kind() {
-        "class_declaration"
-        | "interface_declaration"
-        | "enum_declaration"
-        | "record_declaration"
-        | "annotation_type_declaration" => {
-            if is_top_level(node)
-                && let Some(name) = node.child_by_field_name("name")
-                && let Some(location) = location_from_node(path, source, name)
-            {
-                emit(location);
-            }
+/// The name of a member prefixed with the types declaring it, such as
+/// `User.save` or `User.Builder.build`. `None` for a top-level type.
+pub(crate) fn qualified_name(source: &str, name: Node) -> Option<String> {
+    let mut qualified = vec![name.utf8_text(source.as_bytes()).ok()?];
+    let mut node = name.parent().and_then(|declaration| declaration.parent());
+    while let Some(current) = node {
+        if matches!(
+            current.kind(),
+            "class_declaration"
+                | "record_declaration"
+                | "interface_declaration"
+                | "annotation_type_declaration"
+                | "enum_declaration"
+        ) && let Some(type_name) = current.child_by_field_name("name")
+        {
+            qualified.push(type_name.utf8_text(source.as_bytes()).ok()?);
         }
-        _ => {}
-    });
+        node = current.parent();
+    }
+    if qualified.len() < 2 {
+        return None;
+    }
+    qualified.reverse();
+    Some(qualified.join("."))
 }
 
-pub(crate) fn emit_references(
+/// Emits the package declaration of a Java or Kotlin file as a [`PACKAGE`]
+/// binding, then a binding for each import: `import a.b.Client;` binds
+/// `Client` with specifier `a.b.Client`, and `import a.b.*;` binds
+/// [`IMPORT_ALL`] with specifier `a.b`. Static imports are kept qualified
+/// by their class, e.g. `a.b.Util.helper`.
+pub(crate) fn emit_package_imports(
     path: &Path,
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut(ImportBinding),
 ) {
-    walk_tree(tree, |node| {
-        if REFERENCE_KINDS.contains(&node.kind())
-            && let Some(location) = location_from_node(path, source, node)
-        {
-            emit(location);
-        }
-    });
+    let file = intern(&path.to_string_lossy());
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    for node in root.named_children(&mut cursor) {
+        let Ok(text) = node.utf8_text(source.as_bytes()) else {
+            continue;
+        };
+        let (local, imported, specifier) = match node.kind() {
+            "package_declaration" | "package_header" => (
+                PACKAGE,
+                PACKAGE,
+                statement_name(text, "package").to_string(),
+            ),
+            "import_declaration" | "import" => {
+                let imported = statement_name(text, "import");
+                let (imported, alias) = match imported.split_once(" as ") {
+                    Some((imported, alias)) => (imported.trim(), Some(alias.trim())),
+                    None => (imported, None),
+                };
+                match imported.strip_suffix(".*") {
+                    Some(package) => (IMPORT_ALL, IMPORT_ALL, package.to_string()),
+                    None => {
+                        let name = imported.rsplit('.').next().unwrap_or(imported);
+                        (alias.unwrap_or(name), name, imported.to_string())
+                    }
+                }
+            }
+            _ => continue,
+        };
+        emit(ImportBinding {
+            path: file,
+            local: intern(local),
+            imported: intern(imported),
+            specifier,
+            site: None,
+        });
+    }
 }
 
-fn is_top_level(node: Node) -> bool {
-    node.parent()
-        .map(|parent| parent.kind() == "program")
-        .unwrap_or(false)
+/// The dotted name of a `package` or `import` statement, without the
+/// keyword, `static` or the semicolon.
+fn statement_name<'a>(statement: &'a str, keyword: &str) -> &'a str {
+    let name = statement.trim();
+    let name = name.strip_prefix(keyword).unwrap_or(name).trim_start();
+    let name = name
+        .strip_prefix("static")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .unwrap_or(name);
+    name.trim().trim_end_matches(';').trim_end()
 }
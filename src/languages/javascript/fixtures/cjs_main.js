@@ -0,0 +1,4 @@
+const { slugify, Registry, MAX_ITEMS, version } = require("./cjs_utils.js");
+
+const registry = new Registry();
+console.log(slugify("Hello World"), MAX_ITEMS, version, registry);
@@ -0,0 +1,15 @@
+function slugify(text) {
+  return text.toLowerCase().replace(/\s+/g, "-");
+}
+
+class Registry {
+  constructor() {
+    this.items = [];
+  }
+}
+
+const MAX_ITEMS = 100;
+
+module.exports = { slugify, Registry };
+module.exports.MAX_ITEMS = MAX_ITEMS;
+exports.version = "1.0.0";
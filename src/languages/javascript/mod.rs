@@ -1,13 +1,71 @@
+use std::collections::HashMap;
 use std::path::Path;
 
 use tree_sitter::Node;
 
-use crate::find_references::{Location, collect_identifier_nodes, location_from_node, walk_tree};
+use crate::find_references::{
+    IMPORT_ALL, ImportBinding, Location, collect_identifier_nodes, location_from_node, walk_tree,
+};
+use crate::intern::intern;
+use crate::languages::{SymbolKind, normalize_path};
 
 pub(crate) const EXTENSIONS: &[&str] = &["js", "jsx"];
 pub(crate) const TYPESCRIPT_EXTENSIONS: &[&str] = &["ts"];
 pub(crate) const TSX_EXTENSIONS: &[&str] = &["tsx"];
-pub(crate) const REFERENCE_KINDS: &[&str] = &["identifier", "jsx_identifier", "type_identifier"];
+/// Vue and Svelte single-file components, scanned as TypeScript (which also
+/// accepts plain JavaScript) after [`embedded_scripts`] extracts their
+/// `<script>` blocks.
+pub(crate) const COMPONENT_EXTENSIONS: &[&str] = &["vue", "svelte"];
+/// HTML pages, whose `<script>` blocks are scanned as JavaScript.
+pub(crate) const HTML_EXTENSIONS: &[&str] = &["html", "htm"];
+/// `type` attributes of `<script>` elements that hold JavaScript, besides
+/// none at all.
+const SCRIPT_TYPES: &[&str] = &[
+    "module",
+    "text/javascript",
+    "application/javascript",
+    "text/ecmascript",
+    "application/ecmascript",
+];
+/// Extensions tried, in order, when an import specifier omits one.
+const RESOLVE_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+pub(crate) const REFERENCES_QUERY: &str = include_str!("references.scm");
+pub(crate) const TYPESCRIPT_REFERENCES_QUERY: &str = include_str!("typescript-references.scm");
+/// Globals and built-in methods, shared by JavaScript and TypeScript.
+pub(crate) const COMMON_NAMES: &[&str] = &[
+    "console",
+    "log",
+    "require",
+    "Object",
+    "Array",
+    "String",
+    "Number",
+    "Boolean",
+    "Promise",
+    "Error",
+    "JSON",
+    "Math",
+    "Date",
+    "Map",
+    "Set",
+    "Symbol",
+    "parseInt",
+    "parseFloat",
+    "setTimeout",
+    "setInterval",
+    "main",
+    "get",
+    "set",
+    "then",
+    "catch",
+    "push",
+    "map",
+    "filter",
+    "forEach",
+    "reduce",
+    "toString",
+];
 
 pub(crate) fn language() -> tree_sitter::Language {
     tree_sitter_javascript::LANGUAGE.into()
@@ -25,7 +83,7 @@ pub(crate) fn emit_definitions(
     path: &Path,
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut(Location, SymbolKind),
 ) {
     walk_tree(tree, |node| match node.kind() {
         "function_declaration"
@@ -37,7 +95,7 @@ pub(crate) fn emit_definitions(
                 && let Some(name) = node.child_by_field_name("name")
                 && let Some(location) = location_from_node(path, source, name)
             {
-                emit(location);
+                emit(location, definition_kind(name));
             }
         }
         "variable_declarator" => {
@@ -46,30 +104,335 @@ pub(crate) fn emit_definitions(
             {
                 collect_identifier_nodes(name, source, |ident| {
                     if let Some(location) = location_from_node(path, source, ident) {
-                        emit(location);
+                        emit(location, definition_kind(ident));
                     }
                 });
             }
         }
         _ => {}
     });
+
+    for node in commonjs_exports(tree, source) {
+        if let Some(location) = location_from_node(path, source, node) {
+            emit(location, definition_kind(node));
+        }
+    }
 }
 
-pub(crate) fn emit_references(
+/// Emits named bindings from relative `import { a, b as c } from "./x"` and
+/// `const { a, b: c } = require("./x")` statements, and from the re-exports
+/// `export { a, b as c } from "./x"` and `export * from "./x"`. Default and
+/// namespace imports are not bound to a name and fall back to plain name
+/// matching.
+pub(crate) fn emit_imports(
     path: &Path,
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut(ImportBinding),
 ) {
-    walk_tree(tree, |node| {
-        if REFERENCE_KINDS.contains(&node.kind())
-            && let Some(location) = location_from_node(path, source, node)
-        {
-            emit(location);
+    let file = intern(&path.to_string_lossy());
+    let text = |node: Node| node.utf8_text(source.as_bytes()).ok();
+    let mut bind = |local: Option<&str>, imported: Option<&str>, specifier: &str| {
+        if let (Some(local), Some(imported)) = (local, imported) {
+            emit(ImportBinding {
+                path: file,
+                local: intern(local),
+                imported: intern(imported),
+                specifier: specifier.to_string(),
+                site: None,
+            });
         }
+    };
+    walk_tree(tree, |node| match node.kind() {
+        "import_statement" | "export_statement" => {
+            let Some(specifier) = node
+                .child_by_field_name("source")
+                .and_then(|source_node| string_value(source_node, source))
+                .filter(|specifier| is_relative(specifier))
+            else {
+                return;
+            };
+            let mut cursor = node.walk();
+            if node.kind() == "export_statement"
+                && node.children(&mut cursor).any(|child| child.kind() == "*")
+            {
+                bind(Some(IMPORT_ALL), Some(IMPORT_ALL), specifier);
+                return;
+            }
+            walk_subtree(node, |child| {
+                if matches!(child.kind(), "import_specifier" | "export_specifier")
+                    && let Some(name) = child.child_by_field_name("name")
+                {
+                    // The imported name itself appears in the specifier, so
+                    // bind it alongside any alias.
+                    bind(text(name), text(name), specifier);
+                    if let Some(alias) = child.child_by_field_name("alias") {
+                        bind(text(alias), text(name), specifier);
+                    }
+                }
+            });
+        }
+        "variable_declarator" => {
+            let (Some(pattern), Some(value)) = (
+                node.child_by_field_name("name"),
+                node.child_by_field_name("value"),
+            ) else {
+                return;
+            };
+            if pattern.kind() != "object_pattern" {
+                return;
+            }
+            let Some(specifier) = require_specifier(value, source).filter(|spec| is_relative(spec))
+            else {
+                return;
+            };
+            let mut cursor = pattern.walk();
+            for property in pattern.named_children(&mut cursor) {
+                match property.kind() {
+                    "shorthand_property_identifier_pattern" => {
+                        bind(text(property), text(property), specifier);
+                    }
+                    "pair_pattern" => {
+                        if let (Some(key), Some(value)) = (
+                            property.child_by_field_name("key"),
+                            property.child_by_field_name("value"),
+                        ) && value.kind() == "identifier"
+                        {
+                            bind(text(value), text(key), specifier);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
     });
 }
 
+/// Whether `path` holds scripts inside markup, see [`embedded_scripts`].
+pub(crate) fn has_embedded_scripts(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| COMPONENT_EXTENSIONS.contains(&ext) || HTML_EXTENSIONS.contains(&ext))
+}
+
+/// Keeps the contents of every `<script>` element holding JavaScript and
+/// blanks the rest of the file byte for byte, keeping line breaks, so the
+/// scripts parse as one program at their original lines and columns.
+/// Scripts of other types, such as JSON data or client-side templates, are
+/// blanked too.
+pub(crate) fn embedded_scripts(source: &str) -> String {
+    let lower = source.to_ascii_lowercase();
+    let mut scripts = Vec::new();
+    let mut at = 0;
+    while let Some(found) = lower[at..].find("<script") {
+        let start = at + found;
+        let after_name = start + "<script".len();
+        if !lower[after_name..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+            at = after_name;
+            continue;
+        }
+        let Some(tag_len) = lower[start..].find('>') else {
+            break;
+        };
+        let content = start + tag_len + 1;
+        if lower[start..content].ends_with("/>") {
+            at = content;
+            continue;
+        }
+        let end = lower[content..]
+            .find("</script")
+            .map_or(source.len(), |close| content + close);
+        if script_type(&lower[after_name..content - 1])
+            .is_none_or(|script_type| SCRIPT_TYPES.contains(&script_type))
+        {
+            scripts.push(content..end);
+        }
+        at = end;
+    }
+    let mut blanked = String::with_capacity(source.len());
+    for (index, c) in source.char_indices() {
+        if c == '\n' || c == '\r' || scripts.iter().any(|script| script.contains(&index)) {
+            blanked.push(c);
+        } else {
+            blanked.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        }
+    }
+    blanked
+}
+
+/// Value of the `type` attribute among a tag's lowercased `attributes`.
+fn script_type(attributes: &str) -> Option<&str> {
+    let value = attributes
+        .match_indices("type")
+        .filter(|(index, _)| attributes[..*index].ends_with(|c: char| c.is_ascii_whitespace()))
+        .find_map(|(index, _)| {
+            attributes[index + "type".len()..]
+                .trim_start()
+                .strip_prefix('=')
+        })?
+        .trim_start();
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split_whitespace().next()?,
+    };
+    Some(value.trim())
+}
+
+/// Resolves a relative specifier like `./utils` against `exists`, trying the
+/// path as written, known extensions, `index.*` files, and `.js` written for
+/// a TypeScript source.
+pub(crate) fn resolve_import(
+    from: &str,
+    specifier: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Option<String> {
+    if !is_relative(specifier) {
+        return None;
+    }
+    let base = Path::new(from).parent()?.join(specifier);
+    let base = normalize_path(&base);
+    let base_str = base.to_string_lossy();
+
+    let mut candidates = vec![base_str.to_string()];
+    if let Some(stem) = base_str.strip_suffix(".js") {
+        candidates.push(format!("{stem}.ts"));
+        candidates.push(format!("{stem}.tsx"));
+    }
+    for ext in RESOLVE_EXTENSIONS {
+        candidates.push(format!("{base_str}.{ext}"));
+    }
+    for ext in RESOLVE_EXTENSIONS {
+        candidates.push(
+            base.join(format!("index.{ext}"))
+                .to_string_lossy()
+                .to_string(),
+        );
+    }
+    candidates.into_iter().find(|candidate| exists(candidate))
+}
+
+/// Scanned files named by the `main` and `bin` fields of the `package.json`
+/// at `manifest_path`, resolved like relative imports.
+pub(crate) fn package_entry_files(
+    manifest_path: &str,
+    manifest: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let Ok(manifest) = serde_json::from_str::<serde_json::Value>(manifest) else {
+        return Vec::new();
+    };
+    let mut specifiers: Vec<&str> = manifest
+        .get("main")
+        .and_then(serde_json::Value::as_str)
+        .into_iter()
+        .collect();
+    match manifest.get("bin") {
+        Some(serde_json::Value::String(bin)) => specifiers.push(bin),
+        Some(serde_json::Value::Object(bins)) => {
+            specifiers.extend(bins.values().filter_map(serde_json::Value::as_str));
+        }
+        _ => {}
+    }
+    specifiers
+        .into_iter()
+        .filter_map(|specifier| {
+            let specifier = if is_relative(specifier) {
+                specifier.to_string()
+            } else {
+                format!("./{specifier}")
+            };
+            resolve_import(manifest_path, &specifier, &exists)
+        })
+        .collect()
+}
+
+/// The public API of the `package.json` at `manifest_path`: the `main`,
+/// `bin` and `exports` paths (every condition and subpath) as written,
+/// with the scanned file each resolves to. Paths outside the scan, such as
+/// build output, are dropped.
+pub(crate) fn package_exports(
+    manifest_path: &str,
+    manifest: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Vec<(String, String)> {
+    let Ok(manifest) = serde_json::from_str::<serde_json::Value>(manifest) else {
+        return Vec::new();
+    };
+    let mut specifiers = Vec::new();
+    for field in ["main", "bin", "exports"] {
+        if let Some(value) = manifest.get(field) {
+            string_leaves(value, &mut specifiers);
+        }
+    }
+    specifiers.sort_unstable();
+    specifiers.dedup();
+    specifiers
+        .into_iter()
+        .filter_map(|specifier| {
+            let relative = if is_relative(specifier) {
+                specifier.to_string()
+            } else {
+                format!("./{specifier}")
+            };
+            let resolved = resolve_import(manifest_path, &relative, &exists)?;
+            Some((specifier.to_string(), resolved))
+        })
+        .collect()
+}
+
+/// Every string in `value`, however deeply nested in objects and arrays.
+fn string_leaves<'a>(value: &'a serde_json::Value, out: &mut Vec<&'a str>) {
+    match value {
+        serde_json::Value::String(string) => out.push(string),
+        serde_json::Value::Array(items) => {
+            for item in items {
+                string_leaves(item, out);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for field in fields.values() {
+                string_leaves(field, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_relative(specifier: &str) -> bool {
+    specifier.starts_with("./") || specifier.starts_with("../")
+}
+
+fn string_value<'a>(node: Node, source: &'a str) -> Option<&'a str> {
+    if node.kind() != "string" {
+        return None;
+    }
+    let text = node.utf8_text(source.as_bytes()).ok()?;
+    Some(text.trim_matches(|c| c == '"' || c == '\'' || c == '`'))
+}
+
+/// Returns `"./x"` from a `require("./x")` call.
+fn require_specifier<'a>(node: Node, source: &'a str) -> Option<&'a str> {
+    if node.kind() != "call_expression" {
+        return None;
+    }
+    let function = node.child_by_field_name("function")?;
+    if !is_identifier(function, source, "require") {
+        return None;
+    }
+    let arguments = node.child_by_field_name("arguments")?;
+    string_value(arguments.named_child(0)?, source)
+}
+
+fn walk_subtree(node: Node, mut visit: impl FnMut(Node)) {
+    let mut stack = vec![node];
+    while let Some(current) = stack.pop() {
+        visit(current);
+        let mut cursor = current.walk();
+        stack.extend(current.children(&mut cursor));
+    }
+}
+
 fn is_exported(node: Node) -> bool {
     let mut current = node;
     while let Some(parent) = current.parent() {
@@ -84,3 +447,167 @@ fn is_exported(node: Node) -> bool {
     }
     false
 }
+
+/// Name nodes exported through CommonJS assignments at the top level:
+/// `module.exports = {...}`, `module.exports.foo = ...`, and `exports.foo = ...`.
+/// Exports of local declarations resolve to the declaration's name.
+fn commonjs_exports<'tree>(tree: &'tree tree_sitter::Tree, source: &str) -> Vec<Node<'tree>> {
+    let root = tree.root_node();
+    let declarations = top_level_declarations(root, source);
+    let local_or = |name: Node<'tree>| -> Node<'tree> {
+        name.utf8_text(source.as_bytes())
+            .ok()
+            .and_then(|text| declarations.get(text).copied())
+            .unwrap_or(name)
+    };
+
+    let mut exports = Vec::new();
+    let mut cursor = root.walk();
+    for statement in root.named_children(&mut cursor) {
+        if statement.kind() != "expression_statement" {
+            continue;
+        }
+        let Some(assignment) = statement
+            .named_child(0)
+            .filter(|child| child.kind() == "assignment_expression")
+        else {
+            continue;
+        };
+        let (Some(left), Some(right)) = (
+            assignment.child_by_field_name("left"),
+            assignment.child_by_field_name("right"),
+        ) else {
+            continue;
+        };
+        if is_module_exports(left, source) {
+            match right.kind() {
+                "object" => {
+                    let mut cursor = right.walk();
+                    for property in right.named_children(&mut cursor) {
+                        match property.kind() {
+                            "shorthand_property_identifier" => exports.push(local_or(property)),
+                            "pair" | "method_definition" => {
+                                if let Some(key) = property
+                                    .child_by_field_name("key")
+                                    .or_else(|| property.child_by_field_name("name"))
+                                {
+                                    exports.push(key);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                "identifier" => {
+                    if let Some(declaration) = right
+                        .utf8_text(source.as_bytes())
+                        .ok()
+                        .and_then(|text| declarations.get(text))
+                    {
+                        exports.push(*declaration);
+                    }
+                }
+                "function_expression" | "class" => {
+                    if let Some(name) = right.child_by_field_name("name") {
+                        exports.push(name);
+                    }
+                }
+                _ => {}
+            }
+        } else if left.kind() == "member_expression"
+            && let (Some(object), Some(property)) = (
+                left.child_by_field_name("object"),
+                left.child_by_field_name("property"),
+            )
+            && (is_module_exports(object, source) || is_identifier(object, source, "exports"))
+        {
+            let same_name_local = right.kind() == "identifier"
+                && right.utf8_text(source.as_bytes()).ok()
+                    == property.utf8_text(source.as_bytes()).ok();
+            if same_name_local {
+                exports.push(local_or(property));
+            } else {
+                exports.push(property);
+            }
+        }
+    }
+    exports
+}
+
+/// Classifies a definition by the declaration its name belongs to; names
+/// bound to a function or class expression take that kind.
+fn definition_kind(name: Node) -> SymbolKind {
+    let Some(parent) = name.parent() else {
+        return SymbolKind::Variable;
+    };
+    match parent.kind() {
+        "function_declaration"
+        | "generator_function_declaration"
+        | "function_expression"
+        | "method_definition" => SymbolKind::Function,
+        "class_declaration" | "abstract_class_declaration" | "class" => SymbolKind::Class,
+        "interface_declaration" => SymbolKind::Interface,
+        "type_alias_declaration" => SymbolKind::Type,
+        "enum_declaration" => SymbolKind::Enum,
+        "variable_declarator" | "pair" => {
+            match parent
+                .child_by_field_name("value")
+                .map(|value| value.kind())
+            {
+                Some(
+                    "arrow_function" | "function_expression" | "function" | "generator_function",
+                ) => SymbolKind::Function,
+                Some("class") => SymbolKind::Class,
+                _ => SymbolKind::Variable,
+            }
+        }
+        _ => SymbolKind::Variable,
+    }
+}
+
+fn top_level_declarations<'tree>(root: Node<'tree>, source: &str) -> HashMap<String, Node<'tree>> {
+    let mut declarations = HashMap::new();
+    let mut insert = |name: Node<'tree>| {
+        if let Ok(text) = name.utf8_text(source.as_bytes()) {
+            declarations.insert(text.to_string(), name);
+        }
+    };
+    let mut cursor = root.walk();
+    for statement in root.named_children(&mut cursor) {
+        match statement.kind() {
+            "function_declaration" | "class_declaration" => {
+                if let Some(name) = statement.child_by_field_name("name") {
+                    insert(name);
+                }
+            }
+            "lexical_declaration" | "variable_declaration" => {
+                let mut declarators = statement.walk();
+                for declarator in statement.named_children(&mut declarators) {
+                    if let Some(name) = declarator
+                        .child_by_field_name("name")
+                        .filter(|name| name.kind() == "identifier")
+                    {
+                        insert(name);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    declarations
+}
+
+fn is_module_exports(node: Node, source: &str) -> bool {
+    node.kind() == "member_expression"
+        && node
+            .child_by_field_name("object")
+            .is_some_and(|object| is_identifier(object, source, "module"))
+        && node
+            .child_by_field_name("property")
+            .and_then(|property| property.utf8_text(source.as_bytes()).ok())
+            == Some("exports")
+}
+
+fn is_identifier(node: Node, source: &str, name: &str) -> bool {
+    node.kind() == "identifier" && node.utf8_text(source.as_bytes()).ok() == Some(name)
+}
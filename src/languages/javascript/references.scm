@@ -0,0 +1,5 @@
+; Identifiers linked to definitions of the same name.
+[
+  (identifier)
+  (shorthand_property_identifier_pattern)
+] @reference
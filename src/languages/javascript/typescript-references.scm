@@ -0,0 +1,6 @@
+; Identifiers linked to definitions of the same name.
+[
+  (identifier)
+  (type_identifier)
+  (shorthand_property_identifier_pattern)
+] @reference
@@ -0,0 +1,145 @@
+; Top-level declarations, plus the members of top-level classes and objects
+; and of their companion objects. Members of nested classes are left out.
+; Interfaces and `const` properties have their own patterns, ahead of the
+; general ones, so they win.
+
+; Top level
+
+(source_file
+  (class_declaration "interface" name: (identifier) @definition.interface))
+
+(source_file
+  [
+    (class_declaration name: (identifier) @definition.class)
+    (object_declaration name: (identifier) @definition.class)
+    (function_declaration name: (identifier) @definition.function)
+  ])
+
+(source_file
+  (property_declaration
+    (modifiers (property_modifier) @_const)
+    [
+      (variable_declaration (identifier) @definition.constant)
+      (multi_variable_declaration (variable_declaration (identifier) @definition.constant))
+    ])
+  (#eq? @_const "const"))
+
+(source_file
+  (property_declaration
+    [
+      (variable_declaration (identifier) @definition.variable)
+      (multi_variable_declaration (variable_declaration (identifier) @definition.variable))
+    ]))
+
+; `val`/`var` constructor parameters are properties of the class.
+(source_file
+  (class_declaration
+    (primary_constructor
+      (class_parameters
+        (class_parameter ["val" "var"] (identifier) @definition.field)))))
+
+(source_file
+  (class_declaration
+    (enum_class_body
+      (enum_entry (identifier) @definition.constant))))
+
+; Members, directly in the body or in a companion object
+
+(source_file
+  [
+    (class_declaration (_ (class_declaration "interface" name: (identifier) @definition.interface)))
+    (object_declaration (_ (class_declaration "interface" name: (identifier) @definition.interface)))
+    (class_declaration (_ (companion_object (_
+      (class_declaration "interface" name: (identifier) @definition.interface)))))
+    (object_declaration (_ (companion_object (_
+      (class_declaration "interface" name: (identifier) @definition.interface)))))
+  ])
+
+(source_file
+  [
+    (class_declaration (_
+      [
+        (class_declaration name: (identifier) @definition.class)
+        (object_declaration name: (identifier) @definition.class)
+        (function_declaration name: (identifier) @definition.method)
+      ]))
+    (object_declaration (_
+      [
+        (class_declaration name: (identifier) @definition.class)
+        (object_declaration name: (identifier) @definition.class)
+        (function_declaration name: (identifier) @definition.method)
+      ]))
+    (class_declaration (_ (companion_object (_
+      [
+        (class_declaration name: (identifier) @definition.class)
+        (object_declaration name: (identifier) @definition.class)
+        (function_declaration name: (identifier) @definition.method)
+      ]))))
+    (object_declaration (_ (companion_object (_
+      [
+        (class_declaration name: (identifier) @definition.class)
+        (object_declaration name: (identifier) @definition.class)
+        (function_declaration name: (identifier) @definition.method)
+      ]))))
+  ])
+
+(source_file
+  [
+    (class_declaration (_
+      (property_declaration
+        (modifiers (property_modifier) @_const)
+        [
+          (variable_declaration (identifier) @definition.constant)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.constant))
+        ])))
+    (object_declaration (_
+      (property_declaration
+        (modifiers (property_modifier) @_const)
+        [
+          (variable_declaration (identifier) @definition.constant)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.constant))
+        ])))
+    (class_declaration (_ (companion_object (_
+      (property_declaration
+        (modifiers (property_modifier) @_const)
+        [
+          (variable_declaration (identifier) @definition.constant)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.constant))
+        ])))))
+    (object_declaration (_ (companion_object (_
+      (property_declaration
+        (modifiers (property_modifier) @_const)
+        [
+          (variable_declaration (identifier) @definition.constant)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.constant))
+        ])))))
+  ]
+  (#eq? @_const "const"))
+
+(source_file
+  [
+    (class_declaration (_
+      (property_declaration
+        [
+          (variable_declaration (identifier) @definition.field)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.field))
+        ])))
+    (object_declaration (_
+      (property_declaration
+        [
+          (variable_declaration (identifier) @definition.field)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.field))
+        ])))
+    (class_declaration (_ (companion_object (_
+      (property_declaration
+        [
+          (variable_declaration (identifier) @definition.field)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.field))
+        ])))))
+    (object_declaration (_ (companion_object (_
+      (property_declaration
+        [
+          (variable_declaration (identifier) @definition.field)
+          (multi_variable_declaration (variable_declaration (identifier) @definition.field))
+        ])))))
+  ])
@@ -1,19 +1,23 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 pub(crate) mod c;
 pub(crate) mod cpp;
 pub(crate) mod csharp;
+pub(crate) mod custom;
 pub(crate) mod go;
 pub(crate) mod java;
 pub(crate) mod javascript;
 pub(crate) mod kotlin;
 pub(crate) mod php;
 pub(crate) mod python;
+pub(crate) mod query;
 pub(crate) mod rust;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+/// A parser supported by cruxlines. Several languages can share an
+/// [`Ecosystem`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Language {
     C,
     Cpp,
@@ -27,6 +31,115 @@ pub enum Language {
     TypeScript,
     TypeScriptReact,
     Rust,
+    /// A language registered at runtime, see
+    /// [`register_language`](crate::register_language).
+    Custom(u16),
+}
+
+impl Language {
+    /// The built-in languages.
+    pub const ALL: [Language; 12] = [
+        Language::C,
+        Language::Cpp,
+        Language::CSharp,
+        Language::Go,
+        Language::Java,
+        Language::Kotlin,
+        Language::Php,
+        Language::Python,
+        Language::JavaScript,
+        Language::TypeScript,
+        Language::TypeScriptReact,
+        Language::Rust,
+    ];
+
+    /// Lowercase name used in config files, e.g. `[references.rust]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::CSharp => "csharp",
+            Language::Go => "go",
+            Language::Java => "java",
+            Language::Kotlin => "kotlin",
+            Language::Php => "php",
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::TypeScript => "typescript",
+            Language::TypeScriptReact => "tsx",
+            Language::Rust => "rust",
+            Language::Custom(index) => custom::name(index),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.name() == name)
+            .or_else(|| custom::from_name(name))
+    }
+}
+
+/// What a definition declares, as classified by its language module.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SymbolKind {
+    Function,
+    /// A function defined inside a class or attached to a type.
+    Method,
+    Class,
+    Struct,
+    Enum,
+    Interface,
+    Trait,
+    /// A type alias or other named type that is not a class or struct.
+    Type,
+    Constant,
+    /// Also used for definitions without a more specific kind.
+    #[default]
+    Variable,
+    /// An attribute or property declared on a class.
+    Field,
+    /// A namespace or module.
+    Module,
+}
+
+impl SymbolKind {
+    pub const ALL: [SymbolKind; 12] = [
+        SymbolKind::Function,
+        SymbolKind::Method,
+        SymbolKind::Class,
+        SymbolKind::Struct,
+        SymbolKind::Enum,
+        SymbolKind::Interface,
+        SymbolKind::Trait,
+        SymbolKind::Type,
+        SymbolKind::Constant,
+        SymbolKind::Variable,
+        SymbolKind::Field,
+        SymbolKind::Module,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Method => "method",
+            SymbolKind::Class => "class",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Variable => "variable",
+            SymbolKind::Field => "field",
+            SymbolKind::Module => "module",
+        }
+    }
+
+    /// Inverse of [`SymbolKind::as_str`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -39,6 +152,52 @@ pub enum Ecosystem {
     Python,
     JavaScript,
     Rust,
+    /// The ecosystem of [`Language::Custom`] with the same index.
+    Custom(u16),
+    /// Files matched by the [`Options::namespaces`](crate::Options::namespaces)
+    /// entry with index `index`, one per language ecosystem among them so
+    /// different languages don't link to each other. See [`Ecosystem::base`].
+    Namespace {
+        index: u16,
+        base: u16,
+    },
+}
+
+impl Ecosystem {
+    const BUILT_IN: [Ecosystem; 8] = [
+        Ecosystem::C,
+        Ecosystem::Dotnet,
+        Ecosystem::Go,
+        Ecosystem::Java,
+        Ecosystem::Php,
+        Ecosystem::Python,
+        Ecosystem::JavaScript,
+        Ecosystem::Rust,
+    ];
+
+    /// The ecosystem of `base`'s files under the namespace with `index`.
+    pub(crate) fn namespaced(index: u16, base: Ecosystem) -> Ecosystem {
+        let base = match base.base() {
+            Ecosystem::Custom(custom) => (Self::BUILT_IN.len() as u16).saturating_add(custom),
+            base => Self::BUILT_IN
+                .iter()
+                .position(|ecosystem| *ecosystem == base)
+                .expect("a language ecosystem") as u16,
+        };
+        Ecosystem::Namespace { index, base }
+    }
+
+    /// The language ecosystem: the one namespaced files would link in
+    /// without their namespace, or `self` for the others.
+    pub fn base(self) -> Ecosystem {
+        match self {
+            Ecosystem::Namespace { base, .. } => Self::BUILT_IN
+                .get(usize::from(base))
+                .copied()
+                .unwrap_or_else(|| Ecosystem::Custom(base - Self::BUILT_IN.len() as u16)),
+            ecosystem => ecosystem,
+        }
+    }
 }
 
 pub(crate) fn language_for_path(path: &Path) -> Option<Language> {
@@ -76,10 +235,16 @@ pub(crate) fn language_for_path(path: &Path) -> Option<Language> {
     if javascript::TSX_EXTENSIONS.contains(&ext) {
         return Some(Language::TypeScriptReact);
     }
+    if javascript::COMPONENT_EXTENSIONS.contains(&ext) {
+        return Some(Language::TypeScript);
+    }
+    if javascript::HTML_EXTENSIONS.contains(&ext) {
+        return Some(Language::JavaScript);
+    }
     if rust::EXTENSIONS.contains(&ext) {
         return Some(Language::Rust);
     }
-    None
+    custom::for_extension(ext)
 }
 
 pub(crate) fn ecosystem_for_language(language: Language) -> Ecosystem {
@@ -94,6 +259,134 @@ pub(crate) fn ecosystem_for_language(language: Language) -> Ecosystem {
             Ecosystem::JavaScript
         }
         Language::Rust => Ecosystem::Rust,
+        Language::Custom(index) => Ecosystem::Custom(index),
+    }
+}
+
+/// The ecosystems of the built-in languages and of every registered custom
+/// language.
+pub(crate) fn all_ecosystems() -> std::collections::HashSet<Ecosystem> {
+    Language::ALL
+        .into_iter()
+        .map(ecosystem_for_language)
+        .chain((0..custom::count()).map(|index| Ecosystem::Custom(index as u16)))
+        .collect()
+}
+
+/// Resolves an import specifier written in `from` to one of the known files.
+pub(crate) fn resolve_import(
+    ecosystem: Ecosystem,
+    from: &str,
+    specifier: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Option<String> {
+    match ecosystem {
+        Ecosystem::JavaScript => javascript::resolve_import(from, specifier, exists),
+        Ecosystem::C => c::resolve_include(from, specifier, exists),
+        Ecosystem::Rust => rust::resolve_import(from, specifier, exists),
+        _ => None,
+    }
+}
+
+/// Resolves `.` and `..` components without touching the file system.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+const TEST_DIRECTORIES: &[&str] = &["test", "tests", "__tests__", "spec"];
+
+/// Whether `path` (relative to the repo root) holds test code: it sits in a
+/// test directory or follows its language's test file naming convention.
+pub(crate) fn is_test_path(path: &Path) -> bool {
+    let Some(language) = language_for_path(path) else {
+        return false;
+    };
+    let in_test_directory = path.parent().is_some_and(|parent| {
+        parent.components().any(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .is_some_and(|name| TEST_DIRECTORIES.contains(&name))
+        })
+    });
+    if in_test_directory {
+        return true;
+    }
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return false;
+    };
+    match language {
+        Language::Python => {
+            stem.starts_with("test_") || stem.ends_with("_test") || stem == "conftest"
+        }
+        Language::Go => stem.ends_with("_test"),
+        Language::JavaScript | Language::TypeScript | Language::TypeScriptReact => {
+            stem.ends_with(".test") || stem.ends_with(".spec")
+        }
+        Language::Java | Language::Kotlin | Language::CSharp | Language::Php => {
+            stem.ends_with("Test") || stem.ends_with("Tests")
+        }
+        Language::C | Language::Cpp => {
+            stem.starts_with("test_") || stem.ends_with("_test") || stem.ends_with("_unittest")
+        }
+        // Rust keeps unit tests in `#[cfg(test)]` modules, handled per line.
+        Language::Rust => false,
+        Language::Custom(_) => false,
+    }
+}
+
+/// Sources of the built-in definitions (if the language has one) and
+/// references queries.
+pub(crate) fn query_sources(language: Language) -> (Option<&'static str>, &'static str) {
+    match language {
+        Language::C => (None, c::REFERENCES_QUERY),
+        Language::Cpp => (None, cpp::REFERENCES_QUERY),
+        Language::CSharp => (Some(csharp::DEFINITIONS_QUERY), csharp::REFERENCES_QUERY),
+        Language::Go => (Some(go::DEFINITIONS_QUERY), go::REFERENCES_QUERY),
+        Language::Java => (Some(java::DEFINITIONS_QUERY), java::REFERENCES_QUERY),
+        Language::Kotlin => (Some(kotlin::DEFINITIONS_QUERY), kotlin::REFERENCES_QUERY),
+        Language::Php => (Some(php::DEFINITIONS_QUERY), php::REFERENCES_QUERY),
+        Language::Python => (Some(python::DEFINITIONS_QUERY), python::REFERENCES_QUERY),
+        Language::JavaScript => (None, javascript::REFERENCES_QUERY),
+        Language::TypeScript | Language::TypeScriptReact => {
+            (None, javascript::TYPESCRIPT_REFERENCES_QUERY)
+        }
+        Language::Rust => (Some(rust::DEFINITIONS_QUERY), rust::REFERENCES_QUERY),
+        Language::Custom(_) => {
+            unreachable!("custom languages compile their queries on registration")
+        }
+    }
+}
+
+/// Names too common to link by name alone in `language`, before the
+/// [`CommonNames`](crate::CommonNames) overrides.
+pub(crate) fn common_names(language: Language) -> &'static [&'static str] {
+    match language {
+        Language::C => c::COMMON_NAMES,
+        Language::Cpp => cpp::COMMON_NAMES,
+        Language::CSharp => csharp::COMMON_NAMES,
+        Language::Go => go::COMMON_NAMES,
+        Language::Java => java::COMMON_NAMES,
+        Language::Kotlin => kotlin::COMMON_NAMES,
+        Language::Php => php::COMMON_NAMES,
+        Language::Python => python::COMMON_NAMES,
+        Language::JavaScript | Language::TypeScript | Language::TypeScriptReact => {
+            javascript::COMMON_NAMES
+        }
+        Language::Rust => rust::COMMON_NAMES,
+        Language::Custom(_) => &[],
     }
 }
 
@@ -111,14 +404,147 @@ pub(crate) fn tree_sitter_language(language: Language) -> tree_sitter::Language
         Language::TypeScript => javascript::language_typescript(),
         Language::TypeScriptReact => javascript::language_tsx(),
         Language::Rust => rust::language(),
+        Language::Custom(index) => custom::grammar(index),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Language, language_for_path};
+    use super::{Ecosystem, Language, is_test_path, language_for_path, resolve_import};
     use std::path::PathBuf;
 
+    #[test]
+    fn detects_test_paths_per_language() {
+        let test_paths = [
+            "tests/cli.rs",
+            "pkg/server_test.go",
+            "app/test_models.py",
+            "web/button.spec.tsx",
+            "src/main/java/OrderServiceTest.java",
+        ];
+        for path in test_paths {
+            assert!(is_test_path(&PathBuf::from(path)), "{path}");
+        }
+        let source_paths = [
+            "src/lib.rs",
+            "pkg/server.go",
+            "app/testing.py",
+            "web/spec.ts",
+        ];
+        for path in source_paths {
+            assert!(!is_test_path(&PathBuf::from(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn resolves_javascript_relative_imports() {
+        let known = ["src/utils.ts", "src/lib/index.js", "shared/models.tsx"];
+        let exists = |candidate: &str| known.contains(&candidate);
+        let resolve = |specifier: &str| {
+            resolve_import(Ecosystem::JavaScript, "src/app.ts", specifier, exists)
+        };
+        assert_eq!(resolve("./utils").as_deref(), Some("src/utils.ts"));
+        assert_eq!(resolve("./utils.js").as_deref(), Some("src/utils.ts"));
+        assert_eq!(resolve("./lib").as_deref(), Some("src/lib/index.js"));
+        assert_eq!(
+            resolve("../shared/models").as_deref(),
+            Some("shared/models.tsx")
+        );
+        assert_eq!(resolve("./missing"), None);
+        assert_eq!(resolve("react"), None);
+    }
+
+    #[test]
+    fn resolves_rust_module_paths() {
+        let known = [
+            "src/lib.rs",
+            "src/models.rs",
+            "src/net/mod.rs",
+            "src/net/http.rs",
+        ];
+        let exists = |candidate: &str| known.contains(&candidate);
+        let resolve =
+            |from: &str, specifier: &str| resolve_import(Ecosystem::Rust, from, specifier, exists);
+        assert_eq!(
+            resolve("src/app.rs", "crate").as_deref(),
+            Some("src/lib.rs")
+        );
+        assert_eq!(
+            resolve("src/net/http.rs", "crate::models").as_deref(),
+            Some("src/models.rs")
+        );
+        assert_eq!(
+            resolve("src/net/mod.rs", "http").as_deref(),
+            Some("src/net/http.rs")
+        );
+        assert_eq!(
+            resolve("src/net/http.rs", "super").as_deref(),
+            Some("src/net/mod.rs")
+        );
+        assert_eq!(
+            resolve("src/app.rs", "net::http").as_deref(),
+            Some("src/net/http.rs")
+        );
+        assert_eq!(resolve("src/app.rs", "std::fmt"), None);
+    }
+
+    #[test]
+    fn reads_cargo_manifest_dependencies() {
+        let manifest = super::rust::cargo_manifest(
+            r#"
+[package]
+name = "cli" # the binary
+
+[dependencies]
+serde = "1"
+core = { path = "../core", features = ["std"] }
+net.workspace = true
+store = { package = "app-store", workspace = true }
+
+[dependencies.log]
+path = "../log"
+
+[target.'cfg(unix)'.build-dependencies]
+sys = { path = "../sys" }
+
+[dev-dependencies]
+fixtures = { path = "../fixtures" }
+
+[workspace.dependencies]
+net = { path = "crates/net" }
+"#,
+        );
+        assert_eq!(manifest.name.as_deref(), Some("cli"));
+        let dependencies: Vec<(&str, &str, Option<&str>, bool)> = manifest
+            .dependencies
+            .iter()
+            .map(|dependency| {
+                (
+                    dependency.key.as_str(),
+                    dependency.package.as_str(),
+                    dependency.path.as_deref(),
+                    dependency.workspace,
+                )
+            })
+            .collect();
+        assert_eq!(
+            dependencies,
+            vec![
+                ("serde", "serde", None, false),
+                ("core", "core", Some("../core"), false),
+                ("net", "net", None, true),
+                ("store", "app-store", None, true),
+                ("log", "log", Some("../log"), false),
+                ("sys", "sys", Some("../sys"), false),
+            ]
+        );
+        assert_eq!(manifest.workspace_dependencies.len(), 1);
+        assert_eq!(
+            manifest.workspace_dependencies[0].path.as_deref(),
+            Some("crates/net")
+        );
+    }
+
     #[test]
     fn recognizes_python_extension() {
         let lang = language_for_path(&PathBuf::from("file.py"));
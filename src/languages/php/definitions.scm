@@ -0,0 +1,23 @@
+; Top-level classes, interfaces, traits, enums, functions and constants,
+; including those inside a braced namespace block.
+
+(program
+  [
+    (class_declaration name: (name) @definition.class)
+    (interface_declaration name: (name) @definition.interface)
+    (trait_declaration name: (name) @definition.trait)
+    (enum_declaration name: (name) @definition.enum)
+    (function_definition name: (name) @definition.function)
+    (const_declaration (const_element . (name) @definition.constant))
+  ])
+
+(namespace_definition
+  body: (compound_statement
+    [
+      (class_declaration name: (name) @definition.class)
+      (interface_declaration name: (name) @definition.interface)
+      (trait_declaration name: (name) @definition.trait)
+      (enum_declaration name: (name) @definition.enum)
+      (function_definition name: (name) @definition.function)
+      (const_declaration (const_element . (name) @definition.constant))
+    ]))
@@ -0,0 +1,5 @@
+; Names linked to definitions of the same name.
+[
+  (name)
+  (qualified_name)
+] @reference
@@ -0,0 +1,117 @@
+; Top-level functions, classes and variables, plus the methods and fields of
+; top-level classes. Dunder methods are left out, and `self.name = ...` in
+; `__init__` declares a field.
+
+(module
+  [
+    (function_definition name: (identifier) @definition.function)
+    (class_definition name: (identifier) @definition.class)
+    (decorated_definition
+      definition: [
+        (function_definition name: (identifier) @definition.function)
+        (class_definition name: (identifier) @definition.class)
+      ])
+  ])
+
+(module
+  (expression_statement
+    (assignment
+      left: [
+        (identifier) @definition.variable
+        (_ (identifier) @definition.variable)
+        (_ (_ (identifier) @definition.variable))
+      ])))
+
+; Class bodies, with and without a decorator on the class.
+
+(module
+  [
+    (class_definition
+      body: (block
+        [
+          (function_definition name: (identifier) @definition.method)
+          (decorated_definition
+            definition: (function_definition name: (identifier) @definition.method))
+        ]))
+    (decorated_definition
+      definition: (class_definition
+        body: (block
+          [
+            (function_definition name: (identifier) @definition.method)
+            (decorated_definition
+              definition: (function_definition name: (identifier) @definition.method))
+          ])))
+  ]
+  (#not-match? @definition.method "^__.+__$"))
+
+(module
+  [
+    (class_definition
+      body: (block
+        (expression_statement
+          (assignment
+            left: [
+              (identifier) @definition.field
+              (_ (identifier) @definition.field)
+              (_ (_ (identifier) @definition.field))
+            ]))))
+    (decorated_definition
+      definition: (class_definition
+        body: (block
+          (expression_statement
+            (assignment
+              left: [
+                (identifier) @definition.field
+                (_ (identifier) @definition.field)
+                (_ (_ (identifier) @definition.field))
+              ])))))
+  ])
+
+(module
+  [
+    (class_definition
+      body: (block
+        [
+          (function_definition
+            name: (identifier) @_init
+            body: (block
+              (expression_statement
+                (assignment
+                  left: (attribute
+                    object: (identifier) @_self
+                    attribute: (identifier) @definition.field)))))
+          (decorated_definition
+            definition: (function_definition
+              name: (identifier) @_init
+              body: (block
+                (expression_statement
+                  (assignment
+                    left: (attribute
+                      object: (identifier) @_self
+                      attribute: (identifier) @definition.field))))))
+        ]))
+    (decorated_definition
+      definition: (class_definition
+        body: (block
+          [
+            (function_definition
+              name: (identifier) @_init
+              body: (block
+                (expression_statement
+                  (assignment
+                    left: (attribute
+                      object: (identifier) @_self
+                      attribute: (identifier) @definition.field)))))
+            (decorated_definition
+              definition: (function_definition
+                name: (identifier) @_init
+                body: (block
+                  (expression_statement
+                    (assignment
+                      left: (attribute
+                        object: (identifier) @_self
+                        attribute: (identifier) @definition.field))))))
+          ])))
+  ]
+  (#eq? @_init "__init__")
+  (#eq? @_self "self"))
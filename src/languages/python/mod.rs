@@ -1,73 +1,143 @@
-use std::path::Path;
-
-use tree_sitter::Node;
-
-use crate::find_references::{Location, collect_identifier_nodes, location_from_node, walk_tree};
+use crate::find_references::collect_identifier_nodes;
 
 pub(crate) const EXTENSIONS: &[&str] = &["py"];
-pub(crate) const REFERENCE_KINDS: &[&str] = &["identifier"];
+pub(crate) const DEFINITIONS_QUERY: &str = include_str!("definitions.scm");
+pub(crate) const REFERENCES_QUERY: &str = include_str!("references.scm");
+/// Builtins and method names most classes define.
+pub(crate) const COMMON_NAMES: &[&str] = &[
+    "print",
+    "len",
+    "str",
+    "int",
+    "float",
+    "bool",
+    "list",
+    "dict",
+    "set",
+    "tuple",
+    "type",
+    "object",
+    "super",
+    "range",
+    "open",
+    "map",
+    "filter",
+    "zip",
+    "sorted",
+    "min",
+    "max",
+    "sum",
+    "any",
+    "all",
+    "isinstance",
+    "getattr",
+    "setattr",
+    "hasattr",
+    "iter",
+    "next",
+    "repr",
+    "format",
+    "main",
+    "get",
+    "setup",
+    "update",
+    "close",
+    "append",
+    "items",
+    "keys",
+    "values",
+    "copy",
+    "clear",
+];
 
 pub(crate) fn language() -> tree_sitter::Language {
     tree_sitter_python::LANGUAGE.into()
 }
 
-pub(crate) fn emit_definitions(
-    path: &Path,
-    source: &str,
-    tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
-) {
-    walk_tree(tree, |node| match node.kind() {
-        "function_definition" | "class_definition" => {
-            if is_top_level(node)
-                && let Some(name) = node.child_by_field_name("name")
-                && let Some(location) = location_from_node(path, source, name)
-            {
-                emit(location);
-            }
+/// Identifiers used inside module-level `if __name__ == "__main__":` blocks.
+pub(crate) fn main_guard_names<'a>(source: &'a str, tree: &tree_sitter::Tree) -> Vec<&'a str> {
+    let root = tree.root_node();
+    let mut names = Vec::new();
+    let mut cursor = root.walk();
+    for statement in root.children(&mut cursor) {
+        if statement.kind() != "if_statement" {
+            continue;
         }
-        "assignment" => {
-            if is_top_level(node)
-                && let Some(left) = node.child_by_field_name("left")
-            {
-                collect_identifier_nodes(left, source, |ident| {
-                    if let Some(location) = location_from_node(path, source, ident) {
-                        emit(location);
-                    }
-                });
-            }
+        let (Some(condition), Some(body)) = (
+            statement.child_by_field_name("condition"),
+            statement.child_by_field_name("consequence"),
+        ) else {
+            continue;
+        };
+        let Ok(condition) = condition.utf8_text(source.as_bytes()) else {
+            continue;
+        };
+        let condition: String = condition
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c == '\'' { '"' } else { c })
+            .collect();
+        if condition != r#"__name__=="__main__""# && condition != r#""__main__"==__name__"# {
+            continue;
         }
-        _ => {}
-    });
+        collect_identifier_nodes(body, source, |node| {
+            if let Ok(name) = node.utf8_text(source.as_bytes()) {
+                names.push(name);
+            }
+        });
+    }
+    names
 }
 
-pub(crate) fn emit_references(
-    path: &Path,
-    source: &str,
-    tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
-) {
-    walk_tree(tree, |node| {
-        if REFERENCE_KINDS.contains(&node.kind())
-            && let Some(location) = location_from_node(path, source, node)
+/// The `module:function` entry points a `pyproject.toml` publishes, with
+/// their 1-based line and column: `[project.scripts]`,
+/// `[project.gui-scripts]`, `[project.entry-points.*]` and Poetry's
+/// `[tool.poetry.scripts]` and `[tool.poetry.plugins.*]`. Only
+/// `name = "module:function"` lines and inline tables with a `callable`
+/// are read.
+pub(crate) fn pyproject_entry_points(contents: &str) -> Vec<(usize, usize, &str)> {
+    let mut entry_points = Vec::new();
+    let mut in_entry_table = false;
+    for (index, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(table) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
         {
-            emit(location);
+            let table = table.trim();
+            in_entry_table = matches!(
+                table,
+                "project.scripts" | "project.gui-scripts" | "tool.poetry.scripts"
+            ) || table.starts_with("project.entry-points.")
+                || table.starts_with("tool.poetry.plugins.");
+            continue;
         }
-    });
-}
-
-fn is_top_level(node: Node) -> bool {
-    let Some(parent) = node.parent() else {
-        return false;
-    };
-    if parent.kind() == "module" {
-        return true;
-    }
-    if parent.kind() == "decorated_definition" {
-        return parent
-            .parent()
-            .map(|grand| grand.kind() == "module")
-            .unwrap_or(false);
+        if !in_entry_table {
+            continue;
+        }
+        let Some((_, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = match value.trim().strip_prefix('{') {
+            // `name = { callable = "module:function" }`
+            Some(table) => table
+                .split(',')
+                .filter_map(|field| field.split_once('='))
+                .find(|(key, _)| key.trim() == "callable")
+                .map_or("", |(_, callable)| callable.trim().trim_end_matches('}')),
+            None => value,
+        };
+        let Some(quoted) = value.trim().strip_prefix(['"', '\'']) else {
+            continue;
+        };
+        // `module:function [extra]`
+        let entry_point = quoted
+            .trim_end_matches(['"', '\''])
+            .split_whitespace()
+            .next()
+            .unwrap_or_default();
+        let column = line.find(entry_point).map_or(1, |offset| offset + 1);
+        entry_points.push((index + 1, column, entry_point));
     }
-    false
+    entry_points
 }
@@ -0,0 +1,2 @@
+; Identifiers linked to definitions of the same name.
+(identifier) @reference
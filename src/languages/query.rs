@@ -0,0 +1,223 @@
+//! Extraction driven by tree-sitter queries.
+//!
+//! Each language module ships a `references.scm` whose `@reference` captures
+//! are the identifiers linked to definitions by name, and most ship a
+//! `definitions.scm` that captures definition names as
+//! `@definition.<kind>`, e.g. `@definition.function`. Captures starting with
+//! `_` are only used by predicates. When several patterns capture the same
+//! node, the first pattern in the file decides its kind.
+
+use std::sync::OnceLock;
+
+use rustc_hash::FxHashMap;
+use tree_sitter::{Node, Query, QueryCursor, StreamingIterator, Tree};
+
+use crate::languages::{Language, SymbolKind};
+
+const REFERENCE_CAPTURE: &str = "reference";
+const DEFINITION_CAPTURE_PREFIX: &str = "definition.";
+
+/// Compiled queries of one language.
+pub(crate) struct LanguageQueries {
+    definitions: Option<DefinitionQuery>,
+    references: Query,
+}
+
+struct DefinitionQuery {
+    query: Query,
+    /// Symbol kind of each capture, indexed like the query's capture names.
+    kinds: Vec<Option<SymbolKind>>,
+}
+
+impl LanguageQueries {
+    /// Compiles `definitions` (optional) and `references` for `grammar`,
+    /// rejecting capture names the engine doesn't understand.
+    pub(crate) fn new(
+        grammar: &tree_sitter::Language,
+        definitions: Option<&str>,
+        references: &str,
+    ) -> Result<Self, String> {
+        let definitions = definitions
+            .map(|source| {
+                let query = Query::new(grammar, source)
+                    .map_err(|err| format!("definitions query: {err}"))?;
+                let kinds = query
+                    .capture_names()
+                    .iter()
+                    .map(|name| definition_kind(name))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| format!("definitions query: {err}"))?;
+                Ok::<_, String>(DefinitionQuery { query, kinds })
+            })
+            .transpose()?;
+        let references =
+            Query::new(grammar, references).map_err(|err| format!("references query: {err}"))?;
+        if let Some(name) = references
+            .capture_names()
+            .iter()
+            .find(|name| **name != REFERENCE_CAPTURE && !name.starts_with('_'))
+        {
+            return Err(format!(
+                "references query: unknown capture @{name}, expected @{REFERENCE_CAPTURE}"
+            ));
+        }
+        Ok(Self {
+            definitions,
+            references,
+        })
+    }
+
+    /// Emits the name node and kind of every definition, in source order.
+    pub(crate) fn emit_definitions<'tree>(
+        &self,
+        source: &str,
+        tree: &'tree Tree,
+        mut emit: impl FnMut(Node<'tree>, SymbolKind),
+    ) {
+        let Some(definitions) = &self.definitions else {
+            return;
+        };
+        // Node id -> (pattern index, node, kind) of the first pattern that
+        // captured it.
+        let mut found: FxHashMap<usize, (usize, Node<'tree>, SymbolKind)> = FxHashMap::default();
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(&definitions.query, tree.root_node(), source.as_bytes());
+        while let Some(found_match) = matches.next() {
+            for capture in found_match.captures {
+                let Some(kind) = definitions.kinds[capture.index as usize] else {
+                    continue;
+                };
+                let candidate = (found_match.pattern_index, capture.node, kind);
+                found
+                    .entry(capture.node.id())
+                    .and_modify(|current| {
+                        if candidate.0 < current.0 {
+                            *current = candidate;
+                        }
+                    })
+                    .or_insert(candidate);
+            }
+        }
+        let mut found: Vec<_> = found.into_values().collect();
+        found.sort_by_key(|(_, node, _)| node.start_byte());
+        for (_, node, kind) in found {
+            emit(node, kind);
+        }
+    }
+
+    /// Emits every `@reference` node, in source order.
+    pub(crate) fn emit_references<'tree>(
+        &self,
+        source: &str,
+        tree: &'tree Tree,
+        mut emit: impl FnMut(Node<'tree>),
+    ) {
+        let mut cursor = QueryCursor::new();
+        let mut captures = cursor.captures(&self.references, tree.root_node(), source.as_bytes());
+        let mut last = None;
+        while let Some((found_match, index)) = captures.next() {
+            let capture = found_match.captures[*index];
+            if self.references.capture_names()[capture.index as usize] != REFERENCE_CAPTURE
+                || last == Some(capture.node.id())
+            {
+                continue;
+            }
+            last = Some(capture.node.id());
+            emit(capture.node);
+        }
+    }
+}
+
+fn definition_kind(capture: &str) -> Result<Option<SymbolKind>, String> {
+    if capture.starts_with('_') {
+        return Ok(None);
+    }
+    capture
+        .strip_prefix(DEFINITION_CAPTURE_PREFIX)
+        .and_then(SymbolKind::from_name)
+        .map(Some)
+        .ok_or_else(|| {
+            format!("unknown capture @{capture}, expected @{DEFINITION_CAPTURE_PREFIX}<kind>")
+        })
+}
+
+/// The queries of `language`, compiled on first use for built-in languages.
+pub(crate) fn queries(language: Language) -> &'static LanguageQueries {
+    if let Language::Custom(index) = language {
+        return crate::languages::custom::queries(index);
+    }
+    static QUERIES: [OnceLock<LanguageQueries>; Language::ALL.len()] =
+        [const { OnceLock::new() }; Language::ALL.len()];
+    let index = Language::ALL
+        .iter()
+        .position(|candidate| *candidate == language)
+        .expect("every language is listed in Language::ALL");
+    QUERIES[index].get_or_init(|| {
+        let (definitions, references) = crate::languages::query_sources(language);
+        let grammar = crate::languages::tree_sitter_language(language);
+        LanguageQueries::new(&grammar, definitions, references)
+            .unwrap_or_else(|err| panic!("built-in {} {err}", language.name()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LanguageQueries, queries};
+    use crate::languages::{Language, SymbolKind, tree_sitter_language};
+
+    #[test]
+    fn built_in_queries_compile() {
+        for language in Language::ALL {
+            queries(language);
+        }
+    }
+
+    #[test]
+    fn first_pattern_decides_the_kind() {
+        let queries = LanguageQueries::new(
+            &tree_sitter_language(Language::Python),
+            Some(
+                "(module (class_definition name: (identifier) @definition.interface (#match? @definition.interface \"^I\")))\n\
+                 (module (class_definition name: (identifier) @definition.class))",
+            ),
+            "(identifier) @reference",
+        )
+        .expect("valid queries");
+        let source = "class IShape:\n    pass\n\nclass Square(IShape):\n    pass\n";
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_language(Language::Python))
+            .expect("grammar");
+        let tree = parser.parse(source, None).expect("parse");
+        let mut found = Vec::new();
+        queries.emit_definitions(source, &tree, |node, kind| {
+            found.push((node.utf8_text(source.as_bytes()).unwrap(), kind));
+        });
+        assert_eq!(
+            found,
+            vec![
+                ("IShape", SymbolKind::Interface),
+                ("Square", SymbolKind::Class)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_captures() {
+        let python = tree_sitter_language(Language::Python);
+        let err = LanguageQueries::new(&python, Some("(identifier) @definition.thing"), "")
+            .err()
+            .expect("unknown kind");
+        assert_eq!(
+            err,
+            "definitions query: unknown capture @definition.thing, expected @definition.<kind>"
+        );
+        let err = LanguageQueries::new(&python, None, "(identifier) @ref")
+            .err()
+            .expect("unknown capture");
+        assert_eq!(
+            err,
+            "references query: unknown capture @ref, expected @reference"
+        );
+    }
+}
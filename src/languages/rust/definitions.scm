@@ -0,0 +1,12 @@
+; Top-level items, i.e. the symbols other modules can import.
+
+(source_file
+  [
+    (function_item name: (identifier) @definition.function)
+    (struct_item name: (type_identifier) @definition.struct)
+    (enum_item name: (type_identifier) @definition.enum)
+    (const_item name: (identifier) @definition.constant)
+    (static_item name: (identifier) @definition.constant)
+    (type_item name: (type_identifier) @definition.type)
+    (trait_item name: (type_identifier) @definition.trait)
+  ])
@@ -1,53 +1,631 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use tree_sitter::Node;
 
-use crate::find_references::{Location, location_from_node, walk_tree};
+use crate::find_references::{FfiExport, IMPORT_ALL, ImportBinding, location_from_node, walk_tree};
+use crate::intern::intern;
+use crate::languages::Ecosystem;
 
 pub(crate) const EXTENSIONS: &[&str] = &["rs"];
-pub(crate) const REFERENCE_KINDS: &[&str] = &["identifier", "type_identifier"];
+pub(crate) const DEFINITIONS_QUERY: &str = include_str!("definitions.scm");
+pub(crate) const REFERENCES_QUERY: &str = include_str!("references.scm");
+/// Prelude items and trait methods most types implement.
+pub(crate) const COMMON_NAMES: &[&str] = &[
+    "new",
+    "default",
+    "from",
+    "into",
+    "clone",
+    "main",
+    "fmt",
+    "drop",
+    "get",
+    "iter",
+    "len",
+    "push",
+    "unwrap",
+    "to_string",
+    "as_ref",
+    "eq",
+    "hash",
+    "Ok",
+    "Err",
+    "Some",
+    "None",
+    "Vec",
+    "String",
+    "Box",
+    "Option",
+    "Result",
+];
 
 pub(crate) fn language() -> tree_sitter::Language {
     tree_sitter_rust::LANGUAGE.into()
 }
 
-pub(crate) fn emit_definitions(
-    path: &Path,
+/// Emits the line ranges of items marked `#[test]`, `#[cfg(test)]` or a cfg
+/// that requires `test`, such as `#[cfg(all(test, unix))]`.
+pub(crate) fn emit_test_ranges(
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut((usize, usize)),
 ) {
-    walk_tree(tree, |node| match node.kind() {
-        "function_item" | "struct_item" | "enum_item" | "const_item" | "static_item"
-        | "type_item" | "trait_item" => {
-            if is_top_level(node)
-                && let Some(name) = node.child_by_field_name("name")
-                && let Some(location) = location_from_node(path, source, name)
-            {
-                emit(location);
-            }
+    walk_tree(tree, |node| {
+        if matches!(node.kind(), "mod_item" | "function_item") && has_test_attribute(node, source) {
+            emit((node.start_position().row + 1, node.end_position().row + 1));
         }
-        _ => {}
     });
 }
 
-pub(crate) fn emit_references(
+fn has_test_attribute(node: Node, source: &str) -> bool {
+    attributes(node, source).iter().any(|attribute| {
+        attribute == "#[test]"
+            || attribute
+                .strip_prefix("#[cfg(")
+                .and_then(|rest| rest.strip_suffix(")]"))
+                .is_some_and(enables_test)
+    })
+}
+
+/// Whether a whitespace-free cfg predicate holds under `cfg(test)`: `test`
+/// itself, or an `all`/`any` with `test` among its operands. `not(..)`
+/// and other predicates don't count.
+fn enables_test(predicate: &str) -> bool {
+    if predicate == "test" {
+        return true;
+    }
+    let Some((operator, operands)) = predicate
+        .strip_suffix(')')
+        .and_then(|rest| rest.split_once('('))
+    else {
+        return false;
+    };
+    matches!(operator, "all" | "any") && cfg_operands(operands).any(enables_test)
+}
+
+/// Splits the operands of `all(..)` or `any(..)` on their top-level commas.
+fn cfg_operands(operands: &str) -> impl Iterator<Item = &str> {
+    let (mut depth, mut in_string, mut start) = (0usize, false, 0);
+    let mut split = Vec::new();
+    for (index, ch) in operands.char_indices() {
+        match ch {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth = depth.saturating_sub(1),
+            ',' if !in_string && depth == 0 => {
+                split.push(&operands[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&operands[start..]);
+    split.into_iter()
+}
+
+/// Emits top-level items exposed to Python (`#[pyfunction]`, `#[pyclass]`)
+/// or JavaScript (`#[wasm_bindgen]`, `#[napi]`), under the name they get on
+/// the other side.
+pub(crate) fn emit_ffi_exports(
     path: &Path,
     source: &str,
     tree: &tree_sitter::Tree,
-    mut emit: impl FnMut(Location),
+    mut emit: impl FnMut(FfiExport),
 ) {
     walk_tree(tree, |node| {
-        if REFERENCE_KINDS.contains(&node.kind())
-            && let Some(location) = location_from_node(path, source, node)
+        if !matches!(node.kind(), "function_item" | "struct_item" | "enum_item")
+            || !is_top_level(node)
         {
-            emit(location);
+            return;
         }
+        let Some(name) = node.child_by_field_name("name") else {
+            return;
+        };
+        let Some(definition) = location_from_node(path, source, name) else {
+            return;
+        };
+        let attributes = attributes(node, source);
+        let Some((ecosystem, exported)) = ffi_binding(&attributes, definition.name_str(), node)
+        else {
+            return;
+        };
+        emit(FfiExport {
+            definition,
+            exported: intern(&exported),
+            ecosystem,
+        });
     });
 }
 
+fn ffi_binding(attributes: &[String], name: &str, node: Node) -> Option<(Ecosystem, String)> {
+    let renamed = |key: &str| {
+        attributes
+            .iter()
+            .find_map(|attribute| attribute_value(attribute, key))
+    };
+    let has = |prefix: &str| {
+        attributes
+            .iter()
+            .any(|attribute| attribute.starts_with(prefix))
+    };
+    if has("#[pyfunction") || has("#[pyclass") {
+        let exported = renamed("name").unwrap_or_else(|| name.to_string());
+        return Some((Ecosystem::Python, exported));
+    }
+    if has("#[wasm_bindgen") {
+        let exported = renamed("js_name").unwrap_or_else(|| name.to_string());
+        return Some((Ecosystem::JavaScript, exported));
+    }
+    if has("#[napi") {
+        // napi-rs exports functions in camelCase unless renamed.
+        let exported = renamed("js_name").unwrap_or_else(|| {
+            if node.kind() == "function_item" {
+                camel_case(name)
+            } else {
+                name.to_string()
+            }
+        });
+        return Some((Ecosystem::JavaScript, exported));
+    }
+    None
+}
+
+/// Reads `key = value` or `key = "value"` out of a whitespace-free attribute.
+fn attribute_value(attribute: &str, key: &str) -> Option<String> {
+    let needle = format!("{key}=");
+    let start = attribute
+        .match_indices(&needle)
+        .find(|(index, _)| {
+            attribute[..*index]
+                .chars()
+                .last()
+                .is_some_and(|ch| ch == '(' || ch == ',')
+        })?
+        .0
+        + needle.len();
+    let value: String = attribute[start..]
+        .chars()
+        .take_while(|ch| !matches!(ch, ',' | ')'))
+        .filter(|ch| *ch != '"')
+        .collect();
+    (!value.is_empty()).then_some(value)
+}
+
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for ch in name.chars() {
+        if ch == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(ch.to_uppercase());
+            upper = false;
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Attributes directly above `node`, with whitespace removed.
+fn attributes(node: Node, source: &str) -> Vec<String> {
+    let mut attributes = Vec::new();
+    let mut sibling = node.prev_sibling();
+    while let Some(attribute) = sibling {
+        match attribute.kind() {
+            "attribute_item" => {
+                let text = attribute.utf8_text(source.as_bytes()).unwrap_or_default();
+                attributes.push(text.chars().filter(|ch| !ch.is_whitespace()).collect());
+            }
+            "line_comment" | "block_comment" => {}
+            _ => break,
+        }
+        sibling = attribute.prev_sibling();
+    }
+    attributes
+}
+
 fn is_top_level(node: Node) -> bool {
     node.parent()
         .map(|parent| parent.kind() == "source_file")
         .unwrap_or(false)
 }
+
+/// Emits a binding for each name a `use` declaration brings in, and one for
+/// the last segment of each qualified path such as `models::User`, bound to
+/// that reference alone so it wins over a `use` of the same name. Specifiers
+/// are module paths (`crate::models`), resolved by [`resolve_import`]; a
+/// glob (`use models::*`) binds [`IMPORT_ALL`].
+pub(crate) fn emit_imports(
+    path: &Path,
+    source: &str,
+    tree: &tree_sitter::Tree,
+    mut emit: impl FnMut(ImportBinding),
+) {
+    let file = intern(&path.to_string_lossy());
+    let mut used: HashMap<String, String> = HashMap::new();
+    let mut use_ranges = Vec::new();
+    walk_tree(tree, |node| {
+        if node.kind() == "use_declaration" {
+            use_ranges.push(node.byte_range());
+            if let Some(argument) = node.child_by_field_name("argument")
+                && let Ok(text) = argument.utf8_text(source.as_bytes())
+            {
+                let mut expanded = Vec::new();
+                expand_use_tree("", &compact_path(text), &mut expanded);
+                for (full, local) in expanded {
+                    used.insert(local, full);
+                }
+            }
+        }
+    });
+    walk_tree(tree, |node| {
+        if !matches!(node.kind(), "scoped_identifier" | "scoped_type_identifier")
+            || use_ranges
+                .iter()
+                .any(|range| range.contains(&node.start_byte()))
+        {
+            return;
+        }
+        let (Some(module), Some(name)) = (
+            node.child_by_field_name("path"),
+            node.child_by_field_name("name"),
+        ) else {
+            return;
+        };
+        let (Ok(module), Some(name)) = (
+            module.utf8_text(source.as_bytes()),
+            location_from_node(path, source, name),
+        ) else {
+            return;
+        };
+        let module = compact_path(module);
+        if !module
+            .split("::")
+            .all(|segment| !segment.is_empty() && segment.chars().all(is_identifier_char))
+        {
+            return;
+        }
+        // A leading segment brought in by `use` stands for its full path.
+        let module = match module.split_once("::") {
+            Some((first, rest)) => used
+                .get(first)
+                .map_or(module.clone(), |full| format!("{full}::{rest}")),
+            None => used.get(&module).cloned().unwrap_or(module),
+        };
+        emit(ImportBinding {
+            path: file,
+            local: name.name,
+            imported: name.name,
+            specifier: module,
+            site: Some((name.line, name.column)),
+        });
+    });
+
+    for (local, full) in used {
+        let Some((module, imported)) = full.rsplit_once("::") else {
+            continue;
+        };
+        let (local, imported) = if imported == "*" {
+            (IMPORT_ALL, IMPORT_ALL)
+        } else {
+            (local.as_str(), imported)
+        };
+        emit(ImportBinding {
+            path: file,
+            local: intern(local),
+            imported: intern(imported),
+            specifier: module.to_string(),
+            site: None,
+        });
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// `path` on one line, without whitespace around `::`, braces or commas.
+fn compact_path(path: &str) -> String {
+    let mut compact = path.split_whitespace().collect::<Vec<_>>().join(" ");
+    for (spaced, tight) in [
+        (" ::", "::"),
+        (":: ", "::"),
+        ("{ ", "{"),
+        (" }", "}"),
+        (" ,", ","),
+        (", ", ","),
+    ] {
+        compact = compact.replace(spaced, tight);
+    }
+    compact
+}
+
+/// Flattens a use tree such as `models::{User, auth::{self, Token as T}}`
+/// into (full path, local name) pairs. Globs end in `::*` and bind `*`;
+/// `as _` imports bind nothing.
+fn expand_use_tree(prefix: &str, tree: &str, out: &mut Vec<(String, String)>) {
+    let join = |path: &str| match (prefix.is_empty(), path.trim_start_matches("::")) {
+        (true, path) => path.to_string(),
+        (false, "") => prefix.to_string(),
+        (false, path) => format!("{prefix}::{path}"),
+    };
+    if let Some(open) = tree.find('{')
+        && let Some(inner) = tree[open + 1..].strip_suffix('}')
+    {
+        let prefix = join(tree[..open].trim_end_matches("::"));
+        let mut depth = 0;
+        let mut start = 0;
+        for (index, c) in inner.char_indices().chain([(inner.len(), ',')]) {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    let part = inner[start..index].trim();
+                    if !part.is_empty() {
+                        expand_use_tree(&prefix, part, out);
+                    }
+                    start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        return;
+    }
+    let (path, alias) = match tree.split_once(" as ") {
+        Some((path, alias)) => (path.trim(), Some(alias.trim())),
+        None => (tree, None),
+    };
+    let full = if path == "self" {
+        prefix.to_string()
+    } else {
+        join(path)
+    };
+    let local = alias.unwrap_or_else(|| full.rsplit("::").next().unwrap_or(&full));
+    if local != "_" && !full.is_empty() {
+        out.push((full.clone(), local.to_string()));
+    }
+}
+
+/// Resolves a module path to the file that defines the module, following
+/// Rust's file layout: `a::b` is `a/b.rs` or `a/b/mod.rs` under the
+/// directory of the module the path starts from. `crate` starts at the
+/// nearest directory holding `lib.rs` or `main.rs`, `self` at `from` and
+/// each `super` one module up. Other paths start at `from`, then at the
+/// crate root; external crates don't resolve.
+pub(crate) fn resolve_import(
+    from: &str,
+    specifier: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Option<String> {
+    let from = Path::new(from);
+    let mut segments: Vec<&str> = specifier.split("::").collect();
+    let walk = |dir: PathBuf, segments: &[&str]| module_file(dir, segments, &exists);
+    match segments[0] {
+        "crate" => walk_from_crate_root(from, &segments[1..], &exists),
+        "self" if segments.len() == 1 => Some(from.to_string_lossy().to_string()),
+        "self" => walk(module_dir(from), &segments[1..]),
+        "super" => {
+            let mut dir = module_dir(from);
+            while segments.first() == Some(&"super") {
+                segments.remove(0);
+                dir = dir.parent()?.to_path_buf();
+            }
+            walk(dir, &segments)
+        }
+        _ => walk(module_dir(from), &segments)
+            .or_else(|| walk_from_crate_root(from, &segments, &exists)),
+    }
+}
+
+/// Directory holding the submodules of the module defined in `file`.
+fn module_dir(file: &Path) -> PathBuf {
+    let is_root = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| matches!(name, "mod.rs" | "lib.rs" | "main.rs"));
+    if is_root {
+        file.parent().unwrap_or(Path::new("")).to_path_buf()
+    } else {
+        file.with_extension("")
+    }
+}
+
+/// Module `segments` below the crate root: the nearest ancestor directory
+/// of `from` holding `lib.rs` or `main.rs`. A crate root without symbols of
+/// its own may not be known, so without one the nearest ancestor holding
+/// the module is used instead.
+fn walk_from_crate_root(
+    from: &Path,
+    segments: &[&str],
+    exists: &impl Fn(&str) -> bool,
+) -> Option<String> {
+    let root = from.ancestors().skip(1).find(|dir| {
+        ["lib.rs", "main.rs"]
+            .iter()
+            .any(|root| exists(&dir.join(root).to_string_lossy()))
+    });
+    match root {
+        Some(root) => module_file(root.to_path_buf(), segments, exists),
+        None => from
+            .ancestors()
+            .skip(1)
+            .find_map(|dir| module_file(dir.to_path_buf(), segments, exists)),
+    }
+}
+
+/// The file of module `segments` below `dir`, or of the module owning
+/// `dir` itself when `segments` is empty.
+fn module_file(
+    mut dir: PathBuf,
+    segments: &[&str],
+    exists: &impl Fn(&str) -> bool,
+) -> Option<String> {
+    let find = |candidates: Vec<PathBuf>| {
+        candidates
+            .into_iter()
+            .map(|candidate| candidate.to_string_lossy().to_string())
+            .find(|candidate| exists(candidate))
+    };
+    let Some((last, parents)) = segments.split_last() else {
+        return find(vec![
+            dir.join("lib.rs"),
+            dir.join("main.rs"),
+            dir.join("mod.rs"),
+            dir.with_extension("rs"),
+        ]);
+    };
+    for segment in parents {
+        dir.push(segment);
+    }
+    find(vec![
+        dir.join(format!("{last}.rs")),
+        dir.join(last).join("mod.rs"),
+    ])
+}
+
+/// The parts of a `Cargo.toml` that link the crates of a workspace.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct CargoManifest {
+    /// `[package]` name; virtual workspace manifests have none.
+    pub(crate) name: Option<String>,
+    /// Normal and build dependencies, including target-specific ones.
+    pub(crate) dependencies: Vec<CargoDependency>,
+    /// `[workspace.dependencies]`, which members inherit with
+    /// `workspace = true`.
+    pub(crate) workspace_dependencies: Vec<CargoDependency>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct CargoDependency {
+    /// Name the dependency is declared under.
+    pub(crate) key: String,
+    /// Package name, which `package = "..."` may rename.
+    pub(crate) package: String,
+    /// Path relative to the manifest's directory.
+    pub(crate) path: Option<String>,
+    pub(crate) workspace: bool,
+}
+
+/// Reads the package name and dependencies of a `Cargo.toml`. Like the
+/// config file, only the single-line TOML forms are understood:
+/// `name = "1.0"`, inline tables, dotted keys and `[dependencies.name]`
+/// tables. Dev-dependencies are skipped.
+pub(crate) fn cargo_manifest(contents: &str) -> CargoManifest {
+    let mut manifest = CargoManifest::default();
+    let mut table = String::new();
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            table = name.trim().to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if table == "package" && key == "name" {
+            manifest.name = toml_string(value).map(str::to_string);
+            continue;
+        }
+        // `[dependencies]` holds `name = ...` lines, `[dependencies.name]`
+        // holds the fields of one dependency.
+        let (list, name, fields) = match dependency_table(&table) {
+            Some((list, None)) => match key.split_once('.') {
+                Some((name, field)) => (list, name.trim(), vec![(field.trim(), value)]),
+                None => (list, key, inline_table(value)),
+            },
+            Some((list, Some(name))) => (list, name, vec![(key, value)]),
+            None => continue,
+        };
+        let list = if list {
+            &mut manifest.workspace_dependencies
+        } else {
+            &mut manifest.dependencies
+        };
+        let index = match list.iter().position(|dependency| dependency.key == name) {
+            Some(index) => index,
+            None => {
+                list.push(CargoDependency {
+                    key: name.to_string(),
+                    package: name.to_string(),
+                    ..CargoDependency::default()
+                });
+                list.len() - 1
+            }
+        };
+        let dependency = &mut list[index];
+        for (field, value) in fields {
+            match field {
+                "package" => {
+                    if let Some(package) = toml_string(value) {
+                        dependency.package = package.to_string();
+                    }
+                }
+                "path" => dependency.path = toml_string(value).map(str::to_string),
+                "workspace" => dependency.workspace = value == "true",
+                _ => {}
+            }
+        }
+    }
+    manifest
+}
+
+/// Whether `table` lists dependencies: `Some((is_workspace, name))`, where
+/// `name` is set for a `[dependencies.name]` table.
+fn dependency_table(table: &str) -> Option<(bool, Option<&str>)> {
+    if let Some(rest) = table.strip_prefix("workspace.dependencies") {
+        return match rest.strip_prefix('.') {
+            Some(name) => Some((true, Some(name))),
+            None => rest.is_empty().then_some((true, None)),
+        };
+    }
+    let rest = match table.strip_prefix("target.") {
+        // `target.'cfg(unix)'.dependencies`: the cfg may contain dots.
+        Some(target) => {
+            let index = target
+                .find(".dependencies")
+                .or_else(|| target.find(".build-dependencies"))?;
+            &target[index + 1..]
+        }
+        None => table,
+    };
+    let rest = rest
+        .strip_prefix("dependencies")
+        .or_else(|| rest.strip_prefix("build-dependencies"))?;
+    match rest.strip_prefix('.') {
+        Some(name) => Some((false, Some(name))),
+        None => rest.is_empty().then_some((false, None)),
+    }
+}
+
+/// `key = value` pairs of a single-line inline table such as
+/// `{ path = "../core", features = ["x"] }`; other values yield none.
+fn inline_table(value: &str) -> Vec<(&str, &str)> {
+    let Some(inner) = value
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+    else {
+        return Vec::new();
+    };
+    inner
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect()
+}
+
+fn toml_string(value: &str) -> Option<&str> {
+    value
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .or_else(|| {
+            value
+                .strip_prefix('\'')
+                .and_then(|rest| rest.strip_suffix('\''))
+        })
+}
@@ -1,16 +1,66 @@
+pub mod aggregate;
 mod analysis;
+mod authors;
+pub mod buckets;
+mod builder;
 mod cache;
+#[cfg(feature = "capi")]
+mod capi;
+mod codeowners;
+pub mod config;
+pub mod coverage;
+pub mod diff;
+mod encoding;
+mod error;
+mod filter;
 mod find_references;
-mod graph;
+mod frecency;
+mod gitattributes;
+pub mod graph;
+pub mod history;
+pub mod impact;
 pub mod intern;
 mod io;
 mod languages;
+mod namespaces;
+mod options;
+mod pipelines;
+mod progress;
+mod read_ahead;
+mod repo;
+pub mod sarif;
+pub mod schema;
+mod session;
+pub mod snapshot;
+mod source;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-pub use analysis::{OutputRow, cruxlines, cruxlines_from_inputs};
-pub use find_references::Location;
-pub use io::CruxlinesError;
-pub use languages::Ecosystem;
+pub use analysis::{
+    Analysis, Explanation, OutputRow, RankedRows, ReferenceWeight, ScannedSource, ScoreBreakdown,
+    cruxlines, cruxlines_at_revision, cruxlines_from_inputs, cruxlines_from_source,
+    cruxlines_in_paths, cruxlines_iter, cruxlines_with_options, explain_definition,
+};
+pub use authors::{Authors, AuthorsProvider};
+pub use builder::{Cruxlines, CruxlinesBuilder};
+pub use error::{CruxlinesError, Diagnostics, ParseCounts, ParseStatus};
+pub use find_references::{Location, ScanStats};
+pub use frecency::{BlendedFrecency, Frecency, FrecencyProvider, JsonFrecency};
+pub use io::{SkippedFiles, gather_paths, gather_paths_counted};
+pub use languages::custom::{CustomLanguage, load_language, register_language};
+pub use languages::{Ecosystem, Language, SymbolKind};
 pub use lasso::Spur;
+pub use options::{
+    Centrality, CommonNames, Encoding, NamePattern, Namespace, Options, ReachabilityRoot,
+    ReferenceAggregation, ReferenceDedup, ReferenceKinds, Sample, ScanOptions, ScopedWeights,
+    Weights,
+};
+pub use progress::{Phase, Progress, ProgressHook};
+pub use repo::{find_repo_root, git_dir};
+pub use session::ParseSession;
+pub use source::{
+    FileSystemSource, GitRevisionSource, InMemorySource, SourceProvider, changed_files,
+};
 
 #[doc(hidden)]
 pub fn ecosystem_for_path(path: &std::path::Path) -> Option<Ecosystem> {
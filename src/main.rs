@@ -1,16 +1,433 @@
 use std::path::PathBuf;
 use std::process;
 
-use clap::{Parser, ValueEnum};
+#[cfg(unix)]
+mod daemon;
+mod output;
+mod phase_timer;
+mod progress_bar;
+#[cfg(unix)]
+mod tui;
 
-use cruxlines::{CruxlinesError, Ecosystem, OutputRow, cruxlines};
+use clap::{Parser, Subcommand, ValueEnum};
+
+use cruxlines::aggregate::{
+    ambiguous_names, split_by_ecosystem, summarize_files, summarize_owners,
+};
+use cruxlines::buckets::BucketScale;
+use cruxlines::config::Config;
+use cruxlines::coverage::Coverage;
+use cruxlines::diff::{Renames, rank_changes_with_renames};
+use cruxlines::graph::{GraphLevel, to_dot};
+use cruxlines::history::{renamed_from, sample_history, symbol_rank};
+use cruxlines::impact::impacted_definitions;
+use cruxlines::sarif::to_sarif;
+use cruxlines::schema::{json_schema, sections_to_json, to_json};
+use cruxlines::snapshot::Snapshot;
+use cruxlines::{
+    Analysis, Authors, BlendedFrecency, Centrality, CruxlinesError, Diagnostics, Ecosystem,
+    Encoding, Explanation, FileSystemSource, Frecency, GitRevisionSource, JsonFrecency, Language,
+    NamePattern, Options, OutputRow, ReachabilityRoot, ReferenceAggregation, ReferenceDedup,
+    Sample, ScanOptions, ScopedWeights, SymbolKind, Weights, changed_files, cruxlines_at_revision,
+    cruxlines_from_source, cruxlines_in_paths, ecosystem_for_path, explain_definition,
+    find_repo_root, gather_paths, gather_paths_counted, git_dir, load_language,
+};
+
+use crate::output::{
+    Style, TextPrinter, display_path, print_github_annotations, print_json, print_tsv,
+};
+use crate::phase_timer::PhaseTimer;
+use crate::progress_bar::progress_bar;
 
 #[derive(Debug, Parser)]
 struct Cli {
-    #[arg(short = 'e', long = "ecosystem", value_enum)]
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(value_name = "PATHS")]
+    paths: Vec<PathBuf>,
+    #[arg(short = 'e', long = "ecosystem", value_enum, global = true)]
     ecosystems: Vec<EcosystemArg>,
     #[arg(short = 'm', long = "metadata")]
     metadata: bool,
+    #[arg(short = 'r', long = "show-references")]
+    show_references: bool,
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+    #[arg(long = "top", value_name = "N", global = true)]
+    top: Option<usize>,
+    #[arg(long = "min-rank", value_name = "X")]
+    min_rank: Option<f64>,
+    #[arg(long = "min-refs", value_name = "K", global = true)]
+    min_refs: Option<usize>,
+    #[arg(long = "sample", value_name = "N")]
+    sample: Option<usize>,
+    #[arg(
+        long = "seed",
+        value_name = "S",
+        default_value_t = 0,
+        requires = "sample"
+    )]
+    seed: u64,
+    #[arg(long = "symbol-rank", global = true)]
+    symbol_rank: bool,
+    #[arg(long = "emit-graph", value_enum, value_name = "FORMAT")]
+    emit_graph: Option<GraphFormatArg>,
+    #[arg(long = "graph-level", value_enum, default_value = "file")]
+    graph_level: GraphLevelArg,
+    #[arg(long = "include-generated")]
+    include_generated: bool,
+    #[arg(short = 'f', long = "format", value_enum, default_value = "text")]
+    format: FormatArg,
+    #[arg(long = "print-schema", value_enum, value_name = "FORMAT")]
+    print_schema: Option<SchemaFormatArg>,
+    #[arg(long = "no-header")]
+    no_header: bool,
+    #[arg(long = "buckets", value_enum, value_name = "SCALE")]
+    buckets: Option<BucketsArg>,
+    #[arg(
+        long = "group-by",
+        value_enum,
+        value_name = "KEY",
+        conflicts_with_all = ["format", "emit_graph", "show_references"]
+    )]
+    group_by: Option<GroupByArg>,
+    #[arg(
+        long = "show-ambiguous",
+        conflicts_with_all = ["group_by", "format", "emit_graph", "stats_only"]
+    )]
+    show_ambiguous: bool,
+    #[arg(
+        long = "split-by-ecosystem",
+        conflicts_with_all = ["group_by", "show_ambiguous", "emit_graph", "stats_only"]
+    )]
+    split_by_ecosystem: bool,
+    #[arg(long = "config", value_name = "FILE")]
+    config: Option<PathBuf>,
+    #[arg(long = "no-frecency", global = true)]
+    no_frecency: bool,
+    #[arg(long = "frecency-file", value_name = "JSON", global = true)]
+    frecency_file: Option<PathBuf>,
+    #[arg(
+        long = "frecency-blend",
+        value_name = "W",
+        default_value_t = 0.5,
+        value_parser = parse_fraction,
+        requires = "frecency_file",
+        global = true
+    )]
+    frecency_blend: f64,
+    #[arg(long = "author-churn", global = true)]
+    author_churn: bool,
+    #[arg(long = "coverage", value_name = "FILE", global = true)]
+    coverage: Option<PathBuf>,
+    #[arg(
+        long = "coverage-weight",
+        value_name = "W",
+        requires = "coverage",
+        global = true
+    )]
+    coverage_weight: Option<f64>,
+    #[arg(long = "include", value_name = "GLOB")]
+    include: Vec<String>,
+    #[arg(long = "exclude", value_name = "GLOB")]
+    exclude: Vec<String>,
+    #[arg(long = "no-ignore")]
+    no_ignore: bool,
+    #[arg(long = "no-ignore-global")]
+    no_ignore_global: bool,
+    #[arg(long = "hidden")]
+    hidden: bool,
+    #[arg(short = 'L', long = "follow")]
+    follow: bool,
+    #[arg(long = "max-filesize", value_name = "SIZE", default_value = "2M", value_parser = parse_size)]
+    max_filesize: u64,
+    #[arg(long = "max-files", value_name = "N")]
+    max_files: Option<usize>,
+    #[arg(long = "encoding", value_enum, default_value = "strict", global = true)]
+    encoding: EncodingArg,
+    #[arg(short = 'j', long = "threads", value_name = "N", global = true)]
+    threads: Option<usize>,
+    #[arg(
+        long = "read-threads",
+        value_name = "N",
+        default_value = "16",
+        global = true
+    )]
+    read_threads: usize,
+    #[arg(long = "ffi", global = true)]
+    ffi: bool,
+    #[arg(long = "pipelines", global = true)]
+    pipelines: bool,
+    #[arg(short = 'C', long = "context", value_name = "N", default_value_t = 0)]
+    context: usize,
+    #[arg(
+        long = "color",
+        value_enum,
+        value_name = "WHEN",
+        default_value = "auto"
+    )]
+    color: ColorArg,
+    #[arg(
+        long = "kind",
+        value_enum,
+        value_name = "KIND",
+        value_delimiter = ',',
+        global = true
+    )]
+    kinds: Vec<KindArg>,
+    #[arg(long = "name", value_name = "GLOB", global = true)]
+    name: Option<String>,
+    #[arg(
+        long = "name-regex",
+        value_name = "REGEX",
+        conflicts_with = "name",
+        global = true
+    )]
+    name_regex: Option<String>,
+    #[arg(long = "stdin-paths", conflicts_with_all = ["paths", "stdin_content"])]
+    stdin_paths: bool,
+    #[arg(long = "stdin-content", value_name = "PATH")]
+    stdin_content: Option<PathBuf>,
+    #[arg(long = "quantize", value_name = "DIGITS", global = true)]
+    quantize: Option<u32>,
+    #[arg(
+        long = "count-references",
+        value_enum,
+        default_value = "occurrences",
+        global = true
+    )]
+    count_references: CountReferencesArg,
+    #[arg(long = "dedup-references", value_enum, global = true)]
+    dedup_references: Option<DedupReferencesArg>,
+    #[arg(
+        long = "centrality",
+        value_enum,
+        default_value = "pagerank",
+        global = true
+    )]
+    centrality: CentralityArg,
+    #[arg(long = "personalize", value_name = "PATH", num_args = 1.., global = true)]
+    personalize: Vec<PathBuf>,
+    #[arg(
+        long = "low-memory",
+        conflicts_with_all = ["symbol_rank", "show_references", "emit_graph"]
+    )]
+    low_memory: bool,
+    #[arg(long = "changed-since", value_name = "REV")]
+    changed_since: Option<String>,
+    #[arg(long = "scope", value_name = "DIR", conflicts_with = "changed_since")]
+    scopes: Vec<PathBuf>,
+    #[arg(long = "from", value_name = "ROOT", conflicts_with = "low_memory")]
+    from: Vec<String>,
+    #[arg(long = "rev", value_name = "REV", conflicts_with = "stdin_content")]
+    rev: Option<String>,
+    #[arg(long = "stats")]
+    stats: bool,
+    #[arg(long = "stats-only", conflicts_with_all = ["group_by", "emit_graph"])]
+    stats_only: bool,
+    #[arg(long = "progress", global = true)]
+    progress: bool,
+    #[arg(long = "language-lib", value_name = "LIBRARY", global = true)]
+    language_libs: Vec<PathBuf>,
+    #[arg(long = "lang-ext", value_name = "EXTS", global = true)]
+    lang_exts: Vec<String>,
+    #[arg(long = "lang-queries", value_name = "DIR", global = true)]
+    lang_queries: Vec<PathBuf>,
+    #[arg(long = "repo-root", value_name = "PATH", global = true)]
+    repo_root: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compare definition ranks between two git revisions.
+    Diff {
+        #[arg(value_name = "REV1")]
+        before: String,
+        #[arg(value_name = "REV2")]
+        after: String,
+        #[arg(long = "no-renames")]
+        no_renames: bool,
+    },
+    /// List definitions that transitively depend on the given files.
+    Impact {
+        #[arg(value_name = "PATHS", required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Print the score breakdown of the definitions on one line.
+    Explain {
+        #[arg(value_name = "PATH:LINE")]
+        target: String,
+    },
+    /// Print the rank of one definition at commits sampled over time.
+    History {
+        #[arg(long = "every", value_name = "PERIOD", default_value = "30d", value_parser = parse_period)]
+        every: std::time::Duration,
+        #[arg(long = "last", value_name = "PERIOD", default_value = "1y", value_parser = parse_period)]
+        last: std::time::Duration,
+        #[arg(value_name = "PATH:SYMBOL")]
+        target: String,
+        #[arg(long = "no-renames")]
+        no_renames: bool,
+    },
+    /// Keep the analysis in memory and answer JSON-RPC requests on a unix
+    /// socket, re-analyzing as files change.
+    Daemon {
+        #[arg(long = "socket", value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+    /// Browse the ranked definitions in the terminal: type to filter, Enter
+    /// opens the selected one in $EDITOR.
+    Top,
+    /// Record the top definitions in a lockfile, or check them against it.
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Write the top definitions (`--top`, 20 by default) to FILE.
+    Write {
+        #[arg(value_name = "FILE", default_value = "cruxlines.lock")]
+        file: PathBuf,
+    },
+    /// Fail with a diff when more than `--threshold` of the definitions in
+    /// FILE left the top.
+    Check {
+        #[arg(value_name = "FILE", default_value = "cruxlines.lock")]
+        file: PathBuf,
+        #[arg(long = "threshold", value_name = "N", default_value_t = 0)]
+        threshold: usize,
+    },
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum FormatArg {
+    #[value(name = "text")]
+    Text,
+    #[value(name = "sarif")]
+    Sarif,
+    #[value(name = "json")]
+    Json,
+    #[value(name = "tsv")]
+    Tsv,
+    #[value(name = "github-annotations")]
+    GithubAnnotations,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum CentralityArg {
+    #[value(name = "pagerank")]
+    PageRank,
+    #[value(name = "betweenness")]
+    Betweenness,
+    #[value(name = "in-degree")]
+    InDegree,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum EncodingArg {
+    #[value(name = "strict")]
+    Strict,
+    #[value(name = "lossy")]
+    Lossy,
+    #[value(name = "auto")]
+    Auto,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum CountReferencesArg {
+    #[value(name = "occurrences")]
+    Occurrences,
+    #[value(name = "files")]
+    Files,
+    #[value(name = "log")]
+    Log,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum DedupReferencesArg {
+    #[value(name = "none")]
+    None,
+    #[value(name = "location")]
+    Location,
+    #[value(name = "line")]
+    Line,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum BucketsArg {
+    #[value(name = "labels")]
+    Labels,
+    #[value(name = "stars")]
+    Stars,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum ColorArg {
+    #[value(name = "auto")]
+    Auto,
+    #[value(name = "always")]
+    Always,
+    #[value(name = "never")]
+    Never,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum GroupByArg {
+    #[value(name = "file")]
+    File,
+    #[value(name = "owner")]
+    Owner,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum GraphFormatArg {
+    #[value(name = "dot")]
+    Dot,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum SchemaFormatArg {
+    #[value(name = "json")]
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum GraphLevelArg {
+    #[value(name = "file")]
+    File,
+    #[value(name = "symbol")]
+    Symbol,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum KindArg {
+    #[value(name = "function", alias = "fn")]
+    Function,
+    #[value(name = "method")]
+    Method,
+    #[value(name = "class")]
+    Class,
+    #[value(name = "struct")]
+    Struct,
+    #[value(name = "enum")]
+    Enum,
+    #[value(name = "interface")]
+    Interface,
+    #[value(name = "trait")]
+    Trait,
+    #[value(name = "type")]
+    Type,
+    #[value(name = "constant", alias = "const")]
+    Constant,
+    #[value(name = "variable", alias = "var")]
+    Variable,
+    #[value(name = "field")]
+    Field,
+    #[value(name = "module", alias = "namespace")]
+    Module,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -35,6 +452,11 @@ enum EcosystemArg {
 
 fn main() {
     let cli = Cli::parse();
+    configure_threads(cli.threads);
+    if let Some(SchemaFormatArg::Json) = cli.print_schema {
+        print_json(&json_schema());
+        return;
+    }
     let cwd = match std::env::current_dir() {
         Ok(cwd) => cwd,
         Err(err) => {
@@ -42,19 +464,343 @@ fn main() {
             process::exit(1);
         }
     };
-    let Some(repo_root) = find_repo_root(&cwd) else {
-        eprintln!("cruxlines: current dir is not inside a git repository");
-        process::exit(1);
+    let repo_root = match &cli.repo_root {
+        Some(path) => {
+            let Some(root) = std::fs::canonicalize(cwd.join(path))
+                .ok()
+                .filter(|root| root.is_dir())
+            else {
+                eprintln!("cruxlines: path not found: {}", path.display());
+                process::exit(1);
+            };
+            if git_dir(&root).is_none() && !cli.no_frecency {
+                eprintln!(
+                    "cruxlines: warning: {} is not a git repository, analyzing it without frecency",
+                    root.display()
+                );
+            }
+            root
+        }
+        None => find_repo_root(&cwd).unwrap_or_else(|| {
+            eprintln!(
+                "cruxlines: warning: no git repository found, analyzing {} without frecency (pass --repo-root to set the root)",
+                cwd.display()
+            );
+            cwd.clone()
+        }),
     };
-    let ecosystems = selected_ecosystems(&cli.ecosystems);
-
-    let output_rows = match cruxlines(&repo_root, &ecosystems) {
-        Ok(rows) => rows,
+    let has_git = git_dir(&repo_root).is_some();
+    let custom_ecosystems = load_custom_languages(&cli, &cwd);
+    let mut ecosystems = selected_ecosystems(&cli.ecosystems);
+    ecosystems.extend(custom_ecosystems);
+    let config = match &cli.config {
+        Some(path) => {
+            Config::from_file(&cwd.join(path)).and_then(|config| config.with_nested(&repo_root))
+        }
+        None => Config::discover(&repo_root),
+    };
+    let config = match config {
+        Ok(config) => config,
         Err(err) => {
             report_error(err);
             process::exit(1);
         }
     };
+    let definition_paths = if let Some(revision) = &cli.changed_since {
+        Some(changed_files(&repo_root, revision).unwrap_or_else(|err| {
+            report_error(err);
+            process::exit(1);
+        }))
+    } else if !cli.scopes.is_empty() {
+        match resolve_scan_paths(&cli.scopes, &cwd, &repo_root) {
+            Ok(paths) => Some(paths.iter().map(|path| normalize_path(path)).collect()),
+            Err(path) => {
+                eprintln!("cruxlines: path not found: {}", path.display());
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let reachable_from = cli
+        .from
+        .iter()
+        .map(|root| reachability_root(root, &cwd))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|path| {
+            eprintln!("cruxlines: path not found: {path}");
+            process::exit(1);
+        });
+    let personalize = cli
+        .personalize
+        .iter()
+        .map(|path| {
+            let resolved = cwd.join(path);
+            if resolved.exists() {
+                normalize_path(&resolved)
+            } else {
+                eprintln!("cruxlines: path not found: {}", path.display());
+                process::exit(1);
+            }
+        })
+        .collect();
+    let stats = cli.stats || cli.stats_only;
+    let timer = std::sync::Arc::new(PhaseTimer::default());
+    let bucket_thresholds = config.buckets;
+    let options = Options {
+        use_cache: !cli.no_cache,
+        top: cli.top,
+        min_rank: cli.min_rank,
+        min_references: cli.min_refs,
+        sample: cli.sample.map(|size| Sample {
+            size,
+            seed: cli.seed,
+        }),
+        symbol_rank: cli.symbol_rank,
+        include_generated: cli.include_generated,
+        scan: ScanOptions {
+            respect_gitignore: !cli.no_ignore,
+            respect_global_gitignore: !cli.no_ignore_global,
+            hidden: cli.hidden,
+            follow_symlinks: cli.follow,
+            max_filesize: (cli.max_filesize > 0).then_some(cli.max_filesize),
+            max_files: cli.max_files,
+            encoding: match cli.encoding {
+                EncodingArg::Strict => Encoding::Strict,
+                EncodingArg::Lossy => Encoding::Lossy,
+                EncodingArg::Auto => Encoding::Auto,
+            },
+            read_threads: cli.read_threads,
+        },
+        weights: Weights {
+            coverage: cli.coverage_weight.unwrap_or(config.weights.coverage),
+            ..config.weights
+        },
+        scoped_weights: config
+            .scoped_weights
+            .into_iter()
+            .map(|scoped| ScopedWeights {
+                weights: Weights {
+                    coverage: cli.coverage_weight.unwrap_or(scoped.weights.coverage),
+                    ..scoped.weights
+                },
+                ..scoped
+            })
+            .collect(),
+        reference_kinds: config.reference_kinds,
+        common_names: config.common_names,
+        namespaces: config.namespaces,
+        progress: if stats {
+            Some(timer.hook(cli.progress.then(progress_bar)))
+        } else {
+            cli.progress.then(progress_bar)
+        },
+        frecency: frecency(&cli, &cwd, has_git),
+        coverage: cli.coverage.as_ref().map(|path| {
+            match Coverage::from_file(&cwd.join(path), &repo_root) {
+                Ok(coverage) => std::sync::Arc::new(coverage),
+                Err(err) => {
+                    report_error(err);
+                    process::exit(1);
+                }
+            }
+        }),
+        authors: if cli.author_churn && has_git {
+            Authors::Git
+        } else {
+            Authors::Disabled
+        },
+        include: cli.include.clone(),
+        exclude: cli.exclude.iter().chain(&config.exclude).cloned().collect(),
+        ffi_edges: cli.ffi,
+        pipeline_references: cli.pipelines,
+        kinds: cli.kinds.iter().map(|kind| symbol_kind(*kind)).collect(),
+        name: match (&cli.name, &cli.name_regex) {
+            (Some(glob), _) => Some(NamePattern::Glob(glob.clone())),
+            (None, Some(regex)) => Some(NamePattern::Regex(regex.clone())),
+            (None, None) => None,
+        },
+        definition_paths,
+        quantize: cli.quantize,
+        low_memory: cli.low_memory,
+        reference_aggregation: match cli.count_references {
+            CountReferencesArg::Occurrences => ReferenceAggregation::Occurrences,
+            CountReferencesArg::Files => ReferenceAggregation::Files,
+            CountReferencesArg::Log => ReferenceAggregation::Log,
+        },
+        reference_dedup: match cli.dedup_references {
+            Some(DedupReferencesArg::None) => ReferenceDedup::None,
+            Some(DedupReferencesArg::Location) => ReferenceDedup::Location,
+            Some(DedupReferencesArg::Line) => ReferenceDedup::Line,
+            None => config.reference_dedup,
+        },
+        centrality: match cli.centrality {
+            CentralityArg::PageRank => Centrality::PageRank,
+            CentralityArg::Betweenness => Centrality::Betweenness,
+            CentralityArg::InDegree => Centrality::InDegree,
+        },
+        personalize,
+        reachable_from,
+        parse_session: None,
+    };
+
+    match &cli.command {
+        Some(Command::Diff {
+            before,
+            after,
+            no_renames,
+        }) => {
+            run_diff(
+                &repo_root,
+                before,
+                after,
+                !no_renames,
+                &ecosystems,
+                &options,
+            );
+            return;
+        }
+        Some(Command::Snapshot { action }) => {
+            run_snapshot(&repo_root, &cwd, action, &ecosystems, &options);
+            return;
+        }
+        Some(Command::Impact { paths }) => {
+            let changed: Vec<PathBuf> = match resolve_scan_paths(paths, &cwd, &repo_root) {
+                Ok(paths) => paths.iter().map(|path| normalize_path(path)).collect(),
+                Err(path) => {
+                    eprintln!("cruxlines: path not found: {}", path.display());
+                    process::exit(1);
+                }
+            };
+            run_impact(&repo_root, &changed, &ecosystems, &options);
+            return;
+        }
+        Some(Command::Explain { target }) => {
+            let Some((path, line)) = target
+                .rsplit_once(':')
+                .and_then(|(path, line)| Some((path, line.parse::<usize>().ok()?)))
+            else {
+                eprintln!("cruxlines: expected PATH:LINE, got {target}");
+                process::exit(1);
+            };
+            let path = normalize_path(&cwd.join(path));
+            if !path.is_file() {
+                eprintln!("cruxlines: path not found: {}", path.display());
+                process::exit(1);
+            }
+            run_explain(&repo_root, &path, line, &ecosystems, &options);
+            return;
+        }
+        Some(Command::History {
+            every,
+            last,
+            target,
+            no_renames,
+        }) => {
+            let Some((path, name)) = target.rsplit_once(':').filter(|(_, name)| !name.is_empty())
+            else {
+                eprintln!("cruxlines: expected PATH:SYMBOL, got {target}");
+                process::exit(1);
+            };
+            // The file may not exist in the working tree any more.
+            let path = normalize_path(&cwd.join(path));
+            run_history(
+                &repo_root,
+                (&path, name),
+                *every,
+                *last,
+                !no_renames,
+                &ecosystems,
+                &options,
+            );
+            return;
+        }
+        Some(Command::Top) => {
+            run_top(&repo_root, ecosystems, options);
+            return;
+        }
+        Some(Command::Daemon { socket }) => {
+            let socket = match socket {
+                Some(socket) => cwd.join(socket),
+                None => git_dir(&repo_root)
+                    .unwrap_or_else(|| repo_root.join(".git"))
+                    .join("cruxlines.sock"),
+            };
+            run_daemon(&repo_root, &socket, ecosystems, options);
+            return;
+        }
+        None => {}
+    }
+
+    let scan_paths = if cli.stdin_paths {
+        stdin_scan_paths(&cwd)
+    } else if cli.rev.is_some() && !cli.paths.is_empty() {
+        // Paths name files at the revision, which need not exist on disk.
+        cli.paths
+            .iter()
+            .map(|path| normalize_path(&cwd.join(path)))
+            .collect()
+    } else {
+        match resolve_scan_paths(&cli.paths, &cwd, &repo_root) {
+            Ok(paths) => paths,
+            Err(path) => {
+                eprintln!("cruxlines: path not found: {}", path.display());
+                process::exit(1);
+            }
+        }
+    };
+    let inputs = Inputs {
+        scan_paths,
+        revision: cli.rev.clone(),
+        unsaved: cli
+            .stdin_content
+            .as_ref()
+            .map(|path| (normalize_path(&cwd.join(path)), read_stdin())),
+    };
+
+    match cli.group_by {
+        Some(GroupByArg::File) => {
+            run_group_by_file(&repo_root, &inputs, &ecosystems, &options);
+            return;
+        }
+        Some(GroupByArg::Owner) => {
+            run_group_by_owner(&repo_root, &inputs, &ecosystems, &options);
+            return;
+        }
+        None => {}
+    }
+    if cli.show_ambiguous {
+        run_show_ambiguous(&repo_root, &inputs, &ecosystems, &options);
+        return;
+    }
+
+    if cli.split_by_ecosystem && !matches!(cli.format, FormatArg::Text | FormatArg::Json) {
+        eprintln!("cruxlines: --split-by-ecosystem only works with --format text or json");
+        process::exit(1);
+    }
+
+    let analysis = if cli.split_by_ecosystem {
+        // `--top` applies to each section.
+        let analysis_options = Options {
+            top: None,
+            ..options.clone()
+        };
+        analyze(&repo_root, &inputs, &ecosystems, &analysis_options)
+    } else {
+        analyze(&repo_root, &inputs, &ecosystems, &options)
+    };
+    if cli.stats_only {
+        for line in stats_lines(&analysis, &timer, &repo_root, &options) {
+            println!("{line}");
+        }
+        return;
+    }
+    if cli.stats {
+        for line in stats_lines(&analysis, &timer, &repo_root, &options) {
+            eprintln!("cruxlines: {line}");
+        }
+    }
+    let mut output_rows = analysis.rows;
 
     // Test-only hook to coordinate snapshot timing in integration tests.
     if let Ok(ready_path) = std::env::var("CRUXLINES_TEST_READY_FILE") {
@@ -66,50 +812,553 @@ fn main() {
         std::thread::sleep(std::time::Duration::from_millis(pause_ms));
     }
 
-    for row in &output_rows {
-        print_row(row, &repo_root, cli.metadata);
+    if let Some(GraphFormatArg::Dot) = cli.emit_graph {
+        let level = match cli.graph_level {
+            GraphLevelArg::File => GraphLevel::File,
+            GraphLevelArg::Symbol => GraphLevel::Symbol,
+        };
+        print!("{}", to_dot(&output_rows, level, Some(&repo_root)));
+        return;
+    }
+
+    let sections = cli.split_by_ecosystem.then(|| {
+        let mut sections = split_by_ecosystem(std::mem::take(&mut output_rows));
+        if let Some(top) = options.top {
+            for section in &mut sections {
+                section.rows.truncate(top);
+            }
+        }
+        sections
+    });
+
+    if let FormatArg::Sarif = cli.format {
+        print_json(&to_sarif(&output_rows, Some(&repo_root)));
+        return;
+    }
+
+    if let FormatArg::Json = cli.format {
+        let document = match &sections {
+            Some(sections) => sections_to_json(
+                sections,
+                |ecosystem| ecosystem_name(ecosystem, &options),
+                Some(&repo_root),
+            ),
+            None => to_json(&output_rows, Some(&repo_root)),
+        };
+        print_json(&document);
+        return;
+    }
+
+    let buckets = cli.buckets.map(|scale| {
+        let scale = match scale {
+            BucketsArg::Labels => BucketScale::Labels,
+            BucketsArg::Stars => BucketScale::Stars,
+        };
+        (bucket_thresholds, scale)
+    });
+    if let FormatArg::Tsv = cli.format {
+        print_tsv(&output_rows, &repo_root, !cli.no_header, buckets);
+        return;
+    }
+
+    if let FormatArg::GithubAnnotations = cli.format {
+        print_github_annotations(&output_rows, &repo_root);
+        return;
+    }
+
+    let style = match cli.color {
+        ColorArg::Auto => Style::detect(),
+        ColorArg::Always => Style::new(true),
+        ColorArg::Never => Style::new(false),
+    };
+    let printer = TextPrinter {
+        repo_root: &repo_root,
+        metadata: cli.metadata,
+        show_references: cli.show_references,
+        context: cli.context,
+        style,
+        buckets,
+        unsaved: inputs
+            .unsaved
+            .as_ref()
+            .map(|(path, contents)| (path.as_path(), contents.as_str())),
+    };
+    let Some(sections) = sections else {
+        printer.print(&output_rows);
+        return;
+    };
+    for (index, section) in sections.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!(
+            "# {}: definitions={} total={:.6} max={:.6}",
+            ecosystem_name(section.ecosystem, &options),
+            section.definition_count,
+            section.total_rank,
+            section.max_rank
+        );
+        printer.print(&section.rows);
+    }
+}
+
+/// What to analyze: the scan roots, read from the working tree or from a
+/// git revision, plus an unsaved buffer read from stdin.
+struct Inputs {
+    scan_paths: Vec<PathBuf>,
+    revision: Option<String>,
+    unsaved: Option<(PathBuf, String)>,
+}
+
+/// Runs the analysis and reports skipped files; exits on fatal errors.
+fn analyze(
+    repo_root: &std::path::Path,
+    inputs: &Inputs,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) -> Analysis {
+    let analysis = match (&inputs.revision, &inputs.unsaved) {
+        (Some(revision), _) => {
+            GitRevisionSource::new(repo_root, revision).and_then(|mut source| {
+                source.retain(|path| {
+                    inputs
+                        .scan_paths
+                        .iter()
+                        .any(|scan_path| path.starts_with(scan_path))
+                        && ecosystem_for_path(path)
+                            .is_some_and(|ecosystem| ecosystems.contains(&ecosystem))
+                });
+                cruxlines_from_source(&source, Some(repo_root), options)
+            })
+        }
+        (None, None) => cruxlines_in_paths(repo_root, &inputs.scan_paths, ecosystems, options),
+        (None, Some((path, contents))) => {
+            gather_paths_counted(repo_root, &inputs.scan_paths, ecosystems, options).and_then(
+                |(paths, skipped)| {
+                    let mut source = FileSystemSource::new(paths);
+                    if ecosystem_for_path(path)
+                        .is_some_and(|ecosystem| ecosystems.contains(&ecosystem))
+                    {
+                        source.insert_unsaved(path.clone(), contents.clone());
+                    }
+                    let mut analysis = cruxlines_from_source(&source, Some(repo_root), options)?;
+                    analysis.stats.skipped_large_files = skipped.large;
+                    analysis.stats.skipped_excess_files = skipped.excess;
+                    Ok(analysis)
+                },
+            )
+        }
+    };
+    match analysis {
+        Ok(analysis) => {
+            report_diagnostics(&analysis.diagnostics);
+            analysis
+        }
+        Err(err) => {
+            report_error(err);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_history(
+    repo_root: &std::path::Path,
+    (path, name): (&std::path::Path, &str),
+    every: std::time::Duration,
+    last: std::time::Duration,
+    track_renames: bool,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    // The symbol is looked up among all rows at each commit.
+    let analysis_options = Options {
+        top: None,
+        min_rank: None,
+        sample: None,
+        ..options.clone()
+    };
+    let samples = sample_history(repo_root, every, last).unwrap_or_else(|err| {
+        report_error(err);
+        process::exit(1);
+    });
+    // Walk back from the newest commit, so the symbol can be followed to
+    // the path and name it had before a rename.
+    let mut target = (path.to_path_buf(), name.to_string());
+    let mut newer: Option<(&str, Vec<OutputRow>)> = None;
+    let mut lines = Vec::new();
+    for sample in samples.iter().rev() {
+        let rows =
+            match cruxlines_at_revision(repo_root, &sample.commit, ecosystems, &analysis_options) {
+                Ok(analysis) => analysis.rows,
+                Err(err) => {
+                    report_error(err);
+                    process::exit(1);
+                }
+            };
+        let mut rank = symbol_rank(&rows, &target.0, &target.1);
+        if rank.is_none()
+            && track_renames
+            && let Some((newer_commit, newer_rows)) = &newer
+        {
+            let renamed = renamed_from(
+                repo_root,
+                &sample.commit,
+                newer_commit,
+                &rows,
+                newer_rows,
+                &target.0,
+                &target.1,
+            )
+            .unwrap_or_else(|err| {
+                report_error(err);
+                process::exit(1);
+            });
+            if let Some(old) = renamed {
+                target = (PathBuf::from(old.path_str()), old.name_str().to_string());
+                rank = symbol_rank(&rows, &target.0, &target.1);
+            }
+        }
+        let renamed = (target.0 != path || target.1 != name).then(|| {
+            format!(
+                " as={}:{}",
+                display_path(&target.0.to_string_lossy(), repo_root),
+                target.1
+            )
+        });
+        lines.push(format!(
+            "{} {} {}{}",
+            sample.date,
+            &sample.commit[..sample.commit.len().min(12)],
+            rank.map_or_else(|| "-".to_string(), |rank| format!("{rank:.6}")),
+            renamed.unwrap_or_default()
+        ));
+        newer = Some((&sample.commit, rows));
+    }
+    for line in lines.iter().rev() {
+        println!("{line}");
     }
 }
 
-fn print_row(row: &OutputRow, repo_root: &std::path::Path, include_metadata: bool) {
-    let line_text = row.definition_line.as_str();
-    if include_metadata {
+fn run_group_by_file(
+    repo_root: &std::path::Path,
+    inputs: &Inputs,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    // Filters apply to the files, not to the definitions being summed.
+    let analysis_options = Options {
+        top: None,
+        min_rank: None,
+        sample: None,
+        ..options.clone()
+    };
+    let analysis = analyze(repo_root, inputs, ecosystems, &analysis_options);
+
+    let mut summaries = summarize_files(&analysis.rows);
+    if let Some(min_rank) = options.min_rank {
+        summaries.retain(|summary| summary.total_rank >= min_rank);
+    }
+    if let Some(top) = options.top {
+        summaries.truncate(top);
+    }
+    for summary in &summaries {
         println!(
-            "{}:{}:{}: rank={:.6} local={:.6} file={:.6} name={} | {}",
-            display_path(row.definition.path_str(), repo_root),
-            row.definition.line,
-            row.definition.column,
-            row.rank,
-            row.local_score,
-            row.file_rank,
-            row.definition.name_str(),
-            line_text
+            "{}:{}:{}: total={:.6} max={:.6} definitions={} top={}",
+            display_path(summary.path_str(), repo_root),
+            summary.top_symbol.line,
+            summary.top_symbol.column,
+            summary.total_rank,
+            summary.max_rank,
+            summary.definition_count,
+            summary.top_symbol.name_str()
         );
-    } else {
+    }
+}
+
+fn run_group_by_owner(
+    repo_root: &std::path::Path,
+    inputs: &Inputs,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    // Filters apply to the owners, not to the definitions being summed.
+    let analysis_options = Options {
+        top: None,
+        min_rank: None,
+        sample: None,
+        ..options.clone()
+    };
+    let analysis = analyze(repo_root, inputs, ecosystems, &analysis_options);
+
+    let mut summaries = summarize_owners(&analysis.rows);
+    if let Some(min_rank) = options.min_rank {
+        summaries.retain(|summary| summary.total_rank >= min_rank);
+    }
+    if let Some(top) = options.top {
+        summaries.truncate(top);
+    }
+    for summary in &summaries {
         println!(
-            "{}:{}:{}: {}",
-            display_path(row.definition.path_str(), repo_root),
-            row.definition.line,
-            row.definition.column,
-            line_text
+            "{}:{}:{}: owner={} total={:.6} max={:.6} definitions={} top={}",
+            display_path(summary.top_symbol.path_str(), repo_root),
+            summary.top_symbol.line,
+            summary.top_symbol.column,
+            summary.owner.as_deref().unwrap_or("-"),
+            summary.total_rank,
+            summary.max_rank,
+            summary.definition_count,
+            summary.top_symbol.name_str()
         );
     }
 }
 
-fn display_path(path: &str, repo_root: &std::path::Path) -> String {
-    let path = std::path::Path::new(path);
-    match path.strip_prefix(repo_root) {
-        Ok(rel) => rel.display().to_string(),
-        Err(_) => path.display().to_string(),
+fn run_show_ambiguous(
+    repo_root: &std::path::Path,
+    inputs: &Inputs,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    // Every definition of a name is listed, whatever its rank; `--top`
+    // counts names.
+    let analysis_options = Options {
+        top: None,
+        min_rank: None,
+        sample: None,
+        ..options.clone()
+    };
+    let analysis = analyze(repo_root, inputs, ecosystems, &analysis_options);
+
+    let mut names = ambiguous_names(&analysis.rows);
+    if let Some(top) = options.top {
+        names.truncate(top);
+    }
+    for name in &names {
+        let (first, others) = name
+            .definitions
+            .split_first()
+            .expect("ambiguous names have definitions");
+        println!(
+            "{}:{}:{}: {} definitions={} refs={}",
+            display_path(first.path_str(), repo_root),
+            first.line,
+            first.column,
+            name.name_str(),
+            name.definitions.len(),
+            name.reference_count
+        );
+        for definition in others {
+            println!(
+                "    {}:{}:{}",
+                display_path(definition.path_str(), repo_root),
+                definition.line,
+                definition.column
+            );
+        }
+    }
+}
+
+fn run_diff(
+    repo_root: &std::path::Path,
+    before: &str,
+    after: &str,
+    track_renames: bool,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    // Filters apply to the list of changes, not to either side's rows.
+    let analysis_options = Options {
+        top: None,
+        min_rank: None,
+        sample: None,
+        ..options.clone()
+    };
+    let analyze = |revision: &str| match cruxlines_at_revision(
+        repo_root,
+        revision,
+        ecosystems,
+        &analysis_options,
+    ) {
+        Ok(analysis) => {
+            report_diagnostics(&analysis.diagnostics);
+            analysis.rows
+        }
+        Err(err) => {
+            report_error(err);
+            process::exit(1);
+        }
+    };
+    let before_rows = analyze(before);
+    let after_rows = analyze(after);
+
+    let renames = if track_renames {
+        Renames::detect(repo_root, before, after, &before_rows, &after_rows).unwrap_or_else(|err| {
+            report_error(err);
+            process::exit(1);
+        })
+    } else {
+        Renames::default()
+    };
+    let mut changes = rank_changes_with_renames(&before_rows, &after_rows, &renames);
+    if let Some(top) = options.top {
+        changes.truncate(top);
+    }
+    let format_rank = |rank: Option<f64>| match rank {
+        Some(rank) => format!("{rank:.6}"),
+        None => "-".to_string(),
+    };
+    for change in &changes {
+        let renamed_from = change.renamed_from.map_or_else(String::new, |old| {
+            format!(
+                " renamed_from={}:{}",
+                display_path(old.path_str(), repo_root),
+                old.name_str()
+            )
+        });
+        println!(
+            "{}:{}:{}: {} {} -> {} ({:+.6}){renamed_from}",
+            display_path(change.definition.path_str(), repo_root),
+            change.definition.line,
+            change.definition.column,
+            change.definition.name_str(),
+            format_rank(change.before),
+            format_rank(change.after),
+            change.delta()
+        );
     }
 }
 
 fn report_error(err: CruxlinesError) {
-    match err {
-        CruxlinesError::ReadFile { path, source } => {
-            eprintln!("cruxlines: failed to read {}: {source}", path.display());
+    eprintln!("cruxlines: {err}");
+}
+
+fn report_diagnostics(diagnostics: &Diagnostics) {
+    for diagnostic in diagnostics {
+        eprintln!("cruxlines: skipped: {diagnostic}");
+    }
+}
+
+/// The `--stats` report: parse status, what the scan found and how long
+/// each phase took.
+fn stats_lines(
+    analysis: &Analysis,
+    timer: &PhaseTimer,
+    repo_root: &std::path::Path,
+    options: &Options,
+) -> Vec<String> {
+    let counts = analysis.diagnostics.parse_counts();
+    let mut lines = vec![format!(
+        "parsed {} files: {} ok, {} partial, {} failed",
+        counts.ok + counts.partial + counts.failed,
+        counts.ok,
+        counts.partial,
+        counts.failed
+    )];
+    for path in analysis.diagnostics.partial() {
+        lines.push(format!(
+            "partial: {} has syntax errors",
+            display_path(&path.to_string_lossy(), repo_root)
+        ));
+    }
+    let stats = &analysis.stats;
+    let mut files: Vec<_> = stats
+        .files
+        .iter()
+        .map(|(ecosystem, count)| format!("{}={count}", ecosystem_name(*ecosystem, options)))
+        .collect();
+    files.sort();
+    lines.push(format!("files: {}", files.join(" ")));
+    if stats.skipped_large_files > 0
+        || stats.skipped_excess_files > 0
+        || stats.skipped_invalid_encoding > 0
+    {
+        lines.push(format!(
+            "skipped: {} over --max-filesize, {} over --max-files, {} not UTF-8",
+            stats.skipped_large_files, stats.skipped_excess_files, stats.skipped_invalid_encoding
+        ));
+    }
+    lines.push(format!(
+        "definitions={} reference_edges={} unresolved_references={}",
+        stats.definitions, stats.reference_edges, stats.unresolved_references
+    ));
+    let timings: Vec<_> = timer
+        .elapsed()
+        .iter()
+        .map(|(phase, elapsed)| format!("{}={}ms", phase.as_str(), elapsed.as_millis()))
+        .collect();
+    lines.push(format!("time: {}", timings.join(" ")));
+    lines
+}
+
+/// Name of `ecosystem` as accepted by `--ecosystem`, or the namespace or
+/// custom language name.
+fn ecosystem_name(ecosystem: Ecosystem, options: &Options) -> String {
+    match ecosystem {
+        Ecosystem::C => "c".to_string(),
+        Ecosystem::Dotnet => "dotnet".to_string(),
+        Ecosystem::Go => "go".to_string(),
+        Ecosystem::Java => "java".to_string(),
+        Ecosystem::Php => "php".to_string(),
+        Ecosystem::Python => "python".to_string(),
+        Ecosystem::JavaScript => "javascript".to_string(),
+        Ecosystem::Rust => "rust".to_string(),
+        Ecosystem::Custom(index) => Language::Custom(index).name().to_string(),
+        Ecosystem::Namespace { index, .. } => {
+            let name = options.namespaces.get(usize::from(index)).map_or_else(
+                || format!("namespace{index}"),
+                |namespace| namespace.name.clone(),
+            );
+            format!("{name}/{}", ecosystem_name(ecosystem.base(), options))
+        }
+    }
+}
+
+fn symbol_kind(value: KindArg) -> SymbolKind {
+    match value {
+        KindArg::Function => SymbolKind::Function,
+        KindArg::Method => SymbolKind::Method,
+        KindArg::Class => SymbolKind::Class,
+        KindArg::Struct => SymbolKind::Struct,
+        KindArg::Enum => SymbolKind::Enum,
+        KindArg::Interface => SymbolKind::Interface,
+        KindArg::Trait => SymbolKind::Trait,
+        KindArg::Type => SymbolKind::Type,
+        KindArg::Constant => SymbolKind::Constant,
+        KindArg::Variable => SymbolKind::Variable,
+        KindArg::Field => SymbolKind::Field,
+        KindArg::Module => SymbolKind::Module,
+    }
+}
+
+/// Registers the languages given with `--language-lib`, pairing each with
+/// the `--lang-ext` and `--lang-queries` at the same position.
+fn load_custom_languages(cli: &Cli, cwd: &std::path::Path) -> Vec<Ecosystem> {
+    let count = cli.language_libs.len();
+    if cli.lang_exts.len() != count || cli.lang_queries.len() != count {
+        eprintln!("cruxlines: each --language-lib needs one --lang-ext and one --lang-queries");
+        process::exit(1);
+    }
+    let mut ecosystems = Vec::new();
+    for ((library, extensions), queries) in cli
+        .language_libs
+        .iter()
+        .zip(&cli.lang_exts)
+        .zip(&cli.lang_queries)
+    {
+        let extensions = extensions
+            .split(',')
+            .map(|extension| extension.trim().trim_start_matches('.').to_string())
+            .filter(|extension| !extension.is_empty())
+            .collect();
+        match load_language(&cwd.join(library), extensions, &cwd.join(queries)) {
+            Ok(Language::Custom(index)) => ecosystems.push(Ecosystem::Custom(index)),
+            Ok(_) => {}
+            Err(err) => {
+                report_error(err);
+                process::exit(1);
+            }
         }
     }
+    ecosystems
 }
 
 fn selected_ecosystems(values: &[EcosystemArg]) -> std::collections::HashSet<Ecosystem> {
@@ -141,11 +1390,435 @@ fn selected_ecosystems(values: &[EcosystemArg]) -> std::collections::HashSet<Eco
     ecosystems
 }
 
-fn find_repo_root(start: &std::path::Path) -> Option<PathBuf> {
-    for ancestor in start.ancestors() {
-        if ancestor.join(".git").is_dir() {
-            return Some(ancestor.to_path_buf());
+fn run_snapshot(
+    repo_root: &std::path::Path,
+    cwd: &std::path::Path,
+    action: &SnapshotCommand,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    let (file, locked) = match action {
+        SnapshotCommand::Write { file } => (cwd.join(file), None),
+        SnapshotCommand::Check { file, threshold } => {
+            let file = cwd.join(file);
+            let locked = std::fs::read_to_string(&file)
+                .map_err(|err| err.to_string())
+                .and_then(|text| Snapshot::parse(&text));
+            match locked {
+                Ok(locked) => (file, Some((locked, *threshold))),
+                Err(err) => {
+                    eprintln!("cruxlines: invalid snapshot {}: {err}", file.display());
+                    process::exit(1);
+                }
+            }
+        }
+    };
+    let top = locked
+        .as_ref()
+        .map_or(options.top.unwrap_or(20), |(locked, _)| locked.top);
+    // The snapshot takes its own top, after merging duplicate names.
+    let analysis_options = Options {
+        top: None,
+        sample: None,
+        ..options.clone()
+    };
+    let inputs = Inputs {
+        scan_paths: vec![repo_root.to_path_buf()],
+        revision: None,
+        unsaved: None,
+    };
+    let analysis = analyze(repo_root, &inputs, ecosystems, &analysis_options);
+    let current = Snapshot::from_rows(&analysis.rows, top, Some(repo_root));
+
+    let Some((locked, threshold)) = locked else {
+        if let Err(err) = std::fs::write(&file, current.to_string()) {
+            eprintln!("cruxlines: failed to write {}: {err}", file.display());
+            process::exit(1);
         }
+        return;
+    };
+    let changes = locked.changes(&current);
+    for entry in &changes.left {
+        println!("- {}\t{}", entry.path, entry.name);
+    }
+    for entry in &changes.entered {
+        println!("+ {}\t{}", entry.path, entry.name);
+    }
+    if changes.count() > threshold {
+        eprintln!(
+            "cruxlines: {} of the top {} definitions changed (threshold {threshold}); run `cruxlines snapshot write` to acknowledge",
+            changes.count(),
+            locked.top
+        );
+        process::exit(1);
+    }
+}
+
+fn run_impact(
+    repo_root: &std::path::Path,
+    changed: &[PathBuf],
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    // Filters apply to the impacted definitions, not to the graph walked,
+    // and walking it needs every reference location.
+    let analysis_options = Options {
+        top: None,
+        min_rank: None,
+        sample: None,
+        low_memory: false,
+        ..options.clone()
+    };
+    let inputs = Inputs {
+        scan_paths: vec![repo_root.to_path_buf()],
+        revision: None,
+        unsaved: None,
+    };
+    let analysis = analyze(repo_root, &inputs, ecosystems, &analysis_options);
+
+    let mut impacted = impacted_definitions(&analysis.rows, changed);
+    if let Some(min_rank) = options.min_rank {
+        impacted.retain(|impact| impact.rank >= min_rank);
+    }
+    if let Some(top) = options.top {
+        impacted.truncate(top);
+    }
+    for impact in &impacted {
+        println!(
+            "{}:{}:{}: {} rank={:.6} depth={}",
+            display_path(impact.definition.path_str(), repo_root),
+            impact.definition.line,
+            impact.definition.column,
+            impact.definition.name_str(),
+            impact.rank,
+            impact.depth
+        );
+    }
+}
+
+#[cfg(unix)]
+fn run_daemon(
+    repo_root: &std::path::Path,
+    socket: &std::path::Path,
+    ecosystems: std::collections::HashSet<Ecosystem>,
+    options: Options,
+) {
+    if let Err(message) = daemon::run(repo_root, socket, ecosystems, options) {
+        eprintln!("cruxlines: {message}");
+        process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn run_daemon(
+    _repo_root: &std::path::Path,
+    _socket: &std::path::Path,
+    _ecosystems: std::collections::HashSet<Ecosystem>,
+    _options: Options,
+) {
+    eprintln!("cruxlines: the daemon needs unix sockets");
+    process::exit(1);
+}
+
+#[cfg(unix)]
+fn run_top(
+    repo_root: &std::path::Path,
+    ecosystems: std::collections::HashSet<Ecosystem>,
+    options: Options,
+) {
+    if let Err(message) = tui::run(repo_root, ecosystems, options) {
+        eprintln!("cruxlines: {message}");
+        process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn run_top(
+    _repo_root: &std::path::Path,
+    _ecosystems: std::collections::HashSet<Ecosystem>,
+    _options: Options,
+) {
+    eprintln!("cruxlines: top needs a unix terminal");
+    process::exit(1);
+}
+
+fn run_explain(
+    repo_root: &std::path::Path,
+    path: &std::path::Path,
+    line: usize,
+    ecosystems: &std::collections::HashSet<Ecosystem>,
+    options: &Options,
+) {
+    let explanations = gather_paths(repo_root, &[repo_root.to_path_buf()], ecosystems, options)
+        .and_then(|paths| {
+            let source = FileSystemSource::new(paths);
+            explain_definition(&source, Some(repo_root), options, path, line)
+        });
+    let explanations = match explanations {
+        Ok(explanations) => explanations,
+        Err(err) => {
+            report_error(err);
+            process::exit(1);
+        }
+    };
+    if explanations.is_empty() {
+        eprintln!(
+            "cruxlines: no referenced definition at {}:{line}",
+            display_path(&path.to_string_lossy(), repo_root)
+        );
+        process::exit(1);
+    }
+    for (index, explanation) in explanations.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        print_explanation(explanation, repo_root);
+    }
+}
+
+fn print_explanation(explanation: &Explanation, repo_root: &std::path::Path) {
+    let row = &explanation.row;
+    let weights = &explanation.weights;
+    println!(
+        "{}:{}:{}: {} kind={}",
+        display_path(row.definition.path_str(), repo_root),
+        row.definition.line,
+        row.definition.column,
+        row.definition.name_str(),
+        row.kind.as_str()
+    );
+    println!("references ({}):", explanation.references.len());
+    for reference in &explanation.references {
+        println!(
+            "  {}:{}:{}: file_rank={:.6} frecency={:.6} test={:.6} same_file={:.6} external={:.6} share={:.6} weight={:.6}",
+            display_path(reference.location.path_str(), repo_root),
+            reference.location.line,
+            reference.location.column,
+            reference.file_rank,
+            reference.frecency,
+            reference.test_multiplier,
+            reference.same_file_multiplier,
+            reference.external_multiplier,
+            reference.file_share,
+            reference.weight()
+        );
+    }
+    println!("weighted_refs = {:.6}", explanation.weighted_references);
+    println!(
+        "local = weighted_refs^{} / name_count^{} = {:.6}^{} / {}^{} = {:.6}",
+        weights.references,
+        weights.name_ambiguity,
+        explanation.weighted_references,
+        weights.references,
+        explanation.name_count,
+        weights.name_ambiguity,
+        row.local_score
+    );
+    let symbol_rank = row
+        .symbol_rank
+        .map(|rank| format!(" * symbol_rank {rank:.6}"))
+        .unwrap_or_default();
+    let entry_boost = if explanation.entry_boost == 1.0 {
+        String::new()
+    } else {
+        format!(" * entry_reachable {}", explanation.entry_boost)
+    };
+    let author_boost = if explanation.author_boost == 1.0 {
+        String::new()
+    } else {
+        format!(" * authors {:.6}", explanation.author_boost)
+    };
+    let coverage_boost = if explanation.coverage_boost == 1.0 {
+        String::new()
+    } else {
+        format!(" * coverage {:.6}", explanation.coverage_boost)
+    };
+    println!(
+        "rank = local * file_rank^{} = {:.6} * {:.6}^{}{symbol_rank}{entry_boost}{author_boost}{coverage_boost} = {:.6}",
+        weights.file_rank, row.local_score, row.file_rank, weights.file_rank, row.rank
+    );
+}
+
+/// Reads newline-separated paths from stdin, relative to `cwd`. Paths that
+/// no longer exist, such as deleted files in `git diff --name-only`, are
+/// skipped.
+fn stdin_scan_paths(cwd: &std::path::Path) -> Vec<PathBuf> {
+    read_stdin()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| normalize_path(&cwd.join(line)))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+fn read_stdin() -> String {
+    match std::io::read_to_string(std::io::stdin()) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("cruxlines: failed to read stdin: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Drops `.` components so the path matches the ones found by walking.
+fn normalize_path(path: &std::path::Path) -> PathBuf {
+    path.components().collect()
+}
+
+fn resolve_scan_paths(
+    paths: &[PathBuf],
+    cwd: &std::path::Path,
+    repo_root: &std::path::Path,
+) -> Result<Vec<PathBuf>, PathBuf> {
+    if paths.is_empty() {
+        return Ok(vec![repo_root.to_path_buf()]);
+    }
+    paths
+        .iter()
+        .map(|path| {
+            let resolved = cwd.join(path);
+            if resolved.exists() {
+                Ok(resolved)
+            } else {
+                Err(path.clone())
+            }
+        })
+        .collect()
+}
+
+/// Parses a `--from` root: an existing file or directory, `PATH:SYMBOL`, or
+/// a bare symbol name. Returns the missing path when one is given but
+/// doesn't exist.
+fn reachability_root(value: &str, cwd: &std::path::Path) -> Result<ReachabilityRoot, String> {
+    let path = cwd.join(value);
+    if path.exists() {
+        return Ok(ReachabilityRoot::Path(normalize_path(&path)));
+    }
+    if let Some((file, name)) = value.rsplit_once(':')
+        && !name.is_empty()
+    {
+        let path = cwd.join(file);
+        if !path.is_file() {
+            return Err(file.to_string());
+        }
+        return Ok(ReachabilityRoot::Symbol {
+            path: Some(normalize_path(&path)),
+            name: name.to_string(),
+        });
+    }
+    if value.contains(std::path::MAIN_SEPARATOR) {
+        return Err(value.to_string());
+    }
+    Ok(ReachabilityRoot::Symbol {
+        path: None,
+        name: value.to_string(),
+    })
+}
+
+/// Bounds the worker pool to `--threads`, or else `CRUXLINES_THREADS`.
+/// Zero, like leaving both unset, uses one thread per core. Results do not
+/// depend on the thread count.
+fn configure_threads(threads: Option<usize>) {
+    let threads = match threads {
+        Some(threads) => threads,
+        None => match std::env::var("CRUXLINES_THREADS") {
+            Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+                eprintln!("cruxlines: CRUXLINES_THREADS must be a number, got {value:?}");
+                process::exit(2);
+            }),
+            Err(_) => return,
+        },
+    };
+    if let Err(err) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        eprintln!("cruxlines: failed to start {threads} threads: {err}");
+        process::exit(1);
+    }
+}
+
+/// Git history (unless `--no-frecency` or there is no repository), blended
+/// with `--frecency-file` when given.
+fn frecency(cli: &Cli, cwd: &std::path::Path, has_git: bool) -> Frecency {
+    let use_git = has_git && !cli.no_frecency;
+    let git = if use_git {
+        Frecency::Git
+    } else {
+        Frecency::Disabled
+    };
+    let Some(path) = &cli.frecency_file else {
+        return git;
+    };
+    let file = match JsonFrecency::from_file(&cwd.join(path)) {
+        Ok(file) => Frecency::Custom(std::sync::Arc::new(file)),
+        Err(err) => {
+            report_error(err);
+            process::exit(1);
+        }
+    };
+    if !use_git {
+        return file;
+    }
+    Frecency::Custom(std::sync::Arc::new(BlendedFrecency::new([
+        (git, 1.0 - cli.frecency_blend),
+        (file, cli.frecency_blend),
+    ])))
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err(format!("expected a number from 0 to 1, got {value}")),
+    }
+}
+
+/// Parses a size in bytes such as `500000`, `512K`, `2M` or `1G`; `0`
+/// means no limit.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("expected a number optionally followed by K, M or G, got {value}"))?;
+    let scale: u64 = match unit {
+        "" => 1,
+        "K" | "k" => 1 << 10,
+        "M" | "m" => 1 << 20,
+        "G" | "g" => 1 << 30,
+        _ => return Err(format!("unknown unit {unit:?}, expected K, M or G")),
+    };
+    count
+        .checked_mul(scale)
+        .ok_or_else(|| format!("size {value} is too large"))
+}
+
+/// Parses a period such as `30d`, `2w`, `6m` or `1y`. Months count as 30
+/// days and years as 365.
+fn parse_period(value: &str) -> Result<std::time::Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("expected a number followed by d, w, m or y, got {value}"))?;
+    let days = match unit {
+        "d" => 1,
+        "w" => 7,
+        "m" => 30,
+        "y" => 365,
+        _ => return Err(format!("unknown unit {unit:?}, expected d, w, m or y")),
+    };
+    if count == 0 {
+        return Err("period must be positive".to_string());
     }
-    None
+    count
+        .checked_mul(days * 24 * 60 * 60)
+        .map(std::time::Duration::from_secs)
+        .ok_or_else(|| format!("period {value} is too long"))
 }
@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use ignore::overrides::Override;
+
+use crate::error::CruxlinesError;
+use crate::io::build_override;
+use crate::languages::{Ecosystem, normalize_path};
+use crate::options::Options;
+
+/// Compiled form of [`Options::namespaces`].
+pub(crate) struct Namespaces {
+    globs: Vec<Override>,
+}
+
+impl Namespaces {
+    pub(crate) fn new(repo_root: Option<&Path>, options: &Options) -> Result<Self, CruxlinesError> {
+        let root = repo_root.unwrap_or(Path::new(""));
+        let globs = options
+            .namespaces
+            .iter()
+            .map(|namespace| build_override(root, &namespace.paths, ""))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { globs })
+    }
+
+    /// The ecosystem `path` links in: `ecosystem` within its first matching
+    /// namespace, or `ecosystem` itself when none matches.
+    pub(crate) fn ecosystem(&self, path: &Path, ecosystem: Ecosystem) -> Ecosystem {
+        if self.globs.is_empty() {
+            return ecosystem;
+        }
+        let path = normalize_path(path);
+        self.globs
+            .iter()
+            .position(|globs| !globs.is_empty() && globs.matched(&path, false).is_whitelist())
+            .map_or(ecosystem, |index| {
+                Ecosystem::namespaced(index as u16, ecosystem)
+            })
+    }
+}
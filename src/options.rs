@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::authors::Authors;
+use crate::coverage::Coverage;
+use crate::find_references::Location;
+use crate::frecency::Frecency;
+use crate::languages::{Ecosystem, Language, SymbolKind};
+use crate::progress::ProgressHook;
+use crate::session::ParseSession;
+
+/// Settings for a single analysis run.
+///
+/// `Options::default()` matches the CLI defaults.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Reuse parse results from the on-disk file cache for unchanged files.
+    pub use_cache: bool,
+    /// Keep only the N highest-ranked rows.
+    pub top: Option<usize>,
+    /// Drop rows whose rank is below this threshold.
+    pub min_rank: Option<f64>,
+    /// Drop rows with fewer references than this.
+    pub min_references: Option<usize>,
+    /// Keep a random sample of rows, drawn with probability proportional
+    /// to rank. Applied after `min_rank` and `top`.
+    pub sample: Option<Sample>,
+    /// Blend a symbol-level PageRank into each row's rank.
+    pub symbol_rank: bool,
+    /// Scan files marked `linguist-generated` or `linguist-vendored` in
+    /// `.gitattributes`, which are skipped by default.
+    pub include_generated: bool,
+    /// Which ignore files, hidden files and symlinks the file walk honors.
+    pub scan: ScanOptions,
+    /// Exponents applied to the factors of the rank formula.
+    pub weights: Weights,
+    /// Weights that replace `weights` for the definitions under a
+    /// directory. The deepest matching directory wins.
+    pub scoped_weights: Vec<ScopedWeights>,
+    /// Source of the per-file frecency multipliers.
+    pub frecency: Frecency,
+    /// Source of the per-file author counts behind `Weights::authors`.
+    pub authors: Authors,
+    /// Line execution counts from a test run. Each row's
+    /// `execution_count` is the highest count from its definition line to
+    /// the next definition in the file.
+    pub coverage: Option<Arc<Coverage>>,
+    /// Gitignore-style globs, relative to the repo root. When any are given,
+    /// only matching files are scanned.
+    pub include: Vec<String>,
+    /// Gitignore-style globs, relative to the repo root, for files and
+    /// directories to skip.
+    pub exclude: Vec<String>,
+    /// Link Python and JavaScript call sites to Rust definitions exported
+    /// through PyO3, wasm-bindgen or napi-rs attributes.
+    pub ffi_edges: bool,
+    /// Treat script paths, `-m` modules and `module:function` strings in
+    /// YAML files (CI workflows, serverless configs) as usages of the code
+    /// they run.
+    pub pipeline_references: bool,
+    /// Keep only definitions of these kinds; empty keeps every kind.
+    pub kinds: Vec<SymbolKind>,
+    /// Keep only definitions whose name matches this pattern.
+    pub name: Option<NamePattern>,
+    /// Keep only definitions in these files or directories, e.g. the
+    /// [`changed_files`](crate::changed_files) of a review. Every scanned
+    /// file still contributes to the ranks.
+    pub definition_paths: Option<Vec<PathBuf>>,
+    /// Round scores to this many decimal places before sorting, so output
+    /// is identical across platforms. Ties are always broken by path,
+    /// position and name.
+    pub quantize: Option<u32>,
+    /// Count references in a second pass over the files instead of keeping
+    /// every reference location, so memory stays bounded on huge trees.
+    /// Files are parsed twice unless the cache is on. Rows get a
+    /// `reference_count` but no `references`, and `symbol_rank` is ignored.
+    pub low_memory: bool,
+    /// Per-language changes to the tree-sitter node kinds counted as
+    /// references, for tuning noise without recompiling.
+    pub reference_kinds: HashMap<Language, ReferenceKinds>,
+    /// Per-ecosystem changes to the names that don't link by name when the
+    /// repo defines them more than once, such as `print` or `new`. The
+    /// changes apply to every language of the ecosystem.
+    pub common_names: HashMap<Ecosystem, CommonNames>,
+    /// Path-scoped namespaces that split files out of their ecosystem, so
+    /// that names only link within the namespace. The first namespace with a
+    /// matching glob wins.
+    pub namespaces: Vec<Namespace>,
+    /// Called as files are found, parsed and ranked.
+    pub progress: Option<ProgressHook>,
+    /// Trees kept from earlier runs, so files that change between runs are
+    /// reparsed incrementally.
+    pub parse_session: Option<ParseSession>,
+    /// How repeated references from one file add up.
+    pub reference_aggregation: ReferenceAggregation,
+    /// Which edges from the same place to a definition collapse into one.
+    pub reference_dedup: ReferenceDedup,
+    /// The file-graph centrality used as each file's `file_rank`.
+    pub centrality: Centrality,
+    /// Files or directories whose PageRank random jumps land on, so file
+    /// ranks measure closeness to them (e.g. the files being edited)
+    /// rather than global importance. Ecosystems with no file under them
+    /// are ranked as usual. Ignored by the other centralities.
+    pub personalize: Vec<PathBuf>,
+    /// Keep only definitions reachable from these roots by following
+    /// references from each definition to the ones it uses, roots included.
+    /// Empty keeps everything. Every scanned file still contributes to the
+    /// ranks. Ignored with `low_memory`.
+    pub reachable_from: Vec<ReachabilityRoot>,
+}
+
+/// Where [`Options::reachable_from`] starts following references.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReachabilityRoot {
+    /// Every definition in this file or directory.
+    Path(PathBuf),
+    /// Definitions named `name`, only those in `path` when given.
+    Symbol { path: Option<PathBuf>, name: String },
+}
+
+impl ReachabilityRoot {
+    pub(crate) fn matches(&self, definition: &Location) -> bool {
+        let path = Path::new(definition.path_str());
+        match self {
+            ReachabilityRoot::Path(root) => path.starts_with(root),
+            ReachabilityRoot::Symbol { path: root, name } => {
+                definition.name_str() == name
+                    && root.as_ref().is_none_or(|root| path == root.as_path())
+            }
+        }
+    }
+}
+
+/// How important a file is in the file graph, where each file points to
+/// the files it references. Every measure is scaled so an ecosystem's file
+/// ranks sum to one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Centrality {
+    /// Favors files many important files depend on, such as shared
+    /// utilities.
+    #[default]
+    PageRank,
+    /// Favors files on many shortest paths between other files: the
+    /// bridges between parts of the code base. Files nothing passes
+    /// through rank zero.
+    Betweenness,
+    /// The number of other files referencing a file.
+    InDegree,
+}
+
+/// How source files that are not valid UTF-8 are read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Skip the file and report it as a diagnostic.
+    #[default]
+    Strict,
+    /// Replace invalid bytes with U+FFFD; tree-sitter parses around them.
+    Lossy,
+    /// Follow a UTF-8 or UTF-16 byte order mark, and read other files that
+    /// are not UTF-8 as Windows-1252, a superset of Latin-1.
+    Auto,
+}
+
+/// How a definition's references from the same file add up, so a file that
+/// calls `log()` 500 times doesn't outweigh 50 files calling it once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReferenceAggregation {
+    /// Every reference counts in full.
+    #[default]
+    Occurrences,
+    /// Each referencing file counts once, with the average weight of its
+    /// references.
+    Files,
+    /// A file with `n` references counts `1 + ln(n)` times.
+    Log,
+}
+
+impl ReferenceAggregation {
+    /// Share of its full weight each reference keeps when its file makes
+    /// `count` references to the same definition.
+    pub(crate) fn share(self, count: usize) -> f64 {
+        let count = count.max(1) as f64;
+        match self {
+            ReferenceAggregation::Occurrences => 1.0,
+            ReferenceAggregation::Files => 1.0 / count,
+            ReferenceAggregation::Log => (1.0 + count.ln()) / count,
+        }
+    }
+}
+
+/// Which of a definition's edges collapse into one when they are recorded,
+/// so a usage found twice (by two reference kinds, or as both a regular and
+/// an FFI reference) or `f(f(x))` on one line isn't counted twice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReferenceDedup {
+    /// Every edge counts.
+    #[default]
+    None,
+    /// One edge per usage location, to the first of its definitions.
+    Location,
+    /// One edge per name used on a line, to the first of its definitions.
+    Line,
+}
+
+/// Files that form their own ecosystem, such as an embedded scripting
+/// language that shares an extension with the main codebase.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespace {
+    pub name: String,
+    /// Gitignore-style globs, relative to the repo root.
+    pub paths: Vec<String>,
+}
+
+/// Weights for the definitions under `dir`, such as those set by a
+/// `.cruxlines.toml` in a subdirectory of a monorepo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopedWeights {
+    /// Directory in the same form as the scanned paths, usually absolute.
+    pub dir: PathBuf,
+    pub weights: Weights,
+}
+
+/// Node kinds to count as references on top of a language's built-in
+/// list (`add`), or to stop counting (`remove`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReferenceKinds {
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+}
+
+impl ReferenceKinds {
+    pub(crate) fn adds(&self, kind: &str) -> bool {
+        self.add.iter().any(|added| added == kind)
+    }
+
+    /// Whether nodes of `kind` stop counting, even when the language's
+    /// references query captures them. Added kinds are never removed.
+    pub(crate) fn removes(&self, kind: &str) -> bool {
+        !self.adds(kind) && self.remove.iter().any(|removed| removed == kind)
+    }
+}
+
+/// Names to treat as too common to link by name alone, on top of a
+/// language's built-in list (`add`), or to link normally again (`remove`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommonNames {
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+}
+
+impl CommonNames {
+    /// The built-in list for `language` with these changes applied.
+    pub(crate) fn apply(&self, language: Language) -> impl Iterator<Item = &str> {
+        crate::languages::common_names(language)
+            .iter()
+            .copied()
+            .filter(|name| !self.remove.iter().any(|removed| removed == name))
+            .chain(self.add.iter().map(String::as_str))
+    }
+}
+
+/// How files are found when walking directories. The defaults match git:
+/// ignored and hidden files are skipped and symlinks are not followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Skip files matched by `.gitignore` or `.ignore` in any scanned
+    /// directory or its parents, or by `.git/info/exclude`. Honored outside
+    /// git repositories too.
+    pub respect_gitignore: bool,
+    /// Skip files matched by git's global excludes file
+    /// (`core.excludesFile`).
+    pub respect_global_gitignore: bool,
+    /// Scan files and directories whose names start with a dot. `.git` is
+    /// always skipped.
+    pub hidden: bool,
+    /// Follow symbolic links to files and directories.
+    pub follow_symlinks: bool,
+    /// Skip files larger than this many bytes, such as minified bundles.
+    pub max_filesize: Option<u64>,
+    /// Stop after this many files. Directories are walked in file name
+    /// order, so the same files are kept on every run.
+    pub max_files: Option<usize>,
+    /// How files that are not valid UTF-8 are decoded.
+    pub encoding: Encoding,
+    /// Number of threads reading files ahead of the parsers. Reads mostly
+    /// wait on the disk, so more threads than cores help on network
+    /// filesystems.
+    pub read_threads: usize,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            respect_gitignore: true,
+            respect_global_gitignore: true,
+            hidden: false,
+            follow_symlinks: false,
+            max_filesize: Some(2 * 1024 * 1024),
+            max_files: None,
+            encoding: Encoding::Strict,
+            read_threads: 16,
+        }
+    }
+}
+
+/// A weighted random sample of the ranked rows.
+///
+/// The same rows, seed and size always give the same sample, so
+/// dashboards can refresh without their subset reshuffling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Number of rows to keep.
+    pub size: usize,
+    /// Seed for the random draw.
+    pub seed: u64,
+}
+
+/// Pattern matched against definition names.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NamePattern {
+    /// Shell-style glob such as `User*`, matched against the whole name.
+    Glob(String),
+    /// Regular expression such as `Handler$`, matched anywhere in the name
+    /// unless anchored.
+    Regex(String),
+}
+
+/// Scoring weights. Most are exponents on a factor of the rank formula, so
+/// `1.0` keeps the built-in behavior and `0.0` disables the factor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    /// Frecency multiplier of each referencing file.
+    pub frecency: f64,
+    /// PageRank of the file holding the definition.
+    pub file_rank: f64,
+    /// Sum of the weighted references to the definition.
+    pub references: f64,
+    /// Divisor for names defined more than once in the ecosystem.
+    pub name_ambiguity: f64,
+    /// Multiplier (not an exponent) for references made from test files and
+    /// `#[cfg(test)]` code, so assertion helpers don't float to the top.
+    pub test_references: f64,
+    /// Multiplier (not an exponent) for references from the file holding the
+    /// definition, so recursive helpers and file-local constants don't
+    /// outrank code that other files depend on.
+    pub same_file_references: f64,
+    /// Multiplier (not an exponent) for the rank of definitions reachable
+    /// from an entry point such as `fn main`, following references from
+    /// each definition to the ones it uses. Ignored with
+    /// `Options::low_memory`.
+    pub entry_reachable: f64,
+    /// Multiplier (not an exponent) for references from pipeline YAML, see
+    /// `Options::pipeline_references`.
+    pub pipeline_references: f64,
+    /// Multiplier (not an exponent) for references from the `main`,
+    /// `exports` and `bin` of a `package.json` and the scripts and entry
+    /// points of a `pyproject.toml`. Manifests are referenced by nothing, so
+    /// their file rank is low; the default makes up for it, since public
+    /// APIs matter even with few internal references.
+    pub manifest_references: f64,
+    /// Distinct author count of the file holding the definition, from
+    /// `Options::authors`.
+    pub authors: f64,
+    /// One plus the definition's execution count, from
+    /// `Options::coverage`. `0` shows counts without changing ranks.
+    pub coverage: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            frecency: 1.0,
+            file_rank: 1.0,
+            references: 1.0,
+            name_ambiguity: 1.0,
+            test_references: 0.25,
+            same_file_references: 0.5,
+            entry_reachable: 1.0,
+            pipeline_references: 1.0,
+            manifest_references: 4.0,
+            authors: 0.5,
+            coverage: 0.0,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            use_cache: true,
+            top: None,
+            min_rank: None,
+            min_references: None,
+            sample: None,
+            symbol_rank: false,
+            include_generated: false,
+            scan: ScanOptions::default(),
+            weights: Weights::default(),
+            scoped_weights: Vec::new(),
+            frecency: Frecency::Git,
+            authors: Authors::Disabled,
+            coverage: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ffi_edges: false,
+            pipeline_references: false,
+            kinds: Vec::new(),
+            name: None,
+            definition_paths: None,
+            quantize: None,
+            low_memory: false,
+            reference_kinds: HashMap::new(),
+            common_names: HashMap::new(),
+            reachable_from: Vec::new(),
+            namespaces: Vec::new(),
+            progress: None,
+            parse_session: None,
+            reference_aggregation: ReferenceAggregation::Occurrences,
+            reference_dedup: ReferenceDedup::None,
+            centrality: Centrality::PageRank,
+            personalize: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// The weights for a definition in `path`: those of the deepest
+    /// [`ScopedWeights`] directory holding it, else `weights`.
+    pub(crate) fn weights_for(&self, path: &str) -> &Weights {
+        if self.scoped_weights.is_empty() {
+            return &self.weights;
+        }
+        let path = Path::new(path);
+        self.scoped_weights
+            .iter()
+            .filter(|scoped| path.starts_with(&scoped.dir))
+            .max_by_key(|scoped| scoped.dir.components().count())
+            .map_or(&self.weights, |scoped| &scoped.weights)
+    }
+
+    /// `weights` and every scoped override.
+    pub(crate) fn all_weights(&self) -> impl Iterator<Item = &Weights> {
+        std::iter::once(&self.weights)
+            .chain(self.scoped_weights.iter().map(|scoped| &scoped.weights))
+    }
+}
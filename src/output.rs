@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process;
+
+use cruxlines::OutputRow;
+use cruxlines::buckets::{BucketScale, Buckets};
+use serde_json::Value;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+
+/// ANSI styling for text output; every method is a no-op when disabled.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Colors stdout only when it is a terminal and `NO_COLOR` is unset.
+    pub(crate) fn detect() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Self::new(!no_color && std::io::stdout().is_terminal())
+    }
+
+    fn paint(self, code: &str, text: &str) -> String {
+        if self.enabled && !text.is_empty() {
+            format!("{code}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn dim(self, text: &str) -> String {
+        self.paint(DIM, text)
+    }
+
+    /// Colors by position in the ranked output: the top decile red, the next
+    /// two deciles yellow, the rest unstyled.
+    fn bucket(self, index: usize, total: usize, text: &str) -> String {
+        if index * 10 < total {
+            self.paint(RED, text)
+        } else if index * 10 < total * 3 {
+            self.paint(YELLOW, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Bolds the first whole-word occurrence of `name` in `line`.
+    fn highlight(self, line: &str, name: &str) -> String {
+        if !self.enabled || name.is_empty() {
+            return line.to_string();
+        }
+        let is_word = |ch: char| ch.is_alphanumeric() || ch == '_' || ch == '$';
+        let found = line.match_indices(name).find(|(start, _)| {
+            let before = line[..*start].chars().next_back();
+            let after = line[start + name.len()..].chars().next();
+            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+        });
+        match found {
+            Some((start, _)) => format!(
+                "{}{}{}",
+                &line[..start],
+                self.paint(BOLD, name),
+                &line[start + name.len()..]
+            ),
+            None => line.to_string(),
+        }
+    }
+}
+
+/// Default `path:line:col: text` output, optionally with metadata,
+/// references and surrounding source lines.
+pub(crate) struct TextPrinter<'a> {
+    pub(crate) repo_root: &'a Path,
+    pub(crate) metadata: bool,
+    pub(crate) show_references: bool,
+    pub(crate) context: usize,
+    pub(crate) style: Style,
+    /// Bucket printed before each line, or next to the rank with metadata.
+    pub(crate) buckets: Option<(Buckets, BucketScale)>,
+    /// Contents analyzed in place of a file on disk, which context lines
+    /// are taken from as well.
+    pub(crate) unsaved: Option<(&'a Path, &'a str)>,
+}
+
+impl TextPrinter<'_> {
+    pub(crate) fn print(&self, rows: &[OutputRow]) {
+        let mut line_cache = LineCache {
+            unsaved: self.unsaved,
+            files: HashMap::new(),
+        };
+        let max_rank = max_rank(rows);
+        for (index, row) in rows.iter().enumerate() {
+            if self.context > 0 && index > 0 {
+                println!("--");
+            }
+            let lines = (self.context > 0)
+                .then(|| line_cache.lines(row.definition.path_str()))
+                .flatten();
+            if let Some(lines) = lines {
+                self.print_context(lines, row, Side::Before);
+            }
+            let bucket = self
+                .buckets
+                .map(|(buckets, scale)| buckets.bucket(scale, row.rank, max_rank));
+            self.print_row(row, bucket.as_deref(), index, rows.len());
+            if let Some(lines) = lines {
+                self.print_context(lines, row, Side::After);
+            }
+            if self.show_references {
+                self.print_references(row);
+            }
+        }
+    }
+
+    fn print_row(&self, row: &OutputRow, bucket: Option<&str>, index: usize, total: usize) {
+        let style = self.style;
+        let path = style.dim(&display_path(row.definition.path_str(), self.repo_root));
+        let position = style.bucket(
+            index,
+            total,
+            &format!("{}:{}", row.definition.line, row.definition.column),
+        );
+        let line_text = style.highlight(&row.definition_line, row.definition.name_str());
+        if self.metadata {
+            let symbol = row
+                .symbol_rank
+                .map(|symbol_rank| format!(" symbol={symbol_rank:.6}"))
+                .unwrap_or_default();
+            let entry_point = if row.is_entry_point {
+                " entry_point"
+            } else {
+                ""
+            };
+            let bucket = bucket
+                .map(|bucket| format!(" bucket={bucket}"))
+                .unwrap_or_default();
+            let hits = row
+                .execution_count
+                .map(|count| format!(" hits={count}"))
+                .unwrap_or_default();
+            println!(
+                "{}:{}: rank={}{} local={:.6} file={:.6}{} refs={}{} name={} kind={}{} | {}",
+                path,
+                position,
+                style.bucket(index, total, &format!("{:.6}", row.rank)),
+                bucket,
+                row.local_score,
+                row.file_rank,
+                symbol,
+                row.reference_count,
+                hits,
+                row.definition.name_str(),
+                row.kind.as_str(),
+                entry_point,
+                line_text
+            );
+        } else if let Some(bucket) = bucket {
+            println!("{path}:{position}: [{bucket}] {line_text}");
+        } else {
+            println!("{path}:{position}: {line_text}");
+        }
+    }
+
+    fn print_references(&self, row: &OutputRow) {
+        for reference in &row.references {
+            println!(
+                "    {}:{}:{}",
+                self.style
+                    .dim(&display_path(reference.path_str(), self.repo_root)),
+                reference.line,
+                reference.column
+            );
+        }
+    }
+
+    /// Prints up to `context` lines around the definition, grep-style:
+    /// `path-line-text`.
+    fn print_context(&self, lines: &[String], row: &OutputRow, side: Side) {
+        let line = row.definition.line;
+        let range = match side {
+            Side::Before => line.saturating_sub(self.context).max(1)..line,
+            Side::After => line + 1..(line + self.context + 1).min(lines.len() + 1),
+        };
+        let path = self
+            .style
+            .dim(&display_path(row.definition.path_str(), self.repo_root));
+        for number in range {
+            let Some(text) = lines.get(number - 1) else {
+                break;
+            };
+            println!("{path}-{number}-{text}");
+        }
+    }
+}
+
+/// Source lines of the printed files, each file read at most once.
+struct LineCache<'a> {
+    unsaved: Option<(&'a Path, &'a str)>,
+    files: HashMap<&'static str, Option<Vec<String>>>,
+}
+
+impl LineCache<'_> {
+    fn lines(&mut self, path: &'static str) -> Option<&[String]> {
+        let unsaved = self.unsaved;
+        self.files
+            .entry(path)
+            .or_insert_with(|| {
+                let lines = |contents: &str| contents.lines().map(str::to_string).collect();
+                match unsaved.filter(|(unsaved, _)| *unsaved == Path::new(path)) {
+                    Some((_, contents)) => Some(lines(contents)),
+                    None => std::fs::read_to_string(path)
+                        .ok()
+                        .map(|contents| lines(&contents)),
+                }
+            })
+            .as_deref()
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Side {
+    Before,
+    After,
+}
+
+const TSV_COLUMNS: &[&str] = &[
+    "rank",
+    "local",
+    "file_rank",
+    "name",
+    "path",
+    "line",
+    "col",
+    "line_text",
+    "refs",
+];
+
+/// Prints the TSV columns, plus a `bucket` column when `buckets` is set.
+pub(crate) fn print_tsv(
+    rows: &[OutputRow],
+    repo_root: &Path,
+    header: bool,
+    buckets: Option<(Buckets, BucketScale)>,
+) {
+    if header {
+        let bucket = if buckets.is_some() { "\tbucket" } else { "" };
+        println!("{}{bucket}", TSV_COLUMNS.join("\t"));
+    }
+    let max_rank = max_rank(rows);
+    for row in rows {
+        let bucket = buckets
+            .map(|(buckets, scale)| format!("\t{}", buckets.bucket(scale, row.rank, max_rank)))
+            .unwrap_or_default();
+        println!(
+            "{:.6}\t{:.6}\t{:.6}\t{}\t{}\t{}\t{}\t{}\t{}{}",
+            row.rank,
+            row.local_score,
+            row.file_rank,
+            escape_tsv(row.definition.name_str()),
+            escape_tsv(&display_path(row.definition.path_str(), repo_root)),
+            row.definition.line,
+            row.definition.column,
+            escape_tsv(&row.definition_line),
+            row.reference_count,
+            bucket
+        );
+    }
+}
+
+/// Highest rank among `rows`, which buckets are relative to.
+fn max_rank(rows: &[OutputRow]) -> f64 {
+    rows.iter().map(|row| row.rank).fold(0.0, f64::max)
+}
+
+/// Escapes backslashes, tabs and line breaks so every row stays one line
+/// with exactly one field per column.
+fn escape_tsv(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Prints `value` as pretty JSON, for `--format sarif`, `--format json` and
+/// `--print-schema`.
+pub(crate) fn print_json(value: &Value) {
+    match serde_json::to_string_pretty(value) {
+        Ok(text) => println!("{text}"),
+        Err(err) => {
+            eprintln!("cruxlines: failed to serialize JSON: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints one GitHub Actions `::notice` workflow command per row, so a CI
+/// job shows the ranked definitions as annotations on the pull request.
+pub(crate) fn print_github_annotations(rows: &[OutputRow], repo_root: &Path) {
+    for row in rows {
+        let message = format!(
+            "`{}` is a crux definition (rank {:.6}, {} references)",
+            row.definition.name_str(),
+            row.rank,
+            row.reference_count
+        );
+        println!(
+            "::notice file={},line={},col={},title=Crux line::{}",
+            escape_annotation_property(&display_path(row.definition.path_str(), repo_root)),
+            row.definition.line,
+            row.definition.column,
+            escape_annotation_data(&message)
+        );
+    }
+}
+
+/// Escapes a workflow command message the way `@actions/core` does.
+fn escape_annotation_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a workflow command property, which also may not contain `:` or
+/// `,`.
+fn escape_annotation_property(value: &str) -> String {
+    escape_annotation_data(value)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+pub(crate) fn display_path(path: &str, repo_root: &Path) -> String {
+    let path = Path::new(path);
+    match path.strip_prefix(repo_root) {
+        Ok(rel) => rel.display().to_string(),
+        Err(_) => path.display().to_string(),
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cruxlines::{Phase, ProgressHook};
+
+/// Measures how long each phase takes for `--stats`, from the progress
+/// updates the analysis reports. A phase runs from its first update until
+/// the next phase starts, or until [`PhaseTimer::elapsed`] is called.
+#[derive(Debug, Default)]
+pub(crate) struct PhaseTimer {
+    /// Each phase change, in order. Parse shows up twice with
+    /// `--low-memory`.
+    starts: Mutex<Vec<(Phase, Instant)>>,
+}
+
+impl PhaseTimer {
+    /// A hook that records phase changes, then forwards every update to
+    /// `inner` (the `--progress` bar, if any).
+    pub(crate) fn hook(self: &Arc<Self>, inner: Option<ProgressHook>) -> ProgressHook {
+        let timer = Arc::clone(self);
+        ProgressHook::new(move |progress| {
+            if let Ok(mut starts) = timer.starts.lock()
+                && starts
+                    .last()
+                    .is_none_or(|(phase, _)| *phase != progress.phase)
+            {
+                starts.push((progress.phase, Instant::now()));
+            }
+            if let Some(inner) = &inner {
+                inner.call(progress);
+            }
+        })
+    }
+
+    /// Total time spent in each phase so far, in the order phases first ran.
+    pub(crate) fn elapsed(&self) -> Vec<(Phase, Duration)> {
+        let now = Instant::now();
+        let starts = self
+            .starts
+            .lock()
+            .map(|starts| starts.clone())
+            .unwrap_or_default();
+        let mut totals: Vec<(Phase, Duration)> = Vec::new();
+        for (index, (phase, start)) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).map_or(now, |(_, next)| *next);
+            let spent = end.duration_since(*start);
+            match totals.iter_mut().find(|(seen, _)| seen == phase) {
+                Some((_, total)) => *total += spent,
+                None => totals.push((*phase, spent)),
+            }
+        }
+        totals
+    }
+}
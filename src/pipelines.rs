@@ -0,0 +1,341 @@
+//! Usages from outside the source code. CI/CD pipeline YAML, read with
+//! `Options::pipeline_references`, names the code it runs as script paths
+//! (`python scripts/deploy.py`), modules (`python -m tools.migrate`) or
+//! entry points (`handler: app.lambda:handler`). Package manifests name a
+//! package's public API: the files of `package.json`'s `main`, `exports`
+//! and `bin`, and the `module:function` scripts of `pyproject.toml`. Each
+//! of those becomes a usage of the definitions it names.
+
+use std::path::Path;
+
+use lasso::Spur;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::find_references::{Location, ReferenceEdge};
+use crate::intern::{intern, resolve};
+use crate::languages::Ecosystem;
+use crate::source::SourceProvider;
+
+/// Whether `path` is a YAML file that may describe a pipeline.
+pub(crate) fn is_pipeline_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "yml" | "yaml"))
+}
+
+/// Whether `path` is a package manifest whose entries are public API.
+pub(crate) fn is_manifest_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| matches!(name, "package.json" | "pyproject.toml"))
+}
+
+/// What a pipeline or manifest string points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    /// A source file, e.g. `scripts/deploy.py`.
+    Script(String),
+    /// A module run with `-m`, e.g. `tools.migrate`.
+    Module(String),
+    /// A `module:function` entry point, e.g. `app.lambda:handler`.
+    Function { module: String, name: String },
+    /// Every definition of a scanned file, e.g. a package's `main`.
+    File(Spur),
+}
+
+/// A pipeline or manifest string and where it appears.
+#[derive(Debug, Clone)]
+pub(crate) struct PipelineUsage {
+    path: Spur,
+    line: usize,
+    column: usize,
+    target: Target,
+}
+
+impl PipelineUsage {
+    /// A `module:function` string at `line` and `column` of `path`, or
+    /// `None` when `value` has another shape.
+    pub(crate) fn function(path: Spur, line: usize, column: usize, value: &str) -> Option<Self> {
+        let target =
+            target("", value).filter(|target| matches!(target, Target::Function { .. }))?;
+        Some(Self {
+            path,
+            line,
+            column,
+            target,
+        })
+    }
+
+    /// A reference to every definition of the scanned `file`.
+    pub(crate) fn file(path: Spur, line: usize, column: usize, file: Spur) -> Self {
+        Self {
+            path,
+            line,
+            column,
+            target: Target::File(file),
+        }
+    }
+}
+
+/// 1-based line and column of the first `"needle"` string in `contents`,
+/// or the start of the file.
+pub(crate) fn string_position(contents: &str, needle: &str) -> (usize, usize) {
+    let Some(offset) = contents.find(&format!("\"{needle}\"")) else {
+        return (1, 1);
+    };
+    let before = &contents[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (before.matches('\n').count() + 1, offset - line_start + 1)
+}
+
+/// Reads every pipeline file of `source` and collects the strings that look
+/// like they name code. Unreadable files are skipped.
+pub(crate) fn pipeline_usages(source: &dyn SourceProvider) -> Vec<PipelineUsage> {
+    let mut usages = Vec::new();
+    for path in source.paths() {
+        if !is_pipeline_file(&path) {
+            continue;
+        }
+        let Ok(Some(contents)) = source.read(&path) else {
+            continue;
+        };
+        let interned = intern(&path.to_string_lossy());
+        for (line, column, target) in targets(&contents) {
+            usages.push(PipelineUsage {
+                path: interned,
+                line,
+                column,
+                target,
+            });
+        }
+    }
+    usages
+}
+
+/// Targets in `contents` with their 1-based line and column.
+fn targets(contents: &str) -> Vec<(usize, usize, Target)> {
+    let mut found = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let mut previous = "";
+        for (offset, token) in tokens(strip_comment(line)) {
+            if let Some(target) = target(previous, token) {
+                found.push((index + 1, offset + 1, target));
+            }
+            previous = token;
+        }
+    }
+    found
+}
+
+/// `line` without a trailing `# comment`.
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let start = (0..bytes.len()).find(|&index| {
+        bytes[index] == b'#' && (index == 0 || bytes[index - 1].is_ascii_whitespace())
+    });
+    start.map_or(line, |start| &line[..start])
+}
+
+/// Words of `line` with their byte offsets, split on whitespace, quotes,
+/// brackets and other YAML or shell punctuation.
+fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let is_separator = |c: char| c.is_whitespace() || "\"'`[]{}(),;=&|<>".contains(c);
+    let mut start = None;
+    let mut words = Vec::new();
+    for (index, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, is_separator(c)) {
+            (None, false) => start = Some(index),
+            (Some(begin), true) => {
+                words.push((begin, &line[begin..index]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words.into_iter()
+}
+
+fn target(previous: &str, token: &str) -> Option<Target> {
+    if previous == "-m" && is_dotted_name(token) {
+        return Some(Target::Module(token.to_string()));
+    }
+    if let Some((module, name)) = token.split_once(':') {
+        return (is_dotted_name(module) && is_identifier(name)).then(|| Target::Function {
+            module: module.to_string(),
+            name: name.to_string(),
+        });
+    }
+    let script = token.trim_start_matches("./");
+    let is_path = script
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'));
+    (is_path && crate::languages::language_for_path(Path::new(script)).is_some())
+        .then(|| Target::Script(script.to_string()))
+}
+
+fn is_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_alphabetic() || first == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn is_dotted_name(value: &str) -> bool {
+    value.split('.').all(is_identifier)
+}
+
+/// The definitions of one file, with their ecosystem.
+type FileDefinitions = Vec<(Location, Ecosystem)>;
+
+/// Scanned definitions by file, for resolving pipeline targets.
+pub(crate) struct PipelineTargets<'a> {
+    /// Definitions by the file name of their path, then by path.
+    files: FxHashMap<&'static str, FxHashMap<Spur, FileDefinitions>>,
+    entry_points: &'a FxHashSet<Location>,
+}
+
+impl<'a> PipelineTargets<'a> {
+    pub(crate) fn new(
+        definitions: impl IntoIterator<Item = (Location, Ecosystem)>,
+        entry_points: &'a FxHashSet<Location>,
+    ) -> Self {
+        let mut files: FxHashMap<_, FxHashMap<Spur, FileDefinitions>> = FxHashMap::default();
+        for (definition, ecosystem) in definitions {
+            let path = definition.path_str();
+            let file_name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+            files
+                .entry(file_name)
+                .or_default()
+                .entry(definition.path)
+                .or_default()
+                .push((definition, ecosystem));
+        }
+        Self {
+            files,
+            entry_points,
+        }
+    }
+
+    /// Links `usage` to the entry points of the script or module it runs,
+    /// or to the function it names. Edges carry the definition's name so
+    /// they read like any other reference.
+    pub(crate) fn edges(&self, usage: &PipelineUsage) -> Vec<ReferenceEdge> {
+        let link = |(definition, ecosystem): &(Location, Ecosystem)| ReferenceEdge {
+            definition: *definition,
+            usage: Location {
+                path: usage.path,
+                line: usage.line,
+                column: usage.column,
+                name: definition.name,
+            },
+            ecosystem: *ecosystem,
+        };
+        match &usage.target {
+            Target::Script(script) => self
+                .definitions_in(std::slice::from_ref(script))
+                .filter(|(definition, _)| self.entry_points.contains(definition))
+                .map(link)
+                .collect(),
+            Target::Module(module) => {
+                let module = module.replace('.', "/");
+                self.definitions_in(&[format!("{module}.py"), format!("{module}/__main__.py")])
+                    .filter(|(definition, _)| self.entry_points.contains(definition))
+                    .map(link)
+                    .collect()
+            }
+            Target::File(file) => {
+                let path = resolve(*file);
+                let file_name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+                self.files
+                    .get(file_name)
+                    .and_then(|paths| paths.get(file))
+                    .into_iter()
+                    .flatten()
+                    .map(link)
+                    .collect()
+            }
+            Target::Function { module, name } => {
+                let module = module.replace('.', "/");
+                let name = intern(name);
+                self.modules(&module)
+                    .filter(|(definition, _)| definition.name == name)
+                    .map(link)
+                    .collect()
+            }
+        }
+    }
+
+    /// Definitions in files whose path ends with one of `suffixes`.
+    fn definitions_in<'s>(
+        &'s self,
+        suffixes: &'s [String],
+    ) -> impl Iterator<Item = &'s (Location, Ecosystem)> {
+        suffixes.iter().flat_map(move |suffix| {
+            let file_name = suffix.rsplit('/').next().unwrap_or(suffix);
+            self.files
+                .get(file_name)
+                .into_iter()
+                .flatten()
+                .filter(move |(path, _)| ends_with_path(resolve(**path), suffix))
+                .flat_map(|(_, definitions)| definitions)
+        })
+    }
+
+    /// Definitions in the source file or package `__init__` of `module`,
+    /// whatever its extension.
+    fn modules<'s>(&'s self, module: &'s str) -> impl Iterator<Item = &'s (Location, Ecosystem)> {
+        self.files
+            .values()
+            .flatten()
+            .filter(move |(path, _)| {
+                let stem = Path::new(resolve(**path)).with_extension("");
+                let stem = stem.to_string_lossy();
+                ends_with_path(&stem, module)
+                    || stem
+                        .strip_suffix("__init__")
+                        .and_then(|package| package.strip_suffix(['/', '\\']))
+                        .is_some_and(|package| ends_with_path(package, module))
+            })
+            .flat_map(|(_, definitions)| definitions)
+    }
+}
+
+/// Whether `path` is `suffix` or ends with it after a separator.
+fn ends_with_path(path: &str, suffix: &str) -> bool {
+    let path = path.replace('\\', "/");
+    path == suffix || path.ends_with(&format!("/{suffix}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_scripts_modules_and_functions() {
+        let yaml = "\
+steps:
+  - run: python ./scripts/deploy.py --prod  # see docs/setup.md
+  - run: \"python -m tools.migrate\"
+functions:
+  handler: app.lambda:handler
+  image: node:20
+  url: https://example.com/build.js
+";
+        assert_eq!(
+            targets(yaml),
+            vec![
+                (2, 17, Target::Script("scripts/deploy.py".to_string())),
+                (3, 21, Target::Module("tools.migrate".to_string())),
+                (
+                    5,
+                    12,
+                    Target::Function {
+                        module: "app.lambda".to_string(),
+                        name: "handler".to_string(),
+                    }
+                ),
+            ]
+        );
+    }
+}
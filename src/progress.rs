@@ -0,0 +1,60 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Stage of an analysis run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Walking directories for source files.
+    Scan,
+    /// Parsing files, or reading them from the cache. Runs twice with
+    /// [`Options::low_memory`](crate::Options::low_memory).
+    Parse,
+    /// Linking references and computing ranks.
+    Rank,
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Scan => "scan",
+            Phase::Parse => "parse",
+            Phase::Rank => "rank",
+        }
+    }
+}
+
+/// A progress update: `done` out of `total` steps of `phase`. `total` is
+/// `None` while scanning, since the number of files isn't known yet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    pub done: usize,
+    pub total: Option<usize>,
+}
+
+/// Callback for [`Progress`] updates. It is called from worker threads, once
+/// per file while scanning and parsing, so it should be cheap and throttle
+/// any rendering itself.
+#[derive(Clone)]
+pub struct ProgressHook(Arc<dyn Fn(Progress) + Send + Sync>);
+
+impl ProgressHook {
+    pub fn new(callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Passes `progress` to the callback, e.g. from a hook wrapping another.
+    pub fn call(&self, progress: Progress) {
+        (self.0)(progress);
+    }
+
+    pub(crate) fn report(&self, phase: Phase, done: usize, total: Option<usize>) {
+        self.call(Progress { phase, done, total });
+    }
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressHook(..)")
+    }
+}
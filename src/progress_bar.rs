@@ -0,0 +1,54 @@
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use cruxlines::{Phase, Progress, ProgressHook};
+
+const WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Renders progress as a single line on stderr for `--progress`. The line is
+/// redrawn at most every [`REDRAW_INTERVAL`], plus whenever the phase changes
+/// or finishes, and cleared once ranking is done.
+pub(crate) fn progress_bar() -> ProgressHook {
+    let last_draw: Mutex<Option<(Phase, Instant)>> = Mutex::new(None);
+    ProgressHook::new(move |progress| {
+        let Ok(mut last_draw) = last_draw.lock() else {
+            return;
+        };
+        let finished = progress.total == Some(progress.done);
+        let due = match *last_draw {
+            Some((phase, at)) => {
+                phase != progress.phase || finished || at.elapsed() >= REDRAW_INTERVAL
+            }
+            None => true,
+        };
+        if !due {
+            return;
+        }
+        *last_draw = Some((progress.phase, Instant::now()));
+        let line = if progress.phase == Phase::Rank && finished {
+            CLEAR_LINE.to_string()
+        } else {
+            format!("{CLEAR_LINE}{}", render(progress))
+        };
+        let mut stderr = std::io::stderr().lock();
+        let _ = stderr.write_all(line.as_bytes());
+        let _ = stderr.flush();
+    })
+}
+
+fn render(progress: Progress) -> String {
+    let phase = progress.phase.as_str();
+    let Some(total) = progress.total else {
+        return format!("{phase:<5} {} files", progress.done);
+    };
+    let filled = (progress.done * WIDTH).checked_div(total).unwrap_or(WIDTH);
+    format!(
+        "{phase:<5} [{}{}] {}/{total}",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        progress.done
+    )
+}
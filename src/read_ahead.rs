@@ -0,0 +1,56 @@
+//! Reading files on a bounded pool of I/O threads ahead of the parser pool,
+//! so parsers are not left waiting on slow disks or network filesystems.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use rayon::iter::{IterBridge, ParallelBridge};
+
+/// Files read ahead of the parsers, with their index in `paths`, in the
+/// order reads finish.
+pub(crate) type ReadAhead<'a, R> = IterBridge<mpsc::IntoIter<(usize, &'a Path, R)>>;
+
+/// Calls `read` on every path from `threads` reader threads and hands the
+/// results to `consume` as a parallel iterator. At most `threads` results
+/// wait in the channel, which bounds the memory held by reads the parsers
+/// have not caught up with. WebAssembly has no threads, so there every file
+/// is read up front.
+pub(crate) fn read_ahead<'a, R: Send, T>(
+    paths: &'a [PathBuf],
+    threads: usize,
+    read: impl Fn(&'a Path) -> R + Sync,
+    consume: impl FnOnce(ReadAhead<'a, R>) -> T,
+) -> T {
+    if cfg!(target_family = "wasm") {
+        let (sender, receiver) = mpsc::sync_channel(paths.len());
+        for (index, path) in paths.iter().enumerate() {
+            let _ = sender.send((index, path.as_path(), read(path)));
+        }
+        drop(sender);
+        return consume(receiver.into_iter().par_bridge());
+    }
+
+    let threads = threads.clamp(1, paths.len().max(1));
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::sync_channel(threads);
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let (sender, next, read) = (sender.clone(), &next, &read);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    // The parsers stopped early, so nobody wants the rest.
+                    if sender.send((index, path.as_path(), read(path))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        consume(receiver.into_iter().par_bridge())
+    })
+}
@@ -0,0 +1,86 @@
+//! Locating a repository's work tree and metadata the way git does: a
+//! `.git` directory, a `.git` file pointing elsewhere (linked worktrees and
+//! submodules), a bare repository, or the `GIT_DIR`, `GIT_WORK_TREE` and
+//! `GIT_COMMON_DIR` environment variables.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The root of the work tree holding `start`: `GIT_WORK_TREE` (or the
+/// current directory) when `GIT_DIR` is set, else the closest ancestor of
+/// `start` with a `.git` directory or file, or a bare repository.
+pub fn find_repo_root(start: &Path) -> Option<PathBuf> {
+    if env_path("GIT_DIR").is_some() {
+        return env_path("GIT_WORK_TREE").or_else(|| std::env::current_dir().ok());
+    }
+    start
+        .ancestors()
+        .find(|ancestor| dot_git(ancestor).is_some() || is_bare_repo(ancestor))
+        .map(Path::to_path_buf)
+}
+
+/// The metadata directory of the repository at `repo_root`: `GIT_DIR`
+/// when set, `.git` or the directory a `.git` file names, or `repo_root`
+/// itself for a bare repository. `None` outside a repository.
+pub fn git_dir(repo_root: &Path) -> Option<PathBuf> {
+    if let Some(git_dir) = env_path("GIT_DIR") {
+        return Some(git_dir);
+    }
+    dot_git(repo_root).or_else(|| is_bare_repo(repo_root).then(|| repo_root.to_path_buf()))
+}
+
+/// The directory with the metadata linked worktrees share, such as
+/// `info/exclude`: `GIT_COMMON_DIR`, what the git dir's `commondir` file
+/// names, or the git dir itself.
+pub(crate) fn common_dir(repo_root: &Path) -> Option<PathBuf> {
+    if let Some(common_dir) = env_path("GIT_COMMON_DIR") {
+        return Some(common_dir);
+    }
+    let git_dir = git_dir(repo_root)?;
+    let common_dir = std::fs::read_to_string(git_dir.join("commondir"))
+        .ok()
+        .map(|contents| git_dir.join(contents.trim()));
+    Some(common_dir.unwrap_or(git_dir))
+}
+
+/// `git -C repo_root`. Relative `GIT_DIR`, `GIT_WORK_TREE` and
+/// `GIT_COMMON_DIR` are passed on as absolute paths, since git would
+/// otherwise resolve them against `repo_root`.
+pub(crate) fn git_command(repo_root: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo_root);
+    for name in ["GIT_DIR", "GIT_WORK_TREE", "GIT_COMMON_DIR"] {
+        if let Some(path) = env_path(name) {
+            command.env(name, path);
+        }
+    }
+    command
+}
+
+/// `.git` under `dir` when it is a directory, or the directory a `.git`
+/// file points to with its `gitdir: <path>` line.
+fn dot_git(dir: &Path) -> Option<PathBuf> {
+    let dot_git = dir.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let contents = std::fs::read_to_string(&dot_git).ok()?;
+    let target = contents.lines().next()?.strip_prefix("gitdir:")?.trim();
+    let target = dir.join(target);
+    target.is_dir().then_some(target)
+}
+
+fn is_bare_repo(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
+/// An environment variable holding a path, made absolute against the
+/// current directory.
+fn env_path(name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(std::env::var_os(name).filter(|value| !value.is_empty())?);
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        std::env::current_dir().ok().map(|cwd| cwd.join(path))
+    }
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::analysis::OutputRow;
+use crate::schema::SCHEMA_VERSION;
+
+const SCHEMA_URI: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const RULE_ID: &str = "crux-definition";
+
+/// Builds a SARIF 2.1.0 log with one `note` result per row. Artifact URIs are
+/// relative to `repo_root` (resolved through the `%SRCROOT%` base id) when
+/// given, and the scores are attached as result properties. The run's
+/// `schemaVersion` property versions those properties like the JSON rows.
+pub fn to_sarif(rows: &[OutputRow], repo_root: Option<&Path>) -> Value {
+    let results: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            let (uri, base_id) = artifact_uri(row.definition.path_str(), repo_root);
+            let mut artifact = json!({ "uri": uri });
+            if let Some(base_id) = base_id {
+                artifact["uriBaseId"] = json!(base_id);
+            }
+            json!({
+                "ruleId": RULE_ID,
+                "level": "note",
+                "message": {
+                    "text": format!(
+                        "`{}` is a crux definition (rank {:.6}, {} references)",
+                        row.definition.name_str(),
+                        row.rank,
+                        row.reference_count
+                    ),
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": artifact,
+                        "region": {
+                            "startLine": row.definition.line,
+                            "startColumn": row.definition.column,
+                            "snippet": { "text": row.definition_line },
+                        },
+                    },
+                }],
+                "properties": {
+                    "name": row.definition.name_str(),
+                    "rank": row.rank,
+                    "localScore": row.local_score,
+                    "fileRank": row.file_rank,
+                    "referenceCount": row.reference_count,
+                },
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": SCHEMA_URI,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": [{
+                        "id": RULE_ID,
+                        "name": "CruxDefinition",
+                        "shortDescription": {
+                            "text": "Definition ranked by cross-file references",
+                        },
+                    }],
+                },
+            },
+            "results": results,
+            "properties": { "schemaVersion": SCHEMA_VERSION },
+        }],
+    })
+}
+
+fn artifact_uri(path: &str, repo_root: Option<&Path>) -> (String, Option<&'static str>) {
+    match repo_root.and_then(|root| Path::new(path).strip_prefix(root).ok()) {
+        Some(rel) => (to_uri_path(rel), Some("%SRCROOT%")),
+        None => (to_uri_path(Path::new(path)), None),
+    }
+}
+
+fn to_uri_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_sarif;
+    use crate::analysis::cruxlines_from_inputs;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn maps_rows_to_sarif_results() {
+        let inputs = vec![
+            (
+                PathBuf::from("/repo/src/a.py"),
+                "def foo():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("/repo/b.py"),
+                "from a import foo\n\nfoo()\n".to_string(),
+            ),
+        ];
+        let rows = cruxlines_from_inputs(inputs, None);
+        let log = to_sarif(&rows, Some(Path::new("/repo")));
+
+        assert_eq!(log["version"], "2.1.0");
+        assert_eq!(log["runs"][0]["properties"]["schemaVersion"], 1);
+        let result = &log["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "crux-definition");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/a.py");
+        assert_eq!(location["artifactLocation"]["uriBaseId"], "%SRCROOT%");
+        assert_eq!(location["region"]["startLine"], 1);
+        assert_eq!(location["region"]["startColumn"], 5);
+        assert_eq!(location["region"]["snippet"]["text"], "def foo():");
+        assert_eq!(result["properties"]["referenceCount"], 2);
+        assert_eq!(result["properties"]["rank"], rows[0].rank);
+    }
+}
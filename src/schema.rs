@@ -0,0 +1,232 @@
+//! The JSON row format shared by `--format json` and the WebAssembly
+//! bindings, and the JSON Schema describing it. Documents carry
+//! [`SCHEMA_VERSION`] so integrations can tell which shape they got.
+
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::aggregate::EcosystemSection;
+use crate::analysis::OutputRow;
+use crate::languages::Ecosystem;
+
+/// Version of the JSON row format. Adding a field keeps the version;
+/// removing, renaming or retyping one bumps it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A JSON document of `rows`: `{ "schema_version": 1, "rows": [...] }`.
+/// Paths are relative to `repo_root` when given.
+pub fn to_json(rows: &[OutputRow], repo_root: Option<&Path>) -> Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "rows": rows.iter().map(|row| row_json(row, repo_root)).collect::<Vec<_>>(),
+    })
+}
+
+/// A JSON document of per-ecosystem sections, keyed by `name(ecosystem)`:
+/// `{ "schema_version": 1, "ecosystems": { "python": { ..., "rows": [...] } } }`.
+pub fn sections_to_json(
+    sections: &[EcosystemSection],
+    name: impl Fn(Ecosystem) -> String,
+    repo_root: Option<&Path>,
+) -> Value {
+    let ecosystems: serde_json::Map<String, Value> = sections
+        .iter()
+        .map(|section| {
+            (
+                name(section.ecosystem),
+                json!({
+                    "definition_count": section.definition_count,
+                    "total_rank": section.total_rank,
+                    "max_rank": section.max_rank,
+                    "rows": section.rows.iter().map(|row| row_json(row, repo_root)).collect::<Vec<_>>(),
+                }),
+            )
+        })
+        .collect();
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "ecosystems": ecosystems,
+    })
+}
+
+fn row_json(row: &OutputRow, repo_root: Option<&Path>) -> Value {
+    json!({
+        "path": relative_path(row.definition.path_str(), repo_root),
+        "line": row.definition.line,
+        "column": row.definition.column,
+        "name": row.definition.name_str(),
+        "kind": row.kind.as_str(),
+        "rank": row.rank,
+        "local_score": row.local_score,
+        "file_rank": row.file_rank,
+        "symbol_rank": row.symbol_rank,
+        "reference_count": row.reference_count,
+        "execution_count": row.execution_count,
+        "owners": row.owners,
+        "is_entry_point": row.is_entry_point,
+        "definition_line": row.definition_line,
+        "references": row.references.iter().map(|reference| json!({
+            "path": relative_path(reference.path_str(), repo_root),
+            "line": reference.line,
+            "column": reference.column,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn relative_path(path: &str, repo_root: Option<&Path>) -> String {
+    let path = Path::new(path);
+    repo_root
+        .and_then(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// JSON Schema (draft 2020-12) of the documents written by [`to_json`] and
+/// [`sections_to_json`].
+pub fn json_schema() -> Value {
+    let location = json!({
+        "type": "object",
+        "required": ["path", "line", "column"],
+        "properties": {
+            "path": { "type": "string" },
+            "line": { "type": "integer", "minimum": 1 },
+            "column": { "type": "integer", "minimum": 1 },
+        },
+    });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "cruxlines rows",
+        "type": "object",
+        "required": ["schema_version"],
+        "oneOf": [{ "required": ["rows"] }, { "required": ["ecosystems"] }],
+        "properties": {
+            "schema_version": { "const": SCHEMA_VERSION },
+            "rows": {
+                "description": "Definitions, highest rank first.",
+                "type": "array",
+                "items": { "$ref": "#/$defs/row" },
+            },
+            "ecosystems": {
+                "description": "Sections keyed by ecosystem, with --split-by-ecosystem.",
+                "type": "object",
+                "additionalProperties": { "$ref": "#/$defs/section" },
+            },
+        },
+        "$defs": {
+            "location": location,
+            "section": {
+                "type": "object",
+                "required": ["definition_count", "total_rank", "max_rank", "rows"],
+                "properties": {
+                    "definition_count": { "type": "integer", "minimum": 0 },
+                    "total_rank": { "type": "number" },
+                    "max_rank": { "type": "number" },
+                    "rows": {
+                        "description": "Definitions, highest rank first, with ranks divided by max_rank.",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/row" },
+                    },
+                },
+            },
+            "row": {
+                "type": "object",
+                "required": [
+                    "path", "line", "column", "name", "kind", "rank", "local_score",
+                    "file_rank", "symbol_rank", "reference_count", "execution_count",
+                    "owners", "is_entry_point", "definition_line", "references",
+                ],
+                "properties": {
+                    "path": { "type": "string" },
+                    "line": { "type": "integer", "minimum": 1 },
+                    "column": { "type": "integer", "minimum": 1 },
+                    "name": { "type": "string" },
+                    "kind": {
+                        "enum": [
+                            "function", "method", "class", "struct", "enum", "interface",
+                            "trait", "type", "constant", "variable", "field", "module",
+                        ],
+                    },
+                    "rank": { "type": "number" },
+                    "local_score": { "type": "number" },
+                    "file_rank": { "type": "number" },
+                    "symbol_rank": {
+                        "description": "Present with --symbol-rank.",
+                        "type": ["number", "null"],
+                    },
+                    "reference_count": { "type": "integer", "minimum": 0 },
+                    "execution_count": {
+                        "description": "Present with --coverage.",
+                        "type": ["integer", "null"],
+                        "minimum": 0,
+                    },
+                    "owners": { "type": "array", "items": { "type": "string" } },
+                    "is_entry_point": { "type": "boolean" },
+                    "definition_line": { "type": "string" },
+                    "references": {
+                        "description": "Empty with --low-memory.",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/location" },
+                    },
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SCHEMA_VERSION, json_schema, to_json};
+    use crate::analysis::cruxlines_from_inputs;
+    use crate::languages::SymbolKind;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn rows_have_exactly_the_fields_the_schema_requires() {
+        let inputs = vec![
+            (
+                PathBuf::from("/repo/src/a.py"),
+                "def foo():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("/repo/b.py"),
+                "from a import foo\n\nfoo()\n".to_string(),
+            ),
+        ];
+        let rows = cruxlines_from_inputs(inputs, None);
+        let document = to_json(&rows, Some(Path::new("/repo")));
+        assert_eq!(document["schema_version"], SCHEMA_VERSION);
+        let row = &document["rows"][0];
+        assert_eq!(row["path"], "src/a.py");
+        assert_eq!(row["references"][0]["path"], "b.py");
+
+        let schema = json_schema();
+        let row_schema = &schema["$defs"]["row"];
+        let mut required: Vec<&str> = row_schema["required"]
+            .as_array()
+            .expect("required list")
+            .iter()
+            .map(|field| field.as_str().expect("field name"))
+            .collect();
+        let mut fields: Vec<&str> = row
+            .as_object()
+            .expect("row object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        required.sort_unstable();
+        fields.sort_unstable();
+        assert_eq!(fields, required);
+
+        let kinds = row_schema["properties"]["kind"]["enum"]
+            .as_array()
+            .expect("kind enum");
+        assert_eq!(kinds.len(), SymbolKind::ALL.len());
+        for kind in SymbolKind::ALL {
+            assert!(kinds.contains(&kind.as_str().into()), "{kind:?}");
+        }
+    }
+}
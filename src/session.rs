@@ -0,0 +1,114 @@
+//! Parse trees kept across analysis runs, for long-running callers such as
+//! the daemon or an editor integration. When a file is parsed again, its
+//! previous tree is edited to match the new text and handed to tree-sitter,
+//! which only reparses the regions that changed. Definitions and references
+//! are still extracted from the whole new tree.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tree_sitter::Tree;
+
+use crate::find_references::{FileResult, process_file_incremental};
+use crate::languages::Language;
+use crate::options::ReferenceKinds;
+
+/// The text, tree and results of a file as last parsed, with the reference
+/// kinds of its language the results were extracted with.
+struct RetainedFile {
+    contents: String,
+    reference_kinds: Option<ReferenceKinds>,
+    tree: Tree,
+    result: FileResult,
+}
+
+/// Trees of the files parsed so far, shared by every run that has this
+/// session in `Options::parse_session`. Files served from the file cache
+/// are not parsed and so not retained; a file is kept from the first time
+/// it is parsed, typically when it first changes.
+#[derive(Clone, Default)]
+pub struct ParseSession {
+    files: Arc<Mutex<HashMap<PathBuf, RetainedFile>>>,
+}
+
+impl ParseSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of files whose trees are retained.
+    pub fn len(&self) -> usize {
+        self.files.lock().map_or(0, |files| files.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the tree of `path`, e.g. once the file is deleted.
+    pub fn forget(&self, path: &Path) {
+        if let Ok(mut files) = self.files.lock() {
+            files.remove(path);
+        }
+    }
+
+    /// Parses `contents` of `path`, incrementally when an earlier tree is
+    /// retained. Unchanged contents with unchanged reference kinds return
+    /// the retained results as they are.
+    pub(crate) fn parse(
+        &self,
+        path: &Path,
+        contents: String,
+        reference_kinds: &HashMap<Language, ReferenceKinds>,
+    ) -> Option<FileResult> {
+        let kinds = crate::languages::language_for_path(path)
+            .and_then(|language| reference_kinds.get(&language))
+            .cloned();
+        // Taken out so other files parse in parallel without the lock.
+        let previous = self.files.lock().ok()?.remove(path);
+        let previous = match previous {
+            Some(previous)
+                if previous.contents == contents && previous.reference_kinds == kinds =>
+            {
+                let result = previous.result.clone();
+                self.retain(path, previous);
+                return Some(result);
+            }
+            previous => previous,
+        };
+        let (result, tree) = process_file_incremental(
+            path,
+            &contents,
+            reference_kinds,
+            previous
+                .as_ref()
+                .map(|previous| (previous.contents.as_str(), previous.tree.clone())),
+        )?;
+        if let Some(tree) = tree {
+            self.retain(
+                path,
+                RetainedFile {
+                    contents,
+                    reference_kinds: kinds,
+                    tree,
+                    result: result.clone(),
+                },
+            );
+        }
+        Some(result)
+    }
+
+    fn retain(&self, path: &Path, file: RetainedFile) {
+        if let Ok(mut files) = self.files.lock() {
+            files.insert(path.to_path_buf(), file);
+        }
+    }
+}
+
+impl fmt::Debug for ParseSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ParseSession({} files)", self.len())
+    }
+}
@@ -0,0 +1,131 @@
+//! Lockfile of the top-ranked definitions, so a CI job can fail when the
+//! architectural core of a repo shifts until someone acknowledges it by
+//! writing a new snapshot.
+//!
+//! The file lists one definition per line, most crucial first, as its path
+//! relative to the repo root and its name separated by a tab. Ranks are left
+//! out since they drift with git history on every commit.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use crate::analysis::OutputRow;
+
+const HEADER: &str = "# cruxlines snapshot: top ";
+
+/// The top definitions of an analysis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// How many definitions were asked for; `entries` is shorter when the
+    /// analysis had fewer rows.
+    pub top: usize,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SnapshotEntry {
+    /// Path relative to the repo root, with `/` separators.
+    pub path: String,
+    pub name: String,
+}
+
+/// Definitions that entered or left the top between two snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotChanges {
+    pub entered: Vec<SnapshotEntry>,
+    pub left: Vec<SnapshotEntry>,
+}
+
+impl SnapshotChanges {
+    /// Number of places in the top that changed hands.
+    pub fn count(&self) -> usize {
+        self.entered.len().max(self.left.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+}
+
+impl Snapshot {
+    /// The first `top` of `rows`, which must be in rank order. A name
+    /// defined twice in one file is listed once.
+    pub fn from_rows(rows: &[OutputRow], top: usize, repo_root: Option<&Path>) -> Self {
+        let mut seen = HashSet::new();
+        let entries = rows
+            .iter()
+            .map(|row| {
+                let path = Path::new(row.definition.path_str());
+                let path = repo_root
+                    .and_then(|root| path.strip_prefix(root).ok())
+                    .unwrap_or(path);
+                SnapshotEntry {
+                    path: path
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    name: row.definition.name_str().to_string(),
+                }
+            })
+            .filter(|entry| seen.insert(entry.clone()))
+            .take(top)
+            .collect();
+        Self { top, entries }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        let top = lines
+            .next()
+            .and_then(|header| header.strip_prefix(HEADER))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|top| top.parse().ok())
+            .ok_or_else(|| format!("expected a `{HEADER}N` header"))?;
+        let mut entries = Vec::new();
+        for (index, line) in lines.enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (path, name) = line
+                .split_once('\t')
+                .ok_or_else(|| format!("line {}: expected PATH<tab>NAME", index + 2))?;
+            entries.push(SnapshotEntry {
+                path: path.to_string(),
+                name: name.to_string(),
+            });
+        }
+        Ok(Self { top, entries })
+    }
+
+    /// What changed from `self` to `current`, in each snapshot's order.
+    pub fn changes(&self, current: &Snapshot) -> SnapshotChanges {
+        let before: HashSet<&SnapshotEntry> = self.entries.iter().collect();
+        let after: HashSet<&SnapshotEntry> = current.entries.iter().collect();
+        SnapshotChanges {
+            entered: current
+                .entries
+                .iter()
+                .filter(|entry| !before.contains(entry))
+                .cloned()
+                .collect(),
+            left: self
+                .entries
+                .iter()
+                .filter(|entry| !after.contains(entry))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}{} definitions, most crucial first", self.top)?;
+        for entry in &self.entries {
+            writeln!(f, "{}\t{}", entry.path, entry.name)?;
+        }
+        Ok(())
+    }
+}
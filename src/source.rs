@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::{Arc, Mutex};
+
+use crate::encoding::decode;
+use crate::error::CruxlinesError;
+use crate::options::Encoding;
+use crate::repo::git_command;
+
+/// Supplies the files to analyze and reads their contents on demand.
+///
+/// Implement this to feed cruxlines from something other than the working
+/// tree, such as unsaved editor buffers.
+pub trait SourceProvider: Sync {
+    /// Paths to analyze. Files with unsupported extensions are skipped.
+    fn paths(&self) -> Vec<PathBuf>;
+
+    /// Reads the contents of `path`. Returns `Ok(None)` to skip the file
+    /// silently; errors are reported as diagnostics and the file is skipped.
+    fn read(&self, path: &Path) -> Result<Option<String>, CruxlinesError>;
+
+    /// Reads the raw contents of `path`, which the analysis decodes
+    /// according to `ScanOptions::encoding`. Sources whose files may not be
+    /// UTF-8 override this; the default is the text of [`Self::read`].
+    fn read_bytes(&self, path: &Path) -> Result<Option<Vec<u8>>, CruxlinesError> {
+        Ok(self.read(path)?.map(String::into_bytes))
+    }
+
+    /// Whether `path` is read from disk unchanged, so parse results may be
+    /// stored in and served from the file cache.
+    fn is_cacheable(&self, _path: &Path) -> bool {
+        false
+    }
+}
+
+/// Reads files from disk, except for unsaved buffers given with
+/// [`FileSystemSource::insert_unsaved`].
+#[derive(Debug, Clone, Default)]
+pub struct FileSystemSource {
+    paths: Vec<PathBuf>,
+    unsaved: HashMap<PathBuf, String>,
+}
+
+impl FileSystemSource {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            paths,
+            unsaved: HashMap::new(),
+        }
+    }
+
+    /// Serves `contents` instead of the file on disk for `path`, adding the
+    /// path if it isn't listed yet. Unsaved files bypass the file cache.
+    pub fn insert_unsaved(&mut self, path: PathBuf, contents: String) {
+        if !self.paths.contains(&path) {
+            self.paths.push(path.clone());
+        }
+        self.unsaved.insert(path, contents);
+    }
+}
+
+impl SourceProvider for FileSystemSource {
+    fn paths(&self) -> Vec<PathBuf> {
+        self.paths.clone()
+    }
+
+    fn read(&self, path: &Path) -> Result<Option<String>, CruxlinesError> {
+        if let Some(contents) = self.unsaved.get(path) {
+            return Ok(Some(contents.clone()));
+        }
+        let bytes = std::fs::read(path).map_err(|source| CruxlinesError::ReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        decode(path, bytes, Encoding::Strict).map(Some)
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<Option<Vec<u8>>, CruxlinesError> {
+        if let Some(contents) = self.unsaved.get(path) {
+            return Ok(Some(contents.clone().into_bytes()));
+        }
+        std::fs::read(path)
+            .map(Some)
+            .map_err(|source| CruxlinesError::ReadFile {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    fn is_cacheable(&self, path: &Path) -> bool {
+        !self.unsaved.contains_key(path)
+    }
+}
+
+/// Serves contents held in memory, without touching the disk.
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySource {
+    paths: Vec<PathBuf>,
+    contents: HashMap<PathBuf, String>,
+}
+
+impl InMemorySource {
+    pub fn new(inputs: impl IntoIterator<Item = (PathBuf, String)>) -> Self {
+        let mut source = Self::default();
+        for (path, contents) in inputs {
+            source.insert(path, contents);
+        }
+        source
+    }
+
+    /// Adds or replaces the contents of `path`.
+    pub fn insert(&mut self, path: PathBuf, contents: String) {
+        if !self.contents.contains_key(&path) {
+            self.paths.push(path.clone());
+        }
+        self.contents.insert(path, contents);
+    }
+}
+
+impl SourceProvider for InMemorySource {
+    fn paths(&self) -> Vec<PathBuf> {
+        self.paths.clone()
+    }
+
+    fn read(&self, path: &Path) -> Result<Option<String>, CruxlinesError> {
+        Ok(self.contents.get(path).cloned())
+    }
+}
+
+/// Reads files as they were at a git revision without touching the working
+/// tree. Files are listed with `git ls-tree` and read through a single
+/// `git cat-file --batch` process, shared by clones of the source.
+#[derive(Debug, Clone)]
+pub struct GitRevisionSource {
+    revision: String,
+    paths: Vec<PathBuf>,
+    /// Blob id of each path.
+    blobs: HashMap<PathBuf, String>,
+    reader: Arc<BlobReader>,
+}
+
+impl GitRevisionSource {
+    /// Lists the files tracked at `revision`. Paths are joined onto
+    /// `repo_root` so they line up with working-tree paths.
+    pub fn new(repo_root: &Path, revision: &str) -> Result<Self, CruxlinesError> {
+        let commit = resolve_revision(repo_root, revision)?;
+        let output = git(repo_root, revision, &["ls-tree", "-r", "-z", &commit])?;
+        let mut paths = Vec::new();
+        let mut blobs = HashMap::new();
+        // Entries read `<mode> <type> <id>\t<path>`.
+        for entry in output.split(|byte| *byte == 0) {
+            let Some((meta, name)) = std::str::from_utf8(entry)
+                .ok()
+                .and_then(|entry| entry.split_once('\t'))
+            else {
+                continue;
+            };
+            let mut meta = meta.split(' ');
+            let (Some(_mode), Some("blob"), Some(id)) = (meta.next(), meta.next(), meta.next())
+            else {
+                continue;
+            };
+            let path = repo_root.join(name);
+            paths.push(path.clone());
+            blobs.insert(path, id.to_string());
+        }
+        Ok(Self {
+            revision: revision.to_string(),
+            paths,
+            blobs,
+            reader: Arc::new(BlobReader {
+                repo_root: repo_root.to_path_buf(),
+                process: Mutex::new(None),
+            }),
+        })
+    }
+
+    /// Keeps only the paths for which `keep` returns true.
+    pub fn retain(&mut self, keep: impl FnMut(&PathBuf) -> bool) {
+        self.paths.retain(keep);
+    }
+}
+
+impl SourceProvider for GitRevisionSource {
+    fn paths(&self) -> Vec<PathBuf> {
+        self.paths.clone()
+    }
+
+    fn read(&self, path: &Path) -> Result<Option<String>, CruxlinesError> {
+        match self.read_bytes(path)? {
+            Some(bytes) => decode(path, bytes, Encoding::Strict).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<Option<Vec<u8>>, CruxlinesError> {
+        let Some(id) = self.blobs.get(path) else {
+            return Ok(None);
+        };
+        self.reader.read(id).map_err(|err| CruxlinesError::Git {
+            revision: self.revision.clone(),
+            message: err.to_string(),
+        })
+    }
+}
+
+/// A `git cat-file --batch` process, started on the first read and
+/// restarted after a failed one.
+struct BlobReader {
+    repo_root: PathBuf,
+    process: Mutex<Option<CatFile>>,
+}
+
+impl std::fmt::Debug for BlobReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlobReader")
+            .field("repo_root", &self.repo_root)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BlobReader {
+    /// Contents of the object `id`, or `None` when git doesn't have it.
+    fn read(&self, id: &str) -> std::io::Result<Option<Vec<u8>>> {
+        let mut process = self
+            .process
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if process.is_none() {
+            *process = Some(CatFile::spawn(&self.repo_root)?);
+        }
+        let read = process.as_mut().expect("started above").read(id);
+        if read.is_err() {
+            *process = None;
+        }
+        read
+    }
+}
+
+struct CatFile {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl CatFile {
+    fn spawn(repo_root: &Path) -> std::io::Result<Self> {
+        let mut child = git_command(repo_root)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Asks for `id` and reads the `<id> <type> <size>` header, the contents
+    /// and their trailing newline, or a `<id> missing` line.
+    fn read(&mut self, id: &str) -> std::io::Result<Option<Vec<u8>>> {
+        writeln!(self.stdin, "{id}")?;
+        self.stdin.flush()?;
+        let mut header = String::new();
+        if self.stdout.read_line(&mut header)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut fields = header.split_whitespace().skip(1);
+        let (Some(_kind), Some(size)) = (fields.next(), fields.next()) else {
+            return Ok(None);
+        };
+        let size: usize = size.parse().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected cat-file header: {}", header.trim_end()),
+            )
+        })?;
+        let mut contents = vec![0; size + 1];
+        self.stdout.read_exact(&mut contents)?;
+        contents.pop();
+        Ok(Some(contents))
+    }
+}
+
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Files changed between `revision` and `HEAD`, joined onto `repo_root`.
+/// Deleted files are included, so they simply match nothing.
+pub fn changed_files(repo_root: &Path, revision: &str) -> Result<Vec<PathBuf>, CruxlinesError> {
+    let commit = resolve_revision(repo_root, revision)?;
+    let range = format!("{commit}..HEAD");
+    let output = git(
+        repo_root,
+        revision,
+        &["diff", "--name-only", "--no-relative", "-z", &range],
+    )?;
+    Ok(output
+        .split(|byte| *byte == 0)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| std::str::from_utf8(name).ok())
+        .map(|name| repo_root.join(name))
+        .collect())
+}
+
+/// The id of the commit `revision` names. Revisions come from users and CI
+/// (branch names, for example), so only the id is passed on to other git
+/// commands, where a revision such as `--output=file` would be an option.
+pub(crate) fn resolve_revision(repo_root: &Path, revision: &str) -> Result<String, CruxlinesError> {
+    if revision.starts_with('-') {
+        return Err(CruxlinesError::Git {
+            revision: revision.to_string(),
+            message: "revisions may not start with `-`".to_string(),
+        });
+    }
+    let commit = format!("{revision}^{{commit}}");
+    let output = git(
+        repo_root,
+        revision,
+        &["rev-parse", "--verify", "--end-of-options", &commit],
+    )?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+/// Runs git in `repo_root`. Revisions in `args` must come from
+/// [`resolve_revision`] rather than straight from the user.
+pub(crate) fn git(
+    repo_root: &Path,
+    revision: &str,
+    args: &[&str],
+) -> Result<Vec<u8>, CruxlinesError> {
+    let output = git_command(repo_root)
+        .args(args)
+        .output()
+        .map_err(|err| CruxlinesError::Git {
+            revision: revision.to_string(),
+            message: err.to_string(),
+        })?;
+    if !output.status.success() {
+        return Err(CruxlinesError::Git {
+            revision: revision.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(output.stdout)
+}
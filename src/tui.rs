@@ -0,0 +1,655 @@
+//! `cruxlines top`: browses the ranked definitions in the terminal, with an
+//! incremental filter, a preview of the selected definition and a key that
+//! opens it in `$EDITOR`.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use cruxlines::{Ecosystem, Location, Options, OutputRow, ParseSession, cruxlines_in_paths};
+
+use crate::output::display_path;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const REVERSE: &str = "\x1b[7m";
+
+/// Source lines shown above the definition in the preview.
+const PREVIEW_CONTEXT: usize = 3;
+
+const HELP: &str =
+    "type to filter (name: path: kind:)  ↑↓ PgUp PgDn move  Enter open  ^R reload  Esc quit";
+
+/// Runs the browser until the user quits. Analyses after the first reuse
+/// the file cache and parse trees, so reloading only reparses edited files.
+pub(crate) fn run(
+    repo_root: &Path,
+    ecosystems: HashSet<Ecosystem>,
+    options: Options,
+) -> Result<(), String> {
+    if !terminal::is_interactive() {
+        return Err("top needs an interactive terminal".to_string());
+    }
+    let options = Options {
+        parse_session: Some(ParseSession::new()),
+        ..options
+    };
+    let roots = [repo_root.to_path_buf()];
+    let analyze = || {
+        cruxlines_in_paths(repo_root, &roots, &ecosystems, &options)
+            .map(|analysis| analysis.rows)
+            .map_err(|err| err.to_string())
+    };
+    let mut browser = Browser::new(entries(analyze()?, repo_root));
+    let mut preview = Preview::default();
+    let mut terminal = terminal::Terminal::enter()?;
+    loop {
+        let (width, height) = terminal::size();
+        browser.scroll_into_view(list_height(height));
+        let screen = render(&browser, &mut preview, width, height);
+        terminal.draw(&screen).map_err(|err| err.to_string())?;
+        for key in terminal.read_keys().map_err(|err| err.to_string())? {
+            match key {
+                Key::Quit => return Ok(()),
+                Key::Up => browser.move_by(-1),
+                Key::Down => browser.move_by(1),
+                Key::PageUp => browser.move_by(-(list_height(height) as isize)),
+                Key::PageDown => browser.move_by(list_height(height) as isize),
+                Key::Home => browser.move_by(isize::MIN),
+                Key::End => browser.move_by(isize::MAX),
+                Key::Char(ch) => browser.edit_query(|query| query.push(ch)),
+                Key::Backspace => browser.edit_query(|query| {
+                    query.pop();
+                }),
+                Key::ClearQuery => browser.edit_query(String::clear),
+                Key::Reload => match analyze() {
+                    Ok(rows) => {
+                        browser.replace_entries(entries(rows, repo_root));
+                        preview = Preview::default();
+                        browser.status = None;
+                    }
+                    Err(err) => browser.status = Some(err),
+                },
+                Key::Open => {
+                    if let Some(entry) = browser.selected() {
+                        browser.status = terminal
+                            .suspend(|| open_in_editor(&entry.row.definition))
+                            .err();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A row and the text the filter matches against.
+struct Entry {
+    row: OutputRow,
+    /// Path relative to the repo root.
+    path: String,
+}
+
+fn entries(rows: Vec<OutputRow>, repo_root: &Path) -> Vec<Entry> {
+    rows.into_iter()
+        .map(|row| Entry {
+            path: display_path(row.definition.path_str(), repo_root),
+            row,
+        })
+        .collect()
+}
+
+impl Entry {
+    /// Whether every whitespace-separated term of `query` matches,
+    /// ignoring case. `name:`, `path:` and `kind:` terms match that field
+    /// only; other terms match the name, the path or the exact kind.
+    fn matches(&self, query: &str) -> bool {
+        let name = self.row.definition.name_str().to_lowercase();
+        let path = self.path.to_lowercase();
+        let kind = self.row.kind.as_str();
+        query.split_whitespace().all(|term| {
+            let term = term.to_lowercase();
+            if let Some(term) = term.strip_prefix("name:") {
+                name.contains(term)
+            } else if let Some(term) = term.strip_prefix("path:") {
+                path.contains(term)
+            } else if let Some(term) = term.strip_prefix("kind:") {
+                kind.starts_with(term)
+            } else {
+                name.contains(&term) || path.contains(&term) || kind == term
+            }
+        })
+    }
+}
+
+/// The list state: every entry, the filter and the selection within the
+/// entries it keeps.
+struct Browser {
+    entries: Vec<Entry>,
+    query: String,
+    /// Indices of the entries matching `query`, in rank order.
+    matches: Vec<usize>,
+    /// Position in `matches`.
+    selected: usize,
+    /// First position in `matches` on screen.
+    offset: usize,
+    /// Error from the last reload or editor run.
+    status: Option<String>,
+}
+
+impl Browser {
+    fn new(entries: Vec<Entry>) -> Self {
+        let mut browser = Self {
+            entries,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+            offset: 0,
+            status: None,
+        };
+        browser.refilter();
+        browser
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.matches
+            .get(self.selected)
+            .map(|index| &self.entries[*index])
+    }
+
+    fn edit_query(&mut self, edit: impl FnOnce(&mut String)) {
+        edit(&mut self.query);
+        self.refilter();
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    /// Swaps in freshly ranked entries, keeping the filter and, when it is
+    /// still listed, the selected definition.
+    fn replace_entries(&mut self, entries: Vec<Entry>) {
+        let selected = self.selected().map(|entry| entry.row.definition);
+        self.entries = entries;
+        self.refilter();
+        self.selected = selected
+            .and_then(|definition| {
+                self.matches
+                    .iter()
+                    .position(|index| self.entries[*index].row.definition == definition)
+            })
+            .unwrap_or(0);
+    }
+
+    fn refilter(&mut self) {
+        self.matches = (0..self.entries.len())
+            .filter(|index| self.entries[*index].matches(&self.query))
+            .collect();
+    }
+
+    /// Moves the selection, stopping at either end.
+    fn move_by(&mut self, delta: isize) {
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Scrolls so the selection is one of the `height` rows on screen.
+    fn scroll_into_view(&mut self, height: usize) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height.max(1);
+        }
+    }
+}
+
+/// The last file read for the preview, so redraws don't read it again.
+#[derive(Default)]
+struct Preview {
+    path: Option<PathBuf>,
+    lines: Vec<String>,
+}
+
+impl Preview {
+    fn lines(&mut self, path: &Path) -> &[String] {
+        if self.path.as_deref() != Some(path) {
+            self.lines = std::fs::read(path)
+                .map(|bytes| {
+                    String::from_utf8_lossy(&bytes)
+                        .lines()
+                        .map(|line| line.replace('\t', "    "))
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.path = Some(path.to_path_buf());
+        }
+        &self.lines
+    }
+}
+
+/// Rows of the list pane; the preview gets the rest of the screen below the
+/// header, the prompt and the separator.
+fn list_height(height: usize) -> usize {
+    (height.saturating_sub(3) / 2).max(1)
+}
+
+/// The screen as one line per terminal row.
+fn render(browser: &Browser, preview: &mut Preview, width: usize, height: usize) -> Vec<String> {
+    let mut screen = Vec::with_capacity(height);
+    let header = match &browser.status {
+        Some(status) => format!("cruxlines: {status}"),
+        None => format!(
+            "{}/{} definitions  {HELP}",
+            browser.matches.len(),
+            browser.entries.len()
+        ),
+    };
+    screen.push(format!("{DIM}{}{RESET}", truncate(&header, width)));
+    screen.push(truncate(&format!("> {}", browser.query), width));
+
+    let list_height = list_height(height);
+    for position in browser.offset..browser.offset + list_height {
+        let Some(index) = browser.matches.get(position) else {
+            screen.push(String::new());
+            continue;
+        };
+        let entry = &browser.entries[*index];
+        let definition = &entry.row.definition;
+        let line = truncate(
+            &format!(
+                "{:>10.6}  {:<9} {}  {}:{}",
+                entry.row.rank,
+                entry.row.kind.as_str(),
+                definition.name_str(),
+                entry.path,
+                definition.line
+            ),
+            width,
+        );
+        if position == browser.selected {
+            screen.push(format!("{REVERSE}{line:<width$}{RESET}"));
+        } else {
+            screen.push(line);
+        }
+    }
+
+    let Some(entry) = browser.selected() else {
+        return screen;
+    };
+    let definition = &entry.row.definition;
+    screen.push(format!(
+        "{DIM}{}{RESET}",
+        truncate(
+            &format!(
+                "── {}:{}:{} refs={} ",
+                entry.path, definition.line, definition.column, entry.row.reference_count
+            ),
+            width
+        )
+    ));
+    let lines = preview.lines(Path::new(definition.path_str()));
+    let first = definition.line.saturating_sub(PREVIEW_CONTEXT).max(1);
+    for number in first..first + height.saturating_sub(screen.len()) {
+        let Some(text) = lines.get(number - 1) else {
+            break;
+        };
+        let line = truncate(&format!("{number:>5} │ {text}"), width);
+        if number == definition.line {
+            screen.push(format!("{BOLD}{line}{RESET}"));
+        } else {
+            screen.push(line);
+        }
+    }
+    screen
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/// Opens `definition` in `$EDITOR` (`vi` when unset) and waits for it.
+fn open_in_editor(definition: &Location) -> Result<(), String> {
+    let editor = std::env::var("EDITOR")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let (program, args) = editor_command(
+        &editor,
+        Path::new(definition.path_str()),
+        definition.line,
+        definition.column,
+    )
+    .ok_or("EDITOR is empty")?;
+    let status = Command::new(&program)
+        .args(&args)
+        .status()
+        .map_err(|err| format!("failed to run {program}: {err}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{program} exited with {status}"))
+    }
+}
+
+/// The program and arguments that open `path` at `line` and `column` with
+/// `editor`, which may carry arguments of its own (`code --wait`). Editors
+/// that take `path:line:col` get that; the rest get `+line path`, which vi,
+/// Emacs, nano and most others understand.
+fn editor_command(
+    editor: &str,
+    path: &Path,
+    line: usize,
+    column: usize,
+) -> Option<(String, Vec<String>)> {
+    let mut words = editor.split_whitespace();
+    let program = words.next()?.to_string();
+    let mut args: Vec<String> = words.map(str::to_string).collect();
+    let path = path.to_string_lossy();
+    let name = Path::new(&program)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match name.as_str() {
+        "code" | "code-insiders" | "codium" | "cursor" => {
+            args.extend(["-g".to_string(), format!("{path}:{line}:{column}")]);
+        }
+        "hx" | "helix" | "subl" | "zed" => args.push(format!("{path}:{line}:{column}")),
+        _ => args.extend([format!("+{line}"), path.into_owned()]),
+    }
+    Some((program, args))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Backspace,
+    ClearQuery,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Open,
+    Reload,
+    Quit,
+}
+
+/// Escape sequences of the navigation keys, without the leading escape.
+const ESCAPE_SEQUENCES: [(&[u8], Key); 12] = [
+    (b"[A", Key::Up),
+    (b"OA", Key::Up),
+    (b"[B", Key::Down),
+    (b"OB", Key::Down),
+    (b"[5~", Key::PageUp),
+    (b"[6~", Key::PageDown),
+    (b"[H", Key::Home),
+    (b"OH", Key::Home),
+    (b"[1~", Key::Home),
+    (b"[F", Key::End),
+    (b"OF", Key::End),
+    (b"[4~", Key::End),
+];
+
+/// Decodes the bytes of one read from the terminal. Escape sequences are
+/// expected to arrive whole, so a lone escape byte is the Esc key.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = bytes;
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        let key = match byte {
+            0x1b => match ESCAPE_SEQUENCES
+                .iter()
+                .find(|(sequence, _)| rest.starts_with(sequence))
+            {
+                Some((sequence, key)) => {
+                    rest = &rest[sequence.len()..];
+                    *key
+                }
+                None if rest.is_empty() => Key::Quit,
+                // Unknown sequences are dropped with the rest of the read.
+                None => break,
+            },
+            b'\r' | b'\n' => Key::Open,
+            0x7f | 0x08 => Key::Backspace,
+            0x03 => Key::Quit,
+            0x0e => Key::Down,
+            0x10 => Key::Up,
+            0x12 => Key::Reload,
+            0x15 => Key::ClearQuery,
+            byte if byte < 0x20 => continue,
+            _ => {
+                let length = match byte {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                };
+                let end = (length - 1).min(rest.len());
+                let mut encoded = vec![byte];
+                encoded.extend_from_slice(&rest[..end]);
+                rest = &rest[end..];
+                match std::str::from_utf8(&encoded)
+                    .ok()
+                    .and_then(|text| text.chars().next())
+                {
+                    Some(ch) => Key::Char(ch),
+                    None => continue,
+                }
+            }
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// Raw mode and the alternate screen, through termios and ANSI escapes.
+mod terminal {
+    use std::io::Write;
+
+    use super::{Key, parse_keys};
+
+    pub(super) fn is_interactive() -> bool {
+        // SAFETY: isatty only inspects the descriptors.
+        unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 }
+    }
+
+    /// Columns and rows of the terminal, 80x24 when unknown.
+    pub(super) fn size() -> (usize, usize) {
+        // SAFETY: TIOCGWINSZ fills in the winsize it is given.
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let found = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+        if found && size.ws_col > 0 && size.ws_row > 0 {
+            (usize::from(size.ws_col), usize::from(size.ws_row))
+        } else {
+            (80, 24)
+        }
+    }
+
+    /// The terminal in raw mode on the alternate screen; dropping it
+    /// restores the original settings and screen.
+    pub(super) struct Terminal {
+        original: libc::termios,
+    }
+
+    impl Terminal {
+        pub(super) fn enter() -> Result<Self, String> {
+            // SAFETY: tcgetattr fills in the termios it is given.
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+                return Err(format!(
+                    "failed to read terminal settings: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+            let terminal = Self { original };
+            terminal.raw()?;
+            Ok(terminal)
+        }
+
+        /// Turns off echo, line buffering and signal keys. Reads return
+        /// after a tenth of a second without input, so resizes get redrawn.
+        fn raw(&self) -> Result<(), String> {
+            let mut raw = self.original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 1;
+            // SAFETY: raw is a valid termios copied from tcgetattr.
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+                return Err(format!(
+                    "failed to set terminal settings: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+            write_stdout("\x1b[?1049h\x1b[?25l").map_err(|err| err.to_string())
+        }
+
+        fn restore(&self) {
+            let _ = write_stdout("\x1b[?25h\x1b[?1049l");
+            // SAFETY: original came from tcgetattr.
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+        }
+
+        /// Runs `run` with the terminal back in its original state, for
+        /// handing it to another program.
+        pub(super) fn suspend<T>(
+            &mut self,
+            run: impl FnOnce() -> Result<T, String>,
+        ) -> Result<T, String> {
+            self.restore();
+            let result = run();
+            self.raw()?;
+            result
+        }
+
+        /// Replaces the screen with `lines`, one per row from the top.
+        pub(super) fn draw(&mut self, lines: &[String]) -> std::io::Result<()> {
+            let mut frame = String::new();
+            for (row, line) in lines.iter().enumerate() {
+                frame.push_str(&format!("\x1b[{};1H{line}\x1b[K", row + 1));
+            }
+            frame.push_str(&format!("\x1b[{};1H\x1b[J", lines.len() + 1));
+            write_stdout(&frame)
+        }
+
+        /// Keys typed since the last call; empty after a short wait with
+        /// no input.
+        pub(super) fn read_keys(&mut self) -> std::io::Result<Vec<Key>> {
+            let mut buffer = [0u8; 64];
+            // SAFETY: reads at most buffer.len() bytes into buffer.
+            let read =
+                unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+            match read {
+                ..0 => {
+                    let err = std::io::Error::last_os_error();
+                    if err.kind() == std::io::ErrorKind::Interrupted {
+                        Ok(Vec::new())
+                    } else {
+                        Err(err)
+                    }
+                }
+                read => Ok(parse_keys(&buffer[..read as usize])),
+            }
+        }
+    }
+
+    impl Drop for Terminal {
+        fn drop(&mut self) {
+            self.restore();
+        }
+    }
+
+    fn write_stdout(text: &str) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Browser, Key, editor_command, entries, parse_keys};
+    use cruxlines::cruxlines_from_inputs;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn filters_by_name_path_and_kind() {
+        let inputs = vec![
+            (
+                PathBuf::from("/repo/src/store.py"),
+                "class Store:\n    pass\n\ndef load():\n    pass\n".to_string(),
+            ),
+            (
+                PathBuf::from("/repo/app.py"),
+                "from store import Store, load\n\nStore()\nload()\nload()\n".to_string(),
+            ),
+        ];
+        let rows = cruxlines_from_inputs(inputs, Some(PathBuf::from("/repo")));
+        let mut browser = Browser::new(entries(rows, Path::new("/repo")));
+        let names = |browser: &Browser| -> Vec<String> {
+            browser
+                .matches
+                .iter()
+                .map(|index| {
+                    browser.entries[*index]
+                        .row
+                        .definition
+                        .name_str()
+                        .to_string()
+                })
+                .collect()
+        };
+        assert_eq!(names(&browser).len(), 2);
+
+        browser.move_by(isize::MAX);
+        assert_eq!(browser.selected, 1);
+        browser.edit_query(|query| query.push_str("LOA"));
+        assert_eq!(names(&browser), vec!["load"]);
+        assert_eq!(browser.selected, 0);
+        browser.edit_query(|query| *query = "kind:class src/".to_string());
+        assert_eq!(names(&browser), vec!["Store"]);
+        browser.edit_query(|query| *query = "function".to_string());
+        assert_eq!(names(&browser), vec!["load"]);
+        browser.edit_query(|query| *query = "path:app".to_string());
+        assert!(names(&browser).is_empty());
+        assert!(browser.selected().is_none());
+    }
+
+    #[test]
+    fn decodes_keys_and_editor_arguments() {
+        assert_eq!(
+            parse_keys(b"l\xc3\xa9\x1b[A\x1b[6~\x7f\r"),
+            vec![
+                Key::Char('l'),
+                Key::Char('é'),
+                Key::Up,
+                Key::PageDown,
+                Key::Backspace,
+                Key::Open
+            ]
+        );
+        assert_eq!(parse_keys(b"\x1b"), vec![Key::Quit]);
+
+        let path = Path::new("/repo/a.py");
+        let args = |editor| editor_command(editor, path, 12, 5).expect("editor");
+        assert_eq!(
+            args("vim"),
+            (
+                "vim".to_string(),
+                vec!["+12".to_string(), "/repo/a.py".to_string()]
+            )
+        );
+        assert_eq!(
+            args("/usr/bin/code --wait"),
+            (
+                "/usr/bin/code".to_string(),
+                vec![
+                    "--wait".to_string(),
+                    "-g".to_string(),
+                    "/repo/a.py:12:5".to_string()
+                ]
+            )
+        );
+        assert_eq!(editor_command("  ", path, 1, 1), None);
+    }
+}
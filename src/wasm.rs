@@ -0,0 +1,53 @@
+//! JavaScript bindings, so the ranking can run in a browser. Files are
+//! passed in memory; there is no file cache, git history or `CODEOWNERS`.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::analysis::cruxlines_from_inputs;
+use crate::schema::to_json;
+
+#[derive(Deserialize)]
+struct Input {
+    path: String,
+    contents: String,
+}
+
+/// Ranks the definitions in `files`, a JSON array of `{ path, contents }`
+/// objects, and returns the rows in the [`crate::schema`] JSON format,
+/// highest rank first.
+#[wasm_bindgen(js_name = cruxlinesFromInputs)]
+pub fn cruxlines_from_inputs_json(files: &str) -> Result<String, JsError> {
+    let inputs: Vec<Input> = serde_json::from_str(files)?;
+    let inputs = inputs
+        .into_iter()
+        .map(|input| (PathBuf::from(input.path), input.contents))
+        .collect();
+    let rows = cruxlines_from_inputs(inputs, None);
+    Ok(to_json(&rows, None).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cruxlines_from_inputs_json;
+    use crate::schema::SCHEMA_VERSION;
+
+    #[test]
+    fn ranks_json_inputs_into_the_schema_format() {
+        let files = serde_json::json!([
+            { "path": "a.py", "contents": "def foo():\n    pass\n" },
+            { "path": "b.py", "contents": "from a import foo\n\nfoo()\n" },
+        ]);
+        let Ok(output) = cruxlines_from_inputs_json(&files.to_string()) else {
+            panic!("analysis failed");
+        };
+        let document: serde_json::Value = serde_json::from_str(&output).expect("valid json");
+        assert_eq!(document["schema_version"], SCHEMA_VERSION);
+        let row = &document["rows"][0];
+        assert_eq!(row["name"], "foo");
+        assert_eq!(row["path"], "a.py");
+        assert_eq!(row["references"][0]["path"], "b.py");
+    }
+}
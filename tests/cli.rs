@@ -145,15 +145,43 @@ fn library_cruxlines_scans_repo_root() {
     git_commit(&dir, "init", "2001-01-01T00:00:00Z");
 
     let ecosystems = std::collections::HashSet::from([cruxlines::Ecosystem::Python]);
-    let rows = cruxlines::cruxlines(&dir, &ecosystems).expect("cruxlines");
+    let analysis = cruxlines::cruxlines(&dir, &ecosystems).expect("cruxlines");
     assert!(
-        rows.iter().any(|row| row.definition.name_str() == "add"),
+        analysis
+            .rows
+            .iter()
+            .any(|row| row.definition.name_str() == "add"),
         "expected add definition from repo scan"
     );
 
     let _ = std::fs::remove_dir_all(&dir);
 }
 
+#[test]
+fn cli_warns_about_non_utf8_files_and_keeps_going() {
+    let dir = temp_dir_path("cruxlines-non-utf8");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    std::fs::write(dir.join("latin1.py"), b"name = '\xe9'\n").expect("write latin1");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--no-cache"]).current_dir(&dir);
+    let assert = cmd
+        .assert()
+        .success()
+        .stderr(contains("skipped:").and(contains("latin1.py is not valid UTF-8")));
+    let output = String::from_utf8(assert.get_output().stdout.clone()).expect("utf8 output");
+    assert!(
+        output.contains("defs.py:1:5:"),
+        "expected analysis of the other files, got: {output}"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 #[test]
 fn cli_outputs_non_uniform_pagerank_scores() {
     let output = run_cli_output_with_metadata();
@@ -209,335 +237,2843 @@ fn cli_hides_references_without_flag() {
 }
 
 #[test]
-fn cli_filters_by_ecosystem() {
+fn cli_shows_references_with_flag() {
+    let dir = temp_dir_path("cruxlines-show-refs");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.args(["--ecosystem", "python"]).current_dir(repo_root());
+    cmd.args(["--ecosystem", "py", "--show-references"])
+        .current_dir(&dir);
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = output.lines().collect();
+    let def_index = lines
+        .iter()
+        .position(|line| line.starts_with("defs.py:1:5:"))
+        .expect("definition line");
     assert!(
-        output.contains("src/languages/python/fixtures"),
-        "expected python fixtures in output, got: {output}"
+        lines[def_index + 1..]
+            .iter()
+            .take_while(|line| line.starts_with("    "))
+            .any(|line| line.trim() == "main.py:3:1"),
+        "expected indented reference under definition, got: {output}"
     );
-    assert!(
-        !output.contains("src/languages/javascript/fixtures"),
-        "expected javascript fixtures to be filtered out, got: {output}"
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_no_cache_matches_cached_output() {
+    let cached = run_cli_output();
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--no-cache"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let uncached = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(
+        cached, uncached,
+        "expected --no-cache to match cached output"
     );
 }
 
 #[test]
-fn cli_supports_ecosystem_short_flag() {
+fn cli_limits_output_with_top() {
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.args(["-e", "python"]).current_dir(repo_root());
+    cmd.args(["--ecosystem", "python", "--top", "2"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let full = run_cli_output();
+    let expected: Vec<&str> = full.lines().take(2).collect();
+    assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn cli_drops_rows_below_min_rank() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--ecosystem",
+        "python",
+        "--metadata",
+        "--min-rank",
+        "0.000001",
+    ])
+    .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(!output.trim().is_empty(), "expected some rows to remain");
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        let score = metric_from_line(line, "rank=").expect("rank");
+        assert!(score >= 0.000001, "expected rank above threshold: {line}");
+    }
+}
+
+#[test]
+fn cli_limits_scan_to_path_arguments() {
+    let dir = temp_dir_path("cruxlines-path-args");
+    std::fs::create_dir_all(dir.join("server")).expect("create server dir");
+    std::fs::create_dir_all(dir.join("client")).expect("create client dir");
+    git_init(&dir);
+    std::fs::write(dir.join("server/defs.py"), "def serve():\n    return 1\n")
+        .expect("write server defs");
+    std::fs::write(
+        dir.join("server/main.py"),
+        "from defs import serve\n\nserve()\n",
+    )
+    .expect("write server main");
+    std::fs::write(dir.join("client/defs.py"), "def fetch():\n    return 1\n")
+        .expect("write client defs");
+    std::fs::write(
+        dir.join("client/main.py"),
+        "from defs import fetch\n\nfetch()\n",
+    )
+    .expect("write client main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "py", "server"]).current_dir(&dir);
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
     assert!(
-        output.contains("src/languages/python/fixtures"),
-        "expected python fixtures in output, got: {output}"
+        output.contains("server/defs.py:1:5:"),
+        "expected server definitions, got: {output}"
     );
     assert!(
-        !output.contains("src/languages/javascript/fixtures"),
-        "expected javascript fixtures to be filtered out, got: {output}"
+        !output.contains("client/"),
+        "expected client files to be skipped, got: {output}"
     );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "py", "missing"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("path not found: missing"));
+
+    let _ = std::fs::remove_dir_all(&dir);
 }
 
 #[test]
-fn cli_accepts_ecosystem_aliases() {
+fn cli_reports_symbol_rank_with_flag() {
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.args(["--ecosystem", "py"]).current_dir(repo_root());
+    cmd.args(["--ecosystem", "python", "--metadata", "--symbol-rank"])
+        .current_dir(repo_root());
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        assert!(
+            metric_from_line(line, "symbol=").is_some(),
+            "expected symbol rank in metadata, got: {line}"
+        );
+    }
     assert!(
-        output.contains("src/languages/python/fixtures"),
-        "expected python fixtures in output, got: {output}"
+        !run_cli_output_with_metadata().contains("symbol="),
+        "expected no symbol rank without the flag"
     );
+}
+
+#[test]
+fn cli_emits_dot_graph() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--emit-graph", "dot"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
     assert!(
-        !output.contains("src/languages/javascript/fixtures"),
-        "expected javascript fixtures to be filtered out, got: {output}"
+        output.starts_with("digraph cruxlines {"),
+        "expected DOT output, got: {output}"
+    );
+    assert!(
+        output.contains(
+            "\"src/languages/python/fixtures/main.py\" -> \"src/languages/python/fixtures/utils.py\""
+        ),
+        "expected relative file edge, got: {output}"
     );
 }
 
 #[test]
-fn cli_outputs_paths_relative_to_repo_root() {
-    let dir = temp_dir_path("cruxlines-relpath");
-    std::fs::create_dir_all(&dir).expect("create temp dir");
-    git_init(&dir);
-    std::fs::write(dir.join("main.py"), "def add():\n    return 1\n\nadd()\n").expect("write main");
-    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
-
-    let subdir = dir.join("sub");
-    std::fs::create_dir_all(&subdir).expect("create subdir");
+fn cli_outputs_sarif_log() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--format", "sarif"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let log: serde_json::Value = serde_json::from_slice(&output).expect("json output");
+    assert_eq!(log["version"], "2.1.0");
+    let results = log["runs"][0]["results"].as_array().expect("results array");
+    assert!(!results.is_empty(), "expected SARIF results");
+    let uri = results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]
+        .as_str()
+        .expect("uri");
+    assert!(
+        uri.starts_with("src/languages/python/fixtures/"),
+        "expected repo-relative uri, got: {uri}"
+    );
+}
 
+#[test]
+fn cli_outputs_tsv_with_header_toggle() {
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.args(["--ecosystem", "py"]).current_dir(&subdir);
+    cmd.args(["--ecosystem", "python", "--format", "tsv"])
+        .current_dir(repo_root());
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
+    let mut lines = output.lines();
+    assert_eq!(
+        lines.next(),
+        Some("rank\tlocal\tfile_rank\tname\tpath\tline\tcol\tline_text\trefs")
+    );
+    let first = lines.next().expect("expected a data row");
+    let fields: Vec<&str> = first.split('\t').collect();
+    assert_eq!(fields.len(), 9, "unexpected row: {first}");
     assert!(
-        output.contains("main.py:1:5:"),
-        "expected output paths relative to repo root, got: {output}"
+        fields[0].parse::<f64>().is_ok(),
+        "rank not numeric: {first}"
     );
     assert!(
-        !output.contains(&dir.display().to_string()),
-        "expected output paths to avoid absolute repo root, got: {output}"
+        fields[4].starts_with("src/languages/python/fixtures/"),
+        "expected repo-relative path, got: {first}"
+    );
+    assert!(
+        fields[5].parse::<usize>().is_ok(),
+        "line not numeric: {first}"
+    );
+    assert!(
+        fields[8].parse::<usize>().is_ok(),
+        "refs not numeric: {first}"
     );
 
-    let _ = std::fs::remove_dir_all(&dir);
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--format", "tsv", "--no-header"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(output.lines().next(), Some(first));
 }
 
 #[test]
-fn cli_skips_unknown_extension_inputs() {
-    let dir = temp_dir_path("cruxlines-ignore-ext");
+fn cli_diff_reports_rank_changes_between_revisions() {
+    let dir = temp_dir_path("cruxlines-diff");
     std::fs::create_dir_all(&dir).expect("create temp dir");
-    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("defs.py"),
+        "def steady():\n    return 1\n\ndef rising():\n    return 2\n",
+    )
+    .expect("write defs");
     std::fs::write(
         dir.join("main.py"),
-        "def add(a, b):\n    return a + b\n\nadd(1, 2)\n",
+        "from defs import steady, rising\n\nsteady()\nrising()\n",
     )
     .expect("write main");
-    std::fs::write(dir.join("ignore.txt"), "not source").expect("write temp file");
+    git_commit(&dir, "before", "2001-01-01T00:00:00Z");
+    std::fs::write(
+        dir.join("main.py"),
+        "from defs import steady, rising\n\nsteady()\nrising()\nrising()\nrising()\n",
+    )
+    .expect("rewrite main");
+    git_commit(&dir, "after", "2001-01-02T00:00:00Z");
+    std::fs::write(dir.join("main.py"), "").expect("clear working tree copy");
 
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.current_dir(&dir);
+    cmd.args(["diff", "HEAD~1", "HEAD", "-e", "py", "--top", "1"])
+        .current_dir(&dir);
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1, "expected one change, got: {output}");
     assert!(
-        output.contains("main.py"),
-        "expected output to include main.py, got: {output}"
-    );
-    assert!(
-        !output.contains("ignore.txt"),
-        "expected ignore.txt to be skipped, got: {output}"
+        lines[0].starts_with("defs.py:4:5: rising "),
+        "expected rising to change the most, got: {output}"
     );
 
-    let _ = std::fs::remove_file(dir.join("ignore.txt"));
-    let _ = std::fs::remove_file(dir.join("main.py"));
-    let _ = std::fs::remove_dir(dir.join(".git"));
-    let _ = std::fs::remove_dir(&dir);
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["diff", "HEAD", "no-such-rev"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("git failed for revision no-such-rev"));
+
+    let _ = std::fs::remove_dir_all(&dir);
 }
 
 #[test]
-fn cli_skips_non_utf8_inputs() {
-    let dir = temp_dir_path("cruxlines-binary");
+fn cli_groups_rows_by_file() {
+    let dir = temp_dir_path("cruxlines-group-by-file");
     std::fs::create_dir_all(&dir).expect("create temp dir");
-    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("defs.py"),
+        "def add():\n    return 1\n\ndef sub():\n    return 2\n",
+    )
+    .expect("write defs");
+    std::fs::write(dir.join("util.py"), "def helper():\n    return 3\n").expect("write util");
     std::fs::write(
         dir.join("main.py"),
-        "def add(a, b):\n    return a + b\n\nadd(1, 2)\n",
+        "from defs import add, sub\nfrom util import helper\n\nadd()\nsub()\nhelper()\n",
     )
     .expect("write main");
-    std::fs::write(dir.join("binary.py"), [0xff, 0xfe, 0xfd]).expect("write temp file");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
 
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.current_dir(&dir);
+    cmd.args(["-e", "py", "--group-by", "file"])
+        .current_dir(&dir);
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "expected one line per file, got: {output}");
+    let defs_line = lines
+        .iter()
+        .find(|line| line.starts_with("defs.py:"))
+        .expect("defs.py summary");
     assert!(
-        output.contains("main.py"),
-        "expected output to include main.py, got: {output}"
+        defs_line.contains(" definitions=2 "),
+        "expected both definitions counted, got: {defs_line}"
     );
     assert!(
-        !output.contains("binary.py"),
-        "expected binary.py to be skipped, got: {output}"
+        metric_from_line(defs_line, "total=").is_some(),
+        "expected a total score, got: {defs_line}"
     );
 
-    let _ = std::fs::remove_file(dir.join("binary.py"));
-    let _ = std::fs::remove_file(dir.join("main.py"));
-    let _ = std::fs::remove_dir(dir.join(".git"));
-    let _ = std::fs::remove_dir(&dir);
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--group-by", "file", "--top", "1"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(
+        output.lines().count(),
+        1,
+        "expected one file, got: {output}"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
 }
 
 #[test]
-fn cli_skips_gitignored_when_scanning_directory() {
-    let dir = temp_dir_path("cruxlines-ignore-dir");
+fn cli_applies_weights_from_config_file() {
+    let dir = temp_dir_path("cruxlines-config");
     std::fs::create_dir_all(&dir).expect("create temp dir");
-    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
-    std::fs::write(dir.join(".gitignore"), "ignored.py\n").expect("write gitignore");
-    std::fs::write(dir.join("utils.py"), "def add(a, b):\n    return a + b\n")
-        .expect("write utils");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    std::fs::write(dir.join(".cruxlines.toml"), "[weights]\nfile_rank = 0\n")
+        .expect("write config");
+    std::fs::write(dir.join("broken.toml"), "[weights]\nfile_rank = high\n")
+        .expect("write broken config");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--metadata"]).current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let line = output.lines().next().expect("expected a row");
+    let rank = metric_from_line(line, "rank=").expect("rank");
+    let local = metric_from_line(line, "local=").expect("local");
+    assert_eq!(rank, local, "expected file rank to be ignored, got: {line}");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--config", "broken.toml"])
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("line 2: `file_rank` must be a number"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_filters_paths_with_include_and_exclude_globs() {
+    let dir = temp_dir_path("cruxlines-globs");
+    std::fs::create_dir_all(dir.join("src")).expect("create src dir");
+    std::fs::create_dir_all(dir.join("tests")).expect("create tests dir");
+    git_init(&dir);
+    std::fs::write(dir.join("src/defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("src/main.py"), "from defs import add\n\nadd()\n").expect("write main");
     std::fs::write(
-        dir.join("main.py"),
-        "from utils import add\nfrom ignored import ignored\n\nprint(add(1, 2))\nprint(ignored())\n",
+        dir.join("tests/helpers.py"),
+        "def check():\n    return 1\n\ncheck()\n",
     )
-    .expect("write main");
-    std::fs::write(dir.join("ignored.py"), "def ignored():\n    return 0\n")
-        .expect("write ignored");
+    .expect("write helpers");
+    std::fs::write(
+        dir.join("tests/main_test.py"),
+        "from defs import add\n\nadd()\n",
+    )
+    .expect("write test");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
 
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.current_dir(&dir);
+    cmd.args(["-e", "py", "--exclude", "tests/**"])
+        .current_dir(&dir);
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
+    assert!(output.contains("src/defs.py:1:5:"), "got: {output}");
     assert!(
-        output.contains("utils.py"),
-        "expected output to include utils.py, got: {output}"
+        !output.contains("tests/"),
+        "expected tests excluded, got: {output}"
     );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "-e",
+        "py",
+        "--include",
+        "tests/**",
+        "--exclude",
+        "*_test.py",
+    ])
+    .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(output.contains("tests/helpers.py:1:5:"), "got: {output}");
     assert!(
-        !output.contains("ignored.py"),
-        "expected ignored.py to be skipped when scanning dir, got: {output}"
+        !output.contains("src/"),
+        "expected only tests, got: {output}"
     );
 
-    let _ = std::fs::remove_file(dir.join("ignored.py"));
-    let _ = std::fs::remove_file(dir.join("main.py"));
-    let _ = std::fs::remove_file(dir.join("utils.py"));
-    let _ = std::fs::remove_file(dir.join(".gitignore"));
-    let _ = std::fs::remove_dir(dir.join(".git"));
-    let _ = std::fs::remove_dir(&dir);
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--exclude", "src/{"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid pattern src/{"));
+
+    let _ = std::fs::remove_dir_all(&dir);
 }
 
 #[test]
-fn cli_uses_repo_root_for_frecency() {
-    let dir = temp_dir_path("cruxlines-frecency");
+fn cli_prints_context_lines_around_definitions() {
+    let dir = temp_dir_path("cruxlines-context");
     std::fs::create_dir_all(&dir).expect("create temp dir");
     git_init(&dir);
-
-    std::fs::write(dir.join("defs_a.py"), "def alpha():\n    return 1\n").expect("write defs_a");
-    std::fs::write(dir.join("defs_b.py"), "def beta():\n    return 1\n").expect("write defs_b");
-    let alpha_calls = "    alpha()\n".repeat(50);
-    let beta_calls = "    beta()\n".repeat(50);
-    std::fs::write(
-        dir.join("use_alpha.py"),
-        format!(
-            "from defs_a import alpha\nfrom main import anchor\n\n\
-def helper_alpha():\n{alpha_calls}    anchor()\n"
-        ),
-    )
-    .expect("write use_alpha");
     std::fs::write(
-        dir.join("use_beta.py"),
-        format!(
-            "from defs_b import beta\nfrom main import anchor\n\n\
-def helper_beta():\n{beta_calls}    anchor()\n"
-        ),
+        dir.join("defs.py"),
+        "# helpers\ndef add():\n    return 1\n\ndef sub():\n    return 2\n",
     )
-    .expect("write use_beta");
+    .expect("write defs");
     std::fs::write(
         dir.join("main.py"),
-        "from use_alpha import helper_alpha\nfrom use_beta import helper_beta\n\n\
-def anchor():\n    return None\n\n\
-helper_alpha()\nhelper_beta()\n",
+        "from defs import add, sub\n\nadd()\nadd()\nsub()\n",
     )
     .expect("write main");
-
-    git_commit(&dir, "initial", "2001-01-01T00:00:00Z");
-
-    for day in 2..=11 {
-        std::fs::write(
-            dir.join("use_alpha.py"),
-            format!(
-                "from defs_a import alpha\nfrom main import anchor\n\n\
-def helper_alpha():\n{alpha_calls}    anchor()\n\n# touch {day}\n"
-            ),
-        )
-        .expect("update use_alpha");
-        let date = format!("2001-01-{day:02}T00:00:00Z");
-        git_commit(&dir, "touch alpha", &date);
-    }
-
-    let subdir = dir.join("sub");
-    std::fs::create_dir_all(&subdir).expect("create subdir");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
 
     let mut cmd = cargo_bin_cmd!("cruxlines");
-    cmd.args(["--ecosystem", "py", "--metadata"])
-        .current_dir(&subdir);
+    cmd.args(["-e", "py", "-C", "1"]).current_dir(&dir);
     let output = cmd.assert().success().get_output().stdout.clone();
     let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(
+        output,
+        concat!(
+            "defs.py-1-# helpers\n",
+            "defs.py:2:5: def add():\n",
+            "defs.py-3-    return 1\n",
+            "--\n",
+            "defs.py-4-\n",
+            "defs.py:5:5: def sub():\n",
+            "defs.py-6-    return 2\n",
+        )
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_colors_output_only_when_asked_or_on_a_terminal() {
+    let dir = temp_dir_path("cruxlines-color");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--color", "always"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(
+        output,
+        "\x1b[2mdefs.py\x1b[0m:\x1b[31m1:5\x1b[0m: def \x1b[1madd\x1b[0m():\n"
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py"]).current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(output, "defs.py:1:5: def add():\n");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_filters_definitions_by_kind() {
+    let dir = temp_dir_path("cruxlines-kind");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("models.py"),
+        "LIMIT = 3\n\nclass User:\n    pass\n\ndef load():\n    pass\n",
+    )
+    .expect("write models");
+    std::fs::write(
+        dir.join("main.py"),
+        "from models import LIMIT, User, load\n\nload(User(), LIMIT)\n",
+    )
+    .expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--kind", "function,class"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let mut lines: Vec<&str> = output.lines().collect();
+    lines.sort_unstable();
+    assert_eq!(
+        lines,
+        vec!["models.py:3:7: class User:", "models.py:6:5: def load():"]
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--kind", "variable", "--metadata"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("name=LIMIT kind=variable |") && output.lines().count() == 1,
+        "expected only the variable, got: {output}"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_filters_definitions_by_name_glob_or_regex() {
+    let dir = temp_dir_path("cruxlines-name");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("handlers.py"),
+        "class UserHandler:\n    pass\n\nclass User:\n    pass\n\nclass OrderHandler:\n    pass\n",
+    )
+    .expect("write handlers");
+    std::fs::write(
+        dir.join("main.py"),
+        "from handlers import UserHandler, User, OrderHandler\n\nUserHandler(User(), OrderHandler())\n",
+    )
+    .expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let names = |args: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["-e", "py", "--metadata"])
+            .args(args)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let output = String::from_utf8(output).expect("utf8 output");
+        let mut names: Vec<String> = output
+            .lines()
+            .filter_map(|line| line.split(" name=").nth(1))
+            .filter_map(|rest| rest.split(' ').next())
+            .map(str::to_string)
+            .collect();
+        names.sort();
+        names
+    };
+    assert_eq!(names(&["--name", "User*"]), vec!["User", "UserHandler"]);
+    assert_eq!(
+        names(&["--name-regex", "Handler$"]),
+        vec!["OrderHandler", "UserHandler"]
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--name-regex", "("])
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("cruxlines: invalid pattern ("));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_reads_paths_and_unsaved_contents_from_stdin() {
+    let dir = temp_dir_path("cruxlines-stdin");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    std::fs::write(dir.join("other.py"), "def sub():\n    return 2\n\nsub()\n")
+        .expect("write other");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--stdin-paths"])
+        .current_dir(&dir)
+        .write_stdin("defs.py\nmain.py\ndeleted.py\n");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(output, "defs.py:1:5: def add():\n");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--no-cache", "--stdin-content", "defs.py"])
+        .current_dir(&dir)
+        .write_stdin("def add_renamed():\n    return 1\n\ndef add():\n    return 1\n");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("defs.py:4:5: def add():") && !output.contains("defs.py:1:"),
+        "expected the unsaved contents to be analyzed, got: {output}"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_impact_lists_transitive_dependents() {
+    let dir = temp_dir_path("cruxlines-impact");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("core.py"), "def parse():\n    pass\n").expect("write core");
+    std::fs::write(
+        dir.join("service.py"),
+        "from core import parse\n\ndef handle():\n    parse()\n",
+    )
+    .expect("write service");
+    std::fs::write(
+        dir.join("api.py"),
+        "from service import handle\n\ndef route():\n    handle()\n\nroute()\n",
+    )
+    .expect("write api");
+    std::fs::write(dir.join("other.py"), "def tick():\n    pass\n\ntick()\n").expect("write other");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "impact", "core.py"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let mut lines: Vec<String> = output
+        .lines()
+        .map(|line| {
+            let (location, rest) = line.split_once(": ").expect("location");
+            let name = rest.split(' ').next().expect("name");
+            let depth = rest.rsplit("depth=").next().expect("depth");
+            format!("{location} {name} {depth}")
+        })
+        .collect();
+    lines.sort();
+    assert_eq!(lines, vec!["api.py:3:5 route 2", "service.py:3:5 handle 1"]);
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["impact", "missing.py"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("path not found: missing.py"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_filters_by_ecosystem() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python"]).current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("src/languages/python/fixtures"),
+        "expected python fixtures in output, got: {output}"
+    );
+    assert!(
+        !output.contains("src/languages/javascript/fixtures"),
+        "expected javascript fixtures to be filtered out, got: {output}"
+    );
+}
+
+#[test]
+fn cli_supports_ecosystem_short_flag() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "python"]).current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("src/languages/python/fixtures"),
+        "expected python fixtures in output, got: {output}"
+    );
+    assert!(
+        !output.contains("src/languages/javascript/fixtures"),
+        "expected javascript fixtures to be filtered out, got: {output}"
+    );
+}
+
+#[test]
+fn cli_accepts_ecosystem_aliases() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "py"]).current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("src/languages/python/fixtures"),
+        "expected python fixtures in output, got: {output}"
+    );
+    assert!(
+        !output.contains("src/languages/javascript/fixtures"),
+        "expected javascript fixtures to be filtered out, got: {output}"
+    );
+}
+
+#[test]
+fn cli_filters_php_and_csharp_ecosystems() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "php", "--ecosystem", "csharp"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("src/languages/php/fixtures"),
+        "expected php fixtures in output, got: {output}"
+    );
+    assert!(
+        output.contains("src/languages/csharp/fixtures"),
+        "expected csharp fixtures in output, got: {output}"
+    );
+    assert!(
+        !output.contains("src/languages/python/fixtures"),
+        "expected python fixtures to be filtered out, got: {output}"
+    );
+}
+
+#[test]
+fn cli_outputs_paths_relative_to_repo_root() {
+    let dir = temp_dir_path("cruxlines-relpath");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("main.py"), "def add():\n    return 1\n\nadd()\n").expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let subdir = dir.join("sub");
+    std::fs::create_dir_all(&subdir).expect("create subdir");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "py"]).current_dir(&subdir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("main.py:1:5:"),
+        "expected output paths relative to repo root, got: {output}"
+    );
+    assert!(
+        !output.contains(&dir.display().to_string()),
+        "expected output paths to avoid absolute repo root, got: {output}"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_skips_unknown_extension_inputs() {
+    let dir = temp_dir_path("cruxlines-ignore-ext");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
+    std::fs::write(
+        dir.join("main.py"),
+        "def add(a, b):\n    return a + b\n\nadd(1, 2)\n",
+    )
+    .expect("write main");
+    std::fs::write(dir.join("ignore.txt"), "not source").expect("write temp file");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("main.py"),
+        "expected output to include main.py, got: {output}"
+    );
+    assert!(
+        !output.contains("ignore.txt"),
+        "expected ignore.txt to be skipped, got: {output}"
+    );
+
+    let _ = std::fs::remove_file(dir.join("ignore.txt"));
+    let _ = std::fs::remove_file(dir.join("main.py"));
+    let _ = std::fs::remove_dir(dir.join(".git"));
+    let _ = std::fs::remove_dir(&dir);
+}
+
+#[test]
+fn cli_skips_non_utf8_inputs() {
+    let dir = temp_dir_path("cruxlines-binary");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
+    std::fs::write(
+        dir.join("main.py"),
+        "def add(a, b):\n    return a + b\n\nadd(1, 2)\n",
+    )
+    .expect("write main");
+    std::fs::write(dir.join("binary.py"), [0xff, 0xfe, 0xfd]).expect("write temp file");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("main.py"),
+        "expected output to include main.py, got: {output}"
+    );
+    assert!(
+        !output.contains("binary.py"),
+        "expected binary.py to be skipped, got: {output}"
+    );
+
+    let _ = std::fs::remove_file(dir.join("binary.py"));
+    let _ = std::fs::remove_file(dir.join("main.py"));
+    let _ = std::fs::remove_dir(dir.join(".git"));
+    let _ = std::fs::remove_dir(&dir);
+}
+
+#[test]
+fn cli_skips_gitignored_when_scanning_directory() {
+    let dir = temp_dir_path("cruxlines-ignore-dir");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
+    std::fs::write(dir.join(".gitignore"), "ignored.py\n").expect("write gitignore");
+    std::fs::write(dir.join("utils.py"), "def add(a, b):\n    return a + b\n")
+        .expect("write utils");
+    std::fs::write(
+        dir.join("main.py"),
+        "from utils import add\nfrom ignored import ignored\n\nprint(add(1, 2))\nprint(ignored())\n",
+    )
+    .expect("write main");
+    std::fs::write(dir.join("ignored.py"), "def ignored():\n    return 0\n")
+        .expect("write ignored");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("utils.py"),
+        "expected output to include utils.py, got: {output}"
+    );
+    assert!(
+        !output.contains("ignored.py"),
+        "expected ignored.py to be skipped when scanning dir, got: {output}"
+    );
+
+    let _ = std::fs::remove_file(dir.join("ignored.py"));
+    let _ = std::fs::remove_file(dir.join("main.py"));
+    let _ = std::fs::remove_file(dir.join("utils.py"));
+    let _ = std::fs::remove_file(dir.join(".gitignore"));
+    let _ = std::fs::remove_dir(dir.join(".git"));
+    let _ = std::fs::remove_dir(&dir);
+}
+
+#[test]
+fn cli_skips_linguist_generated_files() {
+    let dir = temp_dir_path("cruxlines-generated");
+    std::fs::create_dir_all(dir.join("dist")).expect("create dist dir");
+    std::fs::create_dir_all(dir.join("vendor")).expect("create vendor dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join(".gitattributes"),
+        "dist/*.py linguist-generated\nvendor/** linguist-vendored=true\n",
+    )
+    .expect("write gitattributes");
+    std::fs::write(
+        dir.join("vendor/.gitattributes"),
+        "kept.py -linguist-vendored\n",
+    )
+    .expect("write nested gitattributes");
+    std::fs::write(dir.join("utils.py"), "def add():\n    return 1\n").expect("write utils");
+    std::fs::write(dir.join("dist/bundle.py"), "def bundled():\n    return 1\n")
+        .expect("write bundle");
+    std::fs::write(dir.join("vendor/lib.py"), "def vendored():\n    return 1\n")
+        .expect("write vendored");
+    std::fs::write(dir.join("vendor/kept.py"), "def kept():\n    return 1\n").expect("write kept");
+    std::fs::write(
+        dir.join("main.py"),
+        "add()\nbundled()\nvendored()\nkept()\n",
+    )
+    .expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("utils.py"),
+        "expected utils.py, got: {output}"
+    );
+    assert!(
+        output.contains("vendor/kept.py"),
+        "expected nested unset attribute to win, got: {output}"
+    );
+    assert!(
+        !output.contains("dist/bundle.py"),
+        "expected generated file to be skipped, got: {output}"
+    );
+    assert!(
+        !output.contains("vendor/lib.py"),
+        "expected vendored file to be skipped, got: {output}"
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.arg("--include-generated").current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.contains("dist/bundle.py") && output.contains("vendor/lib.py"),
+        "expected generated files with --include-generated, got: {output}"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_uses_repo_root_for_frecency() {
+    let dir = temp_dir_path("cruxlines-frecency");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+
+    std::fs::write(dir.join("defs_a.py"), "def alpha():\n    return 1\n").expect("write defs_a");
+    std::fs::write(dir.join("defs_b.py"), "def beta():\n    return 1\n").expect("write defs_b");
+    let alpha_calls = "    alpha()\n".repeat(50);
+    let beta_calls = "    beta()\n".repeat(50);
+    std::fs::write(
+        dir.join("use_alpha.py"),
+        format!(
+            "from defs_a import alpha\nfrom main import anchor\n\n\
+def helper_alpha():\n{alpha_calls}    anchor()\n"
+        ),
+    )
+    .expect("write use_alpha");
+    std::fs::write(
+        dir.join("use_beta.py"),
+        format!(
+            "from defs_b import beta\nfrom main import anchor\n\n\
+def helper_beta():\n{beta_calls}    anchor()\n"
+        ),
+    )
+    .expect("write use_beta");
+    std::fs::write(
+        dir.join("main.py"),
+        "from use_alpha import helper_alpha\nfrom use_beta import helper_beta\n\n\
+def anchor():\n    return None\n\n\
+helper_alpha()\nhelper_beta()\n",
+    )
+    .expect("write main");
+
+    git_commit(&dir, "initial", "2001-01-01T00:00:00Z");
+
+    for day in 2..=11 {
+        std::fs::write(
+            dir.join("use_alpha.py"),
+            format!(
+                "from defs_a import alpha\nfrom main import anchor\n\n\
+def helper_alpha():\n{alpha_calls}    anchor()\n\n# touch {day}\n"
+            ),
+        )
+        .expect("update use_alpha");
+        let date = format!("2001-01-{day:02}T00:00:00Z");
+        git_commit(&dir, "touch alpha", &date);
+    }
+
+    let subdir = dir.join("sub");
+    std::fs::create_dir_all(&subdir).expect("create subdir");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "py", "--metadata"])
+        .current_dir(&subdir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+
+    let alpha_score = local_score_for_symbol(&output, "alpha").expect("alpha score");
+    let beta_score = local_score_for_symbol(&output, "beta").expect("beta score");
+    assert!(
+        alpha_score > beta_score,
+        "expected alpha to score higher due to frecency, got alpha={alpha_score} beta={beta_score}\n{output}"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn repo_root() -> std::path::PathBuf {
+    std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn temp_dir_path(name: &str) -> std::path::PathBuf {
+    let mut path = std::env::temp_dir();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("time")
+        .as_nanos();
+    path.push(format!("{name}-{nanos}"));
+    path
+}
+
+fn local_score_for_symbol(output: &str, symbol: &str) -> Option<f64> {
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        let name = name_from_line(line)?;
+        if name == symbol {
+            return metric_from_line(line, "local=");
+        }
+    }
+    None
+}
+
+fn message_from_line(line: &str) -> Option<&str> {
+    line.splitn(4, ':').nth(3)
+}
+
+fn metric_from_line(line: &str, key: &str) -> Option<f64> {
+    let message = message_from_line(line)?;
+    for part in message.split_whitespace() {
+        if let Some(value) = part.strip_prefix(key) {
+            return value.parse().ok();
+        }
+    }
+    None
+}
+
+fn name_from_line(line: &str) -> Option<&str> {
+    let message = message_from_line(line)?;
+    for part in message.split_whitespace() {
+        if let Some(value) = part.strip_prefix("name=") {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn git_init(dir: &std::path::Path) {
+    let status = git_command(dir).arg("init").status().expect("git init");
+    assert!(status.success(), "git init failed");
+    let status = git_command(dir)
+        .args(["config", "user.name", "Test User"])
+        .status()
+        .expect("git config user.name");
+    assert!(status.success(), "git config user.name failed");
+    let status = git_command(dir)
+        .args(["config", "user.email", "test@example.com"])
+        .status()
+        .expect("git config user.email");
+    assert!(status.success(), "git config user.email failed");
+}
+
+fn git_commit(dir: &std::path::Path, message: &str, date: &str) {
+    let status = git_command(dir)
+        .arg("add")
+        .arg(".")
+        .status()
+        .expect("git add");
+    assert!(status.success(), "git add failed");
+    let status = git_command(dir)
+        .args(["-c", "commit.gpgsign=false", "commit", "-m", message])
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .env("GIT_AUTHOR_NAME", "Test User")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test User")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .status()
+        .expect("git commit");
+    assert!(status.success(), "git commit failed");
+}
+
+fn git_command(dir: &std::path::Path) -> std::process::Command {
+    let mut cmd = std::process::Command::new("git");
+    cmd.arg("-C").arg(dir);
+    cmd.env("GIT_CONFIG_GLOBAL", "/dev/null");
+    cmd.env("GIT_CONFIG_SYSTEM", "/dev/null");
+    cmd.env("GIT_TERMINAL_PROMPT", "0");
+    cmd
+}
+
+#[test]
+fn cli_low_memory_mode_prints_the_same_ranking() {
+    let dir = temp_dir_path("cruxlines-low-memory");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("models.py"),
+        "def load():\n    pass\n\ndef save():\n    pass\n",
+    )
+    .expect("write models");
+    std::fs::write(
+        dir.join("main.py"),
+        "from models import load, save\n\nload()\nload()\nsave()\n",
+    )
+    .expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let run = |extra: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--metadata", "--no-cache"])
+            .args(extra)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let full = run(&[]);
+    assert_eq!(full.lines().count(), 2, "got: {full}");
+    assert_eq!(run(&["--low-memory"]), full);
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--low-memory", "--show-references"])
+        .current_dir(&dir);
+    cmd.assert().failure();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_reports_custom_language_errors() {
+    let dir = temp_dir_path("cruxlines-custom-language");
+    std::fs::create_dir_all(dir.join("queries")).expect("create queries dir");
+    git_init(&dir);
+    std::fs::write(dir.join("queries/definitions.scm"), "").expect("write definitions");
+    std::fs::write(dir.join("queries/references.scm"), "").expect("write references");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--language-lib",
+        "libtree-sitter-zig.so",
+        "--lang-ext",
+        "zig",
+    ])
+    .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("each --language-lib needs one --lang-ext"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--language-lib",
+        "libtree-sitter-zig.so",
+        "--lang-ext",
+        "zig",
+        "--lang-queries",
+        "queries",
+    ])
+    .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("failed to load language zig"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_renders_progress_on_stderr() {
+    let dir = temp_dir_path("cruxlines-progress");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--progress", "--no-cache"]).current_dir(&dir);
+    let output = cmd.assert().success().get_output().clone();
+    let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+    assert!(
+        stderr.contains("parse [##############################] 2/2"),
+        "expected a finished parse bar, got: {stderr:?}"
+    );
+    assert!(
+        stderr.ends_with("\r\x1b[2K"),
+        "expected the bar to be cleared, got: {stderr:?}"
+    );
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(stdout.contains("defs.py:1:5:"), "got: {stdout}");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.arg("--no-cache").current_dir(&dir);
+    cmd.assert().success().stderr("");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_explains_a_definition_score() {
+    let dir = temp_dir_path("cruxlines-explain");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--metadata", "--no-frecency"]).current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let rank = metric_from_line(output.lines().next().expect("row"), "rank=").expect("rank");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["explain", "defs.py:1", "--no-frecency"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[0], "defs.py:1:5: add kind=function", "got: {output}");
+    assert_eq!(lines[1], "references (2):", "got: {output}");
+    assert!(
+        lines[2].starts_with("  main.py:1:18: file_rank="),
+        "got: {output}"
+    );
+    assert!(
+        lines
+            .last()
+            .is_some_and(|line| line.ends_with(&format!(" = {rank:.6}"))),
+        "expected the final rank {rank:.6}, got: {output}"
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["explain", "defs.py:2"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("no referenced definition at defs.py:2"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_changed_since_only_prints_touched_files() {
+    let dir = temp_dir_path("cruxlines-changed-since");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("core.py"), "def core():\n    return 1\n").expect("write core");
+    std::fs::write(dir.join("extra.py"), "def extra():\n    return 2\n").expect("write extra");
+    std::fs::write(
+        dir.join("main.py"),
+        "from core import core\nfrom extra import extra\n\ncore()\ncore()\nextra()\n",
+    )
+    .expect("write main");
+    git_commit(&dir, "base", "2001-01-01T00:00:00Z");
+    std::fs::write(dir.join("extra.py"), "def extra():\n    return 3\n").expect("rewrite extra");
+    git_commit(&dir, "touch extra", "2001-01-02T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--changed-since", "HEAD~1", "-e", "py", "--top", "1"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1, "expected one row, got: {output}");
+    assert!(
+        lines[0].starts_with("extra.py:1:5: "),
+        "expected only the changed file, got: {output}"
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--changed-since", "no-such-rev"])
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("git failed for revision no-such-rev"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_scope_keeps_references_from_outside_it() {
+    let dir = temp_dir_path("cruxlines-scope");
+    std::fs::create_dir_all(dir.join("lib")).expect("create lib dir");
+    std::fs::create_dir_all(dir.join("app")).expect("create app dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("lib/defs.py"),
+        "def shared():\n    return 1\n\ndef local():\n    return 2\n",
+    )
+    .expect("write defs");
+    std::fs::write(
+        dir.join("lib/uses.py"),
+        "from defs import local\n\nlocal()\n",
+    )
+    .expect("write lib uses");
+    std::fs::write(
+        dir.join("app/main.py"),
+        "from defs import shared\n\nshared()\nshared()\nshared()\n",
+    )
+    .expect("write app main");
+
+    let rank_of = |output: &str, name: &str| {
+        output
+            .lines()
+            .find(|line| line.contains(&format!("def {name}(")))
+            .and_then(|line| metric_from_line(line, "rank="))
+    };
+    let run = |args: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(args)
+            .args(["-e", "py", "--metadata", "--no-frecency", "--no-cache"])
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let full = run(&[]);
+    let scoped = run(&["--scope", "lib"]);
+    assert!(
+        scoped.lines().all(|line| line.starts_with("lib/")),
+        "expected only lib definitions, got: {scoped}"
+    );
+    assert_eq!(rank_of(&scoped, "shared"), rank_of(&full, "shared"));
+    assert!(rank_of(&scoped, "shared") > rank_of(&scoped, "local"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--scope", "missing"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("path not found: missing"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_groups_rows_by_codeowners_owner() {
+    let dir = temp_dir_path("cruxlines-codeowners");
+    std::fs::create_dir_all(dir.join(".github")).expect("create .github");
+    std::fs::create_dir_all(dir.join("core")).expect("create core");
+    git_init(&dir);
+    std::fs::write(
+        dir.join(".github/CODEOWNERS"),
+        "# owners\n* @org/everyone\n/core/ @org/core @org/leads\nscratch.py\n",
+    )
+    .expect("write codeowners");
+    std::fs::write(dir.join("core/defs.py"), "def engine():\n    return 1\n")
+        .expect("write core defs");
+    std::fs::write(dir.join("util.py"), "def helper():\n    return 2\n").expect("write util");
+    std::fs::write(dir.join("scratch.py"), "def scratch():\n    return 3\n")
+        .expect("write scratch");
+    std::fs::write(
+        dir.join("main.py"),
+        "from defs import engine\nfrom util import helper\nfrom scratch import scratch\n\n\
+         engine()\nengine()\nengine()\nhelper()\nscratch()\n",
+    )
+    .expect("write main");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--group-by",
+        "owner",
+        "-e",
+        "py",
+        "--no-frecency",
+        "--no-cache",
+    ])
+    .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let owners: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .collect();
+    assert_eq!(
+        owners,
+        vec![
+            "owner=@org/core",
+            "owner=@org/leads",
+            "owner=-",
+            "owner=@org/everyone"
+        ],
+        "unexpected owners: {output}"
+    );
+    assert!(
+        output.lines().next().is_some_and(|line| {
+            line.starts_with("core/defs.py:1:5: owner=@org/core ") && line.ends_with(" top=engine")
+        }),
+        "expected engine to lead, got: {output}"
+    );
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_daemon_answers_queries_and_picks_up_edits() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let dir = temp_dir_path("cruxlines-daemon");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("defs.py"),
+        "def add():\n    return 1\n\ndef sub():\n    return 2\n",
+    )
+    .expect("write defs");
+    std::fs::write(
+        dir.join("main.py"),
+        "from defs import add, sub\n\nadd()\nadd()\nsub()\n",
+    )
+    .expect("write main");
+
+    let socket = dir.join("cruxlines.sock");
+    let exe = assert_cmd::cargo::cargo_bin!("cruxlines");
+    let mut child = std::process::Command::new(exe)
+        .args(["--no-cache", "daemon", "-e", "py", "--socket"])
+        .arg(&socket)
+        .current_dir(&dir)
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn daemon");
+
+    let start = std::time::Instant::now();
+    let stream = loop {
+        if let Ok(stream) = UnixStream::connect(&socket) {
+            break stream;
+        }
+        if start.elapsed() > std::time::Duration::from_secs(10) {
+            let _ = child.kill();
+            panic!("timeout waiting for the daemon socket");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+    let mut writer = stream.try_clone().expect("clone stream");
+    let mut reader = BufReader::new(stream);
+    let mut call = |request: &str| {
+        writeln!(writer, "{request}").expect("send request");
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("read response");
+        serde_json::from_str::<serde_json::Value>(&line).expect("json response")
+    };
+
+    let top = call(r#"{"jsonrpc":"2.0","id":1,"method":"top","params":{"n":1}}"#);
+    assert_eq!(top["id"], 1);
+    assert_eq!(top["result"]["schema_version"], 1);
+    assert_eq!(top["result"]["rows"].as_array().map(Vec::len), Some(1));
+    assert_eq!(top["result"]["rows"][0]["name"], "add");
+    assert_eq!(top["result"]["rows"][0]["path"], "defs.py");
+
+    let explain =
+        call(r#"{"jsonrpc":"2.0","id":2,"method":"explain","params":{"path":"defs.py","line":4}}"#);
+    assert_eq!(explain["result"]["schema_version"], 1);
+    assert_eq!(explain["result"]["explanations"][0]["row"]["name"], "sub");
+    assert_eq!(
+        explain["result"]["explanations"][0]["references"]
+            .as_array()
+            .map(Vec::len),
+        Some(2)
+    );
+
+    let impact =
+        call(r#"{"jsonrpc":"2.0","id":3,"method":"impact","params":{"paths":["defs.py"]}}"#);
+    assert_eq!(impact["result"]["schema_version"], 1);
+    assert!(
+        impact["result"]["impacted"].is_array(),
+        "unexpected impact: {impact}"
+    );
+
+    let unknown = call(r#"{"jsonrpc":"2.0","id":4,"method":"nope"}"#);
+    assert_eq!(unknown["error"]["code"], -32601);
+
+    std::fs::write(
+        dir.join("main.py"),
+        "from defs import add, sub\n\nadd()\nsub()\nsub()\nsub()\n",
+    )
+    .expect("rewrite main");
+    let start = std::time::Instant::now();
+    loop {
+        let top = call(r#"{"jsonrpc":"2.0","id":5,"method":"top","params":{"n":1}}"#);
+        if top["result"]["rows"][0]["name"] == "sub" {
+            break;
+        }
+        if start.elapsed() > std::time::Duration::from_secs(10) {
+            let _ = child.kill();
+            panic!("daemon never picked up the edit: {top}");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    // Explanations come from the same scan as the rows.
+    let explain =
+        call(r#"{"jsonrpc":"2.0","id":7,"method":"explain","params":{"path":"defs.py","line":4}}"#);
+    assert_eq!(
+        explain["result"]["explanations"][0]["references"]
+            .as_array()
+            .map(Vec::len),
+        Some(4)
+    );
+
+    let shutdown = call(r#"{"jsonrpc":"2.0","id":6,"method":"shutdown"}"#);
+    assert_eq!(shutdown["result"], serde_json::Value::Null);
+    assert!(child.wait().expect("wait daemon").success());
+    assert!(!socket.exists(), "expected the socket to be removed");
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_stats_reports_parse_status_counts() {
+    let dir = temp_dir_path("cruxlines-stats");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    std::fs::write(dir.join("broken.py"), "def half(:\n    return [\n").expect("write broken");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--stats", "-e", "py", "--no-cache"])
+        .current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stderr(contains(
+            "cruxlines: parsed 3 files: 2 ok, 1 partial, 0 failed",
+        ))
+        .stderr(contains("cruxlines: partial: broken.py has syntax errors"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["-e", "py", "--no-cache"]).current_dir(&dir);
+    cmd.assert().success().stderr(contains("parsed").not());
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_stats_only_prints_scan_counts_and_timings() {
+    let dir = temp_dir_path("cruxlines-stats-only");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "add()\nadd()\nmissing()\n").expect("write main");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--stats-only", "-e", "py", "--no-cache"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "parsed 2 files: 2 ok, 0 partial, 0 failed");
+    assert_eq!(lines[1], "files: python=2");
+    assert_eq!(
+        lines[2],
+        "definitions=1 reference_edges=2 unresolved_references=1"
+    );
+    assert!(lines[3].starts_with("time: scan="), "{stdout}");
+    assert!(lines[3].contains(" parse=") && lines[3].contains(" rank="));
+    assert!(!stdout.contains("defs.py:1"), "{stdout}");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--stats", "-e", "py", "--no-cache"])
+        .current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stdout(contains("defs.py:1:5"))
+        .stderr(contains("cruxlines: files: python=2"))
+        .stderr(contains("cruxlines: time: scan="));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_rev_reads_files_from_a_bare_repository() {
+    let dir = temp_dir_path("cruxlines-rev");
+    let work = dir.join("work");
+    let bare = dir.join("bare.git");
+    std::fs::create_dir_all(&work).expect("create temp dir");
+    git_init(&work);
+    std::fs::write(work.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(work.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    git_commit(&work, "add", "2024-01-01T00:00:00Z");
+    std::fs::write(
+        work.join("defs.py"),
+        "def add():\n    return 1\n\ndef sub():\n    return 2\n",
+    )
+    .expect("write defs");
+    std::fs::write(
+        work.join("main.py"),
+        "from defs import add, sub\n\nadd()\nsub()\n",
+    )
+    .expect("write main");
+    git_commit(&work, "sub", "2024-01-02T00:00:00Z");
+    let status = git_command(&dir)
+        .args(["clone", "--quiet", "--bare"])
+        .arg(&work)
+        .arg(&bare)
+        .status()
+        .expect("git clone");
+    assert!(status.success(), "git clone failed");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--rev", "HEAD~1", "-e", "py", "--no-cache"])
+        .current_dir(&bare);
+    cmd.assert()
+        .success()
+        .stdout(contains("defs.py:1:5: def add():"))
+        .stdout(contains("sub").not());
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--rev", "HEAD", "-e", "py", "--no-cache"])
+        .current_dir(&bare);
+    cmd.assert()
+        .success()
+        .stdout(contains("defs.py:4:5: def sub():"));
+
+    // The working tree is ignored: an uncommitted edit doesn't show up.
+    std::fs::write(work.join("defs.py"), "def mul():\n    return 3\n").expect("write defs");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--rev", "HEAD", "-e", "py", "--no-cache"])
+        .current_dir(&work);
+    cmd.assert()
+        .success()
+        .stdout(contains("def sub():"))
+        .stdout(contains("mul").not());
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_history_prints_rank_at_sampled_commits() {
+    let dir = temp_dir_path("cruxlines-history");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("other.py"), "def other():\n    return 0\n").expect("write other");
+    git_commit(&dir, "other", "2024-01-01T00:00:00Z");
+    std::fs::write(dir.join("core.py"), "def load():\n    return 1\n").expect("write core");
+    std::fs::write(dir.join("a.py"), "from core import load\n\nload()\n").expect("write a");
+    git_commit(&dir, "core", "2024-03-01T00:00:00Z");
+    std::fs::write(dir.join("b.py"), "from core import load\n\nload()\n").expect("write b");
+    std::fs::write(dir.join("c.py"), "from core import load\n\nload()\n").expect("write c");
+    git_commit(&dir, "callers", "2024-05-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--no-frecency",
+        "--no-cache",
+        "history",
+        "--every",
+        "30d",
+        "--last",
+        "6m",
+        "core.py:load",
+    ])
+    .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<Vec<&str>> = stdout
+        .lines()
+        .map(|line| line.split(' ').collect())
+        .collect();
+    let dates: Vec<&str> = lines.iter().map(|fields| fields[0]).collect();
+    assert_eq!(
+        dates,
+        vec!["2024-01-01", "2024-03-01", "2024-05-01"],
+        "{stdout}"
+    );
+    assert_eq!(lines[0][2], "-");
+    let ranks: Vec<f64> = lines[1..]
+        .iter()
+        .map(|fields| fields[2].parse().expect("rank"))
+        .collect();
+    assert!(ranks[1] > ranks[0], "{stdout}");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["history", "--every", "3x", "core.py:load"])
+        .current_dir(&dir);
+    cmd.assert().failure().stderr(contains("unknown unit"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["history", "--last", "99999999999999999y", "core.py:load"])
+        .current_dir(&dir);
+    cmd.assert().failure().stderr(contains("is too long"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_from_keeps_definitions_reachable_from_the_root() {
+    let dir = temp_dir_path("cruxlines-from");
+    std::fs::create_dir_all(dir.join("services/payment")).expect("create payment dir");
+    std::fs::create_dir_all(dir.join("services/search")).expect("create search dir");
+    std::fs::create_dir_all(dir.join("shared")).expect("create shared dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("services/payment/charge.rs"),
+        "pub fn charge() {\n    validate();\n}\n",
+    )
+    .expect("write charge");
+    std::fs::write(
+        dir.join("services/payment/api.rs"),
+        "fn handle() {\n    charge();\n}\n",
+    )
+    .expect("write api");
+    std::fs::write(
+        dir.join("services/search/query.rs"),
+        "pub fn query() {\n    tokenize();\n}\n",
+    )
+    .expect("write query");
+    std::fs::write(
+        dir.join("services/search/index.rs"),
+        "fn build() {\n    query();\n}\n",
+    )
+    .expect("write index");
+    std::fs::write(
+        dir.join("shared/text.rs"),
+        "pub fn validate() {}\npub fn tokenize() {}\n",
+    )
+    .expect("write text");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-frecency", "--no-cache", "--from", "services/payment"])
+        .current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stdout(contains("pub fn charge"))
+        .stdout(contains("pub fn validate"))
+        .stdout(contains("pub fn tokenize").not())
+        .stdout(contains("pub fn query").not());
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--no-frecency",
+        "--no-cache",
+        "--from",
+        "services/search/query.rs:query",
+    ])
+    .current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stdout(contains("pub fn query"))
+        .stdout(contains("pub fn tokenize"))
+        .stdout(contains("pub fn validate").not());
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--from", "services/billing/"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("path not found: services/billing/"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_sample_is_a_seeded_subset_in_rank_order() {
+    let sample = |seed: &str| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--ecosystem", "python", "--sample", "3", "--seed", seed])
+            .current_dir(repo_root());
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let full = run_cli_output();
+    let first = sample("7");
+    assert_eq!(first, sample("7"), "expected the same seed to repeat");
+    let rows: Vec<&str> = first.lines().collect();
+    assert_eq!(rows.len(), 3);
+    let positions: Vec<usize> = rows
+        .iter()
+        .map(|row| {
+            full.lines()
+                .position(|line| line == *row)
+                .expect("sampled row in full output")
+        })
+        .collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--seed", "7"])
+        .current_dir(repo_root());
+    cmd.assert().failure();
+}
+
+#[test]
+fn cli_min_refs_drops_rarely_referenced_definitions() {
+    let refs = |line: &str| metric_from_line(line, "refs=").expect("refs");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--metadata"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let full = String::from_utf8(output).expect("utf8 output");
+    let expected: Vec<&str> = full.lines().filter(|line| refs(line) >= 2.0).collect();
+    assert!(!expected.is_empty(), "expected widely used definitions");
+    assert!(
+        expected.len() < full.lines().count(),
+        "expected some definitions with a single reference"
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--metadata", "--min-refs", "2"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn cli_github_annotations_flag_changed_crux_definitions() {
+    let dir = temp_dir_path("cruxlines-github-annotations");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("core.py"), "def core():\n    return 1\n").expect("write core");
+    std::fs::write(dir.join("extra.py"), "def extra():\n    return 2\n").expect("write extra");
+    std::fs::write(
+        dir.join("main.py"),
+        "from core import core\nfrom extra import extra\n\ncore()\ncore()\nextra()\n",
+    )
+    .expect("write main");
+    git_commit(&dir, "base", "2001-01-01T00:00:00Z");
+    std::fs::write(dir.join("core.py"), "def core():\n    return 3\n").expect("rewrite core");
+    git_commit(&dir, "touch core", "2001-01-02T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--changed-since",
+        "HEAD~1",
+        "-e",
+        "py",
+        "--no-frecency",
+        "--format",
+        "github-annotations",
+    ])
+    .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1, "got: {output}");
+    assert!(
+        lines[0].starts_with(
+            "::notice file=core.py,line=1,col=5,title=Crux line::`core` is a crux definition (rank "
+        ),
+        "got: {output}"
+    );
+    assert!(lines[0].ends_with(", 4 references)"), "got: {output}");
+    assert!(!lines[0].contains("rank 0.000000"), "got: {output}");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_buckets_label_ranks_relative_to_the_top_row() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--metadata", "--buckets", "labels"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = output.lines().collect();
+    let top = metric_from_line(lines[0], "rank=").expect("rank");
+    assert!(lines[0].contains(" bucket=critical "), "got: {}", lines[0]);
+    for line in &lines {
+        let rank = metric_from_line(line, "rank=").expect("rank");
+        let expected = match rank / top {
+            share if share >= 0.5 => "critical",
+            share if share >= 0.2 => "high",
+            share if share >= 0.05 => "medium",
+            _ => "low",
+        };
+        assert!(
+            line.contains(&format!(" bucket={expected} ")),
+            "expected {expected}: {line}"
+        );
+    }
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--ecosystem",
+        "python",
+        "--format",
+        "tsv",
+        "--buckets",
+        "stars",
+    ])
+    .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let mut lines = output.lines();
+    assert!(
+        lines
+            .next()
+            .is_some_and(|header| header.ends_with("\trefs\tbucket"))
+    );
+    assert!(
+        lines.next().is_some_and(|row| row.ends_with("\t★★★★★")),
+        "got: {output}"
+    );
+}
+
+#[test]
+fn cli_scan_flags_control_ignore_files_and_hidden_directories() {
+    let dir = temp_dir_path("cruxlines-scan-flags");
+    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
+    std::fs::create_dir_all(dir.join("lib/gen")).expect("create gen dir");
+    std::fs::create_dir_all(dir.join(".tools")).expect("create tools dir");
+    std::fs::write(dir.join("lib/.gitignore"), "gen/\n").expect("write nested gitignore");
+    std::fs::write(
+        dir.join("lib/gen/schema.py"),
+        "def schema():\n    return 0\n",
+    )
+    .expect("write schema");
+    std::fs::write(dir.join(".tools/lint.py"), "def lint():\n    return 0\n").expect("write lint");
+    std::fs::write(
+        dir.join("main.py"),
+        "from schema import schema\nfrom lint import lint\n\nschema()\nlint()\n",
+    )
+    .expect("write main");
+
+    let run = |flags: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--no-cache", "--no-frecency"])
+            .args(flags)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let output = run(&[]);
+    assert!(!output.contains("def schema"), "got: {output}");
+    assert!(!output.contains("def lint"), "got: {output}");
+    let output = run(&["--no-ignore"]);
+    assert!(output.contains("def schema"), "got: {output}");
+    assert!(!output.contains("def lint"), "got: {output}");
+    let output = run(&["--hidden"]);
+    assert!(!output.contains("def schema"), "got: {output}");
+    assert!(output.contains("def lint"), "got: {output}");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_follow_visits_linked_directories_once() {
+    use std::os::unix::fs::symlink;
+
+    let dir = temp_dir_path("cruxlines-symlink-cycle");
+    std::fs::create_dir_all(dir.join(".git")).expect("create git dir");
+    std::fs::create_dir_all(dir.join("lib/nested")).expect("create lib dir");
+    std::fs::write(dir.join("lib/util.py"), "def util():\n    return 0\n").expect("write util");
+    std::fs::write(dir.join("main.py"), "from util import util\n\nutil()\n").expect("write main");
+    // A cycle back to the root, a cycle between two directories, and a
+    // second path to the same tree.
+    symlink("../..", dir.join("lib/nested/root")).expect("link root");
+    symlink("../lib", dir.join("lib/nested/lib")).expect("link lib");
+    symlink("lib", dir.join("mirror")).expect("link mirror");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache", "--no-frecency", "--follow"])
+        .current_dir(&dir)
+        .timeout(std::time::Duration::from_secs(30));
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(output.matches("def util").count(), 1, "got: {output}");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_max_filesize_and_max_files_skip_files_and_report_them() {
+    let dir = temp_dir_path("cruxlines-limits");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("a.py"), "def alpha():\n    return 1\n").expect("write a");
+    std::fs::write(dir.join("b.py"), "def beta():\n    return 2\n").expect("write b");
+    std::fs::write(dir.join("c.py"), "alpha()\nbeta()\n").expect("write c");
+    let bundle = format!("def bundle():\n    return 3\n{}", "# padding\n".repeat(200));
+    std::fs::write(dir.join("bundle.py"), bundle).expect("write bundle");
+
+    let stats = |flags: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--stats-only", "-e", "py", "--no-cache"])
+            .args(flags)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let output = stats(&[]);
+    assert!(output.contains("files: python=4"), "{output}");
+    assert!(!output.contains("skipped:"), "{output}");
+
+    let output = stats(&["--max-filesize", "1K"]);
+    assert!(output.contains("files: python=3"), "{output}");
+    assert!(
+        output.contains("skipped: 1 over --max-filesize, 0 over --max-files"),
+        "{output}"
+    );
+
+    let output = stats(&["--max-files", "2"]);
+    assert!(output.contains("files: python=2"), "{output}");
+    assert!(
+        output.contains("skipped: 0 over --max-filesize, 2 over --max-files"),
+        "{output}"
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--max-filesize", "2X"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("unknown unit \"X\""));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_output_does_not_depend_on_thread_count() {
+    let run = |threads: Option<&str>, env: Option<&str>| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args([
+            "--ecosystem",
+            "python",
+            "--metadata",
+            "--no-cache",
+            "--no-frecency",
+            "--symbol-rank",
+        ])
+        .current_dir(repo_root());
+        if let Some(threads) = threads {
+            cmd.args(["--threads", threads]);
+        }
+        if let Some(env) = env {
+            cmd.env("CRUXLINES_THREADS", env);
+        }
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let single = run(Some("1"), None);
+    assert!(!single.trim().is_empty());
+    assert_eq!(single, run(Some("4"), None));
+    assert_eq!(single, run(None, Some("3")));
+    assert_eq!(single, run(Some("2"), Some("nonsense")));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python"])
+        .env("CRUXLINES_THREADS", "many")
+        .current_dir(repo_root());
+    cmd.assert()
+        .failure()
+        .stderr(contains("CRUXLINES_THREADS must be a number"));
+}
+
+#[test]
+fn cli_centrality_selects_the_file_rank_measure() {
+    let dir = temp_dir_path("cruxlines-centrality");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    // app.py -> mid.py -> base.py: mid.py is the only bridge, while base.py
+    // is the sink PageRank favors.
+    std::fs::write(dir.join("base.py"), "def base_helper():\n    pass\n").expect("write base");
+    std::fs::write(
+        dir.join("mid.py"),
+        "from base import base_helper\n\ndef mid_helper():\n    base_helper()\n",
+    )
+    .expect("write mid");
+    std::fs::write(
+        dir.join("app.py"),
+        "from mid import mid_helper\n\ndef run():\n    mid_helper()\n",
+    )
+    .expect("write app");
+
+    let file_rank = |centrality: &str, symbol: &str| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args([
+            "--metadata",
+            "--no-cache",
+            "--no-frecency",
+            "--centrality",
+            centrality,
+        ])
+        .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let output = String::from_utf8(output).expect("utf8 output");
+        let line = output
+            .lines()
+            .find(|line| line.contains(&format!("name={symbol} ")))
+            .unwrap_or_else(|| panic!("{symbol} row in {output}"));
+        metric_from_line(line, "file=").expect("file rank")
+    };
+
+    assert!(file_rank("pagerank", "base_helper") > file_rank("pagerank", "mid_helper"));
+    assert_eq!(file_rank("betweenness", "base_helper"), 0.0);
+    assert_eq!(file_rank("betweenness", "mid_helper"), 1.0);
+    assert_eq!(
+        file_rank("in-degree", "base_helper"),
+        file_rank("in-degree", "mid_helper")
+    );
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_personalize_ranks_files_relative_to_the_anchors() {
+    let dir = temp_dir_path("cruxlines-personalize");
+    std::fs::create_dir_all(dir.join("billing")).expect("create billing");
+    std::fs::create_dir_all(dir.join("search")).expect("create search");
+    git_init(&dir);
+    for area in ["billing", "search"] {
+        std::fs::write(
+            dir.join(area).join("core.py"),
+            format!("def {area}_core():\n    pass\n"),
+        )
+        .expect("write core");
+        std::fs::write(
+            dir.join(area).join("app.py"),
+            format!("from core import {area}_core\n\ndef {area}_app():\n    {area}_core()\n"),
+        )
+        .expect("write app");
+    }
+
+    let file_rank = |args: &[&str], symbol: &str| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--metadata", "--no-frecency"])
+            .args(args)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let output = String::from_utf8(output).expect("utf8 output");
+        output
+            .lines()
+            .find(|line| line.contains(&format!("name={symbol} ")))
+            .and_then(|line| metric_from_line(line, "file="))
+            .unwrap_or(0.0)
+    };
+
+    assert_eq!(
+        file_rank(&[], "billing_core"),
+        file_rank(&[], "search_core")
+    );
+    let anchored = ["--personalize", "billing/app.py"];
+    assert!(file_rank(&anchored, "billing_core") > file_rank(&anchored, "search_core"));
+    assert!(file_rank(&anchored, "billing_core") > file_rank(&[], "billing_core"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--personalize", "missing.py"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("path not found: missing.py"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_frecency_file_scores_referencing_files() {
+    let dir = temp_dir_path("cruxlines-frecency-file");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("lib.py"), "def helper():\n    pass\n").expect("write lib");
+    std::fs::write(dir.join("a.py"), "from lib import helper\n\nhelper()\n").expect("write a");
+    std::fs::write(dir.join("b.py"), "from lib import helper\n\nhelper()\n").expect("write b");
+    std::fs::write(dir.join("frecency.json"), r#"{"a.py": 4}"#).expect("write scores");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "explain",
+        "lib.py:1",
+        "--no-frecency",
+        "--frecency-file",
+        "frecency.json",
+    ])
+    .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    let frecency = |file: &str| {
+        output
+            .lines()
+            .find(|line| line.trim_start().starts_with(file))
+            .and_then(|line| {
+                line.split_whitespace()
+                    .find_map(|part| part.strip_prefix("frecency="))
+            })
+            .unwrap_or_else(|| panic!("{file} reference in {output}"))
+            .to_string()
+    };
+    assert_eq!(frecency("a.py"), "4.000000");
+    assert_eq!(frecency("b.py"), "1.000000");
+
+    std::fs::write(dir.join("broken.json"), r#"{"a.py": "often"}"#).expect("write broken");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--frecency-file", "broken.json"])
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid frecency file"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--frecency-blend", "0.5"]).current_dir(&dir);
+    cmd.assert().failure();
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_author_churn_boosts_widely_co_edited_files() {
+    let dir = temp_dir_path("cruxlines-author-churn");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("solo.py"), "def solo_fn():\n    pass\n").expect("write solo");
+    std::fs::write(
+        dir.join("app.py"),
+        "from shared import shared_fn\nfrom solo import solo_fn\n\nshared_fn()\nsolo_fn()\n",
+    )
+    .expect("write app");
+    git_commit(&dir, "initial", "2024-01-01T00:00:00Z");
+    for (index, author) in ["ana", "bo", "cy", "di"].iter().enumerate() {
+        std::fs::write(
+            dir.join("shared.py"),
+            format!("def shared_fn():\n    return {index}\n"),
+        )
+        .expect("write shared");
+        let status = git_command(&dir)
+            .args(["add", "."])
+            .status()
+            .expect("git add");
+        assert!(status.success(), "git add failed");
+        let status = git_command(&dir)
+            .args(["-c", "commit.gpgsign=false", "commit", "-m", author])
+            .env("GIT_AUTHOR_NAME", author)
+            .env("GIT_AUTHOR_EMAIL", format!("{author}@example.com"))
+            .status()
+            .expect("git commit");
+        assert!(status.success(), "git commit failed");
+    }
+
+    let rank = |args: &[&str], symbol: &str| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--metadata", "--no-cache", "--no-frecency"])
+            .args(args)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let output = String::from_utf8(output).expect("utf8 output");
+        output
+            .lines()
+            .find(|line| line.contains(&format!("name={symbol} ")))
+            .and_then(|line| metric_from_line(line, "rank="))
+            .unwrap_or_else(|| panic!("{symbol} row in {output}"))
+    };
+    assert_eq!(rank(&[], "shared_fn"), rank(&[], "solo_fn"));
+    let churn = ["--author-churn"];
+    assert!(rank(&churn, "shared_fn") > rank(&churn, "solo_fn"));
+    assert_eq!(rank(&churn, "solo_fn"), rank(&[], "solo_fn"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["explain", "shared.py:1", "--no-frecency", "--author-churn"])
+        .current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stdout(contains("* authors 2.000000"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
 
-    let alpha_score = local_score_for_symbol(&output, "alpha").expect("alpha score");
-    let beta_score = local_score_for_symbol(&output, "beta").expect("beta score");
+#[test]
+fn cli_coverage_attaches_execution_counts_and_can_weight_ranks() {
+    let dir = temp_dir_path("cruxlines-coverage");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("lib.py"),
+        "def hot():\n    return 1\n\ndef cold():\n    return 2\n",
+    )
+    .expect("write lib");
+    std::fs::write(
+        dir.join("app.py"),
+        "from lib import hot, cold\n\nhot()\ncold()\n",
+    )
+    .expect("write app");
+    std::fs::write(
+        dir.join("lcov.info"),
+        "SF:lib.py\nDA:1,1\nDA:2,40\nDA:4,1\nDA:5,0\nend_of_record\n",
+    )
+    .expect("write lcov");
+
+    let run = |args: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--metadata", "--no-frecency", "--coverage", "lcov.info"])
+            .args(args)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let row = |output: &str, symbol: &str| {
+        output
+            .lines()
+            .find(|line| line.contains(&format!("name={symbol} ")))
+            .unwrap_or_else(|| panic!("{symbol} row in {output}"))
+            .to_string()
+    };
+    let output = run(&[]);
+    // The body's count is the definition's, not the `def` line's.
+    assert!(row(&output, "hot").contains(" hits=40 "), "{output}");
+    assert!(row(&output, "cold").contains(" hits=1 "), "{output}");
+    assert_eq!(
+        metric_from_line(&row(&output, "hot"), "rank="),
+        metric_from_line(&row(&output, "cold"), "rank=")
+    );
+
+    let output = run(&["--coverage-weight", "1"]);
+    let hot = metric_from_line(&row(&output, "hot"), "rank=").expect("hot rank");
+    let cold = metric_from_line(&row(&output, "cold"), "rank=").expect("cold rank");
+    assert!(hot > cold * 10.0, "{output}");
+
+    std::fs::write(dir.join("broken.info"), "DA:1,1\n").expect("write broken");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--coverage", "broken.info"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid coverage report"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_snapshot_check_fails_when_the_top_shifts() {
+    let dir = temp_dir_path("cruxlines-snapshot");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("lib.py"),
+        "def core():\n    pass\n\ndef util():\n    pass\n\ndef rare():\n    pass\n",
+    )
+    .expect("write lib");
+    std::fs::write(
+        dir.join("app.py"),
+        "from lib import core, util, rare\n\ncore()\ncore()\ncore()\nutil()\nutil()\nrare()\n",
+    )
+    .expect("write app");
+
+    let snapshot = |args: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--no-frecency", "--no-cache", "snapshot"])
+            .args(args)
+            .current_dir(&dir);
+        cmd.assert()
+    };
+    snapshot(&["write", "--top", "2"]).success();
+    let lock = std::fs::read_to_string(dir.join("cruxlines.lock")).expect("read lock");
+    assert_eq!(
+        lock,
+        "# cruxlines snapshot: top 2 definitions, most crucial first\nlib.py\tcore\nlib.py\tutil\n"
+    );
+    snapshot(&["check"]).success().stdout("");
+
+    std::fs::write(
+        dir.join("app.py"),
+        "from lib import core, util, rare\n\ncore()\ncore()\ncore()\nutil()\nrare()\nrare()\n",
+    )
+    .expect("rewrite app");
+    snapshot(&["check"])
+        .failure()
+        .stdout("- lib.py\tutil\n+ lib.py\trare\n")
+        .stderr(contains("1 of the top 2 definitions changed (threshold 0)"));
+    snapshot(&["check", "--threshold", "1"]).success();
+
+    std::fs::write(dir.join("broken.lock"), "lib.py\tcore\n").expect("write broken");
+    snapshot(&["check", "broken.lock"])
+        .failure()
+        .stderr(contains("invalid snapshot"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
+}
+
+#[test]
+fn cli_json_rows_carry_the_schema_version_they_print() {
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--ecosystem", "python", "--format", "json", "--top", "3"])
+        .current_dir(repo_root());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let document: serde_json::Value = serde_json::from_slice(&output).expect("json output");
+    let rows = document["rows"].as_array().expect("rows array");
+    assert_eq!(rows.len(), 3);
+    let path = rows[0]["path"].as_str().expect("path");
     assert!(
-        alpha_score > beta_score,
-        "expected alpha to score higher due to frecency, got alpha={alpha_score} beta={beta_score}\n{output}"
+        path.starts_with("src/languages/python/fixtures/"),
+        "expected repo-relative path, got: {path}"
     );
 
-    let _ = std::fs::remove_dir_all(&dir);
+    // The schema does not need a repo to be printed.
+    let dir = temp_dir_path("cruxlines-print-schema");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--print-schema", "json"]).current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let schema: serde_json::Value = serde_json::from_slice(&output).expect("json schema");
+    assert_eq!(
+        schema["properties"]["schema_version"]["const"],
+        document["schema_version"]
+    );
+    for field in schema["$defs"]["row"]["required"]
+        .as_array()
+        .expect("required fields")
+    {
+        let field = field.as_str().expect("field name");
+        assert!(rows[0].get(field).is_some(), "row lacks {field}");
+    }
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn repo_root() -> std::path::PathBuf {
-    std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+#[test]
+fn cli_show_ambiguous_lists_names_defined_in_several_files() {
+    let dir = temp_dir_path("cruxlines-show-ambiguous");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("a.py"), "def load():\n    pass\n").expect("write a");
+    std::fs::write(
+        dir.join("b.py"),
+        "def helper():\n    pass\n\n\ndef load():\n    pass\n",
+    )
+    .expect("write b");
+    std::fs::write(dir.join("app.py"), "load()\nload()\nhelper()\n").expect("write app");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-frecency", "--no-cache", "--show-ambiguous"])
+        .current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stdout("a.py:1:5: load definitions=2 refs=4\n    b.py:5:5\n");
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn temp_dir_path(name: &str) -> std::path::PathBuf {
-    let mut path = std::env::temp_dir();
-    let nanos = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("time")
-        .as_nanos();
-    path.push(format!("{name}-{nanos}"));
-    path
+#[test]
+fn cli_split_by_ecosystem_ranks_each_ecosystem_on_its_own() {
+    let dir = temp_dir_path("cruxlines-split-by-ecosystem");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("a.py"),
+        "def load():\n    pass\n\ndef other():\n    pass\n",
+    )
+    .expect("write a");
+    std::fs::write(
+        dir.join("app.py"),
+        "from a import load, other\n\nload()\nload()\nother()\n",
+    )
+    .expect("write app");
+    std::fs::write(dir.join("lib.rs"), "pub fn run() {}\n").expect("write lib");
+    std::fs::write(dir.join("main.rs"), "fn main() { run(); }\n").expect("write main");
+
+    let split = |args: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--no-frecency", "--no-cache", "--split-by-ecosystem"])
+            .args(args)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    let text = split(&["--metadata", "--top", "1"]);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 5, "unexpected output: {text}");
+    assert!(lines[0].starts_with("# python: definitions=2 total="));
+    assert!(lines[1].starts_with("a.py:1:5: rank=1.000000 "));
+    assert_eq!(lines[2], "");
+    assert!(lines[3].starts_with("# rust: definitions=1 total="));
+    assert!(lines[4].starts_with("lib.rs:1:8: rank=1.000000 "));
+
+    let document: serde_json::Value =
+        serde_json::from_str(&split(&["--format", "json"])).expect("json output");
+    let python = &document["ecosystems"]["python"];
+    assert_eq!(python["definition_count"], 2);
+    let rows = python["rows"].as_array().expect("rows");
+    assert_eq!(rows[0]["rank"], 1.0);
+    assert!(rows[1]["rank"].as_f64().expect("rank") < 1.0);
+    assert_eq!(document["ecosystems"]["rust"]["rows"][0]["name"], "run");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--split-by-ecosystem", "--format", "tsv"])
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("only works with --format text or json"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn local_score_for_symbol(output: &str, symbol: &str) -> Option<f64> {
-    for line in output.lines().filter(|line| !line.trim().is_empty()) {
-        let name = name_from_line(line)?;
-        if name == symbol {
-            return metric_from_line(line, "local=");
-        }
-    }
-    None
+#[test]
+fn cli_encoding_decodes_non_utf8_files_instead_of_skipping_them() {
+    let dir = temp_dir_path("cruxlines-encoding");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("legacy.py"),
+        b"# caf\xe9\ndef greet():\n    return '\xe9'\n",
+    )
+    .expect("write legacy");
+    std::fs::write(dir.join("main.py"), "from legacy import greet\n\ngreet()\n")
+        .expect("write main");
+
+    let run = |encoding: &str| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args([
+            "--no-frecency",
+            "--no-cache",
+            "--stats",
+            "--encoding",
+            encoding,
+        ])
+        .current_dir(&dir);
+        let output = cmd.assert().success().get_output().clone();
+        (
+            String::from_utf8(output.stdout).expect("utf8 stdout"),
+            String::from_utf8(output.stderr).expect("utf8 stderr"),
+        )
+    };
+
+    let (stdout, stderr) = run("strict");
+    assert!(!stdout.contains("greet"), "unexpected output: {stdout}");
+    assert!(
+        stderr.contains("skipped: 0 over --max-filesize, 0 over --max-files, 1 not UTF-8"),
+        "unexpected stats: {stderr}"
+    );
+
+    let (stdout, stderr) = run("lossy");
+    assert!(
+        stdout.contains("legacy.py:2:5: def greet():"),
+        "unexpected output: {stdout}"
+    );
+    assert!(!stderr.contains("not UTF-8"), "unexpected stats: {stderr}");
+
+    let (stdout, _) = run("auto");
+    assert!(
+        stdout.contains("legacy.py:2:5: def greet():"),
+        "unexpected output: {stdout}"
+    );
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn message_from_line(line: &str) -> Option<&str> {
-    line.splitn(4, ':').nth(3)
+#[test]
+fn cli_diff_and_history_follow_renamed_definitions() {
+    let dir = temp_dir_path("cruxlines-renames");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    let body = "    tokens = text.split()\n    return [token.upper() for token in tokens]\n\n\ndef helper():\n    return 1\n";
+    std::fs::write(dir.join("util.py"), format!("def parse_old(text):\n{body}"))
+        .expect("write util");
+    std::fs::write(
+        dir.join("app.py"),
+        "from util import parse_old, helper\n\nparse_old(\"a\")\nparse_old(\"b\")\nhelper()\n",
+    )
+    .expect("write app");
+    git_commit(&dir, "one", "2001-01-01T00:00:00Z");
+    std::fs::remove_file(dir.join("util.py")).expect("remove util");
+    std::fs::write(dir.join("helpers.py"), format!("def parse(text):\n{body}"))
+        .expect("write helpers");
+    std::fs::write(
+        dir.join("app.py"),
+        "from helpers import parse, helper\n\nparse(\"a\")\nparse(\"b\")\nparse(\"c\")\nhelper()\n",
+    )
+    .expect("rewrite app");
+    git_commit(&dir, "two", "2001-03-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-frecency", "--no-cache", "diff", "HEAD~1", "HEAD"])
+        .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line.starts_with("helpers.py:1:5: parse ")
+                && line.ends_with(" renamed_from=util.py:parse_old")),
+        "expected parse to pair with parse_old, got: {stdout}"
+    );
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--no-frecency",
+        "--no-cache",
+        "diff",
+        "--no-renames",
+        "HEAD~1",
+        "HEAD",
+    ])
+    .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).expect("utf8 output");
+    assert!(!stdout.contains("renamed_from="), "{stdout}");
+    assert!(stdout.contains("util.py:1:5: parse_old "), "{stdout}");
+    assert!(stdout.contains("helpers.py:1:5: parse "), "{stdout}");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "--no-frecency",
+        "--no-cache",
+        "history",
+        "--every",
+        "30d",
+        "--last",
+        "90d",
+        "helpers.py:parse",
+    ])
+    .current_dir(&dir);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).expect("utf8 output");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(
+        lines.first().is_some_and(
+            |line| line.starts_with("2001-01-01 ") && line.ends_with(" as=util.py:parse_old")
+        ),
+        "expected the oldest sample to follow the rename, got: {stdout}"
+    );
+    assert!(
+        lines.last().is_some_and(|line| !line.contains(" as=")),
+        "{stdout}"
+    );
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn metric_from_line(line: &str, key: &str) -> Option<f64> {
-    let message = message_from_line(line)?;
-    for part in message.split_whitespace() {
-        if let Some(value) = part.strip_prefix(key) {
-            return value.parse().ok();
-        }
-    }
-    None
+#[test]
+fn cli_nested_configs_tune_their_own_subtree() {
+    let dir = temp_dir_path("cruxlines-nested-config");
+    std::fs::create_dir_all(dir.join("api/gen")).expect("create api dir");
+    std::fs::create_dir_all(dir.join("web")).expect("create web dir");
+    git_init(&dir);
+    std::fs::write(
+        dir.join("api/.cruxlines.toml"),
+        "[weights]\nname_ambiguity = 0\n\n[files]\nexclude = [\"gen/\"]\n",
+    )
+    .expect("write api config");
+    std::fs::write(dir.join("api/core.py"), "def load():\n    return 1\n").expect("write api");
+    std::fs::write(dir.join("api/gen/stub.py"), "def load():\n    return 2\n").expect("write stub");
+    std::fs::write(dir.join("web/core.py"), "def load():\n    return 3\n").expect("write web");
+    std::fs::write(dir.join("main.py"), "from core import load\n\nload()\n").expect("write main");
+
+    let explain = |target: &str| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["--no-frecency", "--no-cache", "explain", target])
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+    let api = explain("api/core.py:1");
+    assert!(api.contains(" / 2^0 = "), "{api}");
+    let web = explain("web/core.py:1");
+    assert!(web.contains(" / 2^1 = "), "{web}");
+
+    std::fs::write(
+        dir.join("api/.cruxlines.toml"),
+        "[buckets]\nlabels = [0.5, 0.2, 0.1]\n",
+    )
+    .expect("rewrite api config");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("[buckets] is only read from the repo root"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn name_from_line(line: &str) -> Option<&str> {
-    let message = message_from_line(line)?;
-    for part in message.split_whitespace() {
-        if let Some(value) = part.strip_prefix("name=") {
-            return Some(value);
-        }
-    }
-    None
+#[test]
+fn cli_top_needs_an_interactive_terminal() {
+    let dir = temp_dir_path("cruxlines-top");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("a.py"), "def load():\n    return 1\n").expect("write a");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache", "top"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("top needs an interactive terminal"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn git_init(dir: &std::path::Path) {
-    let status = git_command(dir).arg("init").status().expect("git init");
-    assert!(status.success(), "git init failed");
-    let status = git_command(dir)
-        .args(["config", "user.name", "Test User"])
-        .status()
-        .expect("git config user.name");
-    assert!(status.success(), "git config user.name failed");
-    let status = git_command(dir)
-        .args(["config", "user.email", "test@example.com"])
-        .status()
-        .expect("git config user.email");
-    assert!(status.success(), "git config user.email failed");
+#[test]
+fn cli_analyzes_directories_outside_a_git_repository() {
+    let dir = temp_dir_path("cruxlines-no-git");
+    std::fs::create_dir_all(dir.join("sub")).expect("create temp dir");
+    std::fs::write(dir.join("core.py"), "def load():\n    return 1\n").expect("write core");
+    std::fs::write(dir.join("main.py"), "from core import load\n\nload()\n").expect("write main");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache"]).current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stdout("core.py:1:5: def load():\n")
+        .stderr(contains(
+            "cruxlines: warning: no git repository found, analyzing",
+        ));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache", "--no-frecency", "--repo-root", ".."])
+        .current_dir(dir.join("sub"));
+    cmd.assert()
+        .success()
+        .stdout("core.py:1:5: def load():\n")
+        .stderr("");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--repo-root", "missing"]).current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("path not found: missing"));
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn git_commit(dir: &std::path::Path, message: &str, date: &str) {
-    let status = git_command(dir)
-        .arg("add")
-        .arg(".")
-        .status()
-        .expect("git add");
-    assert!(status.success(), "git add failed");
-    let status = git_command(dir)
-        .args(["-c", "commit.gpgsign=false", "commit", "-m", message])
-        .env("GIT_AUTHOR_DATE", date)
-        .env("GIT_COMMITTER_DATE", date)
-        .env("GIT_AUTHOR_NAME", "Test User")
-        .env("GIT_AUTHOR_EMAIL", "test@example.com")
-        .env("GIT_COMMITTER_NAME", "Test User")
-        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+#[test]
+fn cli_finds_the_repo_root_of_linked_worktrees_and_git_dir() {
+    let dir = temp_dir_path("cruxlines-worktree");
+    let main = dir.join("main");
+    std::fs::create_dir_all(&main).expect("create temp dir");
+    git_init(&main);
+    std::fs::write(main.join("core.py"), "def load():\n    return 1\n").expect("write core");
+    std::fs::write(main.join("app.py"), "from core import load\n\nload()\n").expect("write app");
+    git_commit(&main, "one", "2001-01-01T00:00:00Z");
+    std::fs::write(
+        main.join("app.py"),
+        "from core import load\n\nload()\nload()\n",
+    )
+    .expect("rewrite app");
+    git_commit(&main, "two", "2001-01-02T00:00:00Z");
+    let linked = dir.join("linked");
+    let status = git_command(&main)
+        .args(["worktree", "add", "--quiet"])
+        .arg(&linked)
         .status()
-        .expect("git commit");
-    assert!(status.success(), "git commit failed");
+        .expect("git worktree add");
+    assert!(status.success(), "git worktree add failed");
+    std::fs::create_dir_all(linked.join("sub")).expect("create sub dir");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache", "diff", "HEAD~1", "HEAD"])
+        .current_dir(linked.join("sub"));
+    cmd.assert()
+        .success()
+        .stdout(contains("core.py:1:5: load "))
+        .stderr("");
+
+    let git_dir = dir.join("meta.git");
+    std::fs::rename(main.join(".git"), &git_dir).expect("move git dir");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache", "diff", "HEAD~1", "HEAD"])
+        .env("GIT_DIR", "../meta.git")
+        .current_dir(&main);
+    cmd.assert()
+        .success()
+        .stdout(contains("core.py:1:5: load "))
+        .stderr("");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache"])
+        .env("GIT_DIR", "meta.git")
+        .env("GIT_WORK_TREE", "main")
+        .current_dir(&dir);
+    cmd.assert()
+        .success()
+        .stdout("core.py:1:5: def load():\n")
+        .stderr("");
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
 
-fn git_command(dir: &std::path::Path) -> std::process::Command {
-    let mut cmd = std::process::Command::new("git");
-    cmd.arg("-C").arg(dir);
-    cmd.env("GIT_CONFIG_GLOBAL", "/dev/null");
-    cmd.env("GIT_CONFIG_SYSTEM", "/dev/null");
-    cmd.env("GIT_TERMINAL_PROMPT", "0");
-    cmd
+#[test]
+fn cli_dedup_references_reads_config_and_flag() {
+    let dir = temp_dir_path("cruxlines-dedup");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("lib.py"), "def wrap(x):\n    return x\n").expect("write lib");
+    std::fs::write(dir.join("other.py"), "def wrap(x):\n    return x\n").expect("write other");
+    std::fs::write(
+        dir.join("app.py"),
+        "from lib import wrap\n\nwrap(wrap(1))\nwrap(2)\n",
+    )
+    .expect("write app");
+    std::fs::write(
+        dir.join(".cruxlines.toml"),
+        "[references]\ndedup = \"line\"\n",
+    )
+    .expect("write config");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let wrap_references = |args: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("cruxlines");
+        cmd.args(["-e", "py", "--format", "json"])
+            .args(args)
+            .current_dir(&dir);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let document: serde_json::Value = serde_json::from_slice(&output).expect("json output");
+        document["rows"]
+            .as_array()
+            .expect("rows")
+            .iter()
+            .filter(|row| row["name"] == "wrap")
+            .map(|row| row["reference_count"].as_u64().expect("reference count"))
+            .sum::<u64>()
+    };
+    let line = wrap_references(&[]);
+    let none = wrap_references(&["--dedup-references", "none"]);
+    let location = wrap_references(&["--dedup-references", "location"]);
+    assert_eq!(
+        none,
+        2 * location,
+        "expected each usage to link to one of the two definitions"
+    );
+    assert_eq!(
+        location,
+        line + 1,
+        "expected wrap(wrap(1)) to count once per line"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_shows_unsaved_contents_as_definition_lines_and_context() {
+    let dir = temp_dir_path("cruxlines-unsaved-context");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    std::fs::write(dir.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    git_commit(&dir, "init", "2001-01-01T00:00:00Z");
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args([
+        "-e",
+        "py",
+        "--no-cache",
+        "-C",
+        "1",
+        "--stdin-content",
+        "defs.py",
+    ])
+    .current_dir(&dir)
+    .write_stdin("# unsaved\ndef add(x):\n    return x\n");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(
+        output,
+        "defs.py-1-# unsaved\ndefs.py:2:5: def add(x):\ndefs.py-3-    return x\n"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_rejects_revisions_that_look_like_options() {
+    let dir = temp_dir_path("cruxlines-revision-option");
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    git_init(&dir);
+    std::fs::write(dir.join("core.py"), "def core():\n    return 1\n").expect("write core");
+    git_commit(&dir, "base", "2001-01-01T00:00:00Z");
+
+    let injected = dir.join("injected");
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.arg(format!("--changed-since=--output={}", injected.display()))
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("revisions may not start with `-`"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.arg(format!("--rev=--output={}", injected.display()))
+        .args(["-e", "py", "--no-cache"])
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("revisions may not start with `-`"));
+
+    let mut cmd = cargo_bin_cmd!("cruxlines");
+    cmd.args(["--no-cache", "diff", "HEAD", "--"])
+        .arg(format!("--output={}", injected.display()))
+        .current_dir(&dir);
+    cmd.assert()
+        .failure()
+        .stderr(contains("revisions may not start with `-`"));
+
+    let leftovers: Vec<_> = std::fs::read_dir(&dir)
+        .expect("read dir")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("injected"))
+        .collect();
+    assert!(leftovers.is_empty(), "git wrote {leftovers:?}");
+
+    std::fs::remove_dir_all(&dir).expect("cleanup temp dir");
 }
@@ -92,6 +92,148 @@ fn finds_javascript_cross_file_references() {
     );
 }
 
+#[test]
+fn finds_javascript_commonjs_exports() {
+    let files = vec![
+        read_fixture("src/languages/javascript/fixtures/cjs_utils.js"),
+        read_fixture("src/languages/javascript/fixtures/cjs_main.js"),
+    ];
+
+    let rows = cruxlines_from_inputs(files, None);
+
+    for (name, line) in [
+        ("slugify", 1),
+        ("Registry", 5),
+        ("MAX_ITEMS", 11),
+        ("version", 15),
+    ] {
+        let row = rows
+            .iter()
+            .find(|row| {
+                row.definition.name_str() == name
+                    && row.definition.path_str().ends_with("cjs_utils.js")
+            })
+            .unwrap_or_else(|| panic!("expected {name} to be exported"));
+        assert_eq!(row.definition.line, line, "unexpected line for {name}");
+        assert!(
+            has_reference(&rows, name, "cjs_utils.js", "cjs_main.js"),
+            "expected reference to {name} from cjs_main.js"
+        );
+    }
+}
+
+#[test]
+fn javascript_imports_through_barrel_files_link_to_the_original_definition() {
+    let files = vec![
+        (
+            PathBuf::from("src/components/Button.tsx"),
+            "export function Button() {\n  return null;\n}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/components/Card.tsx"),
+            "export function Card() {\n  return null;\n}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/components/index.ts"),
+            "export { Button as PrimaryButton } from \"./Button\";\nexport * from \"./Card\";\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("src/index.ts"),
+            "export * from \"./components\";\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/legacy.js"),
+            "export function Button() {}\nexport function Card() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/app.tsx"),
+            "import { PrimaryButton } from \"./components\";\nimport { Card } from \"./\";\n\n\
+             PrimaryButton();\nCard();\n"
+                .to_string(),
+        ),
+    ];
+
+    let rows = cruxlines_from_inputs(files, None);
+
+    assert!(has_reference(
+        &rows,
+        "Button",
+        "src/components/Button.tsx",
+        "src/app.tsx"
+    ));
+    assert!(has_reference(
+        &rows,
+        "Card",
+        "src/components/Card.tsx",
+        "src/app.tsx"
+    ));
+    assert!(!has_reference(
+        &rows,
+        "Button",
+        "src/legacy.js",
+        "src/app.tsx"
+    ));
+    assert!(!has_reference(
+        &rows,
+        "Card",
+        "src/legacy.js",
+        "src/app.tsx"
+    ));
+}
+
+#[test]
+fn javascript_imports_link_to_the_imported_file() {
+    let files = vec![
+        (
+            PathBuf::from("src/math/index.ts"),
+            "export function add(a, b) {\n  return a + b;\n}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/strings.js"),
+            "export function add(a, b) {\n  return `${a}${b}`;\n}\nexport const sep = \"-\";\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("src/app.ts"),
+            "import { add as plus } from \"./math\";\n\nplus(1, 2);\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/legacy.js"),
+            "const { add, sep: separator } = require(\"./strings.js\");\n\nadd(separator, 1);\n"
+                .to_string(),
+        ),
+    ];
+
+    let rows = cruxlines_from_inputs(files, None);
+
+    assert!(has_reference(
+        &rows,
+        "add",
+        "src/math/index.ts",
+        "src/app.ts"
+    ));
+    assert!(!has_reference(&rows, "add", "src/strings.js", "src/app.ts"));
+    assert!(has_reference(
+        &rows,
+        "add",
+        "src/strings.js",
+        "src/legacy.js"
+    ));
+    assert!(!has_reference(
+        &rows,
+        "add",
+        "src/math/index.ts",
+        "src/legacy.js"
+    ));
+    assert!(has_reference(
+        &rows,
+        "sep",
+        "src/strings.js",
+        "src/legacy.js"
+    ));
+}
+
 #[test]
 fn finds_rust_cross_file_references() {
     let files = vec![
@@ -386,6 +528,40 @@ fn ignores_nested_python_definitions() {
     );
 }
 
+#[test]
+fn finds_python_class_member_definitions() {
+    let files = vec![
+        (
+            PathBuf::from("models.py"),
+            "LIMIT = 10\n\n\
+class Counter:\n    step = 1\n\n    def __init__(self):\n        self.value = 0\n\n\
+    @property\n    def doubled(self):\n        return self.value * 2\n\n\
+    def inc(self):\n        self.other = 1\n        def helper():\n            return 1\n        return helper()\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("main.py"),
+            "from models import Counter, LIMIT\n\n\
+c = Counter()\nc.inc()\nprint(c.value, c.doubled, c.step, c.other, LIMIT)\nc.__init__()\nhelper()\n"
+                .to_string(),
+        ),
+    ];
+
+    let rows = cruxlines_from_inputs(files, None);
+    for name in ["LIMIT", "step", "value", "doubled", "inc"] {
+        assert!(
+            has_reference(&rows, name, "models.py", "main.py"),
+            "expected {name} to be defined in models.py"
+        );
+    }
+    for name in ["__init__", "other", "helper"] {
+        assert!(
+            !rows.iter().any(|row| row.definition.name_str() == name),
+            "expected {name} not to be a definition"
+        );
+    }
+}
+
 #[test]
 fn ties_are_sorted_by_definition_location() {
     let mut files = Vec::new();
@@ -1365,6 +1541,51 @@ fn finds_c_multiple_declarators() {
     );
 }
 
+#[test]
+fn c_header_declarations_collapse_onto_paired_definitions() {
+    let files = vec![
+        (
+            PathBuf::from("util.h"),
+            "int add(int a, int b);\nint api_only(void);\nextern int counter;\n".to_string(),
+        ),
+        (
+            PathBuf::from("util.c"),
+            "#include \"util.h\"\n\nint counter = 0;\n\nint add(int a, int b) {\n    return a + b;\n}\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("include/geo.h"),
+            "int area(int w, int h);\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/shapes.c"),
+            "#include \"../include/geo.h\"\n\nint area(int w, int h) {\n    return w * h;\n}\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("main.c"),
+            "#include \"util.h\"\n#include \"include/geo.h\"\n\n\
+             int main(void) {\n    return add(counter, api_only()) + area(1, 2);\n}\n"
+                .to_string(),
+        ),
+    ];
+
+    let rows = cruxlines_from_inputs(files, None);
+    let paths_of = |name: &str| {
+        rows.iter()
+            .filter(|row| row.definition.name_str() == name)
+            .map(|row| row.definition.path_str())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(paths_of("add"), vec!["util.c"]);
+    assert_eq!(paths_of("counter"), vec!["util.c"]);
+    assert_eq!(paths_of("area"), vec!["src/shapes.c"]);
+    assert_eq!(paths_of("api_only"), vec!["util.h"]);
+    assert!(has_reference(&rows, "add", "util.c", "main.c"));
+    assert!(has_reference(&rows, "area", "src/shapes.c", "main.c"));
+}
+
 // C++ Tests
 
 #[test]
@@ -1623,3 +1844,1806 @@ fn finds_cpp_template_function_definitions() {
         "expected reference to maximum template function"
     );
 }
+
+#[test]
+fn analyzes_custom_source_provider() {
+    use std::sync::Mutex;
+
+    use cruxlines::{CruxlinesError, Options, SourceProvider, cruxlines_from_source};
+
+    struct Buffers {
+        files: Vec<(PathBuf, String)>,
+        reads: Mutex<Vec<PathBuf>>,
+    }
+
+    impl SourceProvider for Buffers {
+        fn paths(&self) -> Vec<PathBuf> {
+            self.files.iter().map(|(path, _)| path.clone()).collect()
+        }
+
+        fn read(&self, path: &Path) -> Result<Option<String>, CruxlinesError> {
+            self.reads.lock().unwrap().push(path.to_path_buf());
+            Ok(self
+                .files
+                .iter()
+                .find(|(candidate, _)| candidate == path)
+                .map(|(_, contents)| contents.clone()))
+        }
+    }
+
+    let source = Buffers {
+        files: vec![
+            (
+                PathBuf::from("unsaved/defs.py"),
+                "def add():\n    return 1\n".to_string(),
+            ),
+            (
+                PathBuf::from("unsaved/main.py"),
+                "from defs import add\n\nadd()\n".to_string(),
+            ),
+            (PathBuf::from("unsaved/notes.txt"), "add".to_string()),
+        ],
+        reads: Mutex::new(Vec::new()),
+    };
+
+    let analysis = cruxlines_from_source(&source, None, &Options::default()).expect("analyze");
+    assert!(analysis.diagnostics.is_empty());
+    assert!(has_reference(
+        &analysis.rows,
+        "add",
+        "unsaved/defs.py",
+        "unsaved/main.py"
+    ));
+    let reads = source.reads.lock().unwrap();
+    let source_reads = reads
+        .iter()
+        .filter(|path| !path.ends_with("pyproject.toml"))
+        .count();
+    assert_eq!(
+        source_reads, 2,
+        "expected only supported files and manifests to be read"
+    );
+    assert!(!reads.contains(&PathBuf::from("unsaved/notes.txt")));
+}
+
+#[test]
+fn reports_unreadable_files_as_diagnostics() {
+    use cruxlines::{
+        CruxlinesError, InMemorySource, Options, SourceProvider, cruxlines_from_source,
+    };
+
+    struct FlakyDisk {
+        inner: InMemorySource,
+        broken: PathBuf,
+    }
+
+    impl SourceProvider for FlakyDisk {
+        fn paths(&self) -> Vec<PathBuf> {
+            let mut paths = self.inner.paths();
+            paths.push(self.broken.clone());
+            paths
+        }
+
+        fn read(&self, path: &Path) -> Result<Option<String>, CruxlinesError> {
+            if path == self.broken {
+                return Err(CruxlinesError::ReadFile {
+                    path: path.to_path_buf(),
+                    source: std::io::Error::other("disk on fire"),
+                });
+            }
+            self.inner.read(path)
+        }
+    }
+
+    let source = FlakyDisk {
+        inner: InMemorySource::new([
+            (
+                PathBuf::from("defs.py"),
+                "def add():\n    return 1\n".to_string(),
+            ),
+            (
+                PathBuf::from("main.py"),
+                "from defs import add\n\nadd()\n".to_string(),
+            ),
+        ]),
+        broken: PathBuf::from("broken.py"),
+    };
+
+    let analysis = cruxlines_from_source(&source, None, &Options::default()).expect("analyze");
+    assert!(has_reference(&analysis.rows, "add", "defs.py", "main.py"));
+    let diagnostics: Vec<String> = analysis
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(diagnostics, vec!["failed to read broken.py: disk on fire"]);
+}
+
+#[test]
+fn custom_frecency_provider_weights_references() {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use cruxlines::{Frecency, FrecencyProvider, InMemorySource, Options, cruxlines_from_source};
+
+    struct RecentFiles;
+
+    impl FrecencyProvider for RecentFiles {
+        fn scores(&self, _repo_root: Option<&Path>) -> HashMap<PathBuf, f64> {
+            HashMap::from([(PathBuf::from("hot.py"), 10.0)])
+        }
+    }
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("defs.py"),
+            "def warm():\n    return 1\n\ndef cold():\n    return 2\n".to_string(),
+        ),
+        (
+            PathBuf::from("hot.py"),
+            "from defs import warm\n\nwarm()\n".to_string(),
+        ),
+        (
+            PathBuf::from("stale.py"),
+            "from defs import cold\n\ncold()\n".to_string(),
+        ),
+    ]);
+    let local_score = |options: &Options, name: &str| {
+        cruxlines_from_source(&source, None, options)
+            .expect("analyze")
+            .rows
+            .iter()
+            .find(|row| row.definition.name_str() == name)
+            .map(|row| row.local_score)
+            .expect("row")
+    };
+
+    let options = Options {
+        frecency: Frecency::Custom(Arc::new(RecentFiles)),
+        ..Options::default()
+    };
+    assert!(local_score(&options, "warm") > local_score(&options, "cold"));
+
+    let options = Options {
+        frecency: Frecency::Disabled,
+        ..Options::default()
+    };
+    assert_eq!(local_score(&options, "warm"), local_score(&options, "cold"));
+}
+
+#[test]
+fn references_from_test_code_are_down_weighted() {
+    use cruxlines::{InMemorySource, Options, Weights, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("defs.py"),
+            "def used():\n    return 1\n\ndef checked():\n    return 2\n".to_string(),
+        ),
+        (
+            PathBuf::from("main.py"),
+            "from defs import used\n\nused()\n".to_string(),
+        ),
+        (
+            PathBuf::from("test_defs.py"),
+            "from defs import checked\n\nchecked()\n".to_string(),
+        ),
+        (
+            PathBuf::from("lib.rs"),
+            concat!(
+                "pub fn run() {}\n",
+                "pub fn assert_ok() {}\n",
+                "pub fn serve() {}\n",
+                "pub fn assert_unix() {}\n",
+                "fn main() { run(); }\n",
+                "#[cfg(test)]\n",
+                "mod tests {\n",
+                "    fn check() { super::assert_ok(); }\n",
+                "}\n",
+                "#[cfg(not(test))]\n",
+                "mod live {\n",
+                "    fn start() { super::serve(); }\n",
+                "}\n",
+                "#[cfg(all(unix, any(feature = \"a,b\", test)))]\n",
+                "mod unix_tests {\n",
+                "    fn check() { super::assert_unix(); }\n",
+                "}\n",
+            )
+            .to_string(),
+        ),
+    ]);
+    let local_score = |options: &Options, name: &str| {
+        cruxlines_from_source(&source, None, options)
+            .expect("analyze")
+            .rows
+            .iter()
+            .find(|row| row.definition.name_str() == name)
+            .map(|row| row.local_score)
+            .expect("row")
+    };
+
+    let options = Options::default();
+    assert!(local_score(&options, "used") > local_score(&options, "checked"));
+    assert!(local_score(&options, "run") > local_score(&options, "assert_ok"));
+    assert_eq!(local_score(&options, "serve"), local_score(&options, "run"));
+    assert_eq!(
+        local_score(&options, "assert_unix"),
+        local_score(&options, "assert_ok")
+    );
+
+    let options = Options {
+        weights: Weights {
+            test_references: 1.0,
+            ..Weights::default()
+        },
+        ..Options::default()
+    };
+    assert_eq!(
+        local_score(&options, "used"),
+        local_score(&options, "checked")
+    );
+    assert_eq!(
+        local_score(&options, "run"),
+        local_score(&options, "assert_ok")
+    );
+}
+
+#[test]
+fn ffi_mode_links_python_and_javascript_calls_to_rust_exports() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("native/src/lib.rs"),
+            concat!(
+                "#[pyfunction]\n",
+                "fn sum_as_string(a: usize, b: usize) -> String { (a + b).to_string() }\n",
+                "#[napi]\n",
+                "pub fn sum_two(a: u32, b: u32) -> u32 { a + b }\n",
+                "#[wasm_bindgen(js_name = greetUser)]\n",
+                "pub fn greet(name: &str) -> String { name.to_string() }\n",
+            )
+            .to_string(),
+        ),
+        (
+            PathBuf::from("app/main.py"),
+            "import native\n\nnative.sum_as_string(1, 2)\n".to_string(),
+        ),
+        (
+            PathBuf::from("web/app.js"),
+            "import { sumTwo, greetUser } from \"native\";\n\nsumTwo(1, 2);\ngreetUser(\"a\");\n"
+                .to_string(),
+        ),
+    ]);
+
+    let options = Options {
+        ffi_edges: true,
+        ..Options::default()
+    };
+    let rows = cruxlines_from_source(&source, None, &options)
+        .expect("analyze")
+        .rows;
+    assert!(has_reference(
+        &rows,
+        "sum_as_string",
+        "lib.rs",
+        "app/main.py"
+    ));
+    assert!(has_reference(&rows, "sum_two", "lib.rs", "web/app.js"));
+    assert!(has_reference(&rows, "greet", "lib.rs", "web/app.js"));
+
+    let rows = cruxlines_from_source(&source, None, &Options::default())
+        .expect("analyze")
+        .rows;
+    assert!(
+        rows.iter()
+            .all(|row| !row.definition.path_str().ends_with("lib.rs")),
+        "expected no cross-ecosystem edges without the opt-in"
+    );
+}
+
+#[test]
+fn classifies_definition_kinds() {
+    use cruxlines::SymbolKind;
+
+    let rows = cruxlines_from_inputs(
+        vec![
+            (
+                PathBuf::from("models.py"),
+                concat!(
+                    "LIMIT = 3\n",
+                    "class User:\n",
+                    "    role = \"admin\"\n",
+                    "    def save(self):\n",
+                    "        pass\n",
+                    "def load():\n",
+                    "    pass\n",
+                )
+                .to_string(),
+            ),
+            (
+                PathBuf::from("main.py"),
+                "from models import LIMIT, User, load\n\nload(User().save(), User.role, LIMIT)\n"
+                    .to_string(),
+            ),
+            (
+                PathBuf::from("lib.rs"),
+                "pub struct Config;\npub trait Store {}\npub fn open() {}\n".to_string(),
+            ),
+            (
+                PathBuf::from("main.rs"),
+                "fn run(_: Config, _: &dyn Store) { open(); }\n".to_string(),
+            ),
+        ],
+        None,
+    );
+    let kind_of = |name: &str| {
+        rows.iter()
+            .find(|row| row.definition.name_str() == name)
+            .map(|row| row.kind)
+    };
+    assert_eq!(kind_of("LIMIT"), Some(SymbolKind::Variable));
+    assert_eq!(kind_of("User"), Some(SymbolKind::Class));
+    assert_eq!(kind_of("role"), Some(SymbolKind::Field));
+    assert_eq!(kind_of("save"), Some(SymbolKind::Method));
+    assert_eq!(kind_of("load"), Some(SymbolKind::Function));
+    assert_eq!(kind_of("Config"), Some(SymbolKind::Struct));
+    assert_eq!(kind_of("Store"), Some(SymbolKind::Trait));
+    assert_eq!(kind_of("open"), Some(SymbolKind::Function));
+}
+
+#[test]
+fn kotlin_emits_members_of_top_level_classes_and_objects() {
+    use cruxlines::SymbolKind;
+
+    let rows = cruxlines_from_inputs(
+        vec![
+            (
+                PathBuf::from("User.kt"),
+                concat!(
+                    "class User(val email: String, id: Int) {\n",
+                    "    var nickname = \"\"\n",
+                    "    fun greet() {}\n",
+                    "    companion object {\n",
+                    "        const val MAX = 3\n",
+                    "        fun create(): User = User(\"a\", 1)\n",
+                    "    }\n",
+                    "    class Builder {\n",
+                    "        fun build() {}\n",
+                    "    }\n",
+                    "}\n",
+                    "object Registry {\n",
+                    "    fun lookup() {}\n",
+                    "}\n",
+                    "enum class Color {\n",
+                    "    RED;\n",
+                    "}\n",
+                    "val (first, second) = Pair(1, 2)\n",
+                )
+                .to_string(),
+            ),
+            (
+                PathBuf::from("Main.kt"),
+                concat!(
+                    "fun main() {\n",
+                    "    val user = User.create()\n",
+                    "    user.greet()\n",
+                    "    println(user.email + user.nickname + User.MAX + first + second + id)\n",
+                    "    Registry.lookup()\n",
+                    "    User.Builder().build()\n",
+                    "    println(Color.RED)\n",
+                    "}\n",
+                )
+                .to_string(),
+            ),
+        ],
+        None,
+    );
+    let kind_of = |name: &str| {
+        rows.iter()
+            .find(|row| row.definition.name_str() == name)
+            .map(|row| row.kind)
+    };
+    assert_eq!(kind_of("email"), Some(SymbolKind::Field));
+    assert_eq!(kind_of("nickname"), Some(SymbolKind::Field));
+    assert_eq!(kind_of("greet"), Some(SymbolKind::Method));
+    assert_eq!(kind_of("MAX"), Some(SymbolKind::Constant));
+    assert_eq!(kind_of("create"), Some(SymbolKind::Method));
+    assert_eq!(kind_of("Builder"), Some(SymbolKind::Class));
+    assert_eq!(kind_of("lookup"), Some(SymbolKind::Method));
+    assert_eq!(kind_of("RED"), Some(SymbolKind::Constant));
+    assert_eq!(kind_of("first"), Some(SymbolKind::Variable));
+    assert_eq!(kind_of("second"), Some(SymbolKind::Variable));
+    assert_eq!(
+        kind_of("id"),
+        None,
+        "plain constructor parameters are not properties"
+    );
+    assert_eq!(
+        kind_of("build"),
+        None,
+        "members of nested classes are skipped"
+    );
+}
+
+#[test]
+fn java_emits_members_of_top_level_types() {
+    use cruxlines::SymbolKind;
+
+    let rows = cruxlines_from_inputs(
+        vec![
+            (
+                PathBuf::from("User.java"),
+                concat!(
+                    "public class User {\n",
+                    "  public static final int MAX = 3, MIN = 1;\n",
+                    "  String name;\n",
+                    "  void save() {}\n",
+                    "  static class Builder {\n",
+                    "    void build() {}\n",
+                    "  }\n",
+                    "}\n",
+                    "interface Shape {\n",
+                    "  double area();\n",
+                    "  int SIDES = 3;\n",
+                    "}\n",
+                    "enum Color {\n",
+                    "  RED;\n",
+                    "  int hex() { return 1; }\n",
+                    "}\n",
+                )
+                .to_string(),
+            ),
+            (
+                PathBuf::from("Main.java"),
+                concat!(
+                    "class Main {\n",
+                    "  void run(User user, Shape shape) {\n",
+                    "    user.save();\n",
+                    "    System.out.println(user.name + User.MAX + User.MIN + Shape.SIDES);\n",
+                    "    shape.area();\n",
+                    "    new User.Builder().build();\n",
+                    "    Color.RED.hex();\n",
+                    "  }\n",
+                    "}\n",
+                )
+                .to_string(),
+            ),
+        ],
+        None,
+    );
+    let kind_of = |name: &str| {
+        rows.iter()
+            .find(|row| row.definition.name_str() == name)
+            .map(|row| row.kind)
+    };
+    assert_eq!(kind_of("User"), Some(SymbolKind::Class));
+    assert_eq!(kind_of("User.MAX"), Some(SymbolKind::Constant));
+    assert_eq!(kind_of("User.MIN"), Some(SymbolKind::Constant));
+    assert_eq!(kind_of("User.name"), Some(SymbolKind::Field));
+    assert_eq!(kind_of("User.save"), Some(SymbolKind::Method));
+    assert_eq!(kind_of("User.Builder"), Some(SymbolKind::Class));
+    assert_eq!(kind_of("Shape.area"), Some(SymbolKind::Method));
+    assert_eq!(kind_of("Shape.SIDES"), Some(SymbolKind::Constant));
+    assert_eq!(kind_of("Color.RED"), Some(SymbolKind::Constant));
+    assert_eq!(kind_of("Color.hex"), Some(SymbolKind::Method));
+    assert_eq!(
+        kind_of("User.Builder.build"),
+        None,
+        "members of nested types are skipped"
+    );
+    // Qualified members still link through their own name.
+    assert!(has_reference(&rows, "User.save", "User.java", "Main.java"));
+    assert!(has_reference(&rows, "Color.RED", "User.java", "Main.java"));
+}
+
+#[test]
+fn reference_kinds_can_be_extended_or_trimmed_per_language() {
+    use std::collections::HashMap;
+
+    use cruxlines::{InMemorySource, Language, Options, ReferenceKinds, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("src/parser.rs"),
+            "pub struct Parser;\n\npub fn parse(_input: &str) {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/main.rs"),
+            "use crate::parser::Parser;\n\nfn main() {\n    Parser.parse();\n}\n".to_string(),
+        ),
+    ]);
+    let referenced = |options: &Options, name: &str| {
+        cruxlines_from_source(&source, None, options)
+            .expect("analyze")
+            .rows
+            .iter()
+            .any(|row| row.definition.name_str() == name && !row.references.is_empty())
+    };
+
+    assert!(referenced(&Options::default(), "Parser"));
+    assert!(!referenced(&Options::default(), "parse"));
+
+    let options = Options {
+        reference_kinds: HashMap::from([(
+            Language::Rust,
+            ReferenceKinds {
+                add: vec!["field_identifier".to_string()],
+                remove: vec!["identifier".to_string(), "type_identifier".to_string()],
+            },
+        )]),
+        ..Options::default()
+    };
+    assert!(referenced(&options, "parse"));
+    assert!(!referenced(&options, "Parser"));
+}
+
+#[test]
+fn custom_languages_are_scanned_as_their_own_ecosystem() {
+    use cruxlines::{
+        CruxlinesError, CustomLanguage, Ecosystem, Language, ecosystem_for_path, register_language,
+    };
+
+    let snake = || CustomLanguage {
+        name: "snake".to_string(),
+        extensions: vec!["snk".to_string()],
+        grammar: tree_sitter_python::LANGUAGE.into(),
+        definitions: "(module (function_definition name: (identifier) @definition.function))"
+            .to_string(),
+        references: "(identifier) @reference".to_string(),
+    };
+    let language = register_language(snake()).expect("register");
+    assert_eq!(language.name(), "snake");
+    assert_eq!(Language::from_name("snake"), Some(language));
+    assert!(matches!(
+        ecosystem_for_path(Path::new("lib/a.snk")),
+        Some(Ecosystem::Custom(_))
+    ));
+    assert!(matches!(
+        register_language(snake()),
+        Err(CruxlinesError::Language { .. })
+    ));
+
+    let rows = cruxlines_from_inputs(
+        vec![
+            (
+                PathBuf::from("lib/a.snk"),
+                "def hiss():\n    pass\n".to_string(),
+            ),
+            (PathBuf::from("lib/b.snk"), "hiss()\n".to_string()),
+        ],
+        None,
+    );
+    assert!(has_reference(&rows, "hiss", "lib/a.snk", "lib/b.snk"));
+}
+
+#[test]
+fn namespaces_keep_names_from_linking_across_trees() {
+    use cruxlines::{InMemorySource, Namespace, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("web/util.js"),
+            "export function init() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("web/main.js"),
+            "import { init } from './util';\n\ninit();\n".to_string(),
+        ),
+        (
+            PathBuf::from("firmware/scripts/boot.js"),
+            "init();\n".to_string(),
+        ),
+        (
+            PathBuf::from("firmware/scripts/flash.py"),
+            "def init():\n    pass\n".to_string(),
+        ),
+    ]);
+    let referenced_from = |options: &Options, path: &str| {
+        cruxlines_from_source(&source, None, options)
+            .expect("analyze")
+            .rows
+            .iter()
+            .filter(|row| row.definition.name_str() == "init")
+            .flat_map(|row| &row.references)
+            .any(|reference| reference.path_str() == path)
+    };
+
+    assert!(referenced_from(
+        &Options::default(),
+        "firmware/scripts/boot.js"
+    ));
+
+    let options = Options {
+        namespaces: vec![Namespace {
+            name: "firmware".to_string(),
+            paths: vec!["firmware/scripts/*".to_string()],
+        }],
+        ..Options::default()
+    };
+    // Nor does it link to the Python `init` sharing its namespace.
+    assert!(!referenced_from(&options, "firmware/scripts/boot.js"));
+    assert!(referenced_from(&options, "web/main.js"));
+}
+
+#[test]
+fn progress_hook_reports_each_phase() {
+    use std::sync::{Arc, Mutex};
+
+    use cruxlines::{
+        InMemorySource, Options, Phase, Progress, ProgressHook, cruxlines_from_source,
+    };
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("defs.py"),
+            "def add():\n    return 1\n".to_string(),
+        ),
+        (
+            PathBuf::from("main.py"),
+            "from defs import add\n\nadd()\n".to_string(),
+        ),
+    ]);
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let options = Options {
+        progress: Some(ProgressHook::new({
+            let updates = Arc::clone(&updates);
+            move |progress| updates.lock().unwrap().push(progress)
+        })),
+        ..Options::default()
+    };
+    cruxlines_from_source(&source, None, &options).expect("analyze");
+
+    let updates = updates.lock().unwrap();
+    let mut parsed: Vec<usize> = updates
+        .iter()
+        .filter(|update| update.phase == Phase::Parse)
+        .map(|update| update.done)
+        .collect();
+    parsed.sort();
+    assert_eq!(parsed, vec![1, 2]);
+    assert_eq!(
+        updates.last(),
+        Some(&Progress {
+            phase: Phase::Rank,
+            done: 1,
+            total: Some(1)
+        })
+    );
+}
+
+#[test]
+fn syntax_errors_mark_files_partial_and_drop_broken_definitions() {
+    use cruxlines::{
+        CustomLanguage, InMemorySource, Options, ParseCounts, ParseStatus, cruxlines_from_source,
+        register_language,
+    };
+
+    // Calls count as definitions, so the one tree-sitter folds into an
+    // ERROR node would be picked up if broken subtrees weren't skipped.
+    register_language(CustomLanguage {
+        name: "callsnake".to_string(),
+        extensions: vec!["csnk".to_string()],
+        grammar: tree_sitter_python::LANGUAGE.into(),
+        definitions: "(call function: (identifier) @definition.function)".to_string(),
+        references: "(identifier) @reference".to_string(),
+    })
+    .expect("register");
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("defs.csnk"),
+            "setup()\n\nitems = [\n    1,\n\ndef broken():\n    pass\n".to_string(),
+        ),
+        (PathBuf::from("main.csnk"), "setup\nbroken\n".to_string()),
+    ]);
+    let analysis = cruxlines_from_source(&source, None, &Options::default()).expect("analyze");
+
+    let names: Vec<&str> = analysis
+        .rows
+        .iter()
+        .map(|row| row.definition.name_str())
+        .collect();
+    assert_eq!(names, vec!["setup"]);
+    let diagnostics = &analysis.diagnostics;
+    assert!(diagnostics.is_empty());
+    assert_eq!(diagnostics.partial(), [PathBuf::from("defs.csnk")]);
+    assert_eq!(
+        diagnostics.parse_status(Path::new("defs.csnk")),
+        ParseStatus::Partial
+    );
+    assert_eq!(
+        diagnostics.parse_status(Path::new("main.csnk")),
+        ParseStatus::Ok
+    );
+    assert_eq!(
+        diagnostics.parse_counts(),
+        ParseCounts {
+            ok: 1,
+            partial: 1,
+            failed: 0
+        }
+    );
+}
+
+#[test]
+fn common_names_defined_more_than_once_only_link_through_imports() {
+    use std::collections::HashMap;
+
+    use cruxlines::{CommonNames, Ecosystem, InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("store.js"),
+            "export function get() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("cache.ts"),
+            "export function get(): void {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("views.js"),
+            "import { get } from './store';\n\nget();\n".to_string(),
+        ),
+        (PathBuf::from("client.js"), "get();\n".to_string()),
+        (PathBuf::from("app.ts"), "get();\n".to_string()),
+        (
+            PathBuf::from("setup.py"),
+            "def setup():\n    pass\n".to_string(),
+        ),
+        (
+            PathBuf::from("main.py"),
+            "from setup import setup\n\nsetup()\n".to_string(),
+        ),
+    ]);
+    let referenced_from = |options: &Options, name: &str| {
+        let analysis = cruxlines_from_source(&source, None, options).expect("analyze");
+        let mut paths: Vec<String> = analysis
+            .rows
+            .iter()
+            .filter(|row| row.definition.name_str() == name)
+            .flat_map(|row| &row.references)
+            .map(|reference| reference.path_str().to_string())
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        paths
+    };
+
+    // `get` has two definitions, so only the import links.
+    assert_eq!(
+        referenced_from(&Options::default(), "get"),
+        vec!["views.js"]
+    );
+    // `setup` has one, so it links by name like any other.
+    assert_eq!(
+        referenced_from(&Options::default(), "setup"),
+        vec!["main.py"]
+    );
+
+    // The `javascript` overrides apply to TypeScript too.
+    let options = Options {
+        common_names: HashMap::from([(
+            Ecosystem::JavaScript,
+            CommonNames {
+                add: Vec::new(),
+                remove: vec!["get".to_string()],
+            },
+        )]),
+        ..Options::default()
+    };
+    assert_eq!(
+        referenced_from(&options, "get"),
+        vec!["app.ts", "client.js", "views.js"]
+    );
+}
+
+#[test]
+fn entry_points_are_flagged() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("web/package.json"),
+            r#"{ "name": "web", "main": "index.js" }"#.to_string(),
+        ),
+        (
+            PathBuf::from("web/index.js"),
+            "export function start() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("web/lib.js"),
+            "export function helper() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("web/app.js"),
+            "import { start } from './index';\nimport { helper } from './lib';\n\nstart();\nhelper();\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("tool.py"),
+            "def cli():\n    pass\n\n\ndef parse():\n    pass\n\n\nif __name__ == \"__main__\":\n    cli()\n"
+                .to_string(),
+        ),
+        (PathBuf::from("other.py"), "parse()\n".to_string()),
+    ]);
+    let analysis = cruxlines_from_source(&source, None, &Options::default()).expect("analyze");
+    let mut entry_points: Vec<&str> = analysis
+        .rows
+        .iter()
+        .filter(|row| row.is_entry_point)
+        .map(|row| row.definition.name_str())
+        .collect();
+    entry_points.sort_unstable();
+
+    assert_eq!(entry_points, vec!["cli", "start"]);
+}
+
+#[test]
+fn definitions_reachable_from_main_can_be_boosted() {
+    use cruxlines::{InMemorySource, Options, Weights, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("main.rs"),
+            "fn main() {\n    helper();\n}\n".to_string(),
+        ),
+        (
+            PathBuf::from("lib.rs"),
+            "pub fn helper() {}\npub fn other() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("tools.rs"),
+            "pub fn tool() {\n    other();\n}\n".to_string(),
+        ),
+    ]);
+    let rank = |entry_reachable: f64, name: &str| {
+        let options = Options {
+            weights: Weights {
+                entry_reachable,
+                ..Weights::default()
+            },
+            ..Options::default()
+        };
+        cruxlines_from_source(&source, None, &options)
+            .expect("analyze")
+            .rows
+            .iter()
+            .find(|row| row.definition.name_str() == name)
+            .map(|row| row.rank)
+            .expect("row")
+    };
+
+    assert_eq!(rank(2.0, "helper"), 2.0 * rank(1.0, "helper"));
+    assert_eq!(rank(2.0, "other"), rank(1.0, "other"));
+}
+
+#[test]
+fn scripts_in_vue_and_svelte_components_reference_their_imports() {
+    let files = vec![
+        (
+            PathBuf::from("src/price.ts"),
+            "export function formatPrice(cents: number): string {\n  return `${cents / 100}`;\n}\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("src/Cart.vue"),
+            concat!(
+                "<template>\n",
+                "  <p>{{ label }}</p>\n",
+                "</template>\n",
+                "\n",
+                "<script setup lang=\"ts\">\n",
+                "import { formatPrice } from './price';\n",
+                "const label = formatPrice(250);\n",
+                "</script>\n",
+            )
+            .to_string(),
+        ),
+        (
+            PathBuf::from("src/Total.svelte"),
+            concat!(
+                "<script>\n",
+                "  import { formatPrice } from './price';\n",
+                "  export let cents = 0;\n",
+                "</script>\n",
+                "\n",
+                "<p>{formatPrice(cents)}</p>\n",
+            )
+            .to_string(),
+        ),
+    ];
+
+    let rows = cruxlines_from_inputs(files, None);
+    let format_price = rows
+        .iter()
+        .find(|row| row.definition.name_str() == "formatPrice")
+        .expect("formatPrice row");
+    let positions: Vec<(&str, usize, usize)> = format_price
+        .references
+        .iter()
+        .map(|reference| (reference.path_str(), reference.line, reference.column))
+        .collect();
+
+    assert_eq!(
+        positions,
+        vec![
+            ("src/Cart.vue", 6, 10),
+            ("src/Cart.vue", 7, 15),
+            ("src/Total.svelte", 2, 12),
+        ]
+    );
+}
+
+#[test]
+fn script_blocks_in_html_pages_are_javascript_usages() {
+    let files = vec![
+        (
+            PathBuf::from("static/app.js"),
+            "export function renderChart() {}\nexport function loadConfig() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("static/index.html"),
+            concat!(
+                "<!doctype html>\n",
+                "<html>\n",
+                "  <body>\n",
+                "    <script type=\"text/template\" id=\"row\">loadConfig()</script>\n",
+                "    <script type=\"module\">\n",
+                "      import { renderChart } from './app.js';\n",
+                "      renderChart();\n",
+                "    </script>\n",
+                "  </body>\n",
+                "</html>\n",
+            )
+            .to_string(),
+        ),
+    ];
+
+    let rows = cruxlines_from_inputs(files, None);
+    let render_chart = rows
+        .iter()
+        .find(|row| row.definition.name_str() == "renderChart")
+        .expect("renderChart row");
+    let positions: Vec<(&str, usize, usize)> = render_chart
+        .references
+        .iter()
+        .map(|reference| (reference.path_str(), reference.line, reference.column))
+        .collect();
+
+    assert_eq!(
+        positions,
+        vec![("static/index.html", 6, 16), ("static/index.html", 7, 7)]
+    );
+    assert!(
+        !rows
+            .iter()
+            .any(|row| row.definition.name_str() == "loadConfig"),
+        "template blocks are not scripts"
+    );
+}
+
+#[test]
+fn pipeline_yaml_references_the_code_it_runs() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from(".github/workflows/deploy.yml"),
+            "jobs:\n  deploy:\n    steps:\n      - run: python scripts/deploy.py --prod\n      - run: python -m tools.migrate\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("serverless.yaml"),
+            "functions:\n  api:\n    handler: app.lambda:handler\n".to_string(),
+        ),
+        (
+            PathBuf::from("scripts/deploy.py"),
+            "def deploy():\n    pass\n\n\nif __name__ == \"__main__\":\n    deploy()\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("tools/migrate/__main__.py"),
+            "def migrate():\n    pass\n".to_string(),
+        ),
+        (
+            PathBuf::from("app/lambda.py"),
+            "def handler(event, context):\n    pass\n\n\ndef unused():\n    pass\n".to_string(),
+        ),
+    ]);
+    let references = |options: &Options| {
+        let analysis = cruxlines_from_source(&source, None, options).expect("analyze");
+        let mut references: Vec<(String, String, usize)> = analysis
+            .rows
+            .iter()
+            .flat_map(|row| {
+                row.references.iter().map(|reference| {
+                    (
+                        row.definition.name_str().to_string(),
+                        reference.path_str().to_string(),
+                        reference.line,
+                    )
+                })
+            })
+            .filter(|(_, path, _)| path.ends_with("yml") || path.ends_with("yaml"))
+            .collect();
+        references.sort();
+        references
+    };
+
+    assert!(references(&Options::default()).is_empty());
+    assert_eq!(
+        references(&Options {
+            pipeline_references: true,
+            ..Options::default()
+        }),
+        vec![
+            (
+                "deploy".to_string(),
+                ".github/workflows/deploy.yml".to_string(),
+                4
+            ),
+            ("handler".to_string(), "serverless.yaml".to_string(), 3),
+            (
+                "migrate".to_string(),
+                ".github/workflows/deploy.yml".to_string(),
+                5
+            ),
+        ]
+    );
+}
+
+#[test]
+fn java_references_only_link_to_classes_of_imported_packages() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("src/com/acme/http/Client.java"),
+            "package com.acme.http;\n\npublic class Client {\n    public void send() {}\n}\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("src/com/acme/db/Client.java"),
+            "package com.acme.db;\n\npublic class Client {\n    public void send() {}\n}\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("src/com/acme/app/Web.java"),
+            "package com.acme.app;\n\nimport com.acme.http.Client;\n\npublic class Web {\n    void run(Client client) {\n        client.send();\n    }\n}\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("src/com/acme/db/Pool.java"),
+            "package com.acme.db;\n\npublic class Pool {\n    void fill(Client client) {\n        client.send();\n    }\n}\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("src/Qualified.kt"),
+            "fun ping(client: com.acme.http.Client) {\n    client.send()\n}\n".to_string(),
+        ),
+    ]);
+    let rows = cruxlines_from_source(&source, None, &Options::default())
+        .expect("analyze")
+        .rows;
+
+    assert!(has_reference(
+        &rows,
+        "Client",
+        "http/Client.java",
+        "Web.java"
+    ));
+    assert!(!has_reference(
+        &rows,
+        "Client",
+        "db/Client.java",
+        "Web.java"
+    ));
+    assert!(has_reference(
+        &rows,
+        "Client.send",
+        "http/Client.java",
+        "Web.java"
+    ));
+    assert!(!has_reference(
+        &rows,
+        "Client.send",
+        "db/Client.java",
+        "Web.java"
+    ));
+    assert!(has_reference(
+        &rows,
+        "Client",
+        "db/Client.java",
+        "Pool.java"
+    ));
+    assert!(!has_reference(
+        &rows,
+        "Client",
+        "http/Client.java",
+        "Pool.java"
+    ));
+    // Without a package or imports, every same-named class still matches.
+    assert!(has_reference(
+        &rows,
+        "Client",
+        "http/Client.java",
+        "Qualified.kt"
+    ));
+    assert!(has_reference(
+        &rows,
+        "Client",
+        "db/Client.java",
+        "Qualified.kt"
+    ));
+}
+
+#[test]
+fn rust_module_paths_scope_references() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("src/lib.rs"),
+            "mod admin;\nmod app;\nmod models;\nmod report;\nmod other;\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/models.rs"),
+            "pub struct User;\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/admin/mod.rs"),
+            "pub struct User;\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/app.rs"),
+            "use crate::models::User;\n\nfn load() -> User {\n    User\n}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/report.rs"),
+            "fn show(user: &admin::User) {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/other.rs"),
+            "fn other(user: &User) {}\n".to_string(),
+        ),
+    ]);
+    let rows = cruxlines_from_source(&source, None, &Options::default())
+        .expect("analyze")
+        .rows;
+
+    assert!(has_reference(&rows, "User", "models.rs", "app.rs"));
+    assert!(!has_reference(&rows, "User", "admin/mod.rs", "app.rs"));
+    assert!(has_reference(&rows, "User", "admin/mod.rs", "report.rs"));
+    assert!(!has_reference(&rows, "User", "models.rs", "report.rs"));
+    // Unqualified names without a `use` still match every definition.
+    assert!(has_reference(&rows, "User", "models.rs", "other.rs"));
+    assert!(has_reference(&rows, "User", "admin/mod.rs", "other.rs"));
+}
+
+#[test]
+fn rust_qualified_paths_resolve_per_reference() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let references = |main: &str| {
+        let source = InMemorySource::new([
+            (PathBuf::from("src/main.rs"), main.to_string()),
+            (PathBuf::from("src/a.rs"), "pub fn run() {}\n".to_string()),
+            (PathBuf::from("src/b.rs"), "pub fn run() {}\n".to_string()),
+        ]);
+        let rows = cruxlines_from_source(&source, None, &Options::default())
+            .expect("analyze")
+            .rows;
+        let count = |module: &str| {
+            rows.iter()
+                .find(|row| row.definition.path_str().ends_with(module))
+                .map_or(0, |row| {
+                    row.references
+                        .iter()
+                        .filter(|reference| reference.path_str().ends_with("main.rs"))
+                        .count()
+                })
+        };
+        (count("a.rs"), count("b.rs"))
+    };
+
+    assert_eq!(
+        references(
+            "mod a;\nmod b;\n\nfn main() {\n    a::run();\n    b::run();\n    b::run();\n}\n"
+        ),
+        (1, 2)
+    );
+    // A `use` of the name binds the bare call, not the qualified ones; the
+    // `use` itself also refers to `a::run`.
+    assert_eq!(
+        references(
+            "mod a;\nmod b;\nuse crate::a::run;\n\nfn main() {\n    run();\n    a::run();\n    b::run();\n    b::run();\n}\n"
+        ),
+        (3, 2)
+    );
+}
+
+#[test]
+fn cargo_dependencies_link_workspace_crates_in_the_file_graph() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let files = |manifests: bool| {
+        let mut files = vec![
+            (
+                PathBuf::from("core/src/lib.rs"),
+                "mod config;\n\npub fn parse() {}\n".to_string(),
+            ),
+            (
+                PathBuf::from("core/src/config.rs"),
+                "pub fn load() {}\n\nfn reload() {\n    load();\n    crate::parse();\n}\n"
+                    .to_string(),
+            ),
+            (
+                PathBuf::from("cli/src/main.rs"),
+                "fn main() {\n    run();\n}\n\nfn run() {}\n".to_string(),
+            ),
+            (
+                PathBuf::from("web/src/lib.rs"),
+                "pub fn serve() {}\n\nfn start() {\n    serve();\n}\n".to_string(),
+            ),
+        ];
+        if manifests {
+            files.extend([
+                (
+                    PathBuf::from("Cargo.toml"),
+                    "[workspace]\nmembers = [\"core\", \"cli\", \"web\"]\n\n[workspace.dependencies]\ncore = { path = \"core\" }\n"
+                        .to_string(),
+                ),
+                (
+                    PathBuf::from("core/Cargo.toml"),
+                    "[package]\nname = \"core\"\n".to_string(),
+                ),
+                (
+                    PathBuf::from("cli/Cargo.toml"),
+                    "[package]\nname = \"cli\"\n\n[dependencies]\ncore = { workspace = true }\n"
+                        .to_string(),
+                ),
+                (
+                    PathBuf::from("web/Cargo.toml"),
+                    "[package]\nname = \"web\"\n\n[dependencies]\ncore = { path = \"../core\" }\n"
+                        .to_string(),
+                ),
+            ]);
+        }
+        InMemorySource::new(files)
+    };
+    let file_rank = |manifests: bool, name: &str| {
+        cruxlines_from_source(&files(manifests), None, &Options::default())
+            .expect("analyze")
+            .rows
+            .iter()
+            .find(|row| row.definition.name_str() == name)
+            .map(|row| row.file_rank)
+            .expect("ranked")
+    };
+
+    assert!(file_rank(true, "load") > file_rank(false, "load"));
+    assert!(file_rank(true, "parse") > file_rank(false, "parse"));
+    assert!(file_rank(true, "load") > file_rank(true, "serve"));
+}
+
+#[test]
+fn cargo_manifests_above_the_repo_root_are_ignored() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("Cargo.toml"),
+            "[workspace]\nmembers = [\"repo/core\", \"repo/cli\"]\n\n[workspace.dependencies]\nengine = { path = \"repo/core\" }\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("repo/core/Cargo.toml"),
+            "[package]\nname = \"core\"\n".to_string(),
+        ),
+        (
+            PathBuf::from("repo/core/src/lib.rs"),
+            "pub fn load() {}\n\nfn reload() {\n    load();\n}\n".to_string(),
+        ),
+        (
+            PathBuf::from("repo/cli/Cargo.toml"),
+            "[package]\nname = \"cli\"\n\n[dependencies]\nengine = { workspace = true }\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("repo/cli/src/main.rs"),
+            "fn main() {\n    run();\n}\n\nfn run() {}\n".to_string(),
+        ),
+    ]);
+    let file_rank = |repo_root: Option<&Path>| {
+        cruxlines_from_source(&source, repo_root, &Options::default())
+            .expect("analyze")
+            .rows
+            .iter()
+            .find(|row| row.definition.name_str() == "load")
+            .map(|row| row.file_rank)
+            .expect("ranked")
+    };
+
+    assert!(file_rank(None) > file_rank(Some(Path::new("repo"))));
+}
+
+#[test]
+fn package_manifests_mark_their_public_api() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("web/package.json"),
+            r#"{ "name": "web", "exports": { ".": { "import": "./src/index.js" } } }"#.to_string(),
+        ),
+        (
+            PathBuf::from("web/src/index.js"),
+            "export function render() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("web/src/internal.js"),
+            "export function helper() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("web/src/app.js"),
+            "import { render } from './index';\nimport { helper } from './internal';\n\nrender();\nhelper();\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("tool/pyproject.toml"),
+            "[project]\nname = \"tool\"\n\n[project.scripts]\ntool = \"tool.cli:main [color]\"\n"
+                .to_string(),
+        ),
+        (
+            PathBuf::from("tool/src/tool/cli.py"),
+            "def main():\n    pass\n\n\ndef parse():\n    pass\n".to_string(),
+        ),
+        (
+            PathBuf::from("tool/src/tool/run.py"),
+            "from tool.cli import main, parse\n\nmain()\nparse()\n".to_string(),
+        ),
+    ]);
+    let rows = cruxlines_from_source(&source, None, &Options::default())
+        .expect("analyze")
+        .rows;
+    let row = |name: &str| {
+        rows.iter()
+            .find(|row| row.definition.name_str() == name)
+            .expect("ranked")
+    };
+
+    assert!(has_reference(
+        &rows,
+        "render",
+        "index.js",
+        "web/package.json"
+    ));
+    assert!(has_reference(
+        &rows,
+        "main",
+        "cli.py",
+        "tool/pyproject.toml"
+    ));
+    assert_eq!(row("render").breakdown.manifest_references, 1);
+    assert_eq!(row("helper").breakdown.manifest_references, 0);
+    assert!(row("render").rank > row("helper").rank);
+    assert!(row("main").rank > row("parse").rank);
+    let manifest = row("main")
+        .references
+        .iter()
+        .find(|reference| reference.path_str().ends_with("pyproject.toml"))
+        .expect("manifest reference");
+    assert_eq!((manifest.line, manifest.column), (5, 9));
+}
+
+#[test]
+fn manifests_above_the_repo_root_are_ignored() {
+    use cruxlines::{InMemorySource, Options, cruxlines_from_source};
+
+    let source = InMemorySource::new([
+        (
+            PathBuf::from("package.json"),
+            r#"{ "name": "outer", "main": "./web/src/index.js", "exports": "./web/src/index.js" }"#
+                .to_string(),
+        ),
+        (
+            PathBuf::from("web/src/index.js"),
+            "export function render() {}\n".to_string(),
+        ),
+        (
+            PathBuf::from("web/src/app.js"),
+            "import { render } from './index';\n\nrender();\n".to_string(),
+        ),
+    ]);
+    let render = |repo_root: Option<&Path>| {
+        let row = cruxlines_from_source(&source, repo_root, &Options::default())
+            .expect("analyze")
+            .rows
+            .into_iter()
+            .find(|row| row.definition.name_str() == "render")
+            .expect("ranked");
+        (row.breakdown.manifest_references, row.is_entry_point)
+    };
+
+    assert_eq!(render(None), (1, true));
+    assert_eq!(render(Some(Path::new("web"))), (0, false));
+}
+
+#[test]
+fn inputs_under_a_repo_root_follow_its_ignore_files() {
+    let root =
+        std::env::temp_dir().join(format!("cruxlines-ignored-inputs-{}", std::process::id()));
+    fs::create_dir_all(root.join("lib")).expect("create lib dir");
+    fs::write(root.join("lib/.gitignore"), "gen/\n").expect("write nested gitignore");
+    let files = vec![
+        (
+            PathBuf::from("lib/gen/schema.py"),
+            "def schema():\n    return 0\n".to_string(),
+        ),
+        (
+            PathBuf::from(".tools/lint.py"),
+            "def lint():\n    return 0\n".to_string(),
+        ),
+        (
+            PathBuf::from("lib/util.py"),
+            "def util():\n    return 0\n".to_string(),
+        ),
+        (
+            PathBuf::from("main.py"),
+            "from schema import schema\nfrom lint import lint\nfrom util import util\n\nschema()\nlint()\nutil()\n"
+                .to_string(),
+        ),
+    ];
+    let rows = cruxlines_from_inputs(files.clone(), Some(root.clone()));
+    assert!(has_reference(&rows, "util", "lib/util.py", "main.py"));
+    assert!(!rows.iter().any(|row| row.definition.name_str() == "schema"));
+    assert!(!rows.iter().any(|row| row.definition.name_str() == "lint"));
+
+    let rows = cruxlines_from_inputs(files, None);
+    assert!(has_reference(
+        &rows,
+        "schema",
+        "lib/gen/schema.py",
+        "main.py"
+    ));
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn parse_sessions_reparse_edited_files_incrementally() {
+    use std::collections::HashMap;
+
+    use cruxlines::{
+        InMemorySource, Language, Options, ParseSession, ReferenceKinds, cruxlines_from_source,
+    };
+
+    let utils = "def add(a, b):\n    return a + b\n\n\ndef unused():\n    pass\n";
+    let main = "from utils import add\n\nprint(add(1, 2))\n";
+    // Renames a definition, inserts lines above it and adds a call, so
+    // both the edited tree and every later position must be right.
+    let edited_utils =
+        "import math\n\n\ndef add(a, b):\n    return a + b\n\n\ndef used():\n    return math.pi\n";
+    let edited_main = "from utils import add, used\n\nprint(add(1, 2), used())\n";
+    let summary = |contents: [(&str, &str); 2], session: Option<&ParseSession>| {
+        let source = InMemorySource::new(
+            contents.map(|(path, text)| (PathBuf::from(path), text.to_string())),
+        );
+        let options = Options {
+            parse_session: session.cloned(),
+            ..Options::default()
+        };
+        let analysis = cruxlines_from_source(&source, None, &options).expect("analyze");
+        analysis
+            .rows
+            .iter()
+            .map(|row| {
+                (
+                    row.definition.name_str().to_string(),
+                    row.definition.line,
+                    row.definition.column,
+                    row.references
+                        .iter()
+                        .map(|reference| (reference.line, reference.column))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let session = ParseSession::new();
+    let before = summary([("utils.py", utils), ("main.py", main)], Some(&session));
+    assert_eq!(
+        before,
+        summary([("utils.py", utils), ("main.py", main)], None)
+    );
+    assert_eq!(session.len(), 2);
+
+    let edited = [("utils.py", edited_utils), ("main.py", edited_main)];
+    let after = summary(edited, Some(&session));
+    assert_eq!(after, summary(edited, None));
+    assert!(
+        after
+            .iter()
+            .any(|(name, line, _, _)| name == "used" && *line == 8)
+    );
+    assert_eq!(session.len(), 2);
+
+    // Retained results only stand for the reference kinds they were found with.
+    let reference_count = |session: Option<&ParseSession>| {
+        let source =
+            InMemorySource::new(edited.map(|(path, text)| (PathBuf::from(path), text.to_string())));
+        let options = Options {
+            reference_kinds: HashMap::from([(
+                Language::Python,
+                ReferenceKinds {
+                    add: Vec::new(),
+                    remove: vec!["identifier".to_string()],
+                },
+            )]),
+            parse_session: session.cloned(),
+            ..Options::default()
+        };
+        let analysis = cruxlines_from_source(&source, None, &options).expect("analyze");
+        analysis
+            .rows
+            .iter()
+            .map(|row| row.reference_count)
+            .sum::<usize>()
+    };
+    assert_eq!(reference_count(Some(&session)), reference_count(None));
+
+    session.forget(Path::new("main.py"));
+    assert_eq!(session.len(), 1);
+}
+
+#[test]
+fn file_graph_counts_cross_file_references() {
+    use cruxlines::graph::FileGraph;
+
+    let files = vec![
+        (
+            PathBuf::from("lib.py"),
+            "def foo():\n    pass\n\ndef bar():\n    foo()\n".to_string(),
+        ),
+        (
+            PathBuf::from("app.py"),
+            "from lib import foo, bar\n\nfoo()\nfoo()\nbar()\n".to_string(),
+        ),
+        (
+            PathBuf::from("cli.py"),
+            "from lib import foo\n\nfoo()\n".to_string(),
+        ),
+    ];
+    let rows = cruxlines_from_inputs(files, None);
+    let graph = FileGraph::from_rows(&rows);
+
+    let paths: Vec<_> = graph.nodes.iter().map(|node| node.path.clone()).collect();
+    assert_eq!(
+        paths,
+        ["app.py", "cli.py", "lib.py"].map(PathBuf::from).to_vec()
+    );
+    let lib = graph.node(Path::new("lib.py")).expect("lib node");
+    let app = graph.node(Path::new("app.py")).expect("app node");
+    let cli = graph.node(Path::new("cli.py")).expect("cli node");
+    assert!(graph.nodes[lib].rank.is_some_and(|rank| rank > 0.0));
+    assert_eq!(graph.nodes[app].rank, None);
+
+    let references = |from: usize, to: usize| {
+        graph
+            .edges
+            .iter()
+            .find(|edge| edge.from == from && edge.to == to)
+            .map(|edge| edge.references)
+    };
+    assert!(references(app, lib).is_some_and(|count| count >= 3));
+    assert!(references(cli, lib).is_some_and(|count| count >= 1));
+    assert_eq!(references(lib, lib), None);
+    assert_eq!(references(lib, app), None);
+
+    let petgraph = graph.to_petgraph();
+    assert_eq!(petgraph.node_count(), 3);
+    assert_eq!(petgraph.edge_count(), graph.edges.len());
+}
+
+#[test]
+fn blended_frecency_is_a_weighted_mean_of_its_sources() {
+    use std::sync::Arc;
+
+    use cruxlines::{BlendedFrecency, Frecency, FrecencyProvider, JsonFrecency};
+
+    let source =
+        |json: &str| Frecency::Custom(Arc::new(JsonFrecency::parse(json).expect("valid scores")));
+    let blended = BlendedFrecency::new([
+        (source(r#"{"a.py": 5, "/abs/b.py": 3}"#), 0.25),
+        (source(r#"{"a.py": 1, "c.py": 9}"#), 0.75),
+    ]);
+    let scores = blended.scores(Some(Path::new("/repo")));
+
+    assert_eq!(scores.len(), 3);
+    assert_eq!(scores[Path::new("/repo/a.py")], 0.25 * 5.0 + 0.75 * 1.0);
+    // Sources without a file count it as neutral.
+    assert_eq!(scores[Path::new("/abs/b.py")], 0.25 * 3.0 + 0.75);
+    assert_eq!(scores[Path::new("/repo/c.py")], 0.25 + 0.75 * 9.0);
+
+    assert!(JsonFrecency::parse("[1, 2]").is_err());
+    assert!(JsonFrecency::parse(r#"{"a.py": -1}"#).is_err());
+}
+
+#[test]
+fn coverage_reads_lcov_and_cobertura_reports() {
+    use cruxlines::coverage::Coverage;
+
+    let root = Path::new("/repo");
+    let lcov = "TN:\nSF:src/app.py\nDA:1,1\nDA:2,7\nend_of_record\nSF:/abs/lib.py\nDA:3,0\nend_of_record\n";
+    let coverage = Coverage::parse(lcov, root).expect("valid lcov");
+    assert_eq!(coverage.len(), 3);
+    assert_eq!(
+        coverage.line_hits(Path::new("/repo/src/app.py"), 2),
+        Some(7)
+    );
+    assert_eq!(coverage.line_hits(Path::new("/abs/lib.py"), 3), Some(0));
+    assert_eq!(coverage.line_hits(Path::new("/repo/src/app.py"), 3), None);
+
+    let xml = r#"<?xml version="1.0" ?>
+<coverage version="7.4.0">
+  <sources><source>./pkg</source></sources>
+  <packages><package name="."><classes>
+    <class name="models.py" filename="models.py" line-rate="1">
+      <lines>
+        <line number="1" hits="1"/>
+        <line number="4" hits="12" branch="true" condition-coverage="100% (2/2)"/>
+      </lines>
+    </class>
+  </classes></package></packages>
+</coverage>
+"#;
+    let coverage = Coverage::parse(xml, root).expect("valid cobertura");
+    assert_eq!(
+        coverage.line_hits(Path::new("/repo/models.py"), 4),
+        Some(12)
+    );
+
+    assert!(Coverage::parse("DA:1,1\n", root).is_err());
+    assert!(Coverage::parse("<html></html>", root).is_err());
+}
+
+#[test]
+fn reading_files_ahead_keeps_results_in_path_order() {
+    use cruxlines::{FileSystemSource, Options, ScanOptions, cruxlines_from_source};
+
+    let root = std::env::temp_dir().join(format!("cruxlines-read-ahead-{}", std::process::id()));
+    fs::create_dir_all(&root).expect("create root");
+    // Large enough to finish reading after files queued behind it.
+    let padding = format!("# {}\n", "x".repeat(1_100_000));
+    fs::write(
+        root.join("helper.py"),
+        format!("def helper():\n    return 0\n{padding}"),
+    )
+    .expect("write helper");
+    let mut paths = vec![root.join("helper.py")];
+    for index in 0..24 {
+        let path = root.join(format!("user_{index:02}.py"));
+        fs::write(&path, "from helper import helper\n\nhelper()\n").expect("write user");
+        paths.push(path);
+    }
+
+    let analyze = |read_threads, low_memory| {
+        let options = Options {
+            low_memory,
+            scan: ScanOptions {
+                read_threads,
+                ..ScanOptions::default()
+            },
+            ..Options::default()
+        };
+        cruxlines_from_source(&FileSystemSource::new(paths.clone()), None, &options)
+            .expect("analysis")
+            .rows
+    };
+    let rows = analyze(1, false);
+    let helper = rows
+        .iter()
+        .find(|row| row.definition.name_str() == "helper")
+        .expect("helper row");
+    let users: std::collections::HashSet<_> = helper
+        .references
+        .iter()
+        .map(|reference| reference.path)
+        .collect();
+    assert_eq!(users.len(), 24);
+    for (read_threads, low_memory) in [(8, false), (1, true), (8, true)] {
+        let other = analyze(read_threads, low_memory);
+        assert_eq!(other.len(), rows.len());
+        for (row, other) in rows.iter().zip(&other) {
+            assert_eq!(row.definition, other.definition);
+            assert_eq!(row.reference_count, other.reference_count);
+            if !low_memory {
+                assert_eq!(row.references, other.references);
+            }
+        }
+    }
+    fs::remove_dir_all(&root).expect("remove root");
+}
+
+#[test]
+fn builder_runs_like_the_free_functions() {
+    use cruxlines::config::Config;
+    use cruxlines::{
+        Cruxlines, Ecosystem, Frecency, InMemorySource, Options, Weights, cruxlines_from_source,
+        cruxlines_with_options,
+    };
+
+    let inputs = vec![
+        (
+            PathBuf::from("defs.py"),
+            "def add():\n    return 1\n\ndef sub():\n    return 2\n".to_string(),
+        ),
+        (
+            PathBuf::from("main.py"),
+            "from defs import add, sub\n\nadd()\nadd()\nsub()\n".to_string(),
+        ),
+        (
+            PathBuf::from("util.js"),
+            "export function pad() {}\npad();\n".to_string(),
+        ),
+    ];
+    let names = |rows: &[OutputRow]| {
+        rows.iter()
+            .map(|row| row.definition.name_str().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let builder = Cruxlines::builder();
+    let rows = builder.run_inputs(inputs.clone()).expect("analysis").rows;
+    let free = cruxlines_from_source(
+        &InMemorySource::new(inputs.clone()),
+        None,
+        &Options::default(),
+    )
+    .expect("free analysis")
+    .rows;
+    assert_eq!(names(&rows), names(&free));
+    assert_eq!(
+        rows.iter().map(|row| row.rank).collect::<Vec<_>>(),
+        free.iter().map(|row| row.rank).collect::<Vec<_>>()
+    );
+    assert!(names(&rows).contains(&"pad".to_string()));
+
+    let python = Cruxlines::builder()
+        .ecosystems([Ecosystem::Python])
+        .top(1)
+        .run_inputs(inputs.clone())
+        .expect("analysis")
+        .rows;
+    assert_eq!(names(&python), ["add"]);
+
+    let config = Config {
+        weights: Weights {
+            file_rank: 0.0,
+            ..Weights::default()
+        },
+        ..Config::default()
+    };
+    let rows = Cruxlines::builder()
+        .config(config)
+        .run_inputs(inputs)
+        .expect("analysis")
+        .rows;
+    assert!(rows.iter().all(|row| row.rank == row.local_score));
+
+    let root = std::env::temp_dir().join(format!("cruxlines-builder-{}", std::process::id()));
+    fs::create_dir_all(&root).expect("create root");
+    fs::write(root.join("defs.py"), "def add():\n    return 1\n").expect("write defs");
+    fs::write(root.join("main.py"), "from defs import add\n\nadd()\n").expect("write main");
+    let builder = Cruxlines::builder()
+        .cache(false)
+        .frecency(Frecency::Disabled)
+        .ecosystems([Ecosystem::Python]);
+    let built = builder.run_repo(&root).expect("builder analysis");
+    let free = cruxlines_with_options(&root, &[Ecosystem::Python].into(), builder.as_options())
+        .expect("free analysis");
+    assert_eq!(names(&built.rows), names(&free.rows));
+    assert_eq!(built.rows[0].rank, free.rows[0].rank);
+    fs::remove_dir_all(&root).expect("remove root");
+}